@@ -1,24 +1,36 @@
+#[cfg(target_os = "macos")]
 use std::sync::mpsc::channel;
 
-use core_foundation::base::TCFType;
+#[cfg(target_os = "macos")]
+use core_foundation_0_10::base::TCFType;
+#[cfg(target_os = "macos")]
 use core_media::sample_buffer::{CMSampleBuffer, CMSampleBufferRef};
+#[cfg(target_os = "macos")]
 use core_video::pixel_buffer::CVPixelBuffer;
+#[cfg(target_os = "macos")]
 use dispatch2::{Queue, QueueAttribute};
+#[cfg(target_os = "macos")]
 use libc::size_t;
+#[cfg(target_os = "macos")]
 use objc2::{
     declare_class, extern_methods, msg_send_id, mutability,
     rc::{Allocated, Id},
     runtime::ProtocolObject,
     ClassType, DeclaredClass,
 };
+#[cfg(target_os = "macos")]
 use objc2_foundation::{NSArray, NSError, NSObject, NSObjectProtocol};
+#[cfg(target_os = "macos")]
 use screen_capture_kit::{
+    frame_info::FrameInfo,
     shareable_content::SCShareableContent,
     stream::{SCContentFilter, SCStream, SCStreamConfiguration, SCStreamDelegate, SCStreamOutput, SCStreamOutputType},
 };
 
+#[cfg(target_os = "macos")]
 pub struct DelegateIvars {}
 
+#[cfg(target_os = "macos")]
 declare_class!(
     struct Delegate;
 
@@ -41,6 +53,10 @@ declare_class!(
                 return;
             }
             let sample_buffer = CMSampleBuffer::wrap_under_get_rule(sample_buffer);
+            let status = FrameInfo::from_sample_buffer(&sample_buffer).and_then(|info| info.status);
+            if !status.is_some_and(|status| status.has_new_content()) {
+                return;
+            }
             if let Some(image_buffer) = sample_buffer.get_image_buffer() {
                 if let Some(pixel_buffer) = image_buffer.downcast::<CVPixelBuffer>() {
                     println!("pixel buffer: {:?}", pixel_buffer);
@@ -65,6 +81,7 @@ declare_class!(
     }
 );
 
+#[cfg(target_os = "macos")]
 extern_methods!(
     unsafe impl Delegate {
         #[method_id(new)]
@@ -72,6 +89,7 @@ extern_methods!(
     }
 );
 
+#[cfg(target_os = "macos")]
 fn main() {
     let (tx, rx) = channel();
     SCShareableContent::get_shareable_content_with_completion_closure(move |shareable_content, error| {
@@ -117,3 +135,8 @@ fn main() {
         }
     });
 }
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("This example requires macOS");
+}
@@ -0,0 +1,50 @@
+#[cfg(target_os = "macos")]
+use std::sync::mpsc::channel;
+
+#[cfg(target_os = "macos")]
+use objc2::ClassType;
+#[cfg(target_os = "macos")]
+use screen_capture_kit::{
+    image::ImageOptions,
+    screenshot::{screenshot, ScreenshotOptions, ScreenshotSource},
+    shareable_content::SCShareableContent,
+};
+
+#[cfg(target_os = "macos")]
+fn main() {
+    let (tx, rx) = channel();
+    SCShareableContent::get_shareable_content_with_completion_closure(move |shareable_content, error| {
+        let ret = shareable_content.ok_or_else(|| error.unwrap());
+        tx.send(ret).unwrap();
+    });
+    let shareable_content = match rx.recv().unwrap() {
+        Ok(shareable_content) => shareable_content,
+        Err(error) => {
+            println!("error: {:?}", error);
+            return;
+        }
+    };
+    let displays = shareable_content.displays();
+    let display = match displays.first() {
+        Some(display) => display.retain(),
+        None => {
+            println!("no display found");
+            return;
+        }
+    };
+    let frame = match screenshot(&ScreenshotSource::Display(display), &ScreenshotOptions::default()) {
+        Ok(frame) => frame,
+        Err(error) => {
+            println!("error: {}", error);
+            return;
+        }
+    };
+    if let Err(error) = frame.save("screenshot.png", &ImageOptions::default()) {
+        println!("error: {}", error);
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("This example requires macOS");
+}
@@ -0,0 +1,329 @@
+use std::{io::Write, time::Duration};
+
+use crate::{
+    clock::cmtime_to_nanos,
+    convert::ColorMatrix,
+    frame::VideoFrame,
+    gif::{write_gif, Dither},
+    image::ImageError,
+    png::write_apng,
+    scale::ScaleFilter,
+    sink::{SinkError, VideoSink},
+};
+
+/// Attempts at shrinking an animation into its size budget.
+const MAX_BUDGET_ATTEMPTS: usize = 8;
+/// Smallest side an animation is shrunk to for its size budget.
+const MIN_BUDGET_SIDE: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// Presentation times, in nanoseconds, of the first and last frames to
+    /// include. Frames outside are skipped.
+    pub start_nanos: Option<u64>,
+    pub end_nanos: Option<u64>,
+    /// Highest frame rate of the animation. A frame arriving sooner after
+    /// the last kept one replaces its contents, so the last state before the
+    /// screen goes idle isn't lost.
+    pub frame_rate: f64,
+    /// Frames are downscaled to fit, keeping their aspect ratio.
+    pub max_width: Option<usize>,
+    pub max_height: Option<usize>,
+    /// Largest encoded size. Animations over it are encoded again at a
+    /// smaller size until they fit.
+    pub max_bytes: Option<usize>,
+    /// Largest total size of the kept frames, which are held uncompressed
+    /// until the animation is encoded. Once reached, later frames are
+    /// skipped and the animation ends early.
+    pub max_memory: Option<usize>,
+    /// Dithering of GIF frames; APNG keeps full color.
+    pub dither: Dither,
+    /// Number of repetitions, zero for forever.
+    pub loop_count: u16,
+    /// Matrix used to convert YUV frames to RGB.
+    pub color_matrix: ColorMatrix,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            format: AnimationFormat::default(),
+            start_nanos: None,
+            end_nanos: None,
+            frame_rate: 10.0,
+            max_width: None,
+            max_height: None,
+            max_bytes: None,
+            max_memory: Some(1 << 30),
+            dither: Dither::default(),
+            loop_count: 0,
+            color_matrix: ColorMatrix::default(),
+        }
+    }
+}
+
+/// Collects captured frames into a short GIF or APNG clip, e.g. for bug
+/// reports. Frames are reduced to the frame rate and size limits as they
+/// arrive and kept in memory, up to [`AnimationOptions::max_memory`]; the
+/// animation is encoded and written when the sink is finished.
+#[derive(Debug)]
+pub struct AnimationWriter<W: Write> {
+    writer: W,
+    options: AnimationOptions,
+    /// Kept frames and their presentation times.
+    frames: Vec<(VideoFrame, u64)>,
+    /// Bytes of pixel data in `frames`.
+    memory: usize,
+    /// Whether a frame was skipped for the memory limit.
+    full: bool,
+    bytes_written: usize,
+    finished: bool,
+}
+
+impl<W: Write> AnimationWriter<W> {
+    pub fn new(writer: W, options: AnimationOptions) -> Self {
+        Self {
+            writer,
+            options,
+            frames: Vec::new(),
+            memory: 0,
+            full: false,
+            bytes_written: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &AnimationOptions {
+        &self.options
+    }
+
+    /// Changes the size budget, e.g. to finish again after
+    /// [`ImageError::OverBudget`].
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.options.max_bytes = max_bytes;
+    }
+
+    /// Frames kept so far.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the kept frames reached the memory limit, so later frames are
+    /// skipped.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Size of the encoded animation, once finished.
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn interval(&self) -> u64 {
+        (1e9 / self.options.frame_rate.max(0.001)) as u64
+    }
+
+    /// The kept frames paired with how long each is shown, resized to
+    /// `size` when given.
+    fn timed_frames(&self, size: Option<(usize, usize)>) -> Result<Vec<(VideoFrame, Duration)>, SinkError> {
+        let last_delay = self
+            .options
+            .end_nanos
+            .zip(self.frames.last())
+            .map_or(self.interval(), |(end, (_, last))| end.saturating_sub(*last).clamp(1, self.interval()));
+        let mut timed = Vec::with_capacity(self.frames.len());
+        for (index, (frame, time)) in self.frames.iter().enumerate() {
+            let delay = self.frames.get(index + 1).map_or(last_delay, |(_, next)| next.saturating_sub(*time));
+            let frame = match size {
+                Some((width, height)) => frame.resize(width, height, ScaleFilter::Area)?,
+                None => frame.clone(),
+            };
+            timed.push((frame, Duration::from_nanos(delay)));
+        }
+        Ok(timed)
+    }
+
+    fn encode(&self, frames: &[(VideoFrame, Duration)]) -> Result<Vec<u8>, ImageError> {
+        let mut data = Vec::new();
+        match self.options.format {
+            AnimationFormat::Gif => write_gif(&mut data, frames, self.options.loop_count, self.options.dither, self.options.color_matrix)?,
+            AnimationFormat::Apng => write_apng(&mut data, frames, self.options.loop_count, self.options.color_matrix)?,
+        }
+        Ok(data)
+    }
+
+    /// Encodes the kept frames, shrinking them until the animation fits the
+    /// size budget.
+    fn encode_within_budget(&self) -> Result<Vec<u8>, SinkError> {
+        let (width, height) = match self.frames.first() {
+            Some((frame, _)) => (frame.width(), frame.height()),
+            None => return Err(ImageError::NoFrames.into()),
+        };
+        let mut size = None;
+        for _ in 0..MAX_BUDGET_ATTEMPTS {
+            let data = self.encode(&self.timed_frames(size)?)?;
+            let max_bytes = match self.options.max_bytes {
+                Some(max_bytes) if data.len() > max_bytes => max_bytes,
+                _ => return Ok(data),
+            };
+            // Encoded size roughly follows the pixel count.
+            let (current_width, current_height) = size.unwrap_or((width, height));
+            let scale = (max_bytes as f64 / data.len() as f64).sqrt() * 0.9;
+            let resize = |length: usize| (length as f64 * scale) as usize;
+            if resize(current_width).min(resize(current_height)) < MIN_BUDGET_SIDE {
+                break;
+            }
+            size = Some((resize(current_width), resize(current_height)));
+        }
+        Err(ImageError::OverBudget.into())
+    }
+}
+
+fn frame_size(frame: &VideoFrame) -> usize {
+    frame.planes().iter().map(|plane| plane.data().len()).sum()
+}
+
+impl<W: Write> VideoSink for AnimationWriter<W> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let interval = self.interval();
+        let last = self.frames.last().map(|(_, time)| *time);
+        let time = cmtime_to_nanos(frame.presentation_time).unwrap_or_else(|| last.map_or(0, |last| last + interval));
+        if self.full || self.options.start_nanos.is_some_and(|start| time < start) || self.options.end_nanos.is_some_and(|end| time > end) {
+            return Ok(());
+        }
+        let frame = match (self.options.max_width, self.options.max_height) {
+            (None, None) => frame.clone(),
+            (max_width, max_height) => frame.thumbnail(max_width.unwrap_or(usize::MAX), max_height.unwrap_or(usize::MAX))?,
+        };
+        if let Some((first, _)) = self.frames.first() {
+            if (first.width(), first.height()) != (frame.width(), frame.height()) {
+                return Err(SinkError::FormatChanged);
+            }
+        }
+        let size = frame_size(&frame);
+        if let Some(kept) = self.frames.last_mut().filter(|_| last.is_some_and(|last| time < last + interval)) {
+            self.memory = self.memory - frame_size(&kept.0) + size;
+            kept.0 = frame;
+        } else if last.is_some() && self.options.max_memory.is_some_and(|max| self.memory + size > max) {
+            self.full = true;
+        } else {
+            self.memory += size;
+            self.frames.push((frame, time));
+        }
+        Ok(())
+    }
+
+    /// Encodes and writes the animation. If it doesn't fit the size budget
+    /// the frames are kept, so finishing can be retried with a larger one.
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if self.finished {
+            return Ok(());
+        }
+        let data = self.encode_within_budget()?;
+        self.finished = true;
+        self.frames.clear();
+        self.memory = 0;
+        self.writer.write_all(&data)?;
+        self.writer.flush()?;
+        self.bytes_written = data.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, frame::PixelFormat};
+
+    fn frame(index: u64) -> VideoFrame {
+        let data: Vec<u8> = (0..64 * 64)
+            .flat_map(|pixel| [(pixel as u64 * 7 + index * 13) as u8, (pixel / 64) as u8, index as u8, 255])
+            .collect();
+        let mut frame = VideoFrame::from_bytes(64, 64, PixelFormat::BGRA, &data).unwrap();
+        frame.presentation_time = nanos_to_cmtime(index * 100_000_000, 1000);
+        frame
+    }
+
+    #[test]
+    fn over_budget_finish_can_be_retried() {
+        let options = AnimationOptions {
+            max_bytes: Some(10),
+            ..Default::default()
+        };
+        let mut writer = AnimationWriter::new(Vec::new(), options);
+        for index in 0..3 {
+            writer.write_frame(&frame(index)).unwrap();
+        }
+        assert!(matches!(writer.finish(), Err(SinkError::Image(ImageError::OverBudget))));
+        assert_eq!(writer.frame_count(), 3);
+        assert!(writer.get_ref().is_empty());
+
+        writer.set_max_bytes(None);
+        writer.finish().unwrap();
+        assert!(writer.get_ref().starts_with(b"GIF89a"));
+        assert_eq!(writer.bytes_written(), writer.get_ref().len());
+        assert!(matches!(writer.write_frame(&frame(3)), Err(SinkError::Finished)));
+    }
+
+    #[test]
+    fn memory_limit_ends_the_animation() {
+        let frame_size = 64 * 64 * 4;
+        let options = AnimationOptions {
+            max_memory: Some(2 * frame_size + 1),
+            ..Default::default()
+        };
+        let mut writer = AnimationWriter::new(Vec::new(), options);
+        for index in 0..2 {
+            writer.write_frame(&frame(index)).unwrap();
+        }
+        assert!(!writer.is_full());
+        // Frames replacing the last kept one still fit.
+        let mut replacement = frame(1);
+        replacement.presentation_time = nanos_to_cmtime(150_000_000, 1000);
+        writer.write_frame(&replacement).unwrap();
+        assert!(!writer.is_full());
+
+        for index in 2..5 {
+            writer.write_frame(&frame(index)).unwrap();
+        }
+        assert!(writer.is_full());
+        assert_eq!(writer.frame_count(), 2);
+        writer.finish().unwrap();
+    }
+}
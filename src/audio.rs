@@ -0,0 +1,635 @@
+use std::{error::Error, fmt, ops::Range};
+
+#[cfg(all(target_os = "macos", feature = "audio"))]
+use core_audio_types::base_types::{
+    kAudioFormatFlagIsBigEndian, kAudioFormatFlagIsFloat, kAudioFormatFlagIsNonInterleaved, kAudioFormatFlagIsSignedInteger,
+    kAudioFormatFlagsNativeEndian, kAudioFormatLinearPCM, AudioBufferList, AudioStreamBasicDescription,
+};
+#[cfg(all(target_os = "macos", feature = "audio"))]
+use core_foundation_0_10::base::TCFType;
+#[cfg(all(target_os = "macos", feature = "audio"))]
+use core_media::{
+    block_buffer::{CMBlockBuffer, CMBlockBufferRef},
+    format_description::{kCMMediaType_Audio, CMAudioFormatDescription},
+    sample_buffer::{
+        kCMSampleBufferFlag_AudioBufferList_Assure16ByteAlignment, CMSampleBuffer, CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer,
+    },
+};
+
+use crate::{
+    clock::{cmtime_to_nanos, nanos_to_cmtime},
+    platform::{kCMTimeFlags_Valid, CMTime},
+};
+
+/// The type of one audio sample, stored in native byte order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SampleFormat {
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::I16 => 2,
+            Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    /// Reads a sample scaled to `-1.0..=1.0`.
+    fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            Self::I16 => i16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            Self::I32 => (i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / 2147483648.0) as f32,
+            Self::F32 => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            Self::F64 => {
+                let mut sample = [0; 8];
+                sample.copy_from_slice(&bytes[..8]);
+                f64::from_ne_bytes(sample) as f32
+            }
+        }
+    }
+
+    /// Writes a sample from `-1.0..=1.0`, clamping integers to their range.
+    fn write(self, sample: f32, bytes: &mut [u8]) {
+        match self {
+            Self::I16 => {
+                let sample = (sample * 32768.0).round().clamp(-32768.0, 32767.0) as i16;
+                bytes[..2].copy_from_slice(&sample.to_ne_bytes());
+            }
+            Self::I32 => {
+                let sample = (sample as f64 * 2147483648.0).round().clamp(-2147483648.0, 2147483647.0) as i32;
+                bytes[..4].copy_from_slice(&sample.to_ne_bytes());
+            }
+            Self::F32 => bytes[..4].copy_from_slice(&sample.to_ne_bytes()),
+            Self::F64 => bytes[..8].copy_from_slice(&(sample as f64).to_ne_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I16 => f.write_str("s16"),
+            Self::I32 => f.write_str("s32"),
+            Self::F32 => f.write_str("f32"),
+            Self::F64 => f.write_str("f64"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: f64,
+    pub channels: usize,
+    pub sample_format: SampleFormat,
+    /// Whether channels share one buffer, frame by frame, rather than each
+    /// having a buffer of its own.
+    pub interleaved: bool,
+}
+
+impl AudioFormat {
+    pub fn new(sample_rate: f64, channels: usize, sample_format: SampleFormat, interleaved: bool) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            sample_format,
+            interleaved,
+        }
+    }
+
+    /// The format of linear PCM described by `description`. Big endian,
+    /// packed 24 bit and compressed formats aren't supported.
+    #[cfg(all(target_os = "macos", feature = "audio"))]
+    pub fn from_stream_description(description: &AudioStreamBasicDescription) -> Result<Self, AudioError> {
+        let flags = description.mFormatFlags;
+        if description.mFormatID != kAudioFormatLinearPCM || flags & kAudioFormatFlagIsBigEndian != kAudioFormatFlagsNativeEndian {
+            return Err(AudioError::UnsupportedFormat);
+        }
+        let sample_format = match (
+            flags & kAudioFormatFlagIsFloat != 0,
+            flags & kAudioFormatFlagIsSignedInteger != 0,
+            description.mBitsPerChannel,
+        ) {
+            (true, _, 32) => SampleFormat::F32,
+            (true, _, 64) => SampleFormat::F64,
+            (false, true, 16) => SampleFormat::I16,
+            (false, true, 32) => SampleFormat::I32,
+            _ => return Err(AudioError::UnsupportedFormat),
+        };
+        let format = Self::new(
+            description.mSampleRate,
+            description.mChannelsPerFrame as usize,
+            sample_format,
+            flags & kAudioFormatFlagIsNonInterleaved == 0,
+        );
+        format.validate()?;
+        Ok(format)
+    }
+
+    /// Bytes of one sample for every channel.
+    pub fn bytes_per_frame(&self) -> usize {
+        self.channels * self.sample_format.bytes_per_sample()
+    }
+
+    /// Number of buffers holding the samples: one if interleaved, otherwise
+    /// one per channel.
+    pub fn buffer_count(&self) -> usize {
+        if self.interleaved {
+            1
+        } else {
+            self.channels
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), AudioError> {
+        if self.channels == 0 || !self.sample_rate.is_finite() || self.sample_rate <= 0.0 {
+            return Err(AudioError::InvalidFormat);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} channels, {} {}",
+            self.sample_rate,
+            self.channels,
+            self.sample_format,
+            if self.interleaved {
+                "interleaved"
+            } else {
+                "planar"
+            }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AudioError {
+    InvalidFormat,
+    UnsupportedFormat,
+    BufferCount {
+        expected: usize,
+        actual: usize,
+    },
+    ChannelCount {
+        expected: usize,
+        actual: usize,
+    },
+    BufferSize {
+        buffer: usize,
+        expected: usize,
+        actual: usize,
+    },
+    NoAudioData,
+    /// Core Media failed to provide the samples, with its status code.
+    Status(i32),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => f.write_str("invalid audio format"),
+            Self::UnsupportedFormat => f.write_str("unsupported audio format"),
+            Self::BufferCount { expected, actual } => write!(f, "expected {} audio buffers, got {}", expected, actual),
+            Self::ChannelCount { expected, actual } => write!(f, "expected {} audio channels, got {}", expected, actual),
+            Self::BufferSize { buffer, expected, actual } => write!(f, "audio buffer {} needs {} bytes, got {}", buffer, expected, actual),
+            Self::NoAudioData => f.write_str("sample buffer has no audio data"),
+            Self::Status(status) => write!(f, "failed to get audio buffer list: {}", status),
+        }
+    }
+}
+
+impl Error for AudioError {}
+
+/// Owned audio samples that can outlive the sample callback and move
+/// between threads.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioBuffer {
+    format: AudioFormat,
+    frames: usize,
+    buffers: Vec<Vec<u8>>,
+    pub presentation_time: CMTime,
+    pub duration: CMTime,
+}
+
+impl AudioBuffer {
+    /// Wraps `frames` samples per channel, stored in `buffers` as laid out by
+    /// `format`.
+    pub fn new(format: AudioFormat, frames: usize, buffers: Vec<Vec<u8>>) -> Result<Self, AudioError> {
+        format.validate()?;
+        if buffers.len() != format.buffer_count() {
+            return Err(AudioError::BufferCount {
+                expected: format.buffer_count(),
+                actual: buffers.len(),
+            });
+        }
+        let expected = frames * format.bytes_per_frame() / format.buffer_count();
+        if let Some((buffer, data)) = buffers.iter().enumerate().find(|(_, data)| data.len() != expected) {
+            return Err(AudioError::BufferSize {
+                buffer,
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self::from_parts(format, frames, buffers))
+    }
+
+    fn from_parts(format: AudioFormat, frames: usize, buffers: Vec<Vec<u8>>) -> Self {
+        Self {
+            format,
+            frames,
+            buffers,
+            presentation_time: CMTime::default(),
+            duration: CMTime::default(),
+        }
+    }
+
+    /// `frames` samples of silence per channel.
+    pub fn silence(format: AudioFormat, frames: usize) -> Result<Self, AudioError> {
+        format.validate()?;
+        let size = frames * format.bytes_per_frame() / format.buffer_count();
+        Ok(Self::from_parts(format, frames, vec![vec![0; size]; format.buffer_count()]))
+    }
+
+    /// Interleaved 32 bit float samples; a trailing partial frame is
+    /// dropped.
+    pub fn from_interleaved_f32(sample_rate: f64, channels: usize, samples: &[f32]) -> Result<Self, AudioError> {
+        let format = AudioFormat::new(sample_rate, channels, SampleFormat::F32, true);
+        format.validate()?;
+        let frames = samples.len() / channels;
+        let data = samples[..frames * channels].iter().flat_map(|sample| sample.to_ne_bytes()).collect();
+        Ok(Self::from_parts(format, frames, vec![data]))
+    }
+
+    /// Planar 32 bit float samples, one slice per channel, all the same
+    /// length.
+    pub fn from_planar_f32(sample_rate: f64, channels: &[&[f32]]) -> Result<Self, AudioError> {
+        let format = AudioFormat::new(sample_rate, channels.len(), SampleFormat::F32, false);
+        format.validate()?;
+        let frames = channels[0].len();
+        if let Some((buffer, channel)) = channels.iter().enumerate().find(|(_, channel)| channel.len() != frames) {
+            return Err(AudioError::BufferSize {
+                buffer,
+                expected: frames * 4,
+                actual: channel.len() * 4,
+            });
+        }
+        let buffers = channels
+            .iter()
+            .map(|channel| channel.iter().flat_map(|sample| sample.to_ne_bytes()).collect())
+            .collect();
+        Ok(Self::from_parts(format, frames, buffers))
+    }
+
+    /// Copies the linear PCM samples of an audio sample buffer together with
+    /// its timing.
+    #[cfg(all(target_os = "macos", feature = "audio"))]
+    pub fn from_sample_buffer(sample_buffer: &CMSampleBuffer) -> Result<Self, AudioError> {
+        let description = sample_buffer
+            .get_format_description()
+            .filter(|description| description.get_media_type() == kCMMediaType_Audio)
+            .ok_or(AudioError::NoAudioData)?;
+        let description = unsafe { CMAudioFormatDescription::wrap_under_get_rule(description.as_concrete_TypeRef()) };
+        let format = AudioFormat::from_stream_description(description.get_stream_basic_description().ok_or(AudioError::UnsupportedFormat)?)?;
+        let flags = kCMSampleBufferFlag_AudioBufferList_Assure16ByteAlignment;
+        let mut size = 0;
+        let status = unsafe {
+            CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+                sample_buffer.as_concrete_TypeRef(),
+                &mut size,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+                std::ptr::null(),
+                flags,
+                std::ptr::null_mut(),
+            )
+        };
+        if status != 0 {
+            return Err(AudioError::Status(status));
+        }
+        // u64 storage keeps the list aligned for its pointer fields.
+        let mut storage = vec![0u64; ((size + 7) / 8).max(1)];
+        let list = storage.as_mut_ptr() as *mut AudioBufferList;
+        let mut block_buffer: CMBlockBufferRef = std::ptr::null_mut();
+        let status = unsafe {
+            CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+                sample_buffer.as_concrete_TypeRef(),
+                std::ptr::null_mut(),
+                list,
+                size,
+                std::ptr::null(),
+                std::ptr::null(),
+                flags,
+                &mut block_buffer,
+            )
+        };
+        if status != 0 {
+            return Err(AudioError::Status(status));
+        }
+        // Releases the block buffer once the samples are copied.
+        let _block_buffer = if block_buffer.is_null() {
+            None
+        } else {
+            Some(unsafe { CMBlockBuffer::wrap_under_create_rule(block_buffer) })
+        };
+        let buffers = unsafe { std::slice::from_raw_parts((*list).mBuffers.as_ptr(), (*list).mNumberBuffers as usize) };
+        if buffers.len() != format.buffer_count() {
+            return Err(AudioError::BufferCount {
+                expected: format.buffer_count(),
+                actual: buffers.len(),
+            });
+        }
+        let channels_per_buffer = format.channels / format.buffer_count();
+        let frames = buffers[0].mDataByteSize as usize / (channels_per_buffer * format.sample_format.bytes_per_sample());
+        let expected = frames * channels_per_buffer * format.sample_format.bytes_per_sample();
+        let mut data = Vec::with_capacity(buffers.len());
+        for (index, buffer) in buffers.iter().enumerate() {
+            if buffer.mData.is_null() {
+                return Err(AudioError::NoAudioData);
+            }
+            if (buffer.mDataByteSize as usize) < expected {
+                return Err(AudioError::BufferSize {
+                    buffer: index,
+                    expected,
+                    actual: buffer.mDataByteSize as usize,
+                });
+            }
+            data.push(unsafe { std::slice::from_raw_parts(buffer.mData as *const u8, expected) }.to_vec());
+        }
+        let mut audio = Self::from_parts(format, frames, data);
+        audio.presentation_time = sample_buffer.get_presentation_time_stamp();
+        audio.duration = sample_buffer.get_duration();
+        Ok(audio)
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.format.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.format.channels
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.format.sample_format
+    }
+
+    pub fn is_interleaved(&self) -> bool {
+        self.format.interleaved
+    }
+
+    /// Number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    /// Length in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.frames as f64 / self.format.sample_rate
+    }
+
+    /// The raw sample data: one buffer if interleaved, otherwise one per
+    /// channel.
+    pub fn buffers(&self) -> &[Vec<u8>] {
+        &self.buffers
+    }
+
+    pub fn buffers_mut(&mut self) -> &mut [Vec<u8>] {
+        &mut self.buffers
+    }
+
+    pub fn into_buffers(self) -> Vec<Vec<u8>> {
+        self.buffers
+    }
+
+    /// The frames in `range`, with the presentation time moved to the first
+    /// of them.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.frames, "frame range out of bounds");
+        let unit = self.format.bytes_per_frame() / self.format.buffer_count();
+        let buffers = self
+            .buffers
+            .iter()
+            .map(|buffer| buffer[range.start * unit..range.end * unit].to_vec())
+            .collect();
+        let mut audio = Self::from_parts(self.format, range.len(), buffers);
+        audio.presentation_time = match cmtime_to_nanos(self.presentation_time) {
+            Some(nanos) => nanos_to_cmtime(
+                nanos + (range.start as f64 * 1e9 / self.format.sample_rate) as u64,
+                self.presentation_time.timescale,
+            ),
+            None => self.presentation_time,
+        };
+        audio.duration = CMTime {
+            value: range.len() as i64,
+            timescale: self.format.sample_rate.round() as i32,
+            flags: kCMTimeFlags_Valid,
+            epoch: 0,
+        };
+        audio
+    }
+
+    fn offset(&self, frame: usize, channel: usize) -> (usize, usize) {
+        let bytes = self.format.sample_format.bytes_per_sample();
+        if self.format.interleaved {
+            (0, (frame * self.format.channels + channel) * bytes)
+        } else {
+            (channel, frame * bytes)
+        }
+    }
+
+    /// One sample scaled to `-1.0..=1.0`.
+    pub fn sample(&self, frame: usize, channel: usize) -> f32 {
+        assert!(frame < self.frames && channel < self.format.channels, "sample out of range");
+        let (buffer, offset) = self.offset(frame, channel);
+        self.format.sample_format.read(&self.buffers[buffer][offset..])
+    }
+
+    /// Sets one sample from `-1.0..=1.0`.
+    pub fn set_sample(&mut self, frame: usize, channel: usize, sample: f32) {
+        assert!(frame < self.frames && channel < self.format.channels, "sample out of range");
+        let (buffer, offset) = self.offset(frame, channel);
+        self.format.sample_format.write(sample, &mut self.buffers[buffer][offset..]);
+    }
+
+    /// The samples of one channel scaled to `-1.0..=1.0`.
+    pub fn channel_f32(&self, channel: usize) -> Vec<f32> {
+        (0..self.frames).map(|frame| self.sample(frame, channel)).collect()
+    }
+
+    /// All samples scaled to `-1.0..=1.0`, interleaved.
+    pub fn to_interleaved_f32(&self) -> Vec<f32> {
+        let channels = self.format.channels;
+        (0..self.frames * channels)
+            .map(|index| self.sample(index / channels, index % channels))
+            .collect()
+    }
+
+    /// A copy with another sample type and layout, at the same rate.
+    pub fn convert(&self, sample_format: SampleFormat, interleaved: bool) -> Self {
+        let format = AudioFormat::new(self.format.sample_rate, self.format.channels, sample_format, interleaved);
+        if format == self.format {
+            return self.clone();
+        }
+        let size = self.frames * format.bytes_per_frame() / format.buffer_count();
+        let mut audio = Self::from_parts(format, self.frames, vec![vec![0; size]; format.buffer_count()]);
+        for frame in 0..self.frames {
+            for channel in 0..self.format.channels {
+                audio.set_sample(frame, channel, self.sample(frame, channel));
+            }
+        }
+        audio.presentation_time = self.presentation_time;
+        audio.duration = self.duration;
+        audio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [SampleFormat; 4] = [SampleFormat::I16, SampleFormat::I32, SampleFormat::F32, SampleFormat::F64];
+
+    // Multiples of 1/32768 survive every sample format unchanged.
+    fn ramp(frames: usize, channels: usize) -> Vec<f32> {
+        (0..frames * channels)
+            .map(|index| (index as f32 * 997.0 % 65536.0 - 32768.0) / 32768.0)
+            .collect()
+    }
+
+    #[test]
+    fn new_validates_buffers() {
+        let stereo = AudioFormat::new(48000.0, 2, SampleFormat::I16, false);
+        assert!(AudioBuffer::new(stereo, 3, vec![vec![0; 6], vec![0; 6]]).is_ok());
+        assert_eq!(
+            AudioBuffer::new(stereo, 3, vec![vec![0; 6]]),
+            Err(AudioError::BufferCount { expected: 2, actual: 1 })
+        );
+        assert_eq!(
+            AudioBuffer::new(stereo, 3, vec![vec![0; 6], vec![0; 4]]),
+            Err(AudioError::BufferSize {
+                buffer: 1,
+                expected: 6,
+                actual: 4
+            })
+        );
+
+        let interleaved = AudioFormat::new(48000.0, 2, SampleFormat::F64, true);
+        assert!(AudioBuffer::new(interleaved, 3, vec![vec![0; 48]]).is_ok());
+        assert_eq!(
+            AudioBuffer::new(interleaved, 3, vec![vec![0; 47]]),
+            Err(AudioError::BufferSize {
+                buffer: 0,
+                expected: 48,
+                actual: 47
+            })
+        );
+
+        for format in [
+            AudioFormat::new(48000.0, 0, SampleFormat::F32, true),
+            AudioFormat::new(0.0, 2, SampleFormat::F32, true),
+            AudioFormat::new(f64::NAN, 2, SampleFormat::F32, true),
+        ] {
+            assert_eq!(AudioBuffer::new(format, 0, vec![vec![]]), Err(AudioError::InvalidFormat));
+        }
+        assert!(AudioBuffer::from_planar_f32(48000.0, &[&[0.0; 4], &[0.0; 3]]).is_err());
+    }
+
+    #[test]
+    fn convert_round_trips() {
+        let samples = ramp(37, 3);
+        let source = AudioBuffer::from_interleaved_f32(44100.0, 3, &samples).unwrap();
+        for &from in &FORMATS {
+            for &to in &FORMATS {
+                for &interleaved in &[true, false] {
+                    let converted = source.convert(from, !interleaved).convert(to, interleaved);
+                    assert_eq!((converted.sample_format(), converted.is_interleaved()), (to, interleaved));
+                    assert_eq!(
+                        converted.buffers().len(),
+                        if interleaved {
+                            1
+                        } else {
+                            3
+                        }
+                    );
+                    assert_eq!(converted.to_interleaved_f32(), samples, "{} to {}", from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn convert_clamps_integers() {
+        let source = AudioBuffer::from_interleaved_f32(48000.0, 1, &[1.0, -1.0, 2.0, -2.0]).unwrap();
+        assert_eq!(
+            source.convert(SampleFormat::I16, true).channel_f32(0),
+            [32767.0 / 32768.0, -1.0, 32767.0 / 32768.0, -1.0]
+        );
+        assert_eq!(source.convert(SampleFormat::I32, true).channel_f32(0), [1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn interleaved_and_planar_layouts() {
+        let left = [0.25, 0.5, 0.75];
+        let right = [-0.25, -0.5, -0.75];
+        let planar = AudioBuffer::from_planar_f32(48000.0, &[&left, &right]).unwrap();
+        assert_eq!(planar.to_interleaved_f32(), [0.25, -0.25, 0.5, -0.5, 0.75, -0.75]);
+
+        let interleaved = planar.convert(SampleFormat::I16, true);
+        let bytes: Vec<i16> = interleaved.buffers()[0]
+            .chunks_exact(2)
+            .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(bytes, [8192, -8192, 16384, -16384, 24576, -24576]);
+
+        let back = interleaved.convert(SampleFormat::F32, false);
+        assert_eq!(back, planar);
+        assert_eq!((back.channel_f32(0), back.channel_f32(1)), (left.to_vec(), right.to_vec()));
+    }
+
+    #[test]
+    fn slice_timing() {
+        let mut audio = AudioBuffer::from_planar_f32(48000.0, &[&ramp(4800, 1), &ramp(4800, 1)]).unwrap();
+        audio.presentation_time = nanos_to_cmtime(2_000_000_000, 1_000_000_000);
+
+        let slice = audio.slice(480..1440);
+        assert_eq!(slice.frames(), 960);
+        assert_eq!(slice.channel_f32(1), audio.channel_f32(1)[480..1440]);
+        assert_eq!(cmtime_to_nanos(slice.presentation_time), Some(2_010_000_000));
+        assert_eq!((slice.duration.value, slice.duration.timescale), (960, 48000));
+        assert!((slice.seconds() - 0.02).abs() < 1e-12);
+
+        // Without a valid start time there's nothing to offset.
+        audio.presentation_time = CMTime::default();
+        assert_eq!(audio.slice(480..1440).presentation_time, CMTime::default());
+        assert!(audio.slice(4800..4800).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "frame range out of bounds")]
+    fn slice_out_of_bounds() {
+        AudioBuffer::silence(AudioFormat::new(48000.0, 1, SampleFormat::F32, true), 10)
+            .unwrap()
+            .slice(5..11);
+    }
+}
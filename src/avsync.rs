@@ -0,0 +1,511 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    audio::{AudioBuffer, AudioError},
+    clock::{cmtime_to_nanos, nanos_to_cmtime},
+    frame::VideoFrame,
+    platform::CMTime,
+    resample::{Resampler, ResamplerQuality},
+};
+
+/// Audio needed before the drift estimate is used for correction.
+const DRIFT_SETTLE: Duration = Duration::from_secs(5);
+
+/// How the audio sample clock is reconciled with presentation times when
+/// the two drift apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DriftCorrection {
+    /// Samples and frames keep their times; drift is only reported.
+    None,
+    /// Audio is resampled so its sample count follows presentation time.
+    #[default]
+    ResampleAudio,
+    /// Audio is timed by its sample count and video frames are moved onto
+    /// that clock, so a constant rate writer such as
+    /// [`Y4mWriter`](crate::rawvideo::Y4mWriter) duplicates or drops frames
+    /// to match the audio.
+    RetimeVideo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SyncOptions {
+    pub correction: DriftCorrection,
+    /// Audio gaps longer than this are filled with silence, and overlaps
+    /// longer than this are trimmed.
+    pub gap_threshold: Duration,
+    /// How long a sample waits for the other stream before it's released
+    /// anyway, e.g. while the screen is idle and no frames arrive.
+    pub max_delay: Duration,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            correction: DriftCorrection::default(),
+            gap_threshold: Duration::from_millis(5),
+            max_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// Presentation time of the first audio sample minus that of the first
+    /// video frame.
+    pub start_offset_nanos: Option<i64>,
+    /// Audio time counted in samples minus elapsed presentation time,
+    /// positive when the audio clock runs fast.
+    pub audio_clock_offset_nanos: i64,
+    /// Audio clock drift in parts per million of presentation time.
+    pub drift_ppm: f64,
+    /// Frames of silence inserted into audio gaps.
+    pub silence_inserted: u64,
+    /// Audio frames trimmed from overlapping buffers.
+    pub audio_trimmed: u64,
+    pub video_frames: u64,
+    pub audio_frames: u64,
+}
+
+/// A sample released by [`AvSync`], in presentation order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyncedSample {
+    Video(VideoFrame),
+    Audio(AudioBuffer),
+}
+
+impl SyncedSample {
+    pub fn presentation_time(&self) -> CMTime {
+        match self {
+            Self::Video(frame) => frame.presentation_time,
+            Self::Audio(audio) => audio.presentation_time,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AudioClock {
+    sample_rate: f64,
+    channels: usize,
+    /// Presentation time of the first sample.
+    start: u64,
+    /// Presentation time the next buffer should start at.
+    expected: u64,
+    /// Frames taken in, counting inserted silence.
+    frames: u64,
+    resampler: Option<Resampler>,
+}
+
+impl AudioClock {
+    fn elapsed(&self) -> u64 {
+        self.expected - self.start
+    }
+
+    /// Nanoseconds the sample count runs ahead of presentation time.
+    fn offset(&self) -> i64 {
+        (self.frames as f64 * 1e9 / self.sample_rate) as i64 - self.elapsed() as i64
+    }
+
+    fn drift_ppm(&self) -> f64 {
+        match self.elapsed() {
+            0 => 0.0,
+            elapsed => self.offset() as f64 * 1e6 / elapsed as f64,
+        }
+    }
+}
+
+/// Lines up the screen and audio samples of a stream by presentation time,
+/// filling audio gaps with silence and correcting audio clock drift.
+/// Samples are pushed as they arrive and popped in presentation order once
+/// the other stream has caught up or [`SyncOptions::max_delay`] passed.
+#[derive(Clone, Debug)]
+pub struct AvSync {
+    options: SyncOptions,
+    video: VecDeque<(u64, VideoFrame)>,
+    audio: VecDeque<(u64, AudioBuffer)>,
+    /// Latest time each stream reached.
+    video_time: Option<u64>,
+    audio_time: Option<u64>,
+    first_video: Option<u64>,
+    clock: Option<AudioClock>,
+    report: SyncReport,
+}
+
+impl AvSync {
+    pub fn new(options: SyncOptions) -> Self {
+        Self {
+            options,
+            video: VecDeque::new(),
+            audio: VecDeque::new(),
+            video_time: None,
+            audio_time: None,
+            first_video: None,
+            clock: None,
+            report: SyncReport::default(),
+        }
+    }
+
+    pub fn options(&self) -> &SyncOptions {
+        &self.options
+    }
+
+    pub fn report(&self) -> SyncReport {
+        self.report
+    }
+
+    pub fn push_video(&mut self, mut frame: VideoFrame) {
+        let latest = self.video_time.max(self.audio_time).unwrap_or(0);
+        let time = cmtime_to_nanos(frame.presentation_time).unwrap_or(latest);
+        if self.first_video.is_none() {
+            self.first_video = Some(time);
+            self.update_start_offset();
+        }
+        self.video_time = Some(self.video_time.map_or(time, |video_time| video_time.max(time)));
+        let mut output_time = time;
+        if let (DriftCorrection::RetimeVideo, Some(clock)) = (self.options.correction, &self.clock) {
+            if clock.elapsed() >= DRIFT_SETTLE.as_nanos() as u64 {
+                let since_start = time as f64 - clock.start as f64;
+                output_time = (clock.start as f64 + since_start * (1.0 + clock.drift_ppm() * 1e-6)).max(0.0) as u64;
+                frame.presentation_time = nanos_to_cmtime(output_time, frame.presentation_time.timescale);
+            }
+        }
+        let index = self
+            .video
+            .iter()
+            .rposition(|(queued, _)| *queued <= output_time)
+            .map_or(0, |index| index + 1);
+        self.video.insert(index, (output_time, frame));
+        self.report.video_frames += 1;
+    }
+
+    pub fn push_audio(&mut self, audio: AudioBuffer) -> Result<(), AudioError> {
+        if audio.is_empty() {
+            return Ok(());
+        }
+        let changed = self.clock.as_ref().map_or(true, |clock| {
+            clock.sample_rate != audio.sample_rate() || clock.channels != audio.channels()
+        });
+        let fallback = self.clock.as_ref().map(|clock| clock.expected);
+        let time = cmtime_to_nanos(audio.presentation_time)
+            .or(fallback)
+            .unwrap_or_else(|| self.video_time.unwrap_or(0));
+        if changed {
+            self.flush_resampler()?;
+            let resampler = match self.options.correction {
+                DriftCorrection::ResampleAudio => Some(Resampler::new(
+                    audio.sample_rate(),
+                    audio.sample_rate(),
+                    audio.channels(),
+                    ResamplerQuality::High,
+                )?),
+                _ => None,
+            };
+            self.clock = Some(AudioClock {
+                sample_rate: audio.sample_rate(),
+                channels: audio.channels(),
+                start: time,
+                expected: time,
+                frames: 0,
+                resampler,
+            });
+            self.update_start_offset();
+        }
+        let (expected, sample_rate) = self.clock.as_ref().map(|clock| (clock.expected, clock.sample_rate)).unwrap();
+        let threshold = self.options.gap_threshold.as_nanos() as u64;
+        let to_frames = |nanos: u64| (nanos as f64 * sample_rate / 1e9).round() as usize;
+
+        let mut audio = audio;
+        let mut time = time;
+        if time > expected + threshold {
+            let frames = to_frames(time - expected);
+            let mut silence = AudioBuffer::silence(audio.format(), frames)?;
+            silence.presentation_time = nanos_to_cmtime(expected, audio.presentation_time.timescale.max(1));
+            self.report.silence_inserted += frames as u64;
+            self.append_audio(silence, expected)?;
+        } else if time + threshold < expected {
+            let frames = to_frames(expected - time).min(audio.frames());
+            self.report.audio_trimmed += frames as u64;
+            if frames == audio.frames() {
+                return Ok(());
+            }
+            audio = audio.slice(frames..audio.frames());
+            time = expected;
+        }
+        self.append_audio(audio, time)
+    }
+
+    /// Counts `audio` starting at `time` on the audio clock and queues it
+    /// with the configured correction.
+    fn append_audio(&mut self, audio: AudioBuffer, time: u64) -> Result<(), AudioError> {
+        let correction = self.options.correction;
+        let clock = self.clock.as_mut().expect("audio clock is set up before appending");
+        let sample_clock_time = clock.start + (clock.frames as f64 * 1e9 / clock.sample_rate) as u64;
+        clock.frames += audio.frames() as u64;
+        clock.expected = time + (audio.frames() as f64 * 1e9 / clock.sample_rate) as u64;
+        let settled = clock.elapsed() >= DRIFT_SETTLE.as_nanos() as u64;
+        let drift_ppm = clock.drift_ppm();
+        self.report.audio_clock_offset_nanos = clock.offset();
+        self.report.drift_ppm = drift_ppm;
+        self.audio_time = Some(clock.expected);
+
+        let output = match (correction, &mut clock.resampler) {
+            (DriftCorrection::ResampleAudio, Some(resampler)) => {
+                if settled {
+                    resampler.set_adjustment_ppm(drift_ppm);
+                }
+                let (sample_format, interleaved) = (audio.sample_format(), audio.is_interleaved());
+                resampler.process(&audio)?.convert(sample_format, interleaved)
+            }
+            (DriftCorrection::RetimeVideo, _) => {
+                let mut audio = audio;
+                audio.presentation_time = nanos_to_cmtime(sample_clock_time, audio.presentation_time.timescale.max(1));
+                audio
+            }
+            _ => audio,
+        };
+        self.queue_audio(output);
+        Ok(())
+    }
+
+    fn queue_audio(&mut self, audio: AudioBuffer) {
+        if audio.is_empty() {
+            return;
+        }
+        let time = cmtime_to_nanos(audio.presentation_time).unwrap_or_else(|| self.audio_time.unwrap_or(0));
+        self.report.audio_frames += audio.frames() as u64;
+        self.audio.push_back((time, audio));
+    }
+
+    fn flush_resampler(&mut self) -> Result<(), AudioError> {
+        let clock = match &mut self.clock {
+            Some(clock) => clock,
+            None => return Ok(()),
+        };
+        if let Some(resampler) = &mut clock.resampler {
+            let audio = resampler.flush()?;
+            self.queue_audio(audio);
+        }
+        Ok(())
+    }
+
+    fn update_start_offset(&mut self) {
+        if let (Some(video), Some(clock)) = (self.first_video, &self.clock) {
+            if self.report.start_offset_nanos.is_none() {
+                self.report.start_offset_nanos = Some(clock.start as i64 - video as i64);
+            }
+        }
+    }
+
+    /// The next sample in presentation order, if the other stream has
+    /// caught up with it or it waited longer than the maximum delay.
+    pub fn pop(&mut self) -> Option<SyncedSample> {
+        let video = self.video.front().map(|(time, _)| *time);
+        let audio = self.audio.front().map(|(time, _)| *time);
+        let (time, is_video, other_queued, other_time) = match (video, audio) {
+            (Some(video), Some(audio)) if video <= audio => (video, true, true, self.audio_time),
+            (Some(_), Some(audio)) => (audio, false, true, self.video_time),
+            (Some(video), None) => (video, true, false, self.audio_time),
+            (None, Some(audio)) => (audio, false, false, self.video_time),
+            (None, None) => return None,
+        };
+        let latest = self.video_time.max(self.audio_time).unwrap_or(0);
+        let ready = other_queued
+            || other_time.is_some_and(|other_time| other_time >= time)
+            || latest.saturating_sub(time) >= self.options.max_delay.as_nanos() as u64;
+        if !ready {
+            return None;
+        }
+        if is_video {
+            self.video.pop_front().map(|(_, frame)| SyncedSample::Video(frame))
+        } else {
+            self.audio.pop_front().map(|(_, audio)| SyncedSample::Audio(audio))
+        }
+    }
+
+    /// Ends the stream, returning every remaining sample in presentation
+    /// order, including audio held back by the resampler.
+    pub fn finish(&mut self) -> Result<Vec<SyncedSample>, AudioError> {
+        self.flush_resampler()?;
+        self.clock = None;
+        let mut samples = Vec::with_capacity(self.video.len() + self.audio.len());
+        loop {
+            let video = self.video.front().map(|(time, _)| *time);
+            let audio = self.audio.front().map(|(time, _)| *time);
+            let sample = match (video, audio) {
+                (Some(video), Some(audio)) if video <= audio => self.video.pop_front().map(|(_, frame)| SyncedSample::Video(frame)),
+                (_, Some(_)) => self.audio.pop_front().map(|(_, audio)| SyncedSample::Audio(audio)),
+                (Some(_), None) => self.video.pop_front().map(|(_, frame)| SyncedSample::Video(frame)),
+                (None, None) => break,
+            };
+            samples.extend(sample);
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+
+    const RATE: f64 = 48000.0;
+
+    fn time(nanos: u64) -> CMTime {
+        nanos_to_cmtime(nanos, 1_000_000_000)
+    }
+
+    fn audio(nanos: u64, frames: usize) -> AudioBuffer {
+        let mut audio = AudioBuffer::from_interleaved_f32(RATE, 1, &vec![0.5; frames]).unwrap();
+        audio.presentation_time = time(nanos);
+        audio
+    }
+
+    fn frame(nanos: u64) -> VideoFrame {
+        let mut frame = VideoFrame::zeroed(2, 2, PixelFormat::BGRA).unwrap();
+        frame.presentation_time = time(nanos);
+        frame
+    }
+
+    /// Times and sizes of the released audio, and whether it's silent.
+    fn audio_runs(samples: &[SyncedSample]) -> Vec<(u64, usize, bool)> {
+        samples
+            .iter()
+            .filter_map(|sample| match sample {
+                SyncedSample::Audio(audio) => Some((
+                    cmtime_to_nanos(audio.presentation_time).unwrap(),
+                    audio.frames(),
+                    audio.channel_f32(0).iter().all(|&sample| sample == 0.0),
+                )),
+                SyncedSample::Video(_) => None,
+            })
+            .collect()
+    }
+
+    fn options(correction: DriftCorrection) -> SyncOptions {
+        SyncOptions {
+            correction,
+            ..SyncOptions::default()
+        }
+    }
+
+    #[test]
+    fn gaps_are_filled_and_overlaps_trimmed() {
+        let mut sync = AvSync::new(options(DriftCorrection::None));
+        // 10 ms buffers: one on time, one 20 ms late, one 3 ms late (within
+        // the threshold), one 6 ms early and one entirely repeated.
+        for &start in &[0, 30_000_000, 43_000_000, 47_000_000, 47_000_000] {
+            sync.push_audio(audio(start, 480)).unwrap();
+        }
+        let samples = sync.finish().unwrap();
+        assert_eq!(
+            audio_runs(&samples),
+            [
+                (0, 480, false),
+                (10_000_000, 960, true),
+                (30_000_000, 480, false),
+                (43_000_000, 480, false),
+                (53_000_000, 192, false),
+            ]
+        );
+        let report = sync.report();
+        assert_eq!(report.silence_inserted, 960);
+        assert_eq!(report.audio_trimmed, 288 + 480);
+        // Inserted silence counts as released audio.
+        assert_eq!(report.audio_frames, 480 * 3 + 960 + 192);
+        assert_eq!(report.start_offset_nanos, None);
+    }
+
+    #[test]
+    fn samples_wait_for_the_other_stream() {
+        let mut sync = AvSync::new(options(DriftCorrection::None));
+        sync.push_video(frame(5_000_000));
+        assert_eq!(sync.pop(), None);
+        sync.push_audio(audio(0, 480)).unwrap();
+        assert_eq!(sync.report().start_offset_nanos, Some(-5_000_000));
+        // Audio from 0 comes first, then the frame once audio passed it.
+        assert!(matches!(sync.pop(), Some(SyncedSample::Audio(_))));
+        assert!(matches!(sync.pop(), Some(SyncedSample::Video(_))));
+        assert_eq!(sync.pop(), None);
+
+        // Audio ahead of the video is held until the video catches up or
+        // the maximum delay passes.
+        sync.push_audio(audio(10_000_000, 480)).unwrap();
+        assert_eq!(sync.pop(), None);
+        sync.push_video(frame(15_000_000));
+        assert!(matches!(sync.pop(), Some(SyncedSample::Audio(_))));
+        assert!(matches!(sync.pop(), Some(SyncedSample::Video(_))));
+        for start in 2..60 {
+            sync.push_audio(audio(start * 10_000_000, 480)).unwrap();
+        }
+        let released = std::iter::from_fn(|| sync.pop()).count();
+        // Everything starting at least 500 ms before the audio got to 600 ms.
+        assert_eq!(released, 9);
+        assert_eq!(sync.finish().unwrap().len(), 49);
+    }
+
+    /// Pushes `seconds` of 10 ms audio buffers from a clock `ppm` fast,
+    /// with a frame every 100 ms, returning everything released.
+    fn drifting_stream(sync: &mut AvSync, seconds: u64, ppm: f64) -> Vec<SyncedSample> {
+        let buffer_nanos = 10_000_000.0 / (1.0 + ppm * 1e-6);
+        for buffer in 0..seconds * 100 {
+            let nanos = (buffer as f64 * buffer_nanos) as u64;
+            if buffer % 10 == 0 {
+                sync.push_video(frame(nanos));
+            }
+            sync.push_audio(audio(nanos, 480)).unwrap();
+        }
+        sync.finish().unwrap()
+    }
+
+    #[test]
+    fn drift_is_measured() {
+        let mut sync = AvSync::new(options(DriftCorrection::None));
+        let samples = drifting_stream(&mut sync, 10, 1000.0);
+        let report = sync.report();
+        assert!((report.drift_ppm - 1000.0).abs() < 5.0, "{}", report.drift_ppm);
+        assert!(
+            (report.audio_clock_offset_nanos - 10_000_000).abs() < 50_000,
+            "{}",
+            report.audio_clock_offset_nanos
+        );
+        // Uncorrected, samples keep their times and counts.
+        assert_eq!(report.silence_inserted + report.audio_trimmed, 0);
+        assert_eq!(report.audio_frames, 480_000);
+        assert_eq!(samples.len(), 1100);
+    }
+
+    #[test]
+    fn resampling_follows_presentation_time() {
+        let mut sync = AvSync::new(options(DriftCorrection::ResampleAudio));
+        let samples = drifting_stream(&mut sync, 20, 1000.0);
+        let frames: usize = audio_runs(&samples).iter().map(|run| run.1).sum();
+        // After settling for 5 s, 15 s of audio are consumed a thousandth
+        // faster.
+        let dropped = 960_000 - frames;
+        assert!((700..=740).contains(&dropped), "{}", dropped);
+        assert_eq!(sync.report().audio_frames, frames as u64);
+    }
+
+    #[test]
+    fn retiming_moves_frames_onto_the_sample_clock() {
+        let mut sync = AvSync::new(options(DriftCorrection::RetimeVideo));
+        let samples = drifting_stream(&mut sync, 10, 1000.0);
+        // Audio is timed by its sample count.
+        for (index, run) in audio_runs(&samples).iter().enumerate() {
+            assert_eq!(run.0, index as u64 * 10_000_000);
+        }
+        let frames: Vec<u64> = samples
+            .iter()
+            .filter_map(|sample| match sample {
+                SyncedSample::Video(frame) => cmtime_to_nanos(frame.presentation_time),
+                SyncedSample::Audio(_) => None,
+            })
+            .collect();
+        assert_eq!(frames.len(), 100);
+        // Frames keep their times until the drift has settled, then
+        // stretch with it.
+        let presentation = |index: u64| (index as f64 * 100_000_000.0 / 1.001) as u64;
+        assert_eq!(frames[10], presentation(10));
+        let last = frames[99] as f64 - 99.0 * 100_000_000.0;
+        assert!(last.abs() < 100_000.0, "{}", last);
+    }
+}
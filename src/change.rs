@@ -0,0 +1,647 @@
+use crate::{
+    convert::ColorMatrix,
+    frame::{FrameError, PixelFormat, VideoFrame},
+    region::{PixelRect, Region},
+};
+
+/// An 8 bit luma image used for comparisons.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LumaImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl LumaImage {
+    /// Extracts the luma of a frame. YUV frames use their luma plane as is;
+    /// RGB frames are weighted with BT.601 coefficients.
+    pub fn from_frame(frame: &VideoFrame) -> Result<Self, FrameError> {
+        let pixel_format = frame.pixel_format();
+        let (width, height) = (frame.width(), frame.height());
+        let data = if pixel_format.is_yuv() {
+            frame.planes()[0].rows().flatten().copied().collect()
+        } else {
+            let rgba;
+            let (plane, red, blue) = match pixel_format {
+                PixelFormat::BGRA => (&frame.planes()[0], 2, 0),
+                PixelFormat::RGBA => (&frame.planes()[0], 0, 2),
+                _ => {
+                    rgba = frame.convert(PixelFormat::RGBA, ColorMatrix::default())?;
+                    (&rgba.planes()[0], 0, 2)
+                }
+            };
+            let mut data = Vec::with_capacity(width * height);
+            for row in plane.rows() {
+                data.extend(
+                    row.chunks_exact(4)
+                        .map(|pixel| ((pixel[red] as u32 * 77 + pixel[1] as u32 * 150 + pixel[blue] as u32 * 29 + 128) >> 8) as u8),
+                );
+            }
+            data
+        };
+        Ok(Self { width, height, data })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    /// Averages the image down to a `width` x `height` grid of cells.
+    fn downsample(&self, width: usize, height: usize) -> Vec<f64> {
+        let mut cells = vec![0.0; width * height];
+        for (cell_y, row) in cells.chunks_exact_mut(width).enumerate() {
+            let top = cell_y * self.height / height;
+            let bottom = ((cell_y + 1) * self.height / height).max(top + 1).min(self.height);
+            for (cell_x, cell) in row.iter_mut().enumerate() {
+                let left = cell_x * self.width / width;
+                let right = ((cell_x + 1) * self.width / width).max(left + 1).min(self.width);
+                let sum: u64 = (top..bottom)
+                    .map(|y| self.row(y)[left..right].iter().map(|value| *value as u64).sum::<u64>())
+                    .sum();
+                *cell = sum as f64 / ((bottom - top) * (right - left)) as f64;
+            }
+        }
+        cells
+    }
+}
+
+/// A 64 bit hash of the coarse structure of an image. Similar images have
+/// hashes with a small Hamming distance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(pub u64);
+
+impl PerceptualHash {
+    /// Average hash: one bit per cell of an 8x8 grid, set when the cell is
+    /// brighter than the mean.
+    pub fn average(image: &LumaImage) -> Self {
+        let cells = image.downsample(8, 8);
+        let mean = cells.iter().sum::<f64>() / cells.len() as f64;
+        Self(
+            cells
+                .iter()
+                .enumerate()
+                .fold(0, |hash, (index, cell)| hash | ((*cell > mean) as u64) << index),
+        )
+    }
+
+    /// Difference hash: one bit per horizontally adjacent pair of cells of a
+    /// 9x8 grid, set when brightness increases. More robust than the average
+    /// hash against global brightness changes.
+    pub fn difference(image: &LumaImage) -> Self {
+        let cells = image.downsample(9, 8);
+        let mut hash = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                hash |= ((cells[y * 9 + x + 1] > cells[y * 9 + x]) as u64) << (y * 8 + x);
+            }
+        }
+        Self(hash)
+    }
+
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+/// How one tile differs between two frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileDifference {
+    pub column: u32,
+    pub row: u32,
+    pub rect: PixelRect,
+    /// Mean absolute luma difference over the tile.
+    pub mean: f64,
+    pub max: u8,
+    /// Number of pixels whose difference exceeds the pixel threshold.
+    pub changed_pixels: u32,
+}
+
+impl TileDifference {
+    pub fn changed_fraction(&self) -> f64 {
+        if self.rect.is_empty() {
+            0.0
+        } else {
+            self.changed_pixels as f64 / self.rect.area() as f64
+        }
+    }
+}
+
+/// Compares two equally sized images tile by tile. Only tiles touched by
+/// `region` are compared when it is given.
+pub fn compare_tiles(
+    previous: &LumaImage,
+    current: &LumaImage,
+    tile_size: u32,
+    pixel_threshold: u8,
+    region: Option<&Region>,
+) -> Result<Vec<TileDifference>, FrameError> {
+    if previous.width != current.width || previous.height != current.height {
+        return Err(FrameError::InvalidDimensions);
+    }
+    let tile_size = tile_size.max(1);
+    let bounds = PixelRect::new(0, 0, current.width as u32, current.height as u32);
+    let tiles = match region {
+        Some(region) => region.clip(bounds).tiles(tile_size, tile_size),
+        None => Region::from_rect(bounds).tiles(tile_size, tile_size),
+    };
+    Ok(tiles
+        .into_iter()
+        .filter_map(|(column, row)| {
+            let rect = PixelRect::new(column * tile_size, row * tile_size, tile_size, tile_size).intersection(&bounds)?;
+            let (left, right) = (rect.x as usize, rect.right() as usize);
+            let mut sum = 0u64;
+            let mut max = 0;
+            let mut changed_pixels = 0;
+            for y in rect.y as usize..rect.bottom() as usize {
+                for (a, b) in previous.row(y)[left..right].iter().zip(&current.row(y)[left..right]) {
+                    let difference = a.abs_diff(*b);
+                    sum += difference as u64;
+                    max = max.max(difference);
+                    changed_pixels += (difference > pixel_threshold) as u32;
+                }
+            }
+            Some(TileDifference {
+                column,
+                row,
+                rect,
+                mean: sum as f64 / rect.area() as f64,
+                max,
+                changed_pixels,
+            })
+        })
+        .collect())
+}
+
+/// Thresholds deciding how a change is classified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChangeThresholds {
+    pub tile_size: u32,
+    /// Luma difference below which a pixel counts as unchanged, absorbing
+    /// compression and dithering noise.
+    pub pixel_delta: u8,
+    /// Tiles with fewer changed pixels are ignored.
+    pub tile_min_pixels: u32,
+    /// Share of the frame that must change for a significant change. The
+    /// default ignores a blinking cursor or a ticking clock.
+    pub significant_fraction: f64,
+    /// Share of the frame, or perceptual hash distance out of 64, from which
+    /// a change counts as a scene change.
+    pub scene_change_fraction: f64,
+    pub scene_change_distance: u32,
+    /// Largest displacement in pixels searched when estimating motion.
+    pub motion_search: u32,
+}
+
+impl Default for ChangeThresholds {
+    fn default() -> Self {
+        Self {
+            tile_size: 32,
+            pixel_delta: 24,
+            tile_min_pixels: 4,
+            significant_fraction: 0.002,
+            scene_change_fraction: 0.6,
+            scene_change_distance: 24,
+            motion_search: 64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Unchanged,
+    /// Some pixels changed, but less than the significant threshold.
+    Minor,
+    Significant,
+    /// The changed area mostly moved, e.g. scrolling or dragging a window.
+    Motion,
+    /// The content changed as a whole, or the frame size changed.
+    SceneChange,
+}
+
+impl ChangeKind {
+    /// Whether the frame is worth keeping when recording or streaming only
+    /// meaningful changes.
+    pub fn is_significant(&self) -> bool {
+        !matches!(self, Self::Unchanged | Self::Minor)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeReport {
+    pub kind: ChangeKind,
+    /// The changed tiles.
+    pub region: Region,
+    /// Share of the frame made of changed pixels.
+    pub changed_fraction: f64,
+    pub hash_distance: u32,
+    /// Estimated displacement of the changed content for
+    /// [`ChangeKind::Motion`].
+    pub motion: Option<(i32, i32)>,
+}
+
+/// Classifies each frame by how much it differs from the previous one.
+#[derive(Clone, Debug)]
+pub struct ChangeDetector {
+    thresholds: ChangeThresholds,
+    previous: Option<(LumaImage, PerceptualHash)>,
+    use_dirty_rects: bool,
+    /// Dirty rects of frames skipped since the previous one.
+    skipped: Option<Region>,
+    /// Whether a skipped frame had no dirty rects, so the next frame must be
+    /// compared as a whole.
+    skipped_unknown: bool,
+}
+
+impl Default for ChangeDetector {
+    fn default() -> Self {
+        Self::new(ChangeThresholds::default())
+    }
+}
+
+impl ChangeDetector {
+    pub fn new(thresholds: ChangeThresholds) -> Self {
+        Self {
+            thresholds,
+            previous: None,
+            use_dirty_rects: true,
+            skipped: None,
+            skipped_unknown: false,
+        }
+    }
+
+    pub fn thresholds(&self) -> &ChangeThresholds {
+        &self.thresholds
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: ChangeThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Whether only tiles touched by the frame's dirty rects are compared,
+    /// when the frame info has them. Enabled by default. Dirty rects describe
+    /// changes since the stream's previous frame, so frames that aren't
+    /// processed must be passed to [`skip`](Self::skip).
+    pub fn set_use_dirty_rects(&mut self, use_dirty_rects: bool) {
+        self.use_dirty_rects = use_dirty_rects;
+    }
+
+    /// Forgets the previous frame, so the next one is a scene change.
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.skipped = None;
+        self.skipped_unknown = false;
+    }
+
+    /// Notes a frame that isn't processed, so the next processed frame also
+    /// compares the areas it changed. Frames without new content changed
+    /// nothing; other frames without dirty rects make the next comparison
+    /// cover the whole frame.
+    pub fn skip(&mut self, frame: &VideoFrame) {
+        if self.previous.is_none() || self.skipped_unknown || frame.info.status.is_some_and(|status| !status.has_new_content()) {
+            return;
+        }
+        match Region::from_frame_info(&frame.info) {
+            Some(region) => {
+                self.skipped = Some(match self.skipped.take() {
+                    Some(skipped) => skipped.union(&region),
+                    None => region,
+                })
+            }
+            None => {
+                self.skipped = None;
+                self.skipped_unknown = true;
+            }
+        }
+    }
+
+    pub fn process(&mut self, frame: &VideoFrame) -> Result<ChangeReport, FrameError> {
+        let luma = LumaImage::from_frame(frame)?;
+        let region = if self.use_dirty_rects && !self.skipped_unknown {
+            Region::from_frame_info(&frame.info).map(|region| {
+                let region = match &self.skipped {
+                    Some(skipped) => region.union(skipped),
+                    None => region,
+                };
+                region.clip(PixelRect::new(0, 0, frame.width() as u32, frame.height() as u32))
+            })
+        } else {
+            None
+        };
+        self.process_luma(luma, region.as_ref())
+    }
+
+    /// Compares `luma` against the previous image, limited to `region` when
+    /// given, and keeps it for the next call.
+    pub fn process_luma(&mut self, luma: LumaImage, region: Option<&Region>) -> Result<ChangeReport, FrameError> {
+        let hash = PerceptualHash::difference(&luma);
+        let frame_area = (luma.width * luma.height) as f64;
+        let report = match &self.previous {
+            Some((previous, previous_hash)) if previous.width == luma.width && previous.height == luma.height => {
+                let thresholds = &self.thresholds;
+                let tiles = compare_tiles(previous, &luma, thresholds.tile_size, thresholds.pixel_delta, region)?;
+                let changed: Vec<&TileDifference> = tiles.iter().filter(|tile| tile.changed_pixels >= thresholds.tile_min_pixels).collect();
+                let changed_pixels: u64 = changed.iter().map(|tile| tile.changed_pixels as u64).sum();
+                let changed_fraction = changed_pixels as f64 / frame_area;
+                let hash_distance = hash.distance(previous_hash);
+                let region = Region::from_rects(changed.iter().map(|tile| tile.rect));
+                let mut motion = None;
+                let kind = if changed.is_empty() {
+                    ChangeKind::Unchanged
+                } else if changed_fraction < thresholds.significant_fraction {
+                    ChangeKind::Minor
+                } else {
+                    // Scrolling a whole window changes most pixels, so motion
+                    // is checked before the scene change thresholds.
+                    motion = region
+                        .bounds()
+                        .and_then(|bounds| estimate_motion(previous, &luma, bounds, thresholds.motion_search));
+                    if motion.is_some() {
+                        ChangeKind::Motion
+                    } else if changed_fraction >= thresholds.scene_change_fraction || hash_distance >= thresholds.scene_change_distance {
+                        ChangeKind::SceneChange
+                    } else {
+                        ChangeKind::Significant
+                    }
+                };
+                ChangeReport {
+                    kind,
+                    region,
+                    changed_fraction,
+                    hash_distance,
+                    motion,
+                }
+            }
+            previous => ChangeReport {
+                kind: ChangeKind::SceneChange,
+                region: Region::from_rect(PixelRect::new(0, 0, luma.width as u32, luma.height as u32)),
+                changed_fraction: 1.0,
+                hash_distance: previous.as_ref().map_or(64, |(_, previous_hash)| hash.distance(previous_hash)),
+                motion: None,
+            },
+        };
+        self.previous = Some((luma, hash));
+        self.skipped = None;
+        self.skipped_unknown = false;
+        Ok(report)
+    }
+}
+
+/// Estimates a translation of the content inside `bounds` by matching row
+/// and column brightness profiles, returning it when shifting explains most
+/// of the difference.
+fn estimate_motion(previous: &LumaImage, current: &LumaImage, bounds: PixelRect, search: u32) -> Option<(i32, i32)> {
+    let (left, top) = (bounds.x as usize, bounds.y as usize);
+    let (right, bottom) = (bounds.right() as usize, bounds.bottom() as usize);
+    let row_profile = |image: &LumaImage| -> Vec<f64> {
+        (top..bottom)
+            .map(|y| image.row(y)[left..right].iter().map(|value| *value as f64).sum::<f64>() / (right - left) as f64)
+            .collect()
+    };
+    let column_profile = |image: &LumaImage| -> Vec<f64> {
+        (left..right)
+            .map(|x| (top..bottom).map(|y| image.row(y)[x] as f64).sum::<f64>() / (bottom - top) as f64)
+            .collect()
+    };
+    let dy = best_shift(&row_profile(previous), &row_profile(current), search)?;
+    let dx = best_shift(&column_profile(previous), &column_profile(current), search)?;
+    if dx == 0 && dy == 0 {
+        None
+    } else {
+        Some((dx, dy))
+    }
+}
+
+/// The shift of `current` relative to `previous` that minimizes the mean
+/// absolute difference of the overlapping parts, if it clearly beats not
+/// shifting at all.
+fn best_shift(previous: &[f64], current: &[f64], search: u32) -> Option<i32> {
+    let length = previous.len() as i32;
+    let search = (search as i32).min(length / 2);
+    let cost = |shift: i32| {
+        let overlap = (0..length).filter(|index| (0..length).contains(&(index - shift)));
+        let (sum, count) = overlap.fold((0.0, 0), |(sum, count), index| {
+            (sum + (current[index as usize] - previous[(index - shift) as usize]).abs(), count + 1)
+        });
+        if count == 0 {
+            f64::INFINITY
+        } else {
+            sum / count as f64
+        }
+    };
+    let still = cost(0);
+    let (shift, best) = (-search..=search).map(|shift| (shift, cost(shift))).min_by(|a, b| a.1.total_cmp(&b.1))?;
+    if shift == 0 || best < still * 0.25 {
+        Some(shift)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{CGPoint, CGRect, CGSize};
+
+    const WIDTH: usize = 128;
+    const HEIGHT: usize = 96;
+
+    fn luma(width: usize, height: usize, value: impl Fn(usize, usize) -> u8) -> LumaImage {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| value(x, y))
+            .collect();
+        LumaImage { width, height, data }
+    }
+
+    /// A gray BGRA frame, whose luma is `value`, with the given dirty rects.
+    fn frame(value: impl Fn(usize, usize) -> u8, dirty_rects: Option<&[(f64, f64, f64, f64)]>) -> VideoFrame {
+        let data: Vec<u8> = luma(WIDTH, HEIGHT, value)
+            .data
+            .iter()
+            .flat_map(|value| [*value, *value, *value, 255])
+            .collect();
+        let mut frame = VideoFrame::from_bytes(WIDTH, HEIGHT, PixelFormat::BGRA, &data).unwrap();
+        frame.info.dirty_rects = dirty_rects.map(|rects| {
+            rects
+                .iter()
+                .map(|&(x, y, width, height)| CGRect::new(CGPoint::new(x, y), CGSize::new(width, height)))
+                .collect()
+        });
+        frame
+    }
+
+    /// Deterministic texture with distinct rows and columns.
+    fn texture(x: usize, y: usize) -> u8 {
+        let mut value = (x as u32).wrapping_mul(0x9e37_79b1) ^ (y as u32).wrapping_mul(0x85eb_ca6b);
+        value ^= value >> 15;
+        value = value.wrapping_mul(0x2c1b_3c6d);
+        (value >> 24) as u8
+    }
+
+    #[test]
+    fn luma_weights_rgb() {
+        let data = [[0, 0, 255, 255], [0, 255, 0, 255], [255, 0, 0, 255], [255, 255, 255, 255]].concat();
+        let frame = VideoFrame::from_bytes(4, 1, PixelFormat::BGRA, &data).unwrap();
+        assert_eq!(LumaImage::from_frame(&frame).unwrap().data(), [77, 149, 29, 255]);
+    }
+
+    #[test]
+    fn hashes() {
+        let flat = luma(64, 64, |_, _| 128);
+        assert_eq!(PerceptualHash::average(&flat), PerceptualHash(0));
+        assert_eq!(PerceptualHash::difference(&flat), PerceptualHash(0));
+
+        // Brightness rising to the right sets every difference bit and the
+        // average bits of the right half of each row.
+        let ramp = luma(72, 64, |x, _| (x * 3) as u8);
+        assert_eq!(PerceptualHash::difference(&ramp), PerceptualHash(u64::MAX));
+        assert_eq!(PerceptualHash::average(&ramp), PerceptualHash(0xf0f0_f0f0_f0f0_f0f0));
+
+        // The difference hash ignores a global brightness change.
+        let brighter = luma(72, 64, |x, _| (x * 3 + 20) as u8);
+        assert_eq!(PerceptualHash::difference(&brighter).distance(&PerceptualHash::difference(&ramp)), 0);
+        let reversed = luma(72, 64, |x, _| (255 - x * 3) as u8);
+        assert_eq!(PerceptualHash::difference(&reversed).distance(&PerceptualHash::difference(&ramp)), 64);
+        assert_eq!(PerceptualHash(0b1011).distance(&PerceptualHash(0b0110)), 3);
+    }
+
+    #[test]
+    fn tiles_measure_differences() {
+        let previous = luma(10, 7, |_, _| 100);
+        let current = luma(10, 7, |x, y| {
+            if (x, y) == (1, 1) || (x, y) == (9, 6) {
+                150
+            } else {
+                110
+            }
+        });
+        let tiles = compare_tiles(&previous, &current, 4, 20, None).unwrap();
+        let rects: Vec<PixelRect> = tiles.iter().map(|tile| tile.rect).collect();
+        assert_eq!(
+            rects,
+            [
+                PixelRect::new(0, 0, 4, 4),
+                PixelRect::new(4, 0, 4, 4),
+                PixelRect::new(8, 0, 2, 4),
+                PixelRect::new(0, 4, 4, 3),
+                PixelRect::new(4, 4, 4, 3),
+                PixelRect::new(8, 4, 2, 3),
+            ]
+        );
+        assert_eq!((tiles[0].max, tiles[0].changed_pixels), (50, 1));
+        assert_eq!(tiles[0].mean, (15.0 * 10.0 + 50.0) / 16.0);
+        assert_eq!(tiles[0].changed_fraction(), 1.0 / 16.0);
+        assert_eq!((tiles[1].max, tiles[1].changed_pixels, tiles[1].mean), (10, 0, 10.0));
+        assert_eq!((tiles[5].column, tiles[5].row, tiles[5].changed_pixels), (2, 1, 1));
+
+        // A region limits the comparison to the tiles it touches.
+        let region = Region::from_rect(PixelRect::new(5, 5, 20, 1));
+        let tiles = compare_tiles(&previous, &current, 4, 20, Some(&region)).unwrap();
+        let positions: Vec<(u32, u32)> = tiles.iter().map(|tile| (tile.column, tile.row)).collect();
+        assert_eq!(positions, [(1, 1), (2, 1)]);
+
+        assert_eq!(
+            compare_tiles(&previous, &luma(10, 6, |_, _| 0), 4, 20, None),
+            Err(FrameError::InvalidDimensions)
+        );
+    }
+
+    #[test]
+    fn classification() {
+        type Image = Box<dyn Fn(usize, usize) -> u8>;
+        let base = |x, y| texture(x, y) / 2 + 64;
+        let cases: [(&str, Image, ChangeKind); 5] = [
+            ("same", Box::new(base), ChangeKind::Unchanged),
+            // Four pixels reach a tile's minimum, but not the significant share.
+            (
+                "cursor",
+                Box::new(move |x, y| {
+                    if x < 2 && y < 2 {
+                        255
+                    } else {
+                        base(x, y)
+                    }
+                }),
+                ChangeKind::Minor,
+            ),
+            (
+                "window",
+                Box::new(move |x, y| {
+                    if (40..80).contains(&x) && (30..60).contains(&y) {
+                        0
+                    } else {
+                        base(x, y)
+                    }
+                }),
+                ChangeKind::Significant,
+            ),
+            ("scroll", Box::new(move |x, y| base(x, (y + 8) % HEIGHT)), ChangeKind::Motion),
+            ("inverted", Box::new(move |x, y| 255 - base(x, y)), ChangeKind::SceneChange),
+        ];
+        for (name, current, kind) in &cases {
+            let mut detector = ChangeDetector::default();
+            let first = detector.process(&frame(base, None)).unwrap();
+            assert_eq!(first.kind, ChangeKind::SceneChange);
+            let report = detector.process(&frame(current, None)).unwrap();
+            assert_eq!(report.kind, *kind, "{}: {:?}", name, report);
+            assert_eq!(report.kind.is_significant(), !matches!(kind, ChangeKind::Unchanged | ChangeKind::Minor));
+            if *kind == ChangeKind::Motion {
+                assert_eq!(report.motion, Some((0, -8)));
+            }
+        }
+
+        // A different size starts over.
+        let mut detector = ChangeDetector::default();
+        detector.process_luma(luma(8, 8, |_, _| 0), None).unwrap();
+        let report = detector.process_luma(luma(8, 9, |_, _| 0), None).unwrap();
+        assert_eq!((report.kind, report.changed_fraction), (ChangeKind::SceneChange, 1.0));
+    }
+
+    #[test]
+    fn dirty_rects_accumulate_over_skipped_frames() {
+        let block = |x: usize, y: usize| {
+            if x < 32 && y < 32 {
+                255
+            } else {
+                0
+            }
+        };
+        let changed = Some(&[(0.0, 0.0, 32.0, 32.0)][..]);
+        let elsewhere = Some(&[(96.0, 64.0, 32.0, 32.0)][..]);
+
+        // Dirty rects that miss the change hide it.
+        let mut detector = ChangeDetector::default();
+        detector.process(&frame(|_, _| 0, None)).unwrap();
+        assert_eq!(detector.process(&frame(block, elsewhere)).unwrap().kind, ChangeKind::Unchanged);
+
+        // The change happened in a skipped frame, whose dirty rects count.
+        let mut detector = ChangeDetector::default();
+        detector.process(&frame(|_, _| 0, None)).unwrap();
+        detector.skip(&frame(block, changed));
+        let report = detector.process(&frame(block, elsewhere)).unwrap();
+        assert_eq!(report.kind, ChangeKind::Significant);
+        assert_eq!(report.region, Region::from_rect(PixelRect::new(0, 0, 32, 32)));
+
+        // A skipped frame without dirty rects forces a full comparison.
+        let mut detector = ChangeDetector::default();
+        detector.process(&frame(|_, _| 0, None)).unwrap();
+        detector.skip(&frame(block, None));
+        detector.skip(&frame(block, elsewhere));
+        assert_eq!(detector.process(&frame(block, elsewhere)).unwrap().kind, ChangeKind::Significant);
+
+        // Skipped frames are forgotten once a frame is processed.
+        assert_eq!(detector.process(&frame(block, elsewhere)).unwrap().kind, ChangeKind::Unchanged);
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::platform::{kCMTimeFlags_ImpliedValueFlagsMask, kCMTimeFlags_Valid, CMTime, CMTimeScale};
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Ratio converting host time ticks to nanoseconds, as reported by
+/// `mach_timebase_info`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostTimebase {
+    pub numer: u32,
+    pub denom: u32,
+}
+
+impl HostTimebase {
+    /// The timebase of a host clock already counting in nanoseconds.
+    pub const NANOSECONDS: Self = Self { numer: 1, denom: 1 };
+
+    pub fn ticks_to_nanos(&self, ticks: u64) -> u64 {
+        if self.denom == 0 {
+            return ticks;
+        }
+        (ticks as u128 * self.numer as u128 / self.denom as u128).min(u64::MAX as u128) as u64
+    }
+
+    pub fn nanos_to_ticks(&self, nanos: u64) -> u64 {
+        if self.numer == 0 {
+            return nanos;
+        }
+        (nanos as u128 * self.denom as u128 / self.numer as u128).min(u64::MAX as u128) as u64
+    }
+
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos(self.ticks_to_nanos(ticks))
+    }
+}
+
+/// A source of correlated host, wall clock and monotonic time readings.
+/// Implement it to drive a [`ClockMapper`] from a simulated clock.
+pub trait ClockSource {
+    fn timebase(&self) -> HostTimebase;
+    /// Host time in ticks of [`ClockSource::timebase`], the clock used by
+    /// `SCStreamFrameInfoDisplayTime` and sample buffer timestamps.
+    fn host_time(&self) -> u64;
+    fn system_time(&self) -> SystemTime;
+    fn instant(&self) -> Instant;
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn mach_absolute_time() -> u64;
+    fn mach_timebase_info(info: *mut HostTimebase) -> libc::c_int;
+}
+
+/// The clocks of the running system.
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    timebase: HostTimebase,
+}
+
+impl SystemClock {
+    #[cfg(target_os = "macos")]
+    pub fn new() -> Self {
+        let mut timebase = HostTimebase::NANOSECONDS;
+        if unsafe { mach_timebase_info(&mut timebase) } != 0 || timebase.denom == 0 {
+            timebase = HostTimebase::NANOSECONDS;
+        }
+        Self { timebase }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn new() -> Self {
+        Self {
+            timebase: HostTimebase::NANOSECONDS,
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockSource for SystemClock {
+    fn timebase(&self) -> HostTimebase {
+        self.timebase
+    }
+
+    #[cfg(target_os = "macos")]
+    fn host_time(&self) -> u64 {
+        unsafe { mach_absolute_time() }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn host_time(&self) -> u64 {
+        use std::sync::OnceLock;
+
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        EPOCH.get_or_init(Instant::now).elapsed().as_nanos().min(u64::MAX as u128) as u64
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Anchor {
+    host_nanos: u64,
+    system_time: SystemTime,
+    instant: Instant,
+}
+
+/// The outcome of comparing the clocks against the mapping anchor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftReport {
+    /// Host time elapsed since the anchor was taken.
+    pub elapsed: Duration,
+    /// How far the wall clock moved relative to host time, positive when the
+    /// wall clock runs ahead.
+    pub wall_clock_drift_nanos: i64,
+    /// How far `Instant` moved relative to host time.
+    pub monotonic_drift_nanos: i64,
+    /// Whether the drift exceeded the threshold and the mapper re-anchored.
+    pub resynchronized: bool,
+}
+
+impl DriftReport {
+    /// Wall clock drift in parts per million of the elapsed time.
+    pub fn wall_clock_drift_ppm(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            self.wall_clock_drift_nanos as f64 * 1e6 / self.elapsed.as_nanos() as f64
+        }
+    }
+}
+
+/// Maps host time ticks and sample `CMTime`s to wall clock and monotonic
+/// timestamps by correlating the clocks at an anchor point.
+#[derive(Clone, Debug)]
+pub struct ClockMapper<C = SystemClock> {
+    clock: C,
+    anchor: Anchor,
+    drift_threshold: Duration,
+}
+
+impl ClockMapper<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::new())
+    }
+}
+
+impl Default for ClockMapper<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: ClockSource> ClockMapper<C> {
+    pub fn with_clock(clock: C) -> Self {
+        let anchor = Self::sample(&clock);
+        Self {
+            clock,
+            anchor,
+            drift_threshold: Duration::from_millis(1),
+        }
+    }
+
+    // Read the host clock on both sides of the other clocks and use the
+    // midpoint to halve the error introduced by the reads themselves.
+    fn sample(clock: &C) -> Anchor {
+        let timebase = clock.timebase();
+        let before = timebase.ticks_to_nanos(clock.host_time());
+        let system_time = clock.system_time();
+        let instant = clock.instant();
+        let after = timebase.ticks_to_nanos(clock.host_time());
+        Anchor {
+            host_nanos: before + after.saturating_sub(before) / 2,
+            system_time,
+            instant,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn timebase(&self) -> HostTimebase {
+        self.clock.timebase()
+    }
+
+    pub fn drift_threshold(&self) -> Duration {
+        self.drift_threshold
+    }
+
+    pub fn set_drift_threshold(&mut self, threshold: Duration) {
+        self.drift_threshold = threshold;
+    }
+
+    /// Takes a new anchor, discarding any accumulated drift.
+    pub fn resync(&mut self) {
+        self.anchor = Self::sample(&self.clock);
+    }
+
+    fn offset_nanos(&self, host_nanos: u64) -> i128 {
+        host_nanos as i128 - self.anchor.host_nanos as i128
+    }
+
+    fn host_nanos_to_system_time(&self, host_nanos: u64) -> Option<SystemTime> {
+        let offset = self.offset_nanos(host_nanos);
+        let magnitude = Duration::from_nanos(offset.unsigned_abs().min(u64::MAX as u128) as u64);
+        if offset >= 0 {
+            self.anchor.system_time.checked_add(magnitude)
+        } else {
+            self.anchor.system_time.checked_sub(magnitude)
+        }
+    }
+
+    fn host_nanos_to_instant(&self, host_nanos: u64) -> Option<Instant> {
+        let offset = self.offset_nanos(host_nanos);
+        let magnitude = Duration::from_nanos(offset.unsigned_abs().min(u64::MAX as u128) as u64);
+        if offset >= 0 {
+            self.anchor.instant.checked_add(magnitude)
+        } else {
+            self.anchor.instant.checked_sub(magnitude)
+        }
+    }
+
+    pub fn host_time_to_system_time(&self, host_time: u64) -> Option<SystemTime> {
+        self.host_nanos_to_system_time(self.timebase().ticks_to_nanos(host_time))
+    }
+
+    /// Host time as a duration since the Unix epoch.
+    pub fn host_time_to_unix(&self, host_time: u64) -> Option<Duration> {
+        self.host_time_to_system_time(host_time)?.duration_since(UNIX_EPOCH).ok()
+    }
+
+    pub fn host_time_to_instant(&self, host_time: u64) -> Option<Instant> {
+        self.host_nanos_to_instant(self.timebase().ticks_to_nanos(host_time))
+    }
+
+    pub fn cmtime_to_system_time(&self, time: CMTime) -> Option<SystemTime> {
+        self.host_nanos_to_system_time(cmtime_to_nanos(time)?)
+    }
+
+    pub fn cmtime_to_unix(&self, time: CMTime) -> Option<Duration> {
+        self.cmtime_to_system_time(time)?.duration_since(UNIX_EPOCH).ok()
+    }
+
+    pub fn cmtime_to_instant(&self, time: CMTime) -> Option<Instant> {
+        self.host_nanos_to_instant(cmtime_to_nanos(time)?)
+    }
+
+    /// Host time ticks expressed as a `CMTime` on the host time clock.
+    pub fn host_time_to_cmtime(&self, host_time: u64, timescale: CMTimeScale) -> CMTime {
+        nanos_to_cmtime(self.timebase().ticks_to_nanos(host_time), timescale)
+    }
+
+    /// Compares the clocks against the anchor and re-anchors when either has
+    /// drifted further than the drift threshold, e.g. after an NTP step.
+    pub fn check_drift(&mut self) -> DriftReport {
+        let now = Self::sample(&self.clock);
+        let elapsed = now.host_nanos.saturating_sub(self.anchor.host_nanos) as i128;
+        let wall_clock = match now.system_time.duration_since(self.anchor.system_time) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(error) => -(error.duration().as_nanos() as i128),
+        };
+        let monotonic = now.instant.saturating_duration_since(self.anchor.instant).as_nanos() as i128;
+        let clamp = |drift: i128| drift.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        let wall_clock_drift_nanos = clamp(wall_clock - elapsed);
+        let monotonic_drift_nanos = clamp(monotonic - elapsed);
+        let threshold = self.drift_threshold.as_nanos().min(u64::MAX as u128) as u64;
+        let resynchronized = wall_clock_drift_nanos.unsigned_abs() > threshold || monotonic_drift_nanos.unsigned_abs() > threshold;
+        if resynchronized {
+            self.anchor = now;
+        }
+        DriftReport {
+            elapsed: Duration::from_nanos(elapsed as u64),
+            wall_clock_drift_nanos,
+            monotonic_drift_nanos,
+            resynchronized,
+        }
+    }
+}
+
+/// Converts a numeric `CMTime` to nanoseconds, returning `None` for invalid,
+/// indefinite, infinite or negative times.
+pub fn cmtime_to_nanos(time: CMTime) -> Option<u64> {
+    if time.flags & kCMTimeFlags_Valid == 0 || time.flags & kCMTimeFlags_ImpliedValueFlagsMask != 0 || time.timescale <= 0 || time.value < 0 {
+        return None;
+    }
+    let nanos = time.value as i128 * NANOS_PER_SECOND / time.timescale as i128;
+    u64::try_from(nanos).ok()
+}
+
+pub fn nanos_to_cmtime(nanos: u64, timescale: CMTimeScale) -> CMTime {
+    let timescale = timescale.max(1);
+    let value = (nanos as i128 * timescale as i128 / NANOS_PER_SECOND).min(i64::MAX as i128) as i64;
+    CMTime {
+        value,
+        timescale,
+        flags: kCMTimeFlags_Valid,
+        epoch: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::platform::{kCMTimeFlags_Indefinite, kCMTimeFlags_PositiveInfinity};
+
+    /// 125/3 nanoseconds per tick, so 24 ticks make a microsecond.
+    const TIMEBASE: HostTimebase = HostTimebase { numer: 125, denom: 3 };
+    const ANCHOR_TICKS: u64 = 3_000_000;
+    const ANCHOR_UNIX: Duration = Duration::from_secs(1_000);
+
+    struct FakeClock {
+        ticks: Cell<u64>,
+        system_time: Cell<SystemTime>,
+        start: Instant,
+        monotonic: Cell<Duration>,
+    }
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self {
+                ticks: Cell::new(ANCHOR_TICKS),
+                system_time: Cell::new(UNIX_EPOCH + ANCHOR_UNIX),
+                start: Instant::now(),
+                monotonic: Cell::new(Duration::ZERO),
+            }
+        }
+
+        /// Advances host time by `ticks`, and the other clocks by the same
+        /// time plus their drift.
+        fn advance(&self, ticks: u64, wall_clock_drift_nanos: i64, monotonic_drift_nanos: u64) {
+            let nanos = TIMEBASE.ticks_to_nanos(ticks);
+            self.ticks.set(self.ticks.get() + ticks);
+            let system_time = self.system_time.get() + Duration::from_nanos(nanos);
+            let drift = Duration::from_nanos(wall_clock_drift_nanos.unsigned_abs());
+            self.system_time.set(if wall_clock_drift_nanos >= 0 {
+                system_time + drift
+            } else {
+                system_time - drift
+            });
+            self.monotonic
+                .set(self.monotonic.get() + Duration::from_nanos(nanos + monotonic_drift_nanos));
+        }
+    }
+
+    impl ClockSource for FakeClock {
+        fn timebase(&self) -> HostTimebase {
+            TIMEBASE
+        }
+
+        fn host_time(&self) -> u64 {
+            self.ticks.get()
+        }
+
+        fn system_time(&self) -> SystemTime {
+            self.system_time.get()
+        }
+
+        fn instant(&self) -> Instant {
+            self.start + self.monotonic.get()
+        }
+    }
+
+    fn cmtime(value: i64, timescale: CMTimeScale, flags: u32) -> CMTime {
+        CMTime {
+            value,
+            timescale,
+            flags,
+            epoch: 0,
+        }
+    }
+
+    #[test]
+    fn timebase_conversions() {
+        assert_eq!(TIMEBASE.ticks_to_nanos(24), 1_000);
+        assert_eq!(TIMEBASE.nanos_to_ticks(1_000), 24);
+        assert_eq!(TIMEBASE.ticks_to_nanos(u64::MAX), u64::MAX);
+        assert_eq!(HostTimebase { numer: 1, denom: 0 }.ticks_to_nanos(7), 7);
+        assert_eq!(HostTimebase { numer: 0, denom: 1 }.nanos_to_ticks(7), 7);
+    }
+
+    #[test]
+    fn maps_host_time() {
+        let mapper = ClockMapper::with_clock(FakeClock::new());
+        let anchor_nanos = TIMEBASE.ticks_to_nanos(ANCHOR_TICKS);
+        assert_eq!(mapper.host_time_to_unix(ANCHOR_TICKS), Some(ANCHOR_UNIX));
+        assert_eq!(mapper.host_time_to_unix(ANCHOR_TICKS + 24), Some(ANCHOR_UNIX + Duration::from_micros(1)));
+        assert_eq!(mapper.host_time_to_unix(0), Some(ANCHOR_UNIX - Duration::from_nanos(anchor_nanos)));
+        assert_eq!(
+            mapper.host_time_to_instant(ANCHOR_TICKS + 24_000),
+            Some(mapper.clock().start + Duration::from_millis(1))
+        );
+
+        let time = mapper.host_time_to_cmtime(ANCHOR_TICKS + 24, 1_000_000_000);
+        assert_eq!(time, cmtime(anchor_nanos as i64 + 1_000, 1_000_000_000, kCMTimeFlags_Valid));
+        assert_eq!(mapper.cmtime_to_unix(time), mapper.host_time_to_unix(ANCHOR_TICKS + 24));
+        assert_eq!(mapper.cmtime_to_instant(time), mapper.host_time_to_instant(ANCHOR_TICKS + 24));
+        assert_eq!(mapper.cmtime_to_unix(cmtime(1, 1, 0)), None);
+    }
+
+    #[test]
+    fn drift_within_threshold_keeps_anchor() {
+        let mut mapper = ClockMapper::with_clock(FakeClock::new());
+        mapper.clock().advance(24_000_000, 500_000, 0);
+        let report = mapper.check_drift();
+        assert_eq!(report.elapsed, Duration::from_secs(1));
+        assert_eq!(report.wall_clock_drift_nanos, 500_000);
+        assert_eq!(report.monotonic_drift_nanos, 0);
+        assert_eq!(report.wall_clock_drift_ppm(), 500.0);
+        assert!(!report.resynchronized);
+        assert_eq!(mapper.host_time_to_unix(ANCHOR_TICKS), Some(ANCHOR_UNIX));
+
+        // Drift accumulates against the same anchor until it passes the threshold.
+        mapper.clock().advance(24_000_000, 600_000, 0);
+        let report = mapper.check_drift();
+        assert_eq!(report.elapsed, Duration::from_secs(2));
+        assert_eq!(report.wall_clock_drift_nanos, 1_100_000);
+        assert!(report.resynchronized);
+        let now = ANCHOR_TICKS + 48_000_000;
+        assert_eq!(mapper.host_time_to_unix(now), Some(ANCHOR_UNIX + Duration::from_nanos(2_001_100_000)));
+    }
+
+    #[test]
+    fn drift_thresholds() {
+        let mut mapper = ClockMapper::with_clock(FakeClock::new());
+        mapper.set_drift_threshold(Duration::from_millis(5));
+        mapper.clock().advance(24_000, 0, 5_000_000);
+        let report = mapper.check_drift();
+        assert_eq!(report.monotonic_drift_nanos, 5_000_000);
+        assert!(!report.resynchronized, "drift equal to the threshold is tolerated");
+
+        mapper.clock().advance(0, 0, 1);
+        assert!(mapper.check_drift().resynchronized);
+
+        // A wall clock stepped back is reported as negative drift.
+        mapper.clock().advance(24_000, -10_000_000, 0);
+        let report = mapper.check_drift();
+        assert_eq!(report.wall_clock_drift_nanos, -10_000_000);
+        assert_eq!(report.wall_clock_drift_ppm(), -10_000_000.0);
+        assert!(report.resynchronized);
+
+        let report = mapper.check_drift();
+        assert_eq!(report.elapsed, Duration::ZERO);
+        assert_eq!(report.wall_clock_drift_ppm(), 0.0);
+        assert!(!report.resynchronized);
+    }
+
+    #[test]
+    fn cmtime_to_nanos_rejects_non_numeric_times() {
+        assert_eq!(cmtime_to_nanos(cmtime(1, 1, 0)), None);
+        assert_eq!(cmtime_to_nanos(cmtime(1, 1, kCMTimeFlags_Valid | kCMTimeFlags_Indefinite)), None);
+        assert_eq!(cmtime_to_nanos(cmtime(1, 1, kCMTimeFlags_Valid | kCMTimeFlags_PositiveInfinity)), None);
+        assert_eq!(cmtime_to_nanos(cmtime(1, 0, kCMTimeFlags_Valid)), None);
+        assert_eq!(cmtime_to_nanos(cmtime(1, -600, kCMTimeFlags_Valid)), None);
+        assert_eq!(cmtime_to_nanos(cmtime(-1, 600, kCMTimeFlags_Valid)), None);
+    }
+
+    #[test]
+    fn cmtime_nanos_conversions() {
+        assert_eq!(cmtime_to_nanos(cmtime(0, 600, kCMTimeFlags_Valid)), Some(0));
+        assert_eq!(cmtime_to_nanos(cmtime(900, 600, kCMTimeFlags_Valid)), Some(1_500_000_000));
+        assert_eq!(nanos_to_cmtime(1_500_000_000, 600), cmtime(900, 600, kCMTimeFlags_Valid));
+        // Sub-tick remainders are truncated.
+        assert_eq!(nanos_to_cmtime(1_999_999, 1_000), cmtime(1, 1_000, kCMTimeFlags_Valid));
+        // Invalid timescales fall back to whole seconds.
+        assert_eq!(nanos_to_cmtime(2_500_000_000, 0), cmtime(2, 1, kCMTimeFlags_Valid));
+        assert_eq!(nanos_to_cmtime(2_500_000_000, -1), cmtime(2, 1, kCMTimeFlags_Valid));
+    }
+
+    #[test]
+    fn cmtime_nanos_conversions_saturate() {
+        assert_eq!(
+            cmtime_to_nanos(cmtime(i64::MAX, 1_000_000_000, kCMTimeFlags_Valid)),
+            Some(i64::MAX as u64)
+        );
+        assert_eq!(cmtime_to_nanos(cmtime(i64::MAX, 1, kCMTimeFlags_Valid)), None);
+        assert_eq!(nanos_to_cmtime(u64::MAX, 1_000_000_000).value, i64::MAX);
+        assert_eq!(nanos_to_cmtime(u64::MAX, CMTimeScale::MAX).value, i64::MAX);
+        assert_eq!(
+            cmtime_to_nanos(nanos_to_cmtime(u64::MAX, 1)),
+            Some(u64::MAX / 1_000_000_000 * 1_000_000_000)
+        );
+    }
+}
@@ -0,0 +1,811 @@
+#[cfg(target_os = "macos")]
+use core_foundation::{base::TCFType, string::CFString};
+
+use crate::frame::{FrameError, PixelFormat, VideoFrame};
+#[cfg(target_os = "macos")]
+use crate::stream::SCStreamConfiguration;
+
+const SHIFT: u32 = 14;
+const ONE: f64 = (1 << SHIFT) as f64;
+const HALF: i32 = 1 << (SHIFT - 1);
+
+/// The Y'CbCr matrix used to encode YUV samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorMatrix {
+    Bt601,
+    #[default]
+    Bt709,
+    Bt2020,
+    Smpte240M,
+}
+
+impl ColorMatrix {
+    /// Parses a `kCVImageBufferYCbCrMatrix` value such as `ITU_R_709_2`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ITU_R_601_4" => Some(Self::Bt601),
+            "ITU_R_709_2" => Some(Self::Bt709),
+            "ITU_R_2020" => Some(Self::Bt2020),
+            "SMPTE_240M_1995" => Some(Self::Smpte240M),
+            _ => None,
+        }
+    }
+
+    /// The matrix set with `set_color_matrix`, if any.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Option<Self> {
+        let name = configuration.get_color_matrix();
+        if name.is_null() {
+            return None;
+        }
+        Self::from_name(&unsafe { CFString::wrap_under_get_rule(name) }.to_string())
+    }
+
+    /// The red and blue luma weights.
+    fn weights(&self) -> (f64, f64) {
+        match self {
+            Self::Bt601 => (0.299, 0.114),
+            Self::Bt709 => (0.2126, 0.0722),
+            Self::Bt2020 => (0.2627, 0.0593),
+            Self::Smpte240M => (0.212, 0.087),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorRange {
+    /// Luma in 16-235 and chroma in 16-240.
+    Video,
+    Full,
+}
+
+impl ColorRange {
+    pub fn of(pixel_format: PixelFormat) -> Self {
+        if pixel_format.is_full_range() {
+            Self::Full
+        } else {
+            Self::Video
+        }
+    }
+
+    fn scales(&self) -> (f64, f64, i32) {
+        match self {
+            Self::Video => (219.0 / 255.0, 224.0 / 255.0, 16),
+            Self::Full => (1.0, 1.0, 0),
+        }
+    }
+}
+
+/// Fixed point coefficients for one matrix and range.
+#[derive(Clone, Copy, Debug)]
+struct Coefficients {
+    y_offset: i32,
+    y_scale: i32,
+    r_v: i32,
+    g_u: i32,
+    g_v: i32,
+    b_u: i32,
+    y_r: i32,
+    y_g: i32,
+    y_b: i32,
+    u_r: i32,
+    u_g: i32,
+    u_b: i32,
+    v_r: i32,
+    v_g: i32,
+    v_b: i32,
+}
+
+impl Coefficients {
+    fn new(matrix: ColorMatrix, range: ColorRange) -> Self {
+        let (kr, kb) = matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (luma_scale, chroma_scale, y_offset) = range.scales();
+        let fixed = |value: f64| (value * ONE).round() as i32;
+        Self {
+            y_offset,
+            y_scale: fixed(1.0 / luma_scale),
+            r_v: fixed(2.0 * (1.0 - kr) / chroma_scale),
+            g_u: fixed(2.0 * kb * (1.0 - kb) / kg / chroma_scale),
+            g_v: fixed(2.0 * kr * (1.0 - kr) / kg / chroma_scale),
+            b_u: fixed(2.0 * (1.0 - kb) / chroma_scale),
+            y_r: fixed(kr * luma_scale),
+            y_g: fixed(kg * luma_scale),
+            y_b: fixed(kb * luma_scale),
+            u_r: fixed(-kr / (2.0 * (1.0 - kb)) * chroma_scale),
+            u_g: fixed(-kg / (2.0 * (1.0 - kb)) * chroma_scale),
+            u_b: fixed(0.5 * chroma_scale),
+            v_r: fixed(0.5 * chroma_scale),
+            v_g: fixed(-kg / (2.0 * (1.0 - kr)) * chroma_scale),
+            v_b: fixed(-kb / (2.0 * (1.0 - kr)) * chroma_scale),
+        }
+    }
+}
+
+#[inline]
+fn clamp_u8(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+/// Portable implementations of the row kernels, used for the row tails and
+/// as the reference for the SIMD versions.
+pub mod scalar {
+    pub fn swap_red_blue(src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            dst[0] = src[2];
+            dst[1] = src[1];
+            dst[2] = src[0];
+            dst[3] = src[3];
+        }
+    }
+
+    pub fn drop_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(3)) {
+            if swap_red_blue {
+                dst[0] = src[2];
+                dst[1] = src[1];
+                dst[2] = src[0];
+            } else {
+                dst.copy_from_slice(&src[..3]);
+            }
+        }
+    }
+
+    pub fn add_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) {
+        for (src, dst) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+            if swap_red_blue {
+                dst[0] = src[2];
+                dst[1] = src[1];
+                dst[2] = src[0];
+            } else {
+                dst[..3].copy_from_slice(src);
+            }
+            dst[3] = 255;
+        }
+    }
+
+    pub fn deinterleave_uv(src: &[u8], u: &mut [u8], v: &mut [u8]) {
+        for ((src, u), v) in src.chunks_exact(2).zip(u.iter_mut()).zip(v.iter_mut()) {
+            *u = src[0];
+            *v = src[1];
+        }
+    }
+
+    pub fn interleave_uv(u: &[u8], v: &[u8], dst: &mut [u8]) {
+        for ((u, v), dst) in u.iter().zip(v).zip(dst.chunks_exact_mut(2)) {
+            dst[0] = *u;
+            dst[1] = *v;
+        }
+    }
+}
+
+mod simd {
+    #[cfg(target_arch = "aarch64")]
+    use std::arch::aarch64::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    // Each kernel converts the longest prefix it can handle and returns the
+    // number of pixels converted; the caller finishes the rest with the
+    // scalar kernels.
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn swap_red_blue(src: &[u8], dst: &mut [u8]) -> usize {
+        if is_x86_feature_detected!("ssse3") {
+            unsafe { swap_red_blue_ssse3(src, dst) }
+        } else {
+            0
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "ssse3")]
+    unsafe fn swap_red_blue_ssse3(src: &[u8], dst: &mut [u8]) -> usize {
+        let mask = _mm_setr_epi8(2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15);
+        let pixels = src.len().min(dst.len()) / 16 * 4;
+        for i in (0..pixels * 4).step_by(16) {
+            let value = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, _mm_shuffle_epi8(value, mask));
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn drop_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) -> usize {
+        if is_x86_feature_detected!("ssse3") {
+            unsafe { drop_alpha_ssse3(src, dst, swap_red_blue) }
+        } else {
+            0
+        }
+    }
+
+    // Every store writes 16 bytes of which only 12 are kept, so stop while
+    // the destination still has room for the overhang.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "ssse3")]
+    unsafe fn drop_alpha_ssse3(src: &[u8], dst: &mut [u8], swap_red_blue: bool) -> usize {
+        let mask = if swap_red_blue {
+            _mm_setr_epi8(2, 1, 0, 6, 5, 4, 10, 9, 8, 14, 13, 12, -1, -1, -1, -1)
+        } else {
+            _mm_setr_epi8(0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, -1, -1, -1, -1)
+        };
+        let mut pixels = 0;
+        while (pixels + 4) * 4 <= src.len() && pixels * 3 + 16 <= dst.len() {
+            let value = _mm_loadu_si128(src.as_ptr().add(pixels * 4) as *const __m128i);
+            _mm_storeu_si128(dst.as_mut_ptr().add(pixels * 3) as *mut __m128i, _mm_shuffle_epi8(value, mask));
+            pixels += 4;
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn deinterleave_uv(src: &[u8], u: &mut [u8], v: &mut [u8]) -> usize {
+        let pixels = (src.len() / 2).min(u.len()).min(v.len()) / 16 * 16;
+        unsafe {
+            let mask = _mm_set1_epi16(0x00ff);
+            for i in (0..pixels).step_by(16) {
+                let low = _mm_loadu_si128(src.as_ptr().add(i * 2) as *const __m128i);
+                let high = _mm_loadu_si128(src.as_ptr().add(i * 2 + 16) as *const __m128i);
+                let u_value = _mm_packus_epi16(_mm_and_si128(low, mask), _mm_and_si128(high, mask));
+                let v_value = _mm_packus_epi16(_mm_srli_epi16(low, 8), _mm_srli_epi16(high, 8));
+                _mm_storeu_si128(u.as_mut_ptr().add(i) as *mut __m128i, u_value);
+                _mm_storeu_si128(v.as_mut_ptr().add(i) as *mut __m128i, v_value);
+            }
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn interleave_uv(u: &[u8], v: &[u8], dst: &mut [u8]) -> usize {
+        let pixels = u.len().min(v.len()).min(dst.len() / 2) / 16 * 16;
+        unsafe {
+            for i in (0..pixels).step_by(16) {
+                let u_value = _mm_loadu_si128(u.as_ptr().add(i) as *const __m128i);
+                let v_value = _mm_loadu_si128(v.as_ptr().add(i) as *const __m128i);
+                _mm_storeu_si128(dst.as_mut_ptr().add(i * 2) as *mut __m128i, _mm_unpacklo_epi8(u_value, v_value));
+                _mm_storeu_si128(dst.as_mut_ptr().add(i * 2 + 16) as *mut __m128i, _mm_unpackhi_epi8(u_value, v_value));
+            }
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn swap_red_blue(src: &[u8], dst: &mut [u8]) -> usize {
+        let pixels = src.len().min(dst.len()) / 64 * 16;
+        unsafe {
+            for i in (0..pixels * 4).step_by(64) {
+                let value = vld4q_u8(src.as_ptr().add(i));
+                vst4q_u8(dst.as_mut_ptr().add(i), uint8x16x4_t(value.2, value.1, value.0, value.3));
+            }
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn drop_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) -> usize {
+        let pixels = (src.len() / 4).min(dst.len() / 3) / 16 * 16;
+        unsafe {
+            for i in (0..pixels).step_by(16) {
+                let value = vld4q_u8(src.as_ptr().add(i * 4));
+                let rgb = if swap_red_blue {
+                    uint8x16x3_t(value.2, value.1, value.0)
+                } else {
+                    uint8x16x3_t(value.0, value.1, value.2)
+                };
+                vst3q_u8(dst.as_mut_ptr().add(i * 3), rgb);
+            }
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn deinterleave_uv(src: &[u8], u: &mut [u8], v: &mut [u8]) -> usize {
+        let pixels = (src.len() / 2).min(u.len()).min(v.len()) / 16 * 16;
+        unsafe {
+            for i in (0..pixels).step_by(16) {
+                let value = vld2q_u8(src.as_ptr().add(i * 2));
+                vst1q_u8(u.as_mut_ptr().add(i), value.0);
+                vst1q_u8(v.as_mut_ptr().add(i), value.1);
+            }
+        }
+        pixels
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn interleave_uv(u: &[u8], v: &[u8], dst: &mut [u8]) -> usize {
+        let pixels = u.len().min(v.len()).min(dst.len() / 2) / 16 * 16;
+        unsafe {
+            for i in (0..pixels).step_by(16) {
+                vst2q_u8(
+                    dst.as_mut_ptr().add(i * 2),
+                    uint8x16x2_t(vld1q_u8(u.as_ptr().add(i)), vld1q_u8(v.as_ptr().add(i))),
+                );
+            }
+        }
+        pixels
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn swap_red_blue(_src: &[u8], _dst: &mut [u8]) -> usize {
+        0
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn drop_alpha(_src: &[u8], _dst: &mut [u8], _swap_red_blue: bool) -> usize {
+        0
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn deinterleave_uv(_src: &[u8], _u: &mut [u8], _v: &mut [u8]) -> usize {
+        0
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn interleave_uv(_u: &[u8], _v: &[u8], _dst: &mut [u8]) -> usize {
+        0
+    }
+}
+
+/// Converts BGRA pixels to RGBA or back.
+pub fn swap_red_blue(src: &[u8], dst: &mut [u8]) {
+    let done = simd::swap_red_blue(src, dst) * 4;
+    scalar::swap_red_blue(&src[done..], &mut dst[done..]);
+}
+
+/// Converts 4 byte pixels to 3 byte pixels, optionally swapping red and blue
+/// so BGRA becomes RGB.
+pub fn drop_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) {
+    let done = simd::drop_alpha(src, dst, swap_red_blue);
+    scalar::drop_alpha(&src[done * 4..], &mut dst[done * 3..], swap_red_blue);
+}
+
+/// Converts 3 byte pixels to opaque 4 byte pixels, optionally swapping red
+/// and blue so RGB becomes BGRA.
+pub fn add_alpha(src: &[u8], dst: &mut [u8], swap_red_blue: bool) {
+    scalar::add_alpha(src, dst, swap_red_blue);
+}
+
+/// Splits an NV12 chroma row into separate U and V rows.
+pub fn deinterleave_uv(src: &[u8], u: &mut [u8], v: &mut [u8]) {
+    let done = simd::deinterleave_uv(src, u, v);
+    scalar::deinterleave_uv(&src[done * 2..], &mut u[done..], &mut v[done..]);
+}
+
+/// Merges separate U and V rows into an NV12 chroma row.
+pub fn interleave_uv(u: &[u8], v: &[u8], dst: &mut [u8]) {
+    let done = simd::interleave_uv(u, v, dst);
+    scalar::interleave_uv(&u[done..], &v[done..], &mut dst[done * 2..]);
+}
+
+fn unpack_l10r_pixel(pixel: &[u8]) -> [u16; 4] {
+    let value = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+    [
+        ((value >> 20) & 0x3ff) as u16,
+        ((value >> 10) & 0x3ff) as u16,
+        (value & 0x3ff) as u16,
+        ((value >> 30) * 341) as u16,
+    ]
+}
+
+/// Unpacks `l10r` pixels to 10 bit RGBA values, alpha scaled to the same
+/// 0-1023 range.
+pub fn unpack_l10r(src: &[u8], dst: &mut [u16]) {
+    for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        dst.copy_from_slice(&unpack_l10r_pixel(src));
+    }
+}
+
+/// Unpacks `l10r` pixels to 8 bit RGBA.
+pub fn l10r_to_rgba(src: &[u8], dst: &mut [u8]) {
+    for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        let pixel = unpack_l10r_pixel(src);
+        for (dst, value) in dst.iter_mut().zip(pixel.iter()) {
+            *dst = (value >> 2) as u8;
+        }
+    }
+}
+
+/// Packs 8 bit RGBA pixels as `l10r`.
+pub fn rgba_to_l10r(src: &[u8], dst: &mut [u8]) {
+    let widen = |value: u8| ((value as u32) << 2) | ((value as u32) >> 6);
+    for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        let value = ((src[3] as u32 >> 6) << 30) | (widen(src[0]) << 20) | (widen(src[1]) << 10) | widen(src[2]);
+        dst.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Converts one row of 4:2:0 samples to RGBA. `u` and `v` hold the chroma
+/// row shared by this luma row.
+fn yuv_row_to_rgba(y: &[u8], u: &[u8], v: &[u8], coefficients: &Coefficients, dst: &mut [u8]) {
+    for (x, (luma, dst)) in y.iter().zip(dst.chunks_exact_mut(4)).enumerate() {
+        let luma = (*luma as i32 - coefficients.y_offset) * coefficients.y_scale + HALF;
+        let cb = u[x / 2] as i32 - 128;
+        let cr = v[x / 2] as i32 - 128;
+        dst[0] = clamp_u8((luma + coefficients.r_v * cr) >> SHIFT);
+        dst[1] = clamp_u8((luma - coefficients.g_u * cb - coefficients.g_v * cr) >> SHIFT);
+        dst[2] = clamp_u8((luma + coefficients.b_u * cb) >> SHIFT);
+        dst[3] = 255;
+    }
+}
+
+fn rgba_to_luma(rgba: &[u8], coefficients: &Coefficients, dst: &mut [u8]) {
+    let offset = (coefficients.y_offset << SHIFT) + HALF;
+    for (src, dst) in rgba.chunks_exact(4).zip(dst.iter_mut()) {
+        let value = coefficients.y_r * src[0] as i32 + coefficients.y_g * src[1] as i32 + coefficients.y_b * src[2] as i32;
+        *dst = clamp_u8((value + offset) >> SHIFT);
+    }
+}
+
+/// Computes one chroma row from the two RGBA rows it covers, averaging each
+/// 2x2 block. Odd widths repeat the last column.
+fn rgba_to_chroma(top: &[u8], bottom: &[u8], width: usize, coefficients: &Coefficients, u: &mut [u8], v: &mut [u8]) {
+    let offset = (128 << (SHIFT + 2)) + (1 << (SHIFT + 1));
+    for (x, (u, v)) in u.iter_mut().zip(v.iter_mut()).enumerate() {
+        let left = 2 * x * 4;
+        let right = (2 * x + 1).min(width - 1) * 4;
+        let sum = |channel: usize| {
+            top[left + channel] as i32 + top[right + channel] as i32 + bottom[left + channel] as i32 + bottom[right + channel] as i32
+        };
+        let (r, g, b) = (sum(0), sum(1), sum(2));
+        *u = clamp_u8((coefficients.u_r * r + coefficients.u_g * g + coefficients.u_b * b + offset) >> (SHIFT + 2));
+        *v = clamp_u8((coefficients.v_r * r + coefficients.v_g * g + coefficients.v_b * b + offset) >> (SHIFT + 2));
+    }
+}
+
+/// Reads a row of a packed RGB family format as RGBA.
+fn read_rgba_row(pixel_format: PixelFormat, src: &[u8], dst: &mut [u8]) {
+    match pixel_format {
+        PixelFormat::BGRA => swap_red_blue(src, dst),
+        PixelFormat::RGBA => dst.copy_from_slice(src),
+        PixelFormat::RGB24 => add_alpha(src, dst, false),
+        PixelFormat::ARGB2101010 => l10r_to_rgba(src, dst),
+        _ => unreachable!(),
+    }
+}
+
+fn write_rgba_row(pixel_format: PixelFormat, src: &[u8], dst: &mut [u8]) {
+    match pixel_format {
+        PixelFormat::BGRA => swap_red_blue(src, dst),
+        PixelFormat::RGBA => dst.copy_from_slice(src),
+        PixelFormat::RGB24 => drop_alpha(src, dst, false),
+        PixelFormat::ARGB2101010 => rgba_to_l10r(src, dst),
+        _ => unreachable!(),
+    }
+}
+
+/// Reads the U and V rows of a YUV frame's chroma row `row`.
+fn read_chroma_row(frame: &VideoFrame, row: usize, u: &mut [u8], v: &mut [u8]) {
+    let planes = frame.planes();
+    if planes.len() == 2 {
+        deinterleave_uv(planes[1].row(row), u, v);
+    } else {
+        u.copy_from_slice(planes[1].row(row));
+        v.copy_from_slice(planes[2].row(row));
+    }
+}
+
+fn write_chroma_row(frame: &mut VideoFrame, row: usize, u: &[u8], v: &[u8]) {
+    let mut planes = frame.planes_mut().skip(1);
+    let mut first = planes.next().unwrap();
+    match planes.next() {
+        None => interleave_uv(u, v, first.row_mut(row)),
+        Some(mut second) => {
+            first.row_mut(row).copy_from_slice(u);
+            second.row_mut(row).copy_from_slice(v);
+        }
+    }
+}
+
+fn range_table(from: ColorRange, to: ColorRange, chroma: bool) -> [u8; 256] {
+    let (from_luma, from_chroma, from_offset) = from.scales();
+    let (to_luma, to_chroma, to_offset) = to.scales();
+    let mut table = [0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let value = value as f64;
+        *entry = if chroma {
+            ((value - 128.0) / from_chroma * to_chroma + 128.0).round().clamp(0.0, 255.0) as u8
+        } else {
+            ((value - from_offset as f64) / from_luma * to_luma + to_offset as f64)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+    }
+    table
+}
+
+fn remap(row: &mut [u8], table: &[u8; 256]) {
+    for value in row {
+        *value = table[*value as usize];
+    }
+}
+
+fn yuv_to_yuv(src: &VideoFrame, dst: &mut VideoFrame) {
+    let from = ColorRange::of(src.pixel_format());
+    let to = ColorRange::of(dst.pixel_format());
+    let luma_table = (from != to).then(|| range_table(from, to, false));
+    let chroma_table = (from != to).then(|| range_table(from, to, true));
+    for y in 0..src.height() {
+        let row = dst.plane_mut(0).unwrap().into_row_mut(y);
+        row.copy_from_slice(src.planes()[0].row(y));
+        if let Some(table) = &luma_table {
+            remap(row, table);
+        }
+    }
+    let chroma = src.planes()[1].layout();
+    let mut u = vec![0; chroma.width];
+    let mut v = vec![0; chroma.width];
+    for y in 0..chroma.height {
+        read_chroma_row(src, y, &mut u, &mut v);
+        if let Some(table) = &chroma_table {
+            remap(&mut u, table);
+            remap(&mut v, table);
+        }
+        write_chroma_row(dst, y, &u, &v);
+    }
+}
+
+fn yuv_to_rgb(src: &VideoFrame, dst: &mut VideoFrame, matrix: ColorMatrix) {
+    let coefficients = Coefficients::new(matrix, ColorRange::of(src.pixel_format()));
+    let chroma_width = src.planes()[1].width();
+    let mut u = vec![0; chroma_width];
+    let mut v = vec![0; chroma_width];
+    let mut rgba = vec![0; src.width() * 4];
+    let format = dst.pixel_format();
+    for y in 0..src.height() {
+        if y % 2 == 0 {
+            read_chroma_row(src, y / 2, &mut u, &mut v);
+        }
+        yuv_row_to_rgba(src.planes()[0].row(y), &u, &v, &coefficients, &mut rgba);
+        write_rgba_row(format, &rgba, dst.plane_mut(0).unwrap().into_row_mut(y));
+    }
+}
+
+fn rgb_to_yuv(src: &VideoFrame, dst: &mut VideoFrame, matrix: ColorMatrix) {
+    let coefficients = Coefficients::new(matrix, ColorRange::of(dst.pixel_format()));
+    let width = src.width();
+    let height = src.height();
+    let format = src.pixel_format();
+    let chroma_width = dst.planes()[1].width();
+    let mut u = vec![0; chroma_width];
+    let mut v = vec![0; chroma_width];
+    let mut top = vec![0; width * 4];
+    let mut bottom = vec![0; width * 4];
+    for row in 0..dst.planes()[1].height() {
+        let y0 = row * 2;
+        let y1 = (y0 + 1).min(height - 1);
+        read_rgba_row(format, src.planes()[0].row(y0), &mut top);
+        read_rgba_row(format, src.planes()[0].row(y1), &mut bottom);
+        rgba_to_luma(&top, &coefficients, dst.plane_mut(0).unwrap().into_row_mut(y0));
+        if y1 != y0 {
+            rgba_to_luma(&bottom, &coefficients, dst.plane_mut(0).unwrap().into_row_mut(y1));
+        }
+        rgba_to_chroma(&top, &bottom, width, &coefficients, &mut u, &mut v);
+        write_chroma_row(dst, row, &u, &v);
+    }
+}
+
+fn rgb_to_rgb(src: &VideoFrame, dst: &mut VideoFrame) {
+    let from = src.pixel_format();
+    let to = dst.pixel_format();
+    let mut rgba = vec![0; src.width() * 4];
+    for y in 0..src.height() {
+        let src_row = src.planes()[0].row(y);
+        let dst_row = dst.plane_mut(0).unwrap().into_row_mut(y);
+        match (from, to) {
+            (PixelFormat::BGRA, PixelFormat::RGBA) | (PixelFormat::RGBA, PixelFormat::BGRA) => swap_red_blue(src_row, dst_row),
+            (PixelFormat::BGRA, PixelFormat::RGB24) => drop_alpha(src_row, dst_row, true),
+            (PixelFormat::RGBA, PixelFormat::RGB24) => drop_alpha(src_row, dst_row, false),
+            (PixelFormat::RGB24, PixelFormat::BGRA) => add_alpha(src_row, dst_row, true),
+            _ => {
+                read_rgba_row(from, src_row, &mut rgba);
+                write_rgba_row(to, &rgba, dst_row);
+            }
+        }
+    }
+}
+
+/// Converts a frame to another pixel format. `matrix` selects the Y'CbCr
+/// matrix whenever the conversion crosses between RGB and YUV; the range is
+/// taken from the YUV pixel format.
+pub fn convert(src: &VideoFrame, pixel_format: PixelFormat, matrix: ColorMatrix) -> Result<VideoFrame, FrameError> {
+    if !src.pixel_format().is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(src.pixel_format()));
+    }
+    if src.pixel_format() == pixel_format {
+        return Ok(src.clone());
+    }
+    let mut dst = VideoFrame::zeroed(src.width(), src.height(), pixel_format)?;
+    match (src.pixel_format().is_yuv(), pixel_format.is_yuv()) {
+        (true, true) => yuv_to_yuv(src, &mut dst),
+        (true, false) => yuv_to_rgb(src, &mut dst, matrix),
+        (false, true) => rgb_to_yuv(src, &mut dst, matrix),
+        (false, false) => rgb_to_rgb(src, &mut dst),
+    }
+    dst.copy_metadata_from(src);
+    Ok(dst)
+}
+
+impl VideoFrame {
+    pub fn convert(&self, pixel_format: PixelFormat, matrix: ColorMatrix) -> Result<VideoFrame, FrameError> {
+        convert(self, pixel_format, matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [usize; 5] = [0, 1, 15, 17, 33];
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(37).wrapping_add(seed)).collect()
+    }
+
+    #[test]
+    fn swap_red_blue_matches_scalar() {
+        for &pixels in &LENGTHS {
+            let src = pattern(pixels * 4, 1);
+            let mut expected = vec![0; pixels * 4];
+            scalar::swap_red_blue(&src, &mut expected);
+            let mut prefix = vec![0; pixels * 4];
+            let done = simd::swap_red_blue(&src, &mut prefix);
+            assert!(done <= pixels);
+            assert_eq!(prefix[..done * 4], expected[..done * 4], "{} pixels", pixels);
+            let mut dst = vec![0; pixels * 4];
+            swap_red_blue(&src, &mut dst);
+            assert_eq!(dst, expected, "{} pixels", pixels);
+        }
+    }
+
+    #[test]
+    fn drop_alpha_matches_scalar() {
+        for &swap in &[false, true] {
+            for &pixels in &LENGTHS {
+                let src = pattern(pixels * 4, 2);
+                let mut expected = vec![0; pixels * 3];
+                scalar::drop_alpha(&src, &mut expected, swap);
+                let mut prefix = vec![0; pixels * 3];
+                let done = simd::drop_alpha(&src, &mut prefix, swap);
+                assert!(done <= pixels);
+                assert_eq!(prefix[..done * 3], expected[..done * 3], "{} pixels, swap {}", pixels, swap);
+                let mut dst = vec![0; pixels * 3];
+                drop_alpha(&src, &mut dst, swap);
+                assert_eq!(dst, expected, "{} pixels, swap {}", pixels, swap);
+            }
+        }
+    }
+
+    #[test]
+    fn add_alpha_inverts_drop_alpha() {
+        for &swap in &[false, true] {
+            for &pixels in &LENGTHS {
+                let mut src = pattern(pixels * 4, 3);
+                src.iter_mut().skip(3).step_by(4).for_each(|alpha| *alpha = 255);
+                let mut rgb = vec![0; pixels * 3];
+                drop_alpha(&src, &mut rgb, swap);
+                let mut dst = vec![0; pixels * 4];
+                add_alpha(&rgb, &mut dst, swap);
+                assert_eq!(dst, src, "{} pixels, swap {}", pixels, swap);
+            }
+        }
+    }
+
+    #[test]
+    fn deinterleave_uv_matches_scalar() {
+        for &pixels in &LENGTHS {
+            let src = pattern(pixels * 2, 4);
+            let (mut expected_u, mut expected_v) = (vec![0; pixels], vec![0; pixels]);
+            scalar::deinterleave_uv(&src, &mut expected_u, &mut expected_v);
+            let (mut u, mut v) = (vec![0; pixels], vec![0; pixels]);
+            let done = simd::deinterleave_uv(&src, &mut u, &mut v);
+            assert!(done <= pixels);
+            assert_eq!(u[..done], expected_u[..done], "{} pixels", pixels);
+            assert_eq!(v[..done], expected_v[..done], "{} pixels", pixels);
+            let (mut u, mut v) = (vec![0; pixels], vec![0; pixels]);
+            deinterleave_uv(&src, &mut u, &mut v);
+            assert_eq!((u, v), (expected_u, expected_v), "{} pixels", pixels);
+        }
+    }
+
+    #[test]
+    fn interleave_uv_matches_scalar() {
+        for &pixels in &LENGTHS {
+            let (u, v) = (pattern(pixels, 5), pattern(pixels, 6));
+            let mut expected = vec![0; pixels * 2];
+            scalar::interleave_uv(&u, &v, &mut expected);
+            let mut prefix = vec![0; pixels * 2];
+            let done = simd::interleave_uv(&u, &v, &mut prefix);
+            assert!(done <= pixels);
+            assert_eq!(prefix[..done * 2], expected[..done * 2], "{} pixels", pixels);
+            let mut dst = vec![0; pixels * 2];
+            interleave_uv(&u, &v, &mut dst);
+            assert_eq!(dst, expected, "{} pixels", pixels);
+        }
+    }
+
+    const MATRICES: [ColorMatrix; 4] = [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020, ColorMatrix::Smpte240M];
+    const YUV_FORMATS: [PixelFormat; 4] = [
+        PixelFormat::NV12VideoRange,
+        PixelFormat::NV12FullRange,
+        PixelFormat::I420VideoRange,
+        PixelFormat::I420FullRange,
+    ];
+
+    /// An RGBA frame of 2x2 blocks, so chroma subsampling loses nothing.
+    fn blocks(width: usize, height: usize) -> VideoFrame {
+        let mut data = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let block = (y / 2 * width / 2 + x / 2) as u32;
+                let [r, g, b, _] = (block.wrapping_mul(2_654_435_761)).to_le_bytes();
+                data.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        VideoFrame::from_bytes(width, height, PixelFormat::RGBA, &data).unwrap()
+    }
+
+    fn max_difference(a: &VideoFrame, b: &VideoFrame) -> u8 {
+        let (a, b) = (a.planes()[0].data(), b.planes()[0].data());
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn rgb_yuv_round_trip() {
+        // Quantizing to 8-bit Y'CbCr and back is off by at most one code per
+        // channel in full range, and two in video range with fewer levels.
+        let rgba = blocks(128, 128);
+        for &matrix in &MATRICES {
+            for &format in &YUV_FORMATS {
+                let tolerance = match ColorRange::of(format) {
+                    ColorRange::Full => 1,
+                    ColorRange::Video => 2,
+                };
+                let yuv = rgba.convert(format, matrix).unwrap();
+                let back = yuv.convert(PixelFormat::RGBA, matrix).unwrap();
+                let difference = max_difference(&rgba, &back);
+                assert!(difference <= tolerance, "{:?} {}: {}", matrix, format, difference);
+            }
+        }
+    }
+
+    #[test]
+    fn yuv_levels() {
+        for &matrix in &MATRICES {
+            for &format in &YUV_FORMATS {
+                let (black, white) = match ColorRange::of(format) {
+                    ColorRange::Full => (0, 255),
+                    ColorRange::Video => (16, 235),
+                };
+                for &(value, luma) in &[(0, black), (255, white)] {
+                    let rgba = VideoFrame::from_bytes(2, 2, PixelFormat::RGBA, &[value, value, value, 255].repeat(4)).unwrap();
+                    let yuv = rgba.convert(format, matrix).unwrap();
+                    assert!(yuv.planes()[0].data().iter().all(|y| *y == luma), "{:?} {}", matrix, format);
+                    let chroma = yuv.planes()[1..].iter().flat_map(|plane| plane.data());
+                    assert!(chroma.clone().all(|c| *c == 128), "{:?} {}", matrix, format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn yuv_rgb_yuv_round_trip() {
+        // Grey levels are inside the RGB gamut for every matrix, so their
+        // luma survives a round trip through RGB within one code.
+        for &matrix in &MATRICES {
+            for &format in &YUV_FORMATS {
+                let luma: Vec<u8> = match ColorRange::of(format) {
+                    ColorRange::Full => (0..=255).collect(),
+                    ColorRange::Video => (16..=235).collect(),
+                };
+                let width = luma.len();
+                let mut planes: Vec<Vec<u8>> = vec![luma.repeat(2)];
+                let layouts = format.plane_layouts(width, 2).unwrap();
+                planes.extend(layouts[1..].iter().map(|layout| vec![128; layout.row_bytes() * layout.height]));
+                let data: Vec<u8> = planes.concat();
+                let yuv = VideoFrame::from_bytes(width, 2, format, &data).unwrap();
+                let back = yuv.convert(PixelFormat::BGRA, matrix).unwrap().convert(format, matrix).unwrap();
+                assert!(max_difference(&yuv, &back) <= 1, "{:?} {}", matrix, format);
+            }
+        }
+    }
+}
@@ -0,0 +1,754 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                0xedb88320 ^ (value >> 1)
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+}
+
+/// Feeds `data` into a running CRC-32. Start from `!0` and invert the
+/// result to get the checksum.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter()
+        .fold(crc, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the longest run that can't overflow before reducing.
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+/// Compresses `data` into a zlib stream.
+pub(crate) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x9c];
+    output.extend(deflate(data));
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+const WINDOW_SIZE: usize = 1 << 15;
+const HASH_BITS: u32 = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const BLOCK_TOKENS: usize = 1 << 16;
+const NONE: usize = usize::MAX;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+impl Token {
+    /// The literal/length symbol and distance symbol of the token.
+    fn symbols(&self) -> (usize, Option<usize>) {
+        match *self {
+            Token::Literal(byte) => (byte as usize, None),
+            Token::Match { length, distance } => (
+                257 + LENGTH_BASE.iter().rposition(|base| *base <= length).unwrap(),
+                Some(DISTANCE_BASE.iter().rposition(|base| *base <= distance).unwrap()),
+            ),
+        }
+    }
+}
+
+fn hash(data: &[u8], index: usize) -> usize {
+    let value = (data[index] as u32) << 16 | (data[index + 1] as u32) << 8 | data[index + 2] as u32;
+    (value.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Greedy LZ77 over a 32 KiB window using hash chains of three byte
+/// prefixes.
+fn find_matches(data: &[u8]) -> Vec<Token> {
+    let mut head = vec![NONE; 1 << HASH_BITS];
+    let mut previous = vec![NONE; WINDOW_SIZE];
+    let insert = |index: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>| {
+        if index + MIN_MATCH <= data.len() {
+            let key = hash(data, index);
+            previous[index % WINDOW_SIZE] = head[key];
+            head[key] = index;
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if index + MIN_MATCH <= data.len() {
+            let limit = (data.len() - index).min(MAX_MATCH);
+            let mut candidate = head[hash(data, index)];
+            let mut chain = 0;
+            while candidate != NONE && index - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..candidate + limit]
+                    .iter()
+                    .zip(&data[index..index + limit])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = index - candidate;
+                    if length == limit {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW_SIZE];
+                // Older slots get overwritten as the window slides.
+                if next == NONE || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+        if best_length >= MIN_MATCH {
+            tokens.push(Token::Match {
+                length: best_length as u16,
+                distance: best_distance as u16,
+            });
+            for offset in 0..best_length {
+                insert(index + offset, &mut head, &mut previous);
+            }
+            index += best_length;
+        } else {
+            tokens.push(Token::Literal(data[index]));
+            insert(index, &mut head, &mut previous);
+            index += 1;
+        }
+    }
+    tokens
+}
+
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which deflate stores most significant bit first.
+    fn write_code(&mut self, code: u16, length: u8) {
+        self.write((code.reverse_bits() >> (16 - length as u32)) as u32, length as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.bits as u8);
+        }
+        self.output
+    }
+}
+
+/// Builds Huffman code lengths no longer than `limit`. Frequencies are
+/// flattened until the tree fits, which costs little in practice. At least
+/// two symbols always get a code, as some inflaters reject single code trees.
+fn code_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    let used = frequencies.iter().filter(|frequency| **frequency > 0).count();
+    for frequency in frequencies
+        .iter_mut()
+        .filter(|frequency| **frequency == 0)
+        .take(2usize.saturating_sub(used))
+    {
+        *frequency = 1;
+    }
+    loop {
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+        let mut parents = Vec::new();
+        for (symbol, frequency) in frequencies.iter().enumerate() {
+            if *frequency > 0 {
+                heap.push(Reverse((*frequency as u64, symbol)));
+            }
+        }
+        // Nodes past the symbols are internal nodes, indexed from `frequencies.len()`.
+        let mut parent_of = vec![NONE; frequencies.len()];
+        while heap.len() > 1 {
+            let Reverse((weight_a, a)) = heap.pop().unwrap();
+            let Reverse((weight_b, b)) = heap.pop().unwrap();
+            let node = frequencies.len() + parents.len();
+            parents.push(NONE);
+            for child in [a, b] {
+                if child < frequencies.len() {
+                    parent_of[child] = node;
+                } else {
+                    parents[child - frequencies.len()] = node;
+                }
+            }
+            heap.push(Reverse((weight_a + weight_b, node)));
+        }
+        let depth = |mut node: usize| {
+            let mut depth = 0;
+            while node != NONE {
+                node = if node < frequencies.len() {
+                    parent_of[node]
+                } else {
+                    parents[node - frequencies.len()]
+                };
+                depth += 1;
+            }
+            depth - 1
+        };
+        let lengths: Vec<u8> = (0..frequencies.len())
+            .map(|symbol| {
+                if frequencies[symbol] > 0 {
+                    depth(symbol) as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        if lengths.iter().all(|length| *length <= limit) {
+            return lengths;
+        }
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency -= *frequency / 2;
+        }
+    }
+}
+
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u16; 16];
+    for length in lengths {
+        counts[*length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next = [0u16; 16];
+    let mut code = 0;
+    for bits in 1..16 {
+        code = (code + counts[bits - 1]) << 1;
+        next[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|length| {
+            if *length == 0 {
+                0
+            } else {
+                let code = next[*length as usize];
+                next[*length as usize] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let literals = (0..288)
+        .map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        })
+        .collect();
+    (literals, vec![5; 30])
+}
+
+/// Run length encodes code lengths with the code length alphabet, returning
+/// `(symbol, extra bits value)` pairs.
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        let length = lengths[index];
+        let run = lengths[index..].iter().take_while(|value| **value == length).count();
+        if length == 0 && run >= 3 {
+            let run = run.min(138);
+            symbols.push(if run <= 10 {
+                (17, run as u8 - 3)
+            } else {
+                (18, run as u8 - 11)
+            });
+            index += run;
+        } else if length != 0 && run >= 4 {
+            symbols.push((length, 0));
+            let run = (run - 1).min(6);
+            symbols.push((16, run as u8 - 3));
+            index += run + 1;
+        } else {
+            symbols.push((length, 0));
+            index += 1;
+        }
+    }
+    symbols
+}
+
+fn extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+fn block_cost(tokens: &[Token], literal_lengths: &[u8], distance_lengths: &[u8]) -> u64 {
+    let mut cost = literal_lengths[256] as u64;
+    for token in tokens {
+        let (symbol, distance) = token.symbols();
+        cost += literal_lengths[symbol] as u64;
+        if let Some(distance) = distance {
+            cost += LENGTH_EXTRA[symbol - 257] as u64 + distance_lengths[distance] as u64 + DISTANCE_EXTRA[distance] as u64;
+        }
+    }
+    cost
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], literal_lengths: &[u8], distance_lengths: &[u8]) {
+    let literal_codes = canonical_codes(literal_lengths);
+    let distance_codes = canonical_codes(distance_lengths);
+    for token in tokens {
+        let (symbol, distance) = token.symbols();
+        writer.write_code(literal_codes[symbol], literal_lengths[symbol]);
+        if let (Token::Match { length, distance: offset }, Some(distance)) = (token, distance) {
+            let length_code = symbol - 257;
+            writer.write((length - LENGTH_BASE[length_code]) as u32, LENGTH_EXTRA[length_code] as u32);
+            writer.write_code(distance_codes[distance], distance_lengths[distance]);
+            writer.write((offset - DISTANCE_BASE[distance]) as u32, DISTANCE_EXTRA[distance] as u32);
+        }
+    }
+    writer.write_code(literal_codes[256], literal_lengths[256]);
+}
+
+/// Writes one block with whichever of the fixed or a custom Huffman code is
+/// smaller.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut literal_frequencies = vec![0u32; 286];
+    let mut distance_frequencies = vec![0u32; 30];
+    literal_frequencies[256] = 1;
+    for token in tokens {
+        let (symbol, distance) = token.symbols();
+        literal_frequencies[symbol] += 1;
+        if let Some(distance) = distance {
+            distance_frequencies[distance] += 1;
+        }
+    }
+    let literal_lengths = code_lengths(&literal_frequencies, 15);
+    let distance_lengths = code_lengths(&distance_frequencies, 15);
+    let literal_count = 257.max(literal_lengths.iter().rposition(|length| *length > 0).map_or(0, |index| index + 1));
+    let distance_count = 1.max(distance_lengths.iter().rposition(|length| *length > 0).map_or(0, |index| index + 1));
+    let mut all_lengths = literal_lengths[..literal_count].to_vec();
+    all_lengths.extend_from_slice(&distance_lengths[..distance_count]);
+    let code_length_symbols = encode_code_lengths(&all_lengths);
+    let mut code_length_frequencies = vec![0u32; 19];
+    for (symbol, _) in &code_length_symbols {
+        code_length_frequencies[*symbol as usize] += 1;
+    }
+    let code_length_lengths = code_lengths(&code_length_frequencies, 7);
+    let code_length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|symbol| code_length_lengths[*symbol] > 0)
+            .map_or(0, |index| index + 1),
+    );
+
+    let header_cost = 14
+        + 3 * code_length_count as u64
+        + code_length_symbols
+            .iter()
+            .map(|(symbol, _)| code_length_lengths[*symbol as usize] as u64 + extra_bits(*symbol) as u64)
+            .sum::<u64>();
+    let dynamic_cost = header_cost + block_cost(tokens, &literal_lengths, &distance_lengths);
+    let (fixed_literal_lengths, fixed_distance_lengths) = fixed_lengths();
+    let fixed_cost = block_cost(tokens, &fixed_literal_lengths, &fixed_distance_lengths);
+
+    writer.write(last as u32, 1);
+    if fixed_cost <= dynamic_cost {
+        writer.write(1, 2);
+        write_tokens(writer, tokens, &fixed_literal_lengths, &fixed_distance_lengths);
+        return;
+    }
+    writer.write(2, 2);
+    writer.write(literal_count as u32 - 257, 5);
+    writer.write(distance_count as u32 - 1, 5);
+    writer.write(code_length_count as u32 - 4, 4);
+    for symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        writer.write(code_length_lengths[*symbol] as u32, 3);
+    }
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    for (symbol, extra) in code_length_symbols {
+        writer.write_code(code_length_codes[symbol as usize], code_length_lengths[symbol as usize]);
+        writer.write(extra as u32, extra_bits(symbol));
+    }
+    write_tokens(writer, tokens, &literal_lengths, &distance_lengths);
+}
+
+/// Compresses `data` into a raw deflate stream.
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    let tokens = find_matches(data);
+    let mut writer = BitWriter::new();
+    if tokens.is_empty() {
+        write_block(&mut writer, &[], true);
+    }
+    let blocks = (tokens.len() + BLOCK_TOKENS - 1) / BLOCK_TOKENS;
+    for (index, block) in tokens.chunks(BLOCK_TOKENS).enumerate() {
+        write_block(&mut writer, block, index + 1 == blocks);
+    }
+    writer.finish()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> Option<u32> {
+        while self.count < count {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            self.bits |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1u64 << count) - 1) as u32;
+        self.bits = if count == 32 {
+            0
+        } else {
+            self.bits >> count
+        };
+        self.count -= count;
+        Some(value)
+    }
+
+    /// Drops the bits left in the current byte.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code decoded one bit at a time.
+struct Decoder {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    /// Returns `None` for over-subscribed code lengths.
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|symbol| lengths[*symbol as usize] != 0).collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+        Some(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in &self.counts[1..] {
+            code |= reader.read(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn read_dynamic_decoders(reader: &mut BitReader) -> Option<(Decoder, Decoder)> {
+    let literal_count = reader.read(5)? as usize + 257;
+    let distance_count = reader.read(5)? as usize + 1;
+    let code_length_count = reader.read(4)? as usize + 4;
+    let mut code_length_lengths = [0u8; 19];
+    for symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[*symbol] = reader.read(3)? as u8;
+    }
+    let code_length_decoder = Decoder::new(&code_length_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_length_decoder.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + reader.read(2)?),
+            17 => (0, 3 + reader.read(3)?),
+            _ => (0, 11 + reader.read(7)?),
+        };
+        lengths.extend(std::iter::repeat(value).take(repeat as usize));
+    }
+    if lengths.len() != literal_count + distance_count || lengths[256] == 0 {
+        return None;
+    }
+    Some((Decoder::new(&lengths[..literal_count])?, Decoder::new(&lengths[literal_count..])?))
+}
+
+/// Decompresses a raw deflate stream, failing on malformed input or when the
+/// output would exceed `limit` bytes.
+pub(crate) fn inflate(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+    loop {
+        let last = reader.read(1)? == 1;
+        match reader.read(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.position..reader.position + 4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) || output.len() + length as usize > limit {
+                    return None;
+                }
+                let start = reader.position + 4;
+                output.extend_from_slice(data.get(start..start + length as usize)?);
+                reader.position = start + length as usize;
+            }
+            kind @ 1..=2 => {
+                let (literals, distances) = if kind == 1 {
+                    let (literal_lengths, distance_lengths) = fixed_lengths();
+                    (Decoder::new(&literal_lengths)?, Decoder::new(&distance_lengths)?)
+                } else {
+                    read_dynamic_decoders(&mut reader)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        if output.len() == limit {
+                            return None;
+                        }
+                        output.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let index = symbol - 257;
+                    let length = *LENGTH_BASE.get(index)? as usize + reader.read(*LENGTH_EXTRA.get(index)? as u32)? as usize;
+                    let index = distances.decode(&mut reader)? as usize;
+                    let distance = *DISTANCE_BASE.get(index)? as usize + reader.read(*DISTANCE_EXTRA.get(index)? as u32)? as usize;
+                    if distance > output.len() || output.len() + length > limit {
+                        return None;
+                    }
+                    let start = output.len() - distance;
+                    for offset in 0..length {
+                        output.push(output[start + offset]);
+                    }
+                }
+            }
+            _ => return None,
+        }
+        if last {
+            return Some(output);
+        }
+    }
+}
+
+/// Decompresses a zlib stream, checking its header and checksum.
+#[cfg(test)]
+pub(crate) fn zlib_decompress(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    if data.len() < 6 || data[0] & 0x0f != 8 || u16::from_be_bytes([data[0], data[1]]) % 31 != 0 || data[1] & 0x20 != 0 {
+        return None;
+    }
+    let output = inflate(&data[2..data.len() - 4], limit)?;
+    let tail = &data[data.len() - 4..];
+    let checksum = u32::from_be_bytes([tail[0], tail[1], tail[2], tail[3]]);
+    if adler32(&output) == checksum {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes from a xorshift generator, which deflate can't compress.
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = zlib_compress(data);
+        assert_eq!(zlib_decompress(&compressed, data.len()).as_deref(), Some(data));
+        assert_eq!(inflate(&deflate(data), data.len()).as_deref(), Some(data));
+        compressed
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(!crc32_update(!0, b""), 0);
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(!crc32_update(crc32_update(!0, b"1234"), b"56789"), 0xcbf4_3926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // Long enough to need the periodic modulo reduction.
+        assert_eq!(adler32(&vec![0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn empty() {
+        let compressed = round_trip(&[]);
+        assert!(compressed.len() <= 10);
+    }
+
+    #[test]
+    fn incompressible() {
+        let data = noise(100_000, 1);
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < data.len() + data.len() / 50);
+    }
+
+    #[test]
+    fn repetitive() {
+        let zeros = vec![0; 1 << 20];
+        assert!(round_trip(&zeros).len() < 2048);
+        let text = b"the quick brown fox ".repeat(5_000);
+        assert!(round_trip(&text).len() < 1024);
+        round_trip(b"a");
+        round_trip(b"aaaa");
+    }
+
+    #[test]
+    fn larger_than_window() {
+        // Only the first repeat of `second` is within the 32 KiB window; the
+        // later repeats have to be stored as literals again.
+        let first = noise(40_000, 2);
+        let second = noise(10_000, 3);
+        let data = [&first[..], &second, &second, &first, &second].concat();
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < (first.len() + second.len()) * 2 + 1024);
+        // Enough tokens for several blocks.
+        round_trip(&noise(300_000, 4));
+    }
+
+    #[test]
+    fn stored_blocks() {
+        let mut stream = vec![0b001, 5, 0, !5, !0];
+        stream.extend_from_slice(b"hello");
+        assert_eq!(inflate(&stream, 5).as_deref(), Some(&b"hello"[..]));
+        stream[3] ^= 1;
+        assert_eq!(inflate(&stream, 5), None);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        let data = b"the quick brown fox jumps over the lazy dog ".repeat(100);
+        let compressed = zlib_compress(&data);
+
+        let mut bad_checksum = compressed.clone();
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        assert_eq!(zlib_decompress(&bad_checksum, data.len()), None);
+
+        let mut bad_header = compressed.clone();
+        bad_header[1] ^= 1;
+        assert_eq!(zlib_decompress(&bad_header, data.len()), None);
+
+        let mut preset_dictionary = compressed.clone();
+        preset_dictionary[1] = 0xbc;
+        assert_eq!(zlib_decompress(&preset_dictionary, data.len()), None);
+
+        for len in 0..compressed.len() {
+            assert_eq!(zlib_decompress(&compressed[..len], data.len()), None, "truncated to {}", len);
+        }
+        assert_eq!(zlib_decompress(&compressed, data.len() - 1), None);
+
+        // Block type 3 is reserved.
+        assert_eq!(inflate(&[0b111], 10), None);
+        // A fixed block copying from before the start of the output.
+        let mut writer = BitWriter::new();
+        writer.write(1, 1);
+        writer.write(1, 2);
+        let (literals, distances) = fixed_lengths();
+        write_tokens(&mut writer, &[Token::Match { length: 3, distance: 1 }], &literals, &distances);
+        assert_eq!(inflate(&writer.finish(), 10), None);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for seed in 1..200 {
+            let garbage = noise(64, seed);
+            if let Some(output) = inflate(&garbage, 4096) {
+                assert!(output.len() <= 4096);
+            }
+            let mut zlib = vec![0x78, 0x9c];
+            zlib.extend_from_slice(&garbage);
+            assert_eq!(zlib_decompress(&zlib, 4096), None);
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    convert::ColorMatrix,
+    deflate::{deflate, inflate},
+    frame::{FrameError, PixelFormat, VideoFrame},
+    region::{PixelRect, Region},
+};
+
+const MESSAGE_INIT: u8 = 0;
+const MESSAGE_FRAME: u8 = 1;
+const HEADER_SIZE: usize = 5;
+
+const TILE_SOLID: u8 = 0;
+const TILE_DEFLATE: u8 = 1;
+const TILE_XOR_DEFLATE: u8 = 2;
+
+/// Largest message the decoder accepts, guarding against corrupt lengths.
+pub const MAX_MESSAGE_SIZE: usize = 1 << 30;
+/// Largest frame width and height the stream carries, so a corrupt `Init`
+/// can't make the decoder allocate an arbitrarily large framebuffer.
+pub const MAX_DIMENSION: usize = 16384;
+const MIN_TILE_SIZE: u32 = 8;
+const MAX_TILE_SIZE: u32 = 1024;
+
+#[derive(Debug)]
+pub enum DeltaError {
+    Frame(FrameError),
+    /// The stream is malformed; the message names what was wrong.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frame(error) => write!(f, "{}", error),
+            Self::Corrupt(reason) => write!(f, "corrupt delta stream: {}", reason),
+        }
+    }
+}
+
+impl Error for DeltaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Frame(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for DeltaError {
+    fn from(error: FrameError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+/// The tiles of a frame of `width` x `height` pixels.
+fn tile_rect(column: u32, row: u32, tile_size: u32, width: usize, height: usize) -> Option<PixelRect> {
+    let (x, y) = (column.checked_mul(tile_size)?, row.checked_mul(tile_size)?);
+    PixelRect::new(x, y, tile_size, tile_size).intersection(&PixelRect::new(0, 0, width as u32, height as u32))
+}
+
+fn copy_tile(frame: &VideoFrame, rect: PixelRect) -> Vec<u8> {
+    let plane = &frame.planes()[0];
+    let (start, end) = (rect.x as usize * 4, rect.right() as usize * 4);
+    (rect.y as usize..rect.bottom() as usize)
+        .flat_map(|y| plane.row(y)[start..end].iter().copied())
+        .collect()
+}
+
+fn tile_equals(previous: &VideoFrame, current: &VideoFrame, rect: PixelRect) -> bool {
+    let (start, end) = (rect.x as usize * 4, rect.right() as usize * 4);
+    (rect.y as usize..rect.bottom() as usize).all(|y| previous.planes()[0].row(y)[start..end] == current.planes()[0].row(y)[start..end])
+}
+
+/// Replaces every byte by its difference to the same channel of the pixel to
+/// the left, like the PNG `Sub` filter.
+fn filter_sub(pixels: &mut [u8], row_bytes: usize) {
+    for row in pixels.chunks_exact_mut(row_bytes) {
+        for index in (4..row.len()).rev() {
+            row[index] = row[index].wrapping_sub(row[index - 4]);
+        }
+    }
+}
+
+fn unfilter_sub(pixels: &mut [u8], row_bytes: usize) {
+    for row in pixels.chunks_exact_mut(row_bytes) {
+        for index in 4..row.len() {
+            row[index] = row[index].wrapping_add(row[index - 4]);
+        }
+    }
+}
+
+/// Whether encoded messages start with an `Init` message, so decoding can
+/// begin there.
+pub(crate) fn is_key_frame(messages: &[u8]) -> bool {
+    messages.first() == Some(&MESSAGE_INIT)
+}
+
+fn push_message(output: &mut Vec<u8>, kind: u8, payload: &[u8]) {
+    output.push(kind);
+    output.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    output.extend_from_slice(payload);
+}
+
+/// Encodes BGRA framebuffers as a stream of messages carrying only the tiles
+/// that changed since the previous frame.
+///
+/// The stream is a sequence of messages, each a type byte and a little
+/// endian `u32` payload length followed by the payload:
+///
+/// - `Init` (0): width, height and tile size as `u32`. Sent first, whenever
+///   the size changes and for requested key frames; the decoder clears its
+///   framebuffer and the next frame carries every tile.
+/// - `Frame` (1): sequence number as `u64`, tile count as `u32`, then for
+///   each tile its column and row as `u16` and an encoding byte: solid
+///   (4 BGRA bytes), deflate (`u32` length and the deflated pixels after a
+///   `Sub` filter) or XOR deflate (`u32` length and the deflated XOR with the
+///   previous tile contents).
+#[derive(Clone, Debug)]
+pub struct DeltaEncoder {
+    tile_size: u32,
+    use_dirty_rects: bool,
+    key_frame_requested: bool,
+    sequence: u64,
+    previous: Option<VideoFrame>,
+}
+
+impl Default for DeltaEncoder {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl DeltaEncoder {
+    pub fn new(tile_size: u32) -> Self {
+        Self {
+            tile_size: tile_size.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE),
+            use_dirty_rects: true,
+            key_frame_requested: false,
+            sequence: 0,
+            previous: None,
+        }
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Whether only tiles touched by the frame's dirty rects are checked,
+    /// when the frame info has them. Otherwise, or without dirty rects, every
+    /// tile is compared with the previous frame. Enabled by default.
+    pub fn set_use_dirty_rects(&mut self, use_dirty_rects: bool) {
+        self.use_dirty_rects = use_dirty_rects;
+    }
+
+    /// Makes the next frame carry every tile, e.g. when a new client joins.
+    pub fn request_key_frame(&mut self) {
+        self.key_frame_requested = true;
+    }
+
+    /// Encodes a frame, converting it to BGRA first if needed, and returns
+    /// the messages to send. Frames without changes still produce an empty
+    /// `Frame` message so the receiver can track the sequence. Frames larger
+    /// than [`MAX_DIMENSION`] in either direction are rejected.
+    pub fn encode(&mut self, frame: &VideoFrame) -> Result<Vec<u8>, FrameError> {
+        let mut frame = if frame.pixel_format() == PixelFormat::BGRA {
+            frame.clone()
+        } else {
+            let mut converted = frame.convert(PixelFormat::BGRA, ColorMatrix::default())?;
+            converted.copy_metadata_from(frame);
+            converted
+        };
+        let (width, height) = (frame.width(), frame.height());
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(FrameError::InvalidDimensions);
+        }
+
+        let mut output = Vec::new();
+        let previous = match self.previous.take() {
+            Some(previous) if !self.key_frame_requested && previous.width() == width && previous.height() == height => Some(previous),
+            _ => {
+                let mut payload = Vec::with_capacity(12);
+                for value in [width as u32, height as u32, self.tile_size] {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+                push_message(&mut output, MESSAGE_INIT, &payload);
+                None
+            }
+        };
+        self.key_frame_requested = false;
+
+        let bounds = PixelRect::new(0, 0, width as u32, height as u32);
+        let candidates = match (&previous, Region::from_frame_info(&frame.info)) {
+            (Some(_), Some(dirty)) if self.use_dirty_rects => dirty.clip(bounds),
+            _ => Region::from_rect(bounds),
+        };
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&self.sequence.to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        let mut tile_count = 0u32;
+        for (column, row) in candidates.tiles(self.tile_size, self.tile_size) {
+            let rect = match tile_rect(column, row, self.tile_size, width, height) {
+                Some(rect) => rect,
+                None => continue,
+            };
+            if previous.as_ref().is_some_and(|previous| tile_equals(previous, &frame, rect)) {
+                continue;
+            }
+            payload.extend_from_slice(&(column as u16).to_le_bytes());
+            payload.extend_from_slice(&(row as u16).to_le_bytes());
+            encode_tile(&mut payload, &frame, previous.as_ref(), rect);
+            tile_count += 1;
+        }
+        payload[8..12].copy_from_slice(&tile_count.to_le_bytes());
+        push_message(&mut output, MESSAGE_FRAME, &payload);
+
+        self.sequence += 1;
+        frame.info = Default::default();
+        self.previous = Some(frame);
+        Ok(output)
+    }
+}
+
+/// Appends the smallest encoding of one tile.
+fn encode_tile(payload: &mut Vec<u8>, frame: &VideoFrame, previous: Option<&VideoFrame>, rect: PixelRect) {
+    let pixels = copy_tile(frame, rect);
+    if pixels.chunks_exact(4).all(|pixel| pixel == &pixels[..4]) {
+        payload.push(TILE_SOLID);
+        payload.extend_from_slice(&pixels[..4]);
+        return;
+    }
+    let mut filtered = pixels.clone();
+    filter_sub(&mut filtered, rect.width as usize * 4);
+    let mut best = (TILE_DEFLATE, deflate(&filtered));
+    if let Some(previous) = previous {
+        let xor: Vec<u8> = pixels.iter().zip(copy_tile(previous, rect)).map(|(a, b)| a ^ b).collect();
+        let compressed = deflate(&xor);
+        if compressed.len() < best.1.len() {
+            best = (TILE_XOR_DEFLATE, compressed);
+        }
+    }
+    payload.push(best.0);
+    payload.extend_from_slice(&(best.1.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&best.1);
+}
+
+/// Reads little endian values from a message payload.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], DeltaError> {
+        if self.data.len() < count {
+            return Err(DeltaError::Corrupt("truncated message"));
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, DeltaError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DeltaError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, DeltaError> {
+        Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+    }
+}
+
+/// Reconstructs the framebuffer from a [`DeltaEncoder`] message stream.
+///
+/// ```
+/// use screen_capture_kit::{
+///     delta::{DeltaDecoder, DeltaEncoder},
+///     frame::{PixelFormat, VideoFrame},
+/// };
+///
+/// let mut encoder = DeltaEncoder::new(16);
+/// let mut decoder = DeltaDecoder::new();
+/// let mut pixels = vec![0u8; 100 * 60 * 4];
+/// for step in 0..3 {
+///     pixels[(step * 1000)..(step * 1000 + 40)].fill(0xff);
+///     let frame = VideoFrame::from_bytes(100, 60, PixelFormat::BGRA, &pixels).unwrap();
+///     let messages = encoder.encode(&frame).unwrap();
+///     // Messages may arrive split at any point.
+///     let (first, second) = messages.split_at(messages.len() / 2);
+///     decoder.push(first).unwrap();
+///     decoder.push(second).unwrap();
+///     assert_eq!(decoder.framebuffer().unwrap().planes()[0].data(), &pixels[..]);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DeltaDecoder {
+    buffer: Vec<u8>,
+    tile_size: u32,
+    sequence: Option<u64>,
+    framebuffer: Option<VideoFrame>,
+}
+
+impl DeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The reconstructed BGRA framebuffer, once an `Init` message arrived.
+    pub fn framebuffer(&self) -> Option<&VideoFrame> {
+        self.framebuffer.as_ref()
+    }
+
+    /// Sequence number of the last decoded frame.
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// Feeds received bytes, applying every complete message, and returns
+    /// the area of the framebuffer that changed. Incomplete messages are kept
+    /// until the rest arrives. On errors the offending message is dropped, or
+    /// everything buffered when the message framing itself is corrupt, so
+    /// later pushes don't fail on the same bytes.
+    pub fn push(&mut self, data: &[u8]) -> Result<Region, DeltaError> {
+        self.buffer.extend_from_slice(data);
+        let mut updated = Region::new();
+        let mut consumed = 0;
+        let result = self.apply_messages(&mut consumed, &mut updated);
+        self.buffer.drain(..consumed);
+        result.map(|()| updated)
+    }
+
+    fn apply_messages(&mut self, consumed: &mut usize, updated: &mut Region) -> Result<(), DeltaError> {
+        while self.buffer.len() - *consumed >= HEADER_SIZE {
+            let header = &self.buffer[*consumed..*consumed + HEADER_SIZE];
+            let length = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            if length > MAX_MESSAGE_SIZE {
+                *consumed = self.buffer.len();
+                return Err(DeltaError::Corrupt("message too large"));
+            }
+            if self.buffer.len() - *consumed - HEADER_SIZE < length {
+                break;
+            }
+            let kind = header[0];
+            let start = *consumed + HEADER_SIZE;
+            let payload = self.buffer[start..start + length].to_vec();
+            *consumed = start + length;
+            match kind {
+                MESSAGE_INIT => *updated = self.apply_init(&payload)?,
+                MESSAGE_FRAME => *updated = updated.union(&self.apply_frame(&payload)?),
+                _ => return Err(DeltaError::Corrupt("unknown message type")),
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_init(&mut self, payload: &[u8]) -> Result<Region, DeltaError> {
+        let mut reader = Reader { data: payload };
+        let (width, height, tile_size) = (reader.u32()? as usize, reader.u32()? as usize, reader.u32()?);
+        if !(MIN_TILE_SIZE..=MAX_TILE_SIZE).contains(&tile_size) {
+            return Err(DeltaError::Corrupt("invalid tile size"));
+        }
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(DeltaError::Corrupt("frame too large"));
+        }
+        self.framebuffer = Some(VideoFrame::zeroed(width, height, PixelFormat::BGRA)?);
+        self.tile_size = tile_size;
+        self.sequence = None;
+        Ok(Region::from_rect(PixelRect::new(0, 0, width as u32, height as u32)))
+    }
+
+    fn apply_frame(&mut self, payload: &[u8]) -> Result<Region, DeltaError> {
+        let framebuffer = self.framebuffer.as_mut().ok_or(DeltaError::Corrupt("frame before init"))?;
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        let mut reader = Reader { data: payload };
+        let sequence = reader.u64()?;
+        let tile_count = reader.u32()?;
+        let mut updated = Region::new();
+        for _ in 0..tile_count {
+            let (column, row) = (reader.u16()? as u32, reader.u16()? as u32);
+            let rect = tile_rect(column, row, self.tile_size, width, height).ok_or(DeltaError::Corrupt("tile outside the frame"))?;
+            let size = rect.area() as usize * 4;
+            let row_bytes = rect.width as usize * 4;
+            let pixels = match reader.bytes(1)?[0] {
+                TILE_SOLID => reader.bytes(4)?.repeat(size / 4),
+                kind @ (TILE_DEFLATE | TILE_XOR_DEFLATE) => {
+                    let length = reader.u32()? as usize;
+                    let mut pixels = inflate(reader.bytes(length)?, size)
+                        .filter(|pixels| pixels.len() == size)
+                        .ok_or(DeltaError::Corrupt("bad tile data"))?;
+                    if kind == TILE_DEFLATE {
+                        unfilter_sub(&mut pixels, row_bytes);
+                    } else {
+                        for (pixel, previous) in pixels.iter_mut().zip(copy_tile(framebuffer, rect)) {
+                            *pixel ^= previous;
+                        }
+                    }
+                    pixels
+                }
+                _ => return Err(DeltaError::Corrupt("unknown tile encoding")),
+            };
+            let mut plane = framebuffer.plane_mut(0).unwrap();
+            let start = rect.x as usize * 4;
+            for (y, source) in (rect.y as usize..rect.bottom() as usize).zip(pixels.chunks_exact(row_bytes)) {
+                plane.row_mut(y)[start..start + row_bytes].copy_from_slice(source);
+            }
+            updated.add_rect(rect);
+        }
+        self.sequence = Some(sequence);
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: usize, height: usize, seed: u32) -> VideoFrame {
+        let mut data = vec![0u8; width * height * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = &mut data[(y * width + x) * 4..][..4];
+                if x < width / 2 {
+                    // Solid tiles on the left, noise that changes with the seed on the right.
+                    pixel.copy_from_slice(&[10, 20, 30, 255]);
+                } else {
+                    let value = (x as u32 * 31 + y as u32 * 17 + seed * 7) as u8;
+                    pixel.copy_from_slice(&[value, value ^ 0x55, (x ^ y) as u8, 255]);
+                }
+            }
+        }
+        VideoFrame::from_bytes(width, height, PixelFormat::BGRA, &data).unwrap()
+    }
+
+    fn same_pixels(a: &VideoFrame, b: &VideoFrame) -> bool {
+        let row_bytes = a.width() * 4;
+        a.width() == b.width()
+            && a.height() == b.height()
+            && (0..a.height()).all(|y| a.planes()[0].row(y)[..row_bytes] == b.planes()[0].row(y)[..row_bytes])
+    }
+
+    fn message(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        push_message(&mut output, kind, payload);
+        output
+    }
+
+    fn init(width: u32, height: u32, tile_size: u32) -> Vec<u8> {
+        let payload: Vec<u8> = [width, height, tile_size].iter().flat_map(|value| value.to_le_bytes()).collect();
+        message(MESSAGE_INIT, &payload)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut encoder = DeltaEncoder::new(16);
+        let mut decoder = DeltaDecoder::new();
+        for seed in 0..4 {
+            let source = frame(70, 45, seed);
+            let updated = decoder.push(&encoder.encode(&source).unwrap()).unwrap();
+            assert!(same_pixels(decoder.framebuffer().unwrap(), &source));
+            assert_eq!(decoder.sequence(), Some(seed as u64));
+            if seed > 0 {
+                // Only the noisy right half changes between frames.
+                assert!(updated.rects().iter().all(|rect| rect.right() > 32));
+            }
+        }
+
+        let unchanged = encoder.encode(&frame(70, 45, 3)).unwrap();
+        assert!(decoder.push(&unchanged).unwrap().is_empty());
+        assert_eq!(decoder.sequence(), Some(4));
+    }
+
+    #[test]
+    fn round_trip_across_size_changes_and_key_frames() {
+        let mut encoder = DeltaEncoder::new(8);
+        let mut decoder = DeltaDecoder::new();
+        decoder.push(&encoder.encode(&frame(40, 30, 0)).unwrap()).unwrap();
+
+        let resized = frame(33, 17, 1);
+        let updated = decoder.push(&encoder.encode(&resized).unwrap()).unwrap();
+        assert_eq!(updated.area(), 33 * 17);
+        assert!(same_pixels(decoder.framebuffer().unwrap(), &resized));
+
+        // A new decoder joining mid-stream catches up from a key frame.
+        encoder.request_key_frame();
+        let mut late = DeltaDecoder::new();
+        late.push(&encoder.encode(&frame(33, 17, 2)).unwrap()).unwrap();
+        assert!(same_pixels(late.framebuffer().unwrap(), &frame(33, 17, 2)));
+    }
+
+    #[test]
+    fn split_and_truncated_messages_wait_for_more_data() {
+        let mut encoder = DeltaEncoder::new(16);
+        let source = frame(50, 20, 0);
+        let stream = encoder.encode(&source).unwrap();
+        let mut decoder = DeltaDecoder::new();
+        for chunk in stream[..stream.len() - 1].chunks(7) {
+            decoder.push(chunk).unwrap();
+        }
+        assert_eq!(decoder.sequence(), None);
+        decoder.push(&stream[stream.len() - 1..]).unwrap();
+        assert!(same_pixels(decoder.framebuffer().unwrap(), &source));
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        let mut decoder = DeltaDecoder::new();
+        assert!(matches!(decoder.push(&message(MESSAGE_INIT, &[0; 8])), Err(DeltaError::Corrupt(_))));
+        decoder.push(&init(16, 16, 8)).unwrap();
+        // One tile announced but no tile data follows.
+        let mut payload = 0u64.to_le_bytes().to_vec();
+        payload.extend_from_slice(&1u32.to_le_bytes());
+        assert!(matches!(decoder.push(&message(MESSAGE_FRAME, &payload)), Err(DeltaError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_oversized_input() {
+        let mut decoder = DeltaDecoder::new();
+        let mut header = vec![MESSAGE_FRAME];
+        header.extend_from_slice(&(MAX_MESSAGE_SIZE as u32 + 1).to_le_bytes());
+        assert!(matches!(decoder.push(&header), Err(DeltaError::Corrupt("message too large"))));
+        // The corrupt framing is dropped rather than failing every later push.
+        assert!(decoder.push(&init(16, 16, 8)).is_ok());
+
+        for (width, height, tile_size) in [
+            (16, 16, 0),
+            (16, 16, 7),
+            (16, 16, 2048),
+            (MAX_DIMENSION as u32 + 1, 16, 8),
+            (16, u32::MAX, 8),
+        ] {
+            let mut decoder = DeltaDecoder::new();
+            assert!(
+                matches!(decoder.push(&init(width, height, tile_size)), Err(DeltaError::Corrupt(_))),
+                "{}x{} tiles of {}",
+                width,
+                height,
+                tile_size
+            );
+            assert!(decoder.framebuffer().is_none());
+        }
+
+        let mut encoder = DeltaEncoder::default();
+        assert!(encoder
+            .encode(&VideoFrame::zeroed(MAX_DIMENSION + 1, 1, PixelFormat::BGRA).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut decoder = DeltaDecoder::new();
+        assert!(matches!(
+            decoder.push(&message(MESSAGE_FRAME, &[0; 12])),
+            Err(DeltaError::Corrupt("frame before init"))
+        ));
+        assert!(matches!(
+            decoder.push(&message(7, &[1, 2, 3])),
+            Err(DeltaError::Corrupt("unknown message type"))
+        ));
+
+        decoder.push(&init(16, 16, 8)).unwrap();
+        let tile = |column: u16, encoding: u8, data: &[u8]| {
+            let mut payload = 0u64.to_le_bytes().to_vec();
+            payload.extend_from_slice(&1u32.to_le_bytes());
+            payload.extend_from_slice(&column.to_le_bytes());
+            payload.extend_from_slice(&0u16.to_le_bytes());
+            payload.push(encoding);
+            payload.extend_from_slice(data);
+            message(MESSAGE_FRAME, &payload)
+        };
+        assert!(matches!(
+            decoder.push(&tile(5, TILE_SOLID, &[0; 4])),
+            Err(DeltaError::Corrupt("tile outside the frame"))
+        ));
+        assert!(matches!(
+            decoder.push(&tile(0, 9, &[])),
+            Err(DeltaError::Corrupt("unknown tile encoding"))
+        ));
+        assert!(matches!(
+            decoder.push(&tile(0, TILE_DEFLATE, &[4, 0, 0, 0, 1, 2, 3, 4])),
+            Err(DeltaError::Corrupt("bad tile data"))
+        ));
+
+        // Pseudo-random bytes must fail cleanly rather than panic.
+        let mut state = 0x2545_f491u32;
+        let garbage: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        for chunk in garbage.chunks(64) {
+            let _ = decoder.push(chunk);
+        }
+    }
+}
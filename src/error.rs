@@ -1,5 +1,11 @@
-use objc2_foundation::{NSInteger, NSString};
+use std::{error::Error, fmt};
 
+#[cfg(target_os = "macos")]
+use objc2_foundation::NSString;
+
+use crate::platform::NSInteger;
+
+#[cfg(target_os = "macos")]
 extern "C" {
     pub static SCStreamErrorDomain: &'static NSString;
 }
@@ -48,3 +54,17 @@ impl SCStreamErrorCode {
     #[doc(alias = "SCStreamErrorFailedToStopAudioCapture")]
     pub const FailedToStopAudioCapture: Self = Self(-3819);
 }
+
+/// Returned when an integer doesn't match any known value of a
+/// ScreenCaptureKit enumeration. The raw value is kept so callers can still
+/// forward or log it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownValueError(pub NSInteger);
+
+impl fmt::Display for UnknownValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown value: {}", self.0)
+    }
+}
+
+impl Error for UnknownValueError {}
@@ -0,0 +1,630 @@
+use std::{error::Error, fmt, ops::Deref};
+
+#[cfg(all(target_os = "macos", feature = "video"))]
+use core_media::sample_buffer::CMSampleBuffer;
+#[cfg(all(target_os = "macos", feature = "video"))]
+use core_video::{
+    pixel_buffer::{kCVPixelBufferLock_ReadOnly, CVPixelBuffer},
+    r#return::{kCVReturnSuccess, CVReturn},
+};
+
+use crate::{
+    frame_info::FrameInfo,
+    platform::{CMTime, OSType},
+    pool::{FramePool, Recycler},
+};
+
+const fn fourcc(code: &[u8; 4]) -> OSType {
+    ((code[0] as OSType) << 24) | ((code[1] as OSType) << 16) | ((code[2] as OSType) << 8) | code[3] as OSType
+}
+
+/// A Core Video pixel format type.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PixelFormat(pub OSType);
+
+impl PixelFormat {
+    #[doc(alias = "kCVPixelFormatType_32BGRA")]
+    pub const BGRA: Self = Self(fourcc(b"BGRA"));
+    #[doc(alias = "kCVPixelFormatType_32RGBA")]
+    pub const RGBA: Self = Self(fourcc(b"RGBA"));
+    #[doc(alias = "kCVPixelFormatType_24RGB")]
+    pub const RGB24: Self = Self(0x00000018);
+    #[doc(alias = "kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange")]
+    pub const NV12VideoRange: Self = Self(fourcc(b"420v"));
+    #[doc(alias = "kCVPixelFormatType_420YpCbCr8BiPlanarFullRange")]
+    pub const NV12FullRange: Self = Self(fourcc(b"420f"));
+    #[doc(alias = "kCVPixelFormatType_420YpCbCr8Planar")]
+    pub const I420VideoRange: Self = Self(fourcc(b"y420"));
+    #[doc(alias = "kCVPixelFormatType_420YpCbCr8PlanarFullRange")]
+    pub const I420FullRange: Self = Self(fourcc(b"f420"));
+    #[doc(alias = "kCVPixelFormatType_ARGB2101010LEPacked")]
+    pub const ARGB2101010: Self = Self(fourcc(b"l10r"));
+
+    /// The planes making up a `width` x `height` image, or `None` for
+    /// formats this crate can't lay out.
+    pub fn plane_layouts(&self, width: usize, height: usize) -> Option<Vec<PlaneLayout>> {
+        let chroma_width = width / 2 + width % 2;
+        let chroma_height = height / 2 + height % 2;
+        let layouts = match *self {
+            Self::BGRA | Self::RGBA | Self::ARGB2101010 => vec![PlaneLayout::new(width, height, 4)],
+            Self::RGB24 => vec![PlaneLayout::new(width, height, 3)],
+            Self::NV12VideoRange | Self::NV12FullRange => vec![PlaneLayout::new(width, height, 1), PlaneLayout::new(chroma_width, chroma_height, 2)],
+            Self::I420VideoRange | Self::I420FullRange => vec![
+                PlaneLayout::new(width, height, 1),
+                PlaneLayout::new(chroma_width, chroma_height, 1),
+                PlaneLayout::new(chroma_width, chroma_height, 1),
+            ],
+            _ => return None,
+        };
+        Some(layouts)
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.plane_layouts(1, 1).is_some()
+    }
+
+    pub fn is_yuv(&self) -> bool {
+        matches!(
+            *self,
+            Self::NV12VideoRange | Self::NV12FullRange | Self::I420VideoRange | Self::I420FullRange
+        )
+    }
+
+    /// Whether YUV samples use the full 0-255 range rather than video range.
+    pub fn is_full_range(&self) -> bool {
+        matches!(*self, Self::NV12FullRange | Self::I420FullRange)
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.0.to_be_bytes();
+        if bytes.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ') {
+            bytes.iter().try_for_each(|byte| write!(f, "{}", *byte as char))
+        } else {
+            write!(f, "{:#010x}", self.0)
+        }
+    }
+}
+
+/// Dimensions of one plane, `width` being counted in samples of
+/// `bytes_per_pixel` bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PlaneLayout {
+    pub width: usize,
+    pub height: usize,
+    pub bytes_per_pixel: usize,
+}
+
+impl PlaneLayout {
+    pub const fn new(width: usize, height: usize, bytes_per_pixel: usize) -> Self {
+        Self {
+            width,
+            height,
+            bytes_per_pixel,
+        }
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        self.width * self.bytes_per_pixel
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    InvalidDimensions,
+    UnsupportedPixelFormat(PixelFormat),
+    PlaneCount { expected: usize, actual: usize },
+    BufferTooSmall { plane: usize, expected: usize, actual: usize },
+    NoImageBuffer,
+    LockFailed(i32),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDimensions => f.write_str("invalid frame dimensions"),
+            Self::UnsupportedPixelFormat(format) => write!(f, "unsupported pixel format: {}", format),
+            Self::PlaneCount { expected, actual } => write!(f, "expected {} planes, got {}", expected, actual),
+            Self::BufferTooSmall { plane, expected, actual } => write!(f, "plane {} needs {} bytes, got {}", plane, expected, actual),
+            Self::NoImageBuffer => f.write_str("sample buffer has no image buffer"),
+            Self::LockFailed(status) => write!(f, "failed to lock pixel buffer: {}", status),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+/// One plane of pixel data. Rows are `stride` bytes apart and may be padded
+/// past `layout().row_bytes()`. Planes taken from a [`FramePool`] hand their
+/// buffer back to it when dropped; their clones aren't pooled.
+#[derive(Debug)]
+pub struct Plane {
+    data: Vec<u8>,
+    layout: PlaneLayout,
+    stride: usize,
+    recycler: Option<Recycler>,
+}
+
+impl Clone for Plane {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            layout: self.layout,
+            stride: self.stride,
+            recycler: None,
+        }
+    }
+}
+
+impl PartialEq for Plane {
+    fn eq(&self, other: &Self) -> bool {
+        self.layout == other.layout && self.stride == other.stride && self.data == other.data
+    }
+}
+
+impl Eq for Plane {}
+
+impl Drop for Plane {
+    fn drop(&mut self) {
+        if let Some(recycler) = self.recycler.take() {
+            recycler.recycle(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+impl Plane {
+    pub fn new(data: Vec<u8>, layout: PlaneLayout, stride: usize) -> Result<Self, FrameError> {
+        if stride < layout.row_bytes() {
+            return Err(FrameError::InvalidDimensions);
+        }
+        let expected = Self::required_len(layout, stride);
+        if data.len() < expected {
+            return Err(FrameError::BufferTooSmall {
+                plane: 0,
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data,
+            layout,
+            stride,
+            recycler: None,
+        })
+    }
+
+    /// A plane without row padding over a buffer of exactly
+    /// `layout.row_bytes() * layout.height` bytes.
+    pub(crate) fn pooled(data: Vec<u8>, layout: PlaneLayout, recycler: Option<Recycler>) -> Self {
+        debug_assert_eq!(data.len(), layout.row_bytes() * layout.height);
+        Self {
+            data,
+            layout,
+            stride: layout.row_bytes(),
+            recycler,
+        }
+    }
+
+    fn required_len(layout: PlaneLayout, stride: usize) -> usize {
+        if layout.height == 0 {
+            0
+        } else {
+            stride * (layout.height - 1) + layout.row_bytes()
+        }
+    }
+
+    pub fn zeroed(layout: PlaneLayout) -> Self {
+        Self::pooled(vec![0; layout.row_bytes() * layout.height], layout, None)
+    }
+
+    /// Copies `height` rows of `stride` bytes from `data`, dropping any row
+    /// padding.
+    pub fn copy_from_slice(data: &[u8], layout: PlaneLayout, stride: usize) -> Result<Self, FrameError> {
+        Self::copy_rows(data, layout, stride, None)
+    }
+
+    pub(crate) fn copy_rows(data: &[u8], layout: PlaneLayout, stride: usize, pool: Option<&FramePool>) -> Result<Self, FrameError> {
+        let row_bytes = layout.row_bytes();
+        if stride < row_bytes {
+            return Err(FrameError::InvalidDimensions);
+        }
+        let expected = Self::required_len(layout, stride);
+        if data.len() < expected {
+            return Err(FrameError::BufferTooSmall {
+                plane: 0,
+                expected,
+                actual: data.len(),
+            });
+        }
+        // Every row is overwritten, so a recycled buffer needn't be cleared.
+        let mut plane = match pool {
+            Some(pool) => pool.take_plane(layout),
+            None => Self::zeroed(layout),
+        };
+        if row_bytes > 0 {
+            for (dst, src) in plane.data.chunks_exact_mut(row_bytes).zip(data.chunks(stride)) {
+                dst.copy_from_slice(&src[..row_bytes]);
+            }
+        }
+        Ok(plane)
+    }
+
+    pub fn layout(&self) -> PlaneLayout {
+        self.layout
+    }
+
+    pub fn width(&self) -> usize {
+        self.layout.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.layout.height
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.layout.bytes_per_pixel
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.stride;
+        &self.data[start..start + self.layout.row_bytes()]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.stride;
+        let row_bytes = self.layout.row_bytes();
+        &mut self.data[start..start + row_bytes]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.layout.height).map(move |y| self.row(y))
+    }
+}
+
+/// Mutable access to the samples of a frame's plane. The plane itself can't
+/// be replaced, so its layout and stride keep matching the frame.
+#[derive(Debug)]
+pub struct PlaneMut<'a>(&'a mut Plane);
+
+impl Deref for PlaneMut<'_> {
+    type Target = Plane;
+
+    fn deref(&self) -> &Plane {
+        self.0
+    }
+}
+
+impl<'a> PlaneMut<'a> {
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.0.data_mut()
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        self.0.row_mut(y)
+    }
+
+    /// Like [`row_mut`](Self::row_mut), borrowing the row for as long as the
+    /// frame.
+    pub fn into_row_mut(self, y: usize) -> &'a mut [u8] {
+        self.0.row_mut(y)
+    }
+}
+
+/// An owned video frame that can outlive the sample callback and move
+/// between threads.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoFrame {
+    width: usize,
+    height: usize,
+    pixel_format: PixelFormat,
+    planes: Vec<Plane>,
+    pub presentation_time: CMTime,
+    pub duration: CMTime,
+    pub info: FrameInfo,
+}
+
+impl VideoFrame {
+    pub fn new(width: usize, height: usize, pixel_format: PixelFormat, planes: Vec<Plane>) -> Result<Self, FrameError> {
+        let layouts = Self::layouts(width, height, pixel_format)?;
+        if planes.len() != layouts.len() {
+            return Err(FrameError::PlaneCount {
+                expected: layouts.len(),
+                actual: planes.len(),
+            });
+        }
+        if planes.iter().zip(&layouts).any(|(plane, layout)| plane.layout != *layout) {
+            return Err(FrameError::InvalidDimensions);
+        }
+        Ok(Self::from_parts(width, height, pixel_format, planes))
+    }
+
+    pub(crate) fn from_parts(width: usize, height: usize, pixel_format: PixelFormat, planes: Vec<Plane>) -> Self {
+        Self {
+            width,
+            height,
+            pixel_format,
+            planes,
+            presentation_time: CMTime::default(),
+            duration: CMTime::default(),
+            info: FrameInfo::default(),
+        }
+    }
+
+    pub(crate) fn layouts(width: usize, height: usize, pixel_format: PixelFormat) -> Result<Vec<PlaneLayout>, FrameError> {
+        if width == 0 || height == 0 {
+            return Err(FrameError::InvalidDimensions);
+        }
+        pixel_format
+            .plane_layouts(width, height)
+            .ok_or(FrameError::UnsupportedPixelFormat(pixel_format))
+    }
+
+    /// A frame with every byte set to zero.
+    pub fn zeroed(width: usize, height: usize, pixel_format: PixelFormat) -> Result<Self, FrameError> {
+        let planes = Self::layouts(width, height, pixel_format)?.into_iter().map(Plane::zeroed).collect();
+        Ok(Self::from_parts(width, height, pixel_format, planes))
+    }
+
+    /// Copies a frame whose planes are stored back to back without padding.
+    pub fn from_bytes(width: usize, height: usize, pixel_format: PixelFormat, data: &[u8]) -> Result<Self, FrameError> {
+        let layouts = Self::layouts(width, height, pixel_format)?;
+        let mut offset = 0;
+        let mut planes = Vec::with_capacity(layouts.len());
+        for (index, layout) in layouts.into_iter().enumerate() {
+            let size = layout.row_bytes() * layout.height;
+            let bytes = data.get(offset..offset + size).ok_or(FrameError::BufferTooSmall {
+                plane: index,
+                expected: offset + size,
+                actual: data.len(),
+            })?;
+            planes.push(Plane::copy_from_slice(bytes, layout, layout.row_bytes())?);
+            offset += size;
+        }
+        Ok(Self::from_parts(width, height, pixel_format, planes))
+    }
+
+    /// Copies a frame from one `(data, stride)` pair per plane.
+    pub fn from_plane_bytes(width: usize, height: usize, pixel_format: PixelFormat, planes: &[(&[u8], usize)]) -> Result<Self, FrameError> {
+        let layouts = Self::layouts(width, height, pixel_format)?;
+        if planes.len() != layouts.len() {
+            return Err(FrameError::PlaneCount {
+                expected: layouts.len(),
+                actual: planes.len(),
+            });
+        }
+        let planes = planes
+            .iter()
+            .zip(layouts)
+            .enumerate()
+            .map(|(index, ((data, stride), layout))| {
+                Plane::copy_from_slice(data, layout, *stride).map_err(|error| match error {
+                    FrameError::BufferTooSmall { expected, actual, .. } => FrameError::BufferTooSmall {
+                        plane: index,
+                        expected,
+                        actual,
+                    },
+                    error => error,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::from_parts(width, height, pixel_format, planes))
+    }
+
+    /// Copies the contents of a pixel buffer, locking it for reading while
+    /// copying.
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub fn from_pixel_buffer(pixel_buffer: &CVPixelBuffer) -> Result<Self, FrameError> {
+        Self::copy_pixel_buffer(pixel_buffer, None)
+    }
+
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub(crate) fn copy_pixel_buffer(pixel_buffer: &CVPixelBuffer, pool: Option<&FramePool>) -> Result<Self, FrameError> {
+        let status: CVReturn = pixel_buffer.lock_base_address(kCVPixelBufferLock_ReadOnly);
+        if status != kCVReturnSuccess {
+            return Err(FrameError::LockFailed(status));
+        }
+        let result = Self::copy_locked_pixel_buffer(pixel_buffer, pool);
+        pixel_buffer.unlock_base_address(kCVPixelBufferLock_ReadOnly);
+        result
+    }
+
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    fn copy_locked_pixel_buffer(pixel_buffer: &CVPixelBuffer, pool: Option<&FramePool>) -> Result<Self, FrameError> {
+        let width = pixel_buffer.get_width();
+        let height = pixel_buffer.get_height();
+        let pixel_format = PixelFormat(pixel_buffer.get_pixel_format());
+        let layouts = Self::layouts(width, height, pixel_format)?;
+        if let Some(pool) = pool {
+            pool.expect_layouts(&layouts);
+        }
+        let mut planes = Vec::with_capacity(layouts.len());
+        for (index, layout) in layouts.into_iter().enumerate() {
+            let (base_address, stride) = if pixel_buffer.is_planar() {
+                (
+                    unsafe { pixel_buffer.get_base_address_of_plane(index) },
+                    pixel_buffer.get_bytes_per_row_of_plane(index),
+                )
+            } else {
+                (unsafe { pixel_buffer.get_base_address() }, pixel_buffer.get_bytes_per_row())
+            };
+            if base_address.is_null() {
+                return Err(FrameError::NoImageBuffer);
+            }
+            if stride < layout.row_bytes() {
+                return Err(FrameError::InvalidDimensions);
+            }
+            let size = stride * (layout.height - 1) + layout.row_bytes();
+            let data = unsafe { std::slice::from_raw_parts(base_address as *const u8, size) };
+            planes.push(Plane::copy_rows(data, layout, stride, pool)?);
+        }
+        Ok(Self::from_parts(width, height, pixel_format, planes))
+    }
+
+    /// Copies the image of a screen sample together with its timing and
+    /// frame info.
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub fn from_sample_buffer(sample_buffer: &CMSampleBuffer) -> Result<Self, FrameError> {
+        Self::copy_sample_buffer(sample_buffer, None)
+    }
+
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub(crate) fn copy_sample_buffer(sample_buffer: &CMSampleBuffer, pool: Option<&FramePool>) -> Result<Self, FrameError> {
+        let pixel_buffer = sample_buffer
+            .get_image_buffer()
+            .and_then(|image_buffer| image_buffer.downcast::<CVPixelBuffer>())
+            .ok_or(FrameError::NoImageBuffer)?;
+        let mut frame = Self::copy_pixel_buffer(&pixel_buffer, pool)?;
+        frame.presentation_time = sample_buffer.get_presentation_time_stamp();
+        frame.duration = sample_buffer.get_duration();
+        frame.info = FrameInfo::from_sample_buffer(sample_buffer).unwrap_or_default();
+        Ok(frame)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    pub fn planes(&self) -> &[Plane] {
+        &self.planes
+    }
+
+    pub fn planes_mut(&mut self) -> impl ExactSizeIterator<Item = PlaneMut<'_>> {
+        self.planes.iter_mut().map(PlaneMut)
+    }
+
+    pub fn plane(&self, index: usize) -> Option<&Plane> {
+        self.planes.get(index)
+    }
+
+    pub fn plane_mut(&mut self, index: usize) -> Option<PlaneMut<'_>> {
+        self.planes.get_mut(index).map(PlaneMut)
+    }
+
+    pub fn into_planes(self) -> Vec<Plane> {
+        self.planes
+    }
+
+    /// Total size of the plane buffers in bytes, including row padding.
+    pub fn byte_size(&self) -> usize {
+        self.planes.iter().map(|plane| plane.data.len()).sum()
+    }
+
+    /// Copies timestamps and frame info from another frame, e.g. after a
+    /// conversion.
+    pub fn copy_metadata_from(&mut self, other: &VideoFrame) {
+        self.presentation_time = other.presentation_time;
+        self.duration = other.duration;
+        self.info = other.info.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_bytes(layouts: &[PlaneLayout]) -> Vec<usize> {
+        layouts.iter().map(PlaneLayout::row_bytes).collect()
+    }
+
+    #[test]
+    fn packed_layouts() {
+        for &(format, bytes_per_pixel) in &[
+            (PixelFormat::BGRA, 4),
+            (PixelFormat::RGBA, 4),
+            (PixelFormat::ARGB2101010, 4),
+            (PixelFormat::RGB24, 3),
+        ] {
+            let layouts = format.plane_layouts(7, 3).unwrap();
+            assert_eq!(layouts, vec![PlaneLayout::new(7, 3, bytes_per_pixel)], "{}", format);
+            assert_eq!(row_bytes(&layouts), vec![7 * bytes_per_pixel]);
+        }
+    }
+
+    #[test]
+    fn ten_bit_layout() {
+        // Three 10-bit channels and 2 alpha bits are packed in one 32-bit word.
+        let frame = VideoFrame::zeroed(1921, 2, PixelFormat::ARGB2101010).unwrap();
+        assert_eq!(frame.planes().len(), 1);
+        assert_eq!(frame.planes()[0].stride(), 1921 * 4);
+        assert_eq!(frame.byte_size(), 1921 * 4 * 2);
+        assert!(!PixelFormat::ARGB2101010.is_yuv());
+    }
+
+    #[test]
+    fn chroma_subsampled_layouts() {
+        for &(width, height, chroma_width, chroma_height) in &[(1920, 1080, 960, 540), (7, 5, 4, 3), (1, 1, 1, 1)] {
+            let nv12 = PixelFormat::NV12VideoRange.plane_layouts(width, height).unwrap();
+            assert_eq!(
+                nv12,
+                vec![PlaneLayout::new(width, height, 1), PlaneLayout::new(chroma_width, chroma_height, 2)]
+            );
+            assert_eq!(row_bytes(&nv12), vec![width, chroma_width * 2]);
+
+            let i420 = PixelFormat::I420FullRange.plane_layouts(width, height).unwrap();
+            assert_eq!(row_bytes(&i420), vec![width, chroma_width, chroma_width]);
+            assert_eq!(i420[1].height, chroma_height);
+            assert_eq!(i420[2], i420[1]);
+
+            let frame = VideoFrame::zeroed(width, height, PixelFormat::I420VideoRange).unwrap();
+            let strides: Vec<usize> = frame.planes().iter().map(Plane::stride).collect();
+            assert_eq!(strides, vec![width, chroma_width, chroma_width]);
+            assert_eq!(frame.byte_size(), width * height + 2 * chroma_width * chroma_height);
+        }
+        assert_eq!(PixelFormat(fourcc(b"x420")).plane_layouts(2, 2), None);
+    }
+
+    #[test]
+    fn padded_rows() {
+        let layout = PlaneLayout::new(3, 2, 2);
+        let data = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let plane = Plane::new(data.to_vec(), layout, 8).unwrap();
+        assert_eq!(plane.row(1), &[7, 8, 9, 10, 11, 12]);
+        assert_eq!(Plane::new(data.to_vec(), layout, 5), Err(FrameError::InvalidDimensions));
+        assert_eq!(
+            Plane::new(data[..13].to_vec(), layout, 8),
+            Err(FrameError::BufferTooSmall {
+                plane: 0,
+                expected: 14,
+                actual: 13
+            })
+        );
+
+        let copy = Plane::copy_from_slice(&data, layout, 8).unwrap();
+        assert_eq!(copy.stride(), layout.row_bytes());
+        assert_eq!(copy.data(), &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn planes_mut_edits_samples_in_place() {
+        let mut frame = VideoFrame::zeroed(3, 2, PixelFormat::NV12FullRange).unwrap();
+        frame.plane_mut(0).unwrap().row_mut(1).copy_from_slice(&[1, 2, 3]);
+        for mut plane in frame.planes_mut() {
+            plane.data_mut()[0] = 9;
+        }
+        assert_eq!(frame.plane(0).unwrap().data(), &[9, 0, 0, 1, 2, 3]);
+        assert_eq!(frame.plane(1).unwrap().row(0), &[9, 0, 0, 0]);
+        assert!(frame.plane_mut(2).is_none());
+    }
+}
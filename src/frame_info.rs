@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use crate::{
+    platform::{CGPoint, CGRect, CGSize, NSInteger},
+    stream::SCFrameStatus,
+};
+
+/// The frame info entries ScreenCaptureKit attaches to screen sample buffers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FrameInfoKey {
+    Status,
+    DisplayTime,
+    ScaleFactor,
+    ContentScale,
+    ContentRect,
+    DirtyRects,
+    ScreenRect,
+}
+
+impl FrameInfoKey {
+    pub const ALL: [Self; 7] = [
+        Self::Status,
+        Self::DisplayTime,
+        Self::ScaleFactor,
+        Self::ContentScale,
+        Self::ContentRect,
+        Self::DirtyRects,
+        Self::ScreenRect,
+    ];
+
+    /// The key used in the neutral attachment map, named after the
+    /// corresponding `SCStreamFrameInfo` constant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status => "SCStreamFrameInfoStatus",
+            Self::DisplayTime => "SCStreamFrameInfoDisplayTime",
+            Self::ScaleFactor => "SCStreamFrameInfoScaleFactor",
+            Self::ContentScale => "SCStreamFrameInfoContentScale",
+            Self::ContentRect => "SCStreamFrameInfoContentRect",
+            Self::DirtyRects => "SCStreamFrameInfoDirtyRects",
+            Self::ScreenRect => "SCStreamFrameInfoScreenRect",
+        }
+    }
+}
+
+/// A platform neutral copy of a Core Foundation attachment value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttachmentValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<AttachmentValue>),
+    Dictionary(BTreeMap<String, AttachmentValue>),
+    Unsupported,
+}
+
+impl AttachmentValue {
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Self::Integer(value) => Some(value),
+            Self::Float(value) if value.is_finite() && value.fract() == 0.0 && value.abs() < i64::MAX as f64 => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Self::Integer(value) => Some(value as f64),
+            Self::Float(value) if value.is_finite() => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[AttachmentValue]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&BTreeMap<String, AttachmentValue>> {
+        match self {
+            Self::Dictionary(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Reads a rectangle stored in the `CGRectCreateDictionaryRepresentation`
+    /// layout, returning it with a non-negative width and height.
+    pub fn as_rect(&self) -> Option<CGRect> {
+        let dictionary = self.as_dictionary()?;
+        let field = |name: &str| dictionary.get(name).and_then(AttachmentValue::as_float);
+        let rect = CGRect::new(CGPoint::new(field("X")?, field("Y")?), CGSize::new(field("Width")?, field("Height")?));
+        Some(rect.standardize())
+    }
+}
+
+/// Typed view of the `SCStreamFrameInfo` attachments of a screen sample.
+///
+/// Entries that are missing or can't be interpreted are left empty rather
+/// than failing the whole parse.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameInfo {
+    pub status: Option<SCFrameStatus>,
+    /// Mach absolute time at which the frame was displayed.
+    pub display_time: Option<u64>,
+    pub scale_factor: Option<f64>,
+    pub content_scale: Option<f64>,
+    pub content_rect: Option<CGRect>,
+    /// Rectangles updated since the previous frame; malformed entries are skipped.
+    pub dirty_rects: Option<Vec<CGRect>>,
+    pub screen_rect: Option<CGRect>,
+}
+
+impl FrameInfo {
+    pub fn from_attachments(attachments: &BTreeMap<String, AttachmentValue>) -> Self {
+        let get = |key: FrameInfoKey| attachments.get(key.name());
+        let positive = |key: FrameInfoKey| get(key).and_then(AttachmentValue::as_float).filter(|value| *value > 0.0);
+        Self {
+            status: get(FrameInfoKey::Status)
+                .and_then(AttachmentValue::as_integer)
+                .and_then(|status| NSInteger::try_from(status).ok())
+                .map(SCFrameStatus),
+            display_time: get(FrameInfoKey::DisplayTime)
+                .and_then(AttachmentValue::as_integer)
+                .and_then(|time| u64::try_from(time).ok()),
+            scale_factor: positive(FrameInfoKey::ScaleFactor),
+            content_scale: positive(FrameInfoKey::ContentScale),
+            content_rect: get(FrameInfoKey::ContentRect).and_then(AttachmentValue::as_rect),
+            dirty_rects: get(FrameInfoKey::DirtyRects)
+                .and_then(AttachmentValue::as_array)
+                .map(|rects| rects.iter().filter_map(AttachmentValue::as_rect).collect()),
+            screen_rect: get(FrameInfoKey::ScreenRect).and_then(AttachmentValue::as_rect),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sample_buffer {
+    use std::collections::BTreeMap;
+
+    use core_foundation_0_10::{
+        array::CFArray,
+        base::{CFType, TCFType},
+        boolean::CFBoolean,
+        dictionary::CFDictionary,
+        number::CFNumber,
+        string::{CFString, CFStringRef},
+    };
+    use core_media::sample_buffer::CMSampleBuffer;
+
+    use super::{AttachmentValue, FrameInfo, FrameInfoKey};
+    use crate::stream::{
+        SCStreamFrameInfo, SCStreamFrameInfoContentRect, SCStreamFrameInfoContentScale, SCStreamFrameInfoDirtyRects, SCStreamFrameInfoDisplayTime,
+        SCStreamFrameInfoScaleFactor, SCStreamFrameInfoScreenRect, SCStreamFrameInfoStatus,
+    };
+
+    impl FrameInfoKey {
+        pub fn key(&self) -> &'static SCStreamFrameInfo {
+            unsafe {
+                match self {
+                    Self::Status => SCStreamFrameInfoStatus,
+                    Self::DisplayTime => SCStreamFrameInfoDisplayTime,
+                    Self::ScaleFactor => SCStreamFrameInfoScaleFactor,
+                    Self::ContentScale => SCStreamFrameInfoContentScale,
+                    Self::ContentRect => SCStreamFrameInfoContentRect,
+                    Self::DirtyRects => SCStreamFrameInfoDirtyRects,
+                    Self::ScreenRect => SCStreamFrameInfoScreenRect,
+                }
+            }
+        }
+    }
+
+    impl AttachmentValue {
+        pub fn from_cf_type(value: &CFType) -> Self {
+            if let Some(boolean) = value.downcast::<CFBoolean>() {
+                Self::Bool(boolean.into())
+            } else if let Some(number) = value.downcast::<CFNumber>() {
+                number
+                    .to_i64()
+                    .map(Self::Integer)
+                    .or_else(|| number.to_f64().map(Self::Float))
+                    .unwrap_or(Self::Unsupported)
+            } else if let Some(string) = value.downcast::<CFString>() {
+                Self::String(string.to_string())
+            } else if let Some(array) = value.downcast::<CFArray>() {
+                Self::Array(
+                    array
+                        .get_all_values()
+                        .into_iter()
+                        .map(|item| Self::from_cf_type(&unsafe { CFType::wrap_under_get_rule(item) }))
+                        .collect(),
+                )
+            } else if let Some(dictionary) = value.downcast::<CFDictionary>() {
+                let (keys, values) = dictionary.get_keys_and_values();
+                Self::Dictionary(
+                    keys.into_iter()
+                        .zip(values)
+                        .filter_map(|(key, value)| {
+                            let key = unsafe { CFType::wrap_under_get_rule(key) }.downcast::<CFString>()?;
+                            Some((key.to_string(), Self::from_cf_type(&unsafe { CFType::wrap_under_get_rule(value) })))
+                        })
+                        .collect(),
+                )
+            } else {
+                Self::Unsupported
+            }
+        }
+    }
+
+    impl FrameInfo {
+        pub fn from_sample_buffer(sample_buffer: &CMSampleBuffer) -> Option<Self> {
+            let attachments_array = sample_buffer.get_sample_attachments_array(false)?;
+            let attachments = attachments_array.get(0)?;
+            Some(Self::from_attachments(&attachments_from_dictionary(&attachments)))
+        }
+    }
+
+    fn attachments_from_dictionary(dictionary: &CFDictionary<CFString, CFType>) -> BTreeMap<String, AttachmentValue> {
+        FrameInfoKey::ALL
+            .iter()
+            .filter_map(|key| {
+                let cf_key = unsafe { CFString::wrap_under_get_rule(key.key() as *const SCStreamFrameInfo as CFStringRef) };
+                dictionary
+                    .find(&cf_key)
+                    .map(|value| (key.name().to_string(), AttachmentValue::from_cf_type(&value)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> AttachmentValue {
+        AttachmentValue::Dictionary(
+            [("X", x), ("Y", y), ("Width", width), ("Height", height)]
+                .iter()
+                .map(|(name, value)| (name.to_string(), AttachmentValue::Float(*value)))
+                .collect(),
+        )
+    }
+
+    fn attachments(entries: Vec<(FrameInfoKey, AttachmentValue)>) -> BTreeMap<String, AttachmentValue> {
+        entries.into_iter().map(|(key, value)| (key.name().to_string(), value)).collect()
+    }
+
+    #[test]
+    fn parses_all_entries() {
+        let info = FrameInfo::from_attachments(&attachments(vec![
+            (FrameInfoKey::Status, AttachmentValue::Integer(0)),
+            (FrameInfoKey::DisplayTime, AttachmentValue::Integer(123_456)),
+            (FrameInfoKey::ScaleFactor, AttachmentValue::Float(2.0)),
+            (FrameInfoKey::ContentScale, AttachmentValue::Integer(1)),
+            (FrameInfoKey::ContentRect, rect(0.0, 0.0, 1440.0, 900.0)),
+            (FrameInfoKey::DirtyRects, AttachmentValue::Array(vec![rect(10.0, 20.0, 30.0, 40.0)])),
+            (FrameInfoKey::ScreenRect, rect(0.0, 0.0, 1440.0, 900.0)),
+        ]));
+        assert_eq!(info.status, Some(SCFrameStatus::Complete));
+        assert_eq!(info.display_time, Some(123_456));
+        assert_eq!(info.scale_factor, Some(2.0));
+        assert_eq!(info.content_scale, Some(1.0));
+        assert_eq!(info.content_rect, Some(CGRect::new(CGPoint::new(0.0, 0.0), CGSize::new(1440.0, 900.0))));
+        assert_eq!(
+            info.dirty_rects,
+            Some(vec![CGRect::new(CGPoint::new(10.0, 20.0), CGSize::new(30.0, 40.0))])
+        );
+        assert_eq!(info.screen_rect, info.content_rect);
+    }
+
+    #[test]
+    fn missing_entries_are_empty() {
+        assert_eq!(FrameInfo::from_attachments(&BTreeMap::new()), FrameInfo::default());
+        let info = FrameInfo::from_attachments(&attachments(vec![(FrameInfoKey::Status, AttachmentValue::Integer(1))]));
+        assert_eq!(info.status, Some(SCFrameStatus::Idle));
+        assert_eq!(info.display_time, None);
+        assert_eq!(info.dirty_rects, None);
+    }
+
+    #[test]
+    fn wrong_typed_entries_are_empty() {
+        let info = FrameInfo::from_attachments(&attachments(vec![
+            (FrameInfoKey::Status, AttachmentValue::String("complete".to_string())),
+            (FrameInfoKey::DisplayTime, AttachmentValue::Float(1.5)),
+            (FrameInfoKey::ScaleFactor, AttachmentValue::Bool(true)),
+            (FrameInfoKey::ContentScale, AttachmentValue::Unsupported),
+            (FrameInfoKey::ContentRect, AttachmentValue::Array(vec![])),
+            (FrameInfoKey::DirtyRects, rect(0.0, 0.0, 1.0, 1.0)),
+            (FrameInfoKey::ScreenRect, AttachmentValue::Integer(0)),
+        ]));
+        assert_eq!(info, FrameInfo::default());
+    }
+
+    #[test]
+    fn out_of_range_entries_are_empty() {
+        let info = FrameInfo::from_attachments(&attachments(vec![
+            (FrameInfoKey::DisplayTime, AttachmentValue::Integer(-1)),
+            (FrameInfoKey::ScaleFactor, AttachmentValue::Float(0.0)),
+            (FrameInfoKey::ContentScale, AttachmentValue::Float(f64::NAN)),
+            (FrameInfoKey::ContentRect, rect(0.0, 0.0, f64::INFINITY, 1.0)),
+        ]));
+        assert_eq!(info, FrameInfo::default());
+        assert_eq!(AttachmentValue::Float(1e300).as_integer(), None);
+    }
+
+    #[test]
+    fn malformed_dirty_rects_are_skipped() {
+        let mut partial = rect(1.0, 2.0, 3.0, 4.0);
+        if let AttachmentValue::Dictionary(fields) = &mut partial {
+            fields.remove("Height");
+        }
+        let info = FrameInfo::from_attachments(&attachments(vec![(
+            FrameInfoKey::DirtyRects,
+            AttachmentValue::Array(vec![partial, AttachmentValue::Integer(3), rect(5.0, 6.0, -7.0, -8.0)]),
+        )]));
+        assert_eq!(info.dirty_rects, Some(vec![CGRect::new(CGPoint::new(-2.0, -2.0), CGSize::new(7.0, 8.0))]));
+    }
+}
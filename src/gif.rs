@@ -0,0 +1,645 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    time::Duration,
+};
+
+use crate::{
+    convert::ColorMatrix,
+    frame::VideoFrame,
+    image::{changed_rect, rgb_pixels, ImageError},
+    region::PixelRect,
+};
+
+/// Colors in a palette, leaving one index for transparency.
+const MAX_COLORS: usize = 255;
+/// Bits per channel of the histogram the palette is built from.
+const HISTOGRAM_BITS: u32 = 5;
+/// Bits per channel of the cache of nearest palette colors.
+const CACHE_BITS: u32 = 6;
+const MAX_CODE: u16 = 4096;
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// How colors missing from the palette are approximated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Dither {
+    /// Every pixel takes the nearest palette color, which bands gradients.
+    None,
+    /// A fixed Bayer pattern. Unchanged areas stay identical from frame to
+    /// frame, which keeps animations small and free of shimmer.
+    #[default]
+    Ordered,
+    /// Floyd-Steinberg error diffusion. Smoothest for stills, but the noise
+    /// of a changed area spreads into its surroundings.
+    FloydSteinberg,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Bin {
+    count: u64,
+    sum: [u64; 3],
+}
+
+/// A palette of up to 255 colors chosen by median cut over the pixels of
+/// every frame, with a cache of the nearest color to each RGB value. When
+/// the frames have few enough colors, the palette holds them exactly.
+#[derive(Clone, Debug)]
+struct Palette {
+    colors: Vec<[u8; 3]>,
+    exact: Option<HashMap<[u8; 3], u8>>,
+    cache: Vec<u8>,
+    cached: Vec<bool>,
+}
+
+impl Palette {
+    fn new<'a>(images: impl Iterator<Item = &'a [u8]>) -> Self {
+        let shift = 8 - HISTOGRAM_BITS;
+        let mut bins = vec![Bin::default(); 1 << (3 * HISTOGRAM_BITS)];
+        let mut distinct = Some(HashSet::new());
+        for pixels in images {
+            let mut previous = None;
+            for pixel in pixels.chunks_exact(3) {
+                // Runs of one color are common on screen, so only color
+                // changes are looked up.
+                if let Some(colors) = distinct.as_mut().filter(|_| previous != Some(pixel)) {
+                    colors.insert([pixel[0], pixel[1], pixel[2]]);
+                    if colors.len() > MAX_COLORS {
+                        distinct = None;
+                    }
+                }
+                previous = Some(pixel);
+                let key = ((pixel[0] as usize >> shift) << (2 * HISTOGRAM_BITS))
+                    | ((pixel[1] as usize >> shift) << HISTOGRAM_BITS)
+                    | (pixel[2] as usize >> shift);
+                let bin = &mut bins[key];
+                bin.count += 1;
+                for (sum, value) in bin.sum.iter_mut().zip(pixel) {
+                    *sum += *value as u64;
+                }
+            }
+        }
+        if let Some(colors) = distinct.filter(|colors| !colors.is_empty()) {
+            let mut colors: Vec<[u8; 3]> = colors.into_iter().collect();
+            colors.sort_unstable();
+            let exact = colors.iter().enumerate().map(|(index, color)| (*color, index as u8)).collect();
+            return Self {
+                colors,
+                exact: Some(exact),
+                cache: Vec::new(),
+                cached: Vec::new(),
+            };
+        }
+        let coordinate = |key: usize, channel: usize| (key >> (HISTOGRAM_BITS as usize * (2 - channel))) & ((1 << HISTOGRAM_BITS) - 1);
+        let mut boxes: Vec<Vec<usize>> = vec![(0..bins.len()).filter(|key| bins[*key].count > 0).collect()];
+        while boxes.len() < MAX_COLORS {
+            // Split the box with the most pixels times its widest extent.
+            let mut best = None;
+            for (index, keys) in boxes.iter().enumerate().filter(|(_, keys)| keys.len() > 1) {
+                let count: u64 = keys.iter().map(|key| bins[*key].count).sum();
+                let (channel, range) = (0..3)
+                    .map(|channel| {
+                        let values = keys.iter().map(|key| coordinate(*key, channel));
+                        (channel, values.clone().max().unwrap() - values.min().unwrap())
+                    })
+                    .max_by_key(|(_, range)| *range)
+                    .unwrap();
+                let score = count * range as u64;
+                if score > 0 && best.map_or(true, |(_, _, best_score)| score > best_score) {
+                    best = Some((index, channel, score));
+                }
+            }
+            let (index, channel) = match best {
+                Some((index, channel, _)) => (index, channel),
+                None => break,
+            };
+            let mut keys = boxes.swap_remove(index);
+            keys.sort_unstable_by_key(|key| coordinate(*key, channel));
+            let total: u64 = keys.iter().map(|key| bins[*key].count).sum();
+            let mut running = 0;
+            let mut split = keys.len() - 1;
+            for (position, key) in keys.iter().enumerate() {
+                running += bins[*key].count;
+                if running * 2 >= total {
+                    split = position + 1;
+                    break;
+                }
+            }
+            let upper = keys.split_off(split.clamp(1, keys.len() - 1));
+            boxes.push(keys);
+            boxes.push(upper);
+        }
+        let mut colors: Vec<[u8; 3]> = boxes
+            .iter()
+            .filter(|keys| !keys.is_empty())
+            .map(|keys| {
+                let count: u64 = keys.iter().map(|key| bins[*key].count).sum();
+                let mut color = [0; 3];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    let sum: u64 = keys.iter().map(|key| bins[*key].sum[channel]).sum();
+                    *value = ((sum + count / 2) / count) as u8;
+                }
+                color
+            })
+            .collect();
+        if colors.is_empty() {
+            colors.push([0; 3]);
+        }
+        colors.sort_unstable();
+        colors.dedup();
+        Self {
+            colors,
+            exact: None,
+            cache: vec![0; 1 << (3 * CACHE_BITS)],
+            cached: vec![false; 1 << (3 * CACHE_BITS)],
+        }
+    }
+
+    /// Index of the transparent color, right after the palette colors.
+    fn transparent(&self) -> u8 {
+        self.colors.len() as u8
+    }
+
+    /// Bits per index of the color table, which holds the transparent color
+    /// too.
+    fn bits(&self) -> u32 {
+        (usize::BITS - self.colors.len().leading_zeros()).max(1)
+    }
+
+    fn nearest(&mut self, rgb: [i32; 3]) -> u8 {
+        let rgb = rgb.map(|value| value.clamp(0, 255));
+        let shift = 8 - CACHE_BITS;
+        let key = ((rgb[0] as usize >> shift) << (2 * CACHE_BITS)) | ((rgb[1] as usize >> shift) << CACHE_BITS) | (rgb[2] as usize >> shift);
+        if !self.cached[key] {
+            let distance = |color: &[u8; 3]| (0..3).map(|channel| (color[channel] as i32 - rgb[channel]).pow(2)).sum::<i32>();
+            let (index, _) = self.colors.iter().enumerate().min_by_key(|(_, color)| distance(color)).unwrap();
+            self.cache[key] = index as u8;
+            self.cached[key] = true;
+        }
+        self.cache[key]
+    }
+
+    /// Maps packed RGB pixels to palette indices, dithering unless the
+    /// palette holds every color.
+    fn map(&mut self, pixels: &[u8], width: usize, dither: Dither) -> Vec<u8> {
+        if let Some(exact) = &self.exact {
+            return pixels.chunks_exact(3).map(|pixel| exact[&[pixel[0], pixel[1], pixel[2]]]).collect();
+        }
+        let height = pixels.len() / 3 / width.max(1);
+        let mut indices = Vec::with_capacity(width * height);
+        let mut errors = vec![[0i32; 3]; 2 * (width + 2)];
+        for y in 0..height {
+            if dither == Dither::FloydSteinberg {
+                let (current, next) = errors.split_at_mut(width + 2);
+                current.copy_from_slice(next);
+                next.iter_mut().for_each(|error| *error = [0; 3]);
+            }
+            for x in 0..width {
+                let offset = (y * width + x) * 3;
+                let pixel = [pixels[offset] as i32, pixels[offset + 1] as i32, pixels[offset + 2] as i32];
+                let index = match dither {
+                    Dither::None => self.nearest(pixel),
+                    Dither::Ordered => {
+                        let bias = (BAYER[y % 8][x % 8] as i32 * 2 - 63) / 4;
+                        self.nearest(pixel.map(|value| value + bias))
+                    }
+                    Dither::FloydSteinberg => {
+                        let error = errors[x + 1];
+                        let wanted = [0, 1, 2].map(|channel| (pixel[channel] + error[channel] / 16).clamp(0, 255));
+                        let index = self.nearest(wanted);
+                        let color = self.colors[index as usize];
+                        for channel in 0..3 {
+                            let error = wanted[channel] - color[channel] as i32;
+                            errors[x + 2][channel] += error * 7;
+                            errors[width + 2 + x][channel] += error * 3;
+                            errors[width + 2 + x + 1][channel] += error * 5;
+                            errors[width + 2 + x + 2][channel] += error;
+                        }
+                        index
+                    }
+                };
+                indices.push(index);
+            }
+        }
+        indices
+    }
+}
+
+/// Packs variable width codes, least significant bit first, into GIF data
+/// sub-blocks.
+struct CodeWriter {
+    output: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.bits |= (code as u32) << self.count;
+        self.count += width;
+        while self.count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.bits as u8);
+        }
+        let mut blocks = Vec::with_capacity(self.output.len() + self.output.len() / 255 + 2);
+        for chunk in self.output.chunks(255) {
+            blocks.push(chunk.len() as u8);
+            blocks.extend_from_slice(chunk);
+        }
+        blocks.push(0);
+        blocks
+    }
+}
+
+/// LZW compresses palette indices as GIF image data, without the leading
+/// code size byte.
+fn lzw_compress(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    const TABLE_SIZE: usize = 1 << 13;
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = CodeWriter {
+        output: Vec::with_capacity(indices.len() / 2),
+        bits: 0,
+        count: 0,
+    };
+    // Open addressing table from (prefix code, index) to code.
+    let mut keys = vec![u32::MAX; TABLE_SIZE];
+    let mut codes = vec![0u16; TABLE_SIZE];
+    let slot = |key: u32| (key.wrapping_mul(0x9e37_79b1) >> 19) as usize;
+
+    let mut width = min_code_size + 1;
+    let mut next = end + 1;
+    writer.write(clear, width);
+    let (first, rest) = match indices.split_first() {
+        Some(split) => split,
+        None => {
+            writer.write(end, width);
+            return writer.finish();
+        }
+    };
+    let mut prefix = *first as u16;
+    for index in rest {
+        let key = ((prefix as u32) << 8) | *index as u32;
+        let mut position = slot(key);
+        while keys[position] != u32::MAX && keys[position] != key {
+            position = (position + 1) % TABLE_SIZE;
+        }
+        if keys[position] == key {
+            prefix = codes[position];
+            continue;
+        }
+        writer.write(prefix, width);
+        if next < MAX_CODE {
+            keys[position] = key;
+            codes[position] = next;
+            next += 1;
+            // The decoder adds each code a step later, so widths change when
+            // the encoder is one code past the limit.
+            if next > 1 << width && width < 12 {
+                width += 1;
+            }
+        } else {
+            writer.write(clear, width);
+            keys.iter_mut().for_each(|key| *key = u32::MAX);
+            width = min_code_size + 1;
+            next = end + 1;
+        }
+        prefix = *index as u16;
+    }
+    writer.write(prefix, width);
+    if next < MAX_CODE && next + 1 > 1 << width && width < 12 {
+        width += 1;
+    }
+    writer.write(end, width);
+    writer.finish()
+}
+
+/// Writes frames as an animated GIF with one palette shared by every frame,
+/// shown for the duration paired with them. `loop_count` is the number of
+/// repetitions, zero for forever. Frames after the first only store the
+/// area that changed, with unchanged pixels transparent, and frames without
+/// changes extend the previous one. Every frame must have the size of the
+/// first.
+pub fn write_gif<W: Write>(
+    mut writer: W,
+    frames: &[(VideoFrame, Duration)],
+    loop_count: u16,
+    dither: Dither,
+    matrix: ColorMatrix,
+) -> Result<(), ImageError> {
+    let (first, _) = frames.first().ok_or(ImageError::NoFrames)?;
+    let (width, height) = (first.width(), first.height());
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(ImageError::TooLarge);
+    }
+    let images = frames
+        .iter()
+        .map(|(frame, _)| {
+            if frame.width() != width || frame.height() != height {
+                return Err(ImageError::SizeChanged);
+            }
+            Ok(rgb_pixels(frame, matrix, false)?.0)
+        })
+        .collect::<Result<Vec<_>, ImageError>>()?;
+    let mut palette = Palette::new(images.iter().map(Vec::as_slice));
+    let bits = palette.bits();
+    let min_code_size = bits.max(2);
+    let transparent = palette.transparent();
+
+    // Index frames first so frames without changes can extend the delay of
+    // the previous one.
+    let mut encoded: Vec<(PixelRect, Vec<u8>, u64)> = Vec::with_capacity(frames.len());
+    let mut canvas: Option<Vec<u8>> = None;
+    let mut elapsed = Duration::ZERO;
+    let mut shown = 0u64;
+    for (pixels, (_, delay)) in images.iter().zip(frames) {
+        elapsed += *delay;
+        // Delays are in hundredths of a second, rounded on the running total
+        // so they don't drift. Most viewers slow down shorter delays.
+        let until = (elapsed.as_millis() as u64 + 5) / 10;
+        let indices = palette.map(pixels, width, dither);
+        let rect = match &canvas {
+            None => Some(PixelRect::new(0, 0, width as u32, height as u32)),
+            Some(canvas) => changed_rect(canvas, &indices, width, 1),
+        };
+        match rect {
+            None => {
+                if let Some(last) = encoded.last_mut() {
+                    last.2 += until.saturating_sub(shown);
+                }
+            }
+            Some(rect) => {
+                let mut data = Vec::with_capacity(rect.area() as usize);
+                for row in rect.y as usize..rect.bottom() as usize {
+                    for column in rect.x as usize..rect.right() as usize {
+                        let index = indices[row * width + column];
+                        let unchanged = canvas.as_ref().is_some_and(|canvas| canvas[row * width + column] == index);
+                        data.push(if unchanged {
+                            transparent
+                        } else {
+                            index
+                        });
+                    }
+                }
+                encoded.push((rect, data, until.saturating_sub(shown)));
+            }
+        }
+        shown = shown.max(until);
+        canvas = Some(indices);
+    }
+
+    writer.write_all(b"GIF89a")?;
+    writer.write_all(&(width as u16).to_le_bytes())?;
+    writer.write_all(&(height as u16).to_le_bytes())?;
+    let table_bits = (bits - 1) as u8;
+    writer.write_all(&[0x80 | (table_bits << 4) | table_bits, 0, 0])?;
+    let mut table = vec![0u8; 3 << bits];
+    for (entry, color) in table.chunks_exact_mut(3).zip(&palette.colors) {
+        entry.copy_from_slice(color);
+    }
+    writer.write_all(&table)?;
+    writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01")?;
+    writer.write_all(&loop_count.to_le_bytes())?;
+    writer.write_all(&[0])?;
+    for (rect, data, delay) in encoded {
+        let delay = delay.clamp(2, u16::MAX as u64) as u16;
+        // Graphic control: keep the frame in place and treat the transparent
+        // index as transparent.
+        writer.write_all(&[0x21, 0xf9, 4, (1 << 2) | 1])?;
+        writer.write_all(&delay.to_le_bytes())?;
+        writer.write_all(&[transparent, 0])?;
+        writer.write_all(&[0x2c])?;
+        for value in [rect.x, rect.y, rect.width, rect.height] {
+            writer.write_all(&(value as u16).to_le_bytes())?;
+        }
+        writer.write_all(&[0, min_code_size as u8])?;
+        writer.write_all(&lzw_compress(&data, min_code_size))?;
+    }
+    writer.write_all(&[0x3b])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+
+    /// Joins GIF data sub-blocks, returning the data and the bytes read.
+    fn sub_blocks(data: &[u8]) -> (Vec<u8>, usize) {
+        let (mut joined, mut position) = (Vec::new(), 0);
+        while data[position] != 0 {
+            let length = data[position] as usize;
+            joined.extend_from_slice(&data[position + 1..position + 1 + length]);
+            position += 1 + length;
+        }
+        (joined, position + 1)
+    }
+
+    fn lzw_decompress(data: &[u8], min_code_size: u32) -> Vec<u8> {
+        let (data, _) = sub_blocks(data);
+        let (clear, end) = (1u16 << min_code_size, (1u16 << min_code_size) + 1);
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let reset = |table: &mut Vec<Vec<u8>>| {
+            table.clear();
+            table.extend((0..=end).map(|code| vec![code as u8]));
+        };
+        reset(&mut table);
+        let (mut width, mut bits, mut count, mut position) = (min_code_size + 1, 0u32, 0u32, 0);
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+        loop {
+            while count < width {
+                bits |= (data[position] as u32) << count;
+                position += 1;
+                count += 8;
+            }
+            let code = (bits & ((1 << width) - 1)) as u16;
+            bits >>= width;
+            count -= width;
+            if code == clear {
+                reset(&mut table);
+                width = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) if code as usize == table.len() => [&previous[..], &previous[..1]].concat(),
+                _ => panic!("invalid code {}", code),
+            };
+            if let Some(previous) = previous.filter(|_| table.len() < MAX_CODE as usize) {
+                table.push([&previous[..], &entry[..1]].concat());
+                if table.len() == 1 << width && width < 12 {
+                    width += 1;
+                }
+            }
+            output.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    struct Image {
+        rect: PixelRect,
+        delay: u16,
+        transparent: Option<u8>,
+        indices: Vec<u8>,
+    }
+
+    /// Parses the output of `write_gif`, returning the size, global color
+    /// table, loop count and images.
+    fn parse_gif(data: &[u8]) -> ((usize, usize), Vec<[u8; 3]>, u16, Vec<Image>) {
+        assert_eq!(&data[..6], b"GIF89a");
+        let u16_at = |position: usize| u16::from_le_bytes([data[position], data[position + 1]]);
+        let size = (u16_at(6) as usize, u16_at(8) as usize);
+        assert_eq!(data[10] & 0x80, 0x80);
+        let table_size = 2 << (data[10] & 7);
+        let table = data[13..13 + 3 * table_size]
+            .chunks_exact(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect();
+        let mut position = 13 + 3 * table_size;
+        let (mut loop_count, mut control, mut images) = (None, None, Vec::new());
+        loop {
+            match data[position] {
+                0x21 if data[position + 1] == 0xff => {
+                    assert_eq!(&data[position + 3..position + 14], b"NETSCAPE2.0");
+                    loop_count = Some(u16_at(position + 16));
+                    position += 19;
+                }
+                0x21 if data[position + 1] == 0xf9 => {
+                    let flags = data[position + 3];
+                    control = Some((u16_at(position + 4), Some(data[position + 6]).filter(|_| flags & 1 == 1)));
+                    position += 8;
+                }
+                0x2c => {
+                    let rect = PixelRect::new(
+                        u16_at(position + 1) as u32,
+                        u16_at(position + 3) as u32,
+                        u16_at(position + 5) as u32,
+                        u16_at(position + 7) as u32,
+                    );
+                    assert_eq!(data[position + 9], 0, "no local color table");
+                    let min_code_size = data[position + 10] as u32;
+                    let indices = lzw_decompress(&data[position + 11..], min_code_size);
+                    assert_eq!(indices.len(), rect.area() as usize);
+                    let (delay, transparent) = control.take().expect("graphic control before each image");
+                    images.push(Image {
+                        rect,
+                        delay,
+                        transparent,
+                        indices,
+                    });
+                    position += 11 + sub_blocks(&data[position + 11..]).1;
+                }
+                0x3b => return (size, table, loop_count.unwrap(), images),
+                byte => panic!("unexpected block {:#x}", byte),
+            }
+        }
+    }
+
+    fn rgb_frame(width: usize, height: usize, pixel: impl Fn(usize, usize) -> [u8; 3]) -> VideoFrame {
+        let data: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect();
+        VideoFrame::from_bytes(width, height, PixelFormat::RGB24, &data).unwrap()
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let mut state = 0x1234_5678u32;
+        let mut random = |range: u32| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state % range) as u8
+        };
+        for min_code_size in [2, 4, 8] {
+            let colors = 1 << min_code_size;
+            let inputs = [
+                Vec::new(),
+                vec![1],
+                vec![0; 10000],
+                (0..20000).map(|_| random(colors)).collect(),
+                (0..20000).map(|index| (index / 7 % colors as usize) as u8).collect::<Vec<_>>(),
+            ];
+            for input in &inputs {
+                assert_eq!(
+                    &lzw_decompress(&lzw_compress(input, min_code_size), min_code_size),
+                    input,
+                    "{} bits, {} indices",
+                    min_code_size,
+                    input.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn frames_delays_and_transparency() {
+        let background = |_: usize, _: usize| [0, 0, 255];
+        let with_box = |x: usize, y: usize| {
+            if (4..8).contains(&x) && (2..5).contains(&y) && (x + y) % 2 == 0 {
+                [255, 0, 0]
+            } else {
+                [0, 0, 255]
+            }
+        };
+        let frames = [
+            (rgb_frame(12, 8, background), Duration::from_millis(100)),
+            (rgb_frame(12, 8, background), Duration::from_millis(100)),
+            (rgb_frame(12, 8, with_box), Duration::from_millis(250)),
+            (rgb_frame(12, 8, with_box), Duration::from_millis(5)),
+        ];
+        let mut data = Vec::new();
+        write_gif(&mut data, &frames, 3, Dither::None, ColorMatrix::default()).unwrap();
+
+        let (size, table, loop_count, images) = parse_gif(&data);
+        assert_eq!((size, loop_count), ((12, 8), 3));
+        // Unchanged frames extend the previous delay; delays are rounded on
+        // the running total and never below two hundredths.
+        assert_eq!(images.len(), 2);
+        assert_eq!(images.iter().map(|image| image.delay).collect::<Vec<_>>(), [20, 26]);
+        assert_eq!(images[0].rect, PixelRect::new(0, 0, 12, 8));
+        assert_eq!(images[1].rect, PixelRect::new(4, 2, 4, 3));
+
+        // Two exact colors and the transparent index after them.
+        let transparent = images[0].transparent.unwrap();
+        assert_eq!(transparent, 2);
+        assert!(images.iter().all(|image| image.transparent == Some(transparent)));
+        let mut canvas = vec![[0; 3]; 12 * 8];
+        for image in &images {
+            for (offset, index) in image.indices.iter().enumerate() {
+                let (x, y) = (
+                    image.rect.x as usize + offset % image.rect.width as usize,
+                    image.rect.y as usize + offset / image.rect.width as usize,
+                );
+                if *index != transparent {
+                    canvas[y * 12 + x] = table[*index as usize];
+                } else {
+                    assert_eq!(canvas[y * 12 + x], [0, 0, 255], "only unchanged pixels are transparent");
+                }
+            }
+        }
+        assert_eq!(canvas, (0..8).flat_map(|y| (0..12).map(move |x| with_box(x, y))).collect::<Vec<_>>());
+    }
+}
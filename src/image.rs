@@ -0,0 +1,369 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(target_os = "macos")]
+use core_foundation::{base::TCFType, string::CFString};
+
+#[cfg(target_os = "macos")]
+use crate::stream::SCStreamConfiguration;
+use crate::{
+    clock::cmtime_to_nanos,
+    convert::ColorMatrix,
+    frame::{FrameError, PixelFormat, VideoFrame},
+    jpeg::write_jpeg,
+    png::write_png,
+    pnm::{write_pam, write_ppm},
+    region::PixelRect,
+    sink::{SinkError, VideoSink},
+};
+
+/// The RGB color space pixels are tagged with in exported images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorProfile {
+    Srgb,
+    DisplayP3,
+}
+
+impl ColorProfile {
+    /// Parses a `CGColorSpace` name such as `kCGColorSpaceSRGB`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kCGColorSpaceSRGB" => Some(Self::Srgb),
+            "kCGColorSpaceDisplayP3" => Some(Self::DisplayP3),
+            _ => None,
+        }
+    }
+
+    /// The color space set with `set_color_space_name`, if it is one of the
+    /// supported profiles.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Option<Self> {
+        let name = configuration.get_color_space_name();
+        if name.is_null() {
+            return None;
+        }
+        Self::from_name(&unsafe { CFString::wrap_under_get_rule(name) }.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    /// Binary portable pixmap, `P6`.
+    Ppm,
+    /// Portable arbitrary map, `P7`, which can carry alpha.
+    Pam,
+}
+
+impl ImageFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "ppm" => Some(Self::Ppm),
+            "pam" => Some(Self::Pam),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        path.as_ref().extension().and_then(OsStr::to_str).and_then(Self::from_extension)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Ppm => "ppm",
+            Self::Pam => "pam",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// Matrix used to convert YUV frames to RGB.
+    pub color_matrix: ColorMatrix,
+    /// Profile tagged on PNG images. Other formats can't carry it.
+    pub color_profile: Option<ColorProfile>,
+    /// JPEG quality from 1 to 100.
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            color_matrix: ColorMatrix::default(),
+            color_profile: None,
+            jpeg_quality: 90,
+        }
+    }
+}
+
+impl ImageOptions {
+    /// Options matching the color matrix and color space a stream was
+    /// configured with.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Self {
+        Self {
+            color_matrix: ColorMatrix::from_configuration(configuration).unwrap_or_default(),
+            color_profile: ColorProfile::from_configuration(configuration),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Frame(FrameError),
+    Io(io::Error),
+    UnknownFormat,
+    /// The frame is larger than the format can describe.
+    TooLarge,
+    /// An animation was encoded without frames.
+    NoFrames,
+    /// A frame of an animation differs in size from the first one.
+    SizeChanged,
+    /// An animation couldn't be made to fit its size budget.
+    OverBudget,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frame(error) => write!(f, "{}", error),
+            Self::Io(error) => write!(f, "{}", error),
+            Self::UnknownFormat => write!(f, "unknown image format"),
+            Self::TooLarge => write!(f, "frame too large for the image format"),
+            Self::NoFrames => write!(f, "no frames to encode"),
+            Self::SizeChanged => write!(f, "frame size changed mid animation"),
+            Self::OverBudget => write!(f, "animation exceeds its size budget"),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Frame(error) => Some(error),
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for ImageError {
+    fn from(error: FrameError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Converts a frame to tightly packed 8 bit RGB, or RGBA when `keep_alpha`
+/// is set and some pixel isn't opaque. Returns the pixels and channel count.
+pub(crate) fn rgb_pixels(frame: &VideoFrame, matrix: ColorMatrix, keep_alpha: bool) -> Result<(Vec<u8>, usize), ImageError> {
+    if frame.width() > u32::MAX as usize || frame.height() > u32::MAX as usize {
+        return Err(ImageError::TooLarge);
+    }
+    let has_alpha = !frame.pixel_format().is_yuv() && frame.pixel_format() != PixelFormat::RGB24;
+    if keep_alpha && has_alpha {
+        let rgba = frame.convert(PixelFormat::RGBA, matrix)?;
+        let plane = &rgba.planes()[0];
+        if plane.rows().any(|row| row.chunks_exact(4).any(|pixel| pixel[3] != 255)) {
+            return Ok((plane.rows().flatten().copied().collect(), 4));
+        }
+    }
+    let rgb = frame.convert(PixelFormat::RGB24, matrix)?;
+    Ok((rgb.planes()[0].rows().flatten().copied().collect(), 3))
+}
+
+/// Bounds of the pixels that differ between two packed images of the same
+/// size, for animation frames that only store what changed.
+pub(crate) fn changed_rect(previous: &[u8], current: &[u8], width: usize, bytes_per_pixel: usize) -> Option<PixelRect> {
+    let row_bytes = width * bytes_per_pixel;
+    let height = current.len().checked_div(row_bytes).unwrap_or(0);
+    let row_changed = |y: usize| {
+        let range = y * row_bytes..(y + 1) * row_bytes;
+        previous[range.clone()] != current[range]
+    };
+    let top = (0..height).find(|y| row_changed(*y))?;
+    let bottom = (top..height).rev().find(|y| row_changed(*y)).unwrap() + 1;
+    let column_changed = |x: usize| {
+        (top..bottom).any(|y| {
+            let range = y * row_bytes + x * bytes_per_pixel..y * row_bytes + (x + 1) * bytes_per_pixel;
+            previous[range.clone()] != current[range]
+        })
+    };
+    let left = (0..width).find(|x| column_changed(*x)).unwrap();
+    let right = (left..width).rev().find(|x| column_changed(*x)).unwrap() + 1;
+    Some(PixelRect::new(left as u32, top as u32, (right - left) as u32, (bottom - top) as u32))
+}
+
+/// Encodes a frame in the given format.
+pub fn encode<W: Write>(writer: W, frame: &VideoFrame, format: ImageFormat, options: &ImageOptions) -> Result<(), ImageError> {
+    match format {
+        ImageFormat::Png => write_png(writer, frame, options.color_matrix, options.color_profile),
+        ImageFormat::Jpeg => write_jpeg(writer, frame, options.color_matrix, options.jpeg_quality),
+        ImageFormat::Ppm => write_ppm(writer, frame, options.color_matrix),
+        ImageFormat::Pam => write_pam(writer, frame, options.color_matrix),
+    }
+}
+
+/// Writes a frame to `path`, picking the format from the file extension.
+pub fn save<P: AsRef<Path>>(frame: &VideoFrame, path: P, options: &ImageOptions) -> Result<(), ImageError> {
+    let format = ImageFormat::from_path(&path).ok_or(ImageError::UnknownFormat)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(&mut writer, frame, format, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl VideoFrame {
+    pub fn save<P: AsRef<Path>>(&self, path: P, options: &ImageOptions) -> Result<(), ImageError> {
+        save(self, path, options)
+    }
+}
+
+/// Saves every frame as its own image file, named
+/// `<prefix>-<index>-<milliseconds>.<extension>` after its position and
+/// presentation time, so the files sort in capture order.
+#[derive(Clone, Debug)]
+pub struct ImageSequenceWriter {
+    directory: PathBuf,
+    prefix: String,
+    format: ImageFormat,
+    options: ImageOptions,
+    paths: Vec<PathBuf>,
+    finished: bool,
+}
+
+impl ImageSequenceWriter {
+    pub fn new<P: AsRef<Path>>(directory: P, prefix: &str, format: ImageFormat, options: ImageOptions) -> Self {
+        Self {
+            directory: directory.as_ref().to_owned(),
+            prefix: prefix.to_owned(),
+            format,
+            options,
+            paths: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    pub fn options(&self) -> &ImageOptions {
+        &self.options
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.paths.len() as u64
+    }
+
+    /// Files written so far, in order.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+impl VideoSink for ImageSequenceWriter {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let millis = cmtime_to_nanos(frame.presentation_time).unwrap_or_default() / 1_000_000;
+        let name = format!("{}-{:06}-{:010}.{}", self.prefix, self.paths.len(), millis, self.format.extension());
+        let path = self.directory.join(name);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        encode(&mut writer, frame, self.format, &self.options)?;
+        writer.flush()?;
+        self.paths.push(path);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.finished = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::clock::nanos_to_cmtime;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("image-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn frame(millis: u64, value: u8) -> VideoFrame {
+        let mut frame = VideoFrame::from_bytes(3, 2, PixelFormat::BGRA, &[value; 3 * 2 * 4]).unwrap();
+        frame.presentation_time = nanos_to_cmtime(millis * 1_000_000, 1000);
+        frame
+    }
+
+    #[test]
+    fn formats_from_paths() {
+        for &format in &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Ppm, ImageFormat::Pam] {
+            assert_eq!(ImageFormat::from_path(format!("shot.{}", format.extension())), Some(format));
+        }
+        assert_eq!(ImageFormat::from_path("shot.JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path("shot.gif"), None);
+        assert_eq!(ImageFormat::from_path("shot"), None);
+        assert!(matches!(
+            save(&frame(0, 0), "shot.gif", &ImageOptions::default()),
+            Err(ImageError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn sequence_names_files_in_capture_order() {
+        let directory = directory("sequence");
+        let mut writer = ImageSequenceWriter::new(&directory, "shot", ImageFormat::Ppm, ImageOptions::default());
+        writer.write_frame(&frame(1_500, 10)).unwrap();
+        writer.write_frame(&frame(12_345_678, 200)).unwrap();
+        assert_eq!(writer.frames_written(), 2);
+        assert_eq!(
+            writer.paths(),
+            [directory.join("shot-000000-0000001500.ppm"), directory.join("shot-000001-0012345678.ppm")]
+        );
+
+        // Each file holds exactly what encoding the frame produces.
+        for (path, value) in writer.paths().iter().zip([10, 200]) {
+            let mut expected = Vec::new();
+            encode(&mut expected, &frame(0, value), ImageFormat::Ppm, writer.options()).unwrap();
+            assert_eq!(fs::read(path).unwrap(), expected);
+        }
+
+        writer.finish().unwrap();
+        assert!(matches!(writer.write_frame(&frame(2_000, 0)), Err(SinkError::Finished)));
+        assert_eq!(writer.frames_written(), 2);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_are_not_recorded() {
+        let directory = directory("missing").join("missing");
+        let mut writer = ImageSequenceWriter::new(&directory, "shot", ImageFormat::Png, ImageOptions::default());
+        assert!(matches!(writer.write_frame(&frame(0, 0)), Err(SinkError::Io(_))));
+        assert!(writer.paths().is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::audio::{AudioBuffer, AudioError, AudioFormat, SampleFormat};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct JitterOptions {
+    /// Audio buffered before playback starts, and again after an underrun.
+    pub target_latency: Duration,
+    /// Buffered audio beyond this is discarded, oldest first, down to the
+    /// target latency.
+    pub max_latency: Duration,
+}
+
+impl Default for JitterOptions {
+    fn default() -> Self {
+        Self {
+            target_latency: Duration::from_millis(60),
+            max_latency: Duration::from_millis(250),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JitterStats {
+    /// Audio waiting to be pulled.
+    pub buffered_frames: usize,
+    pub latency: Duration,
+    /// Most audio buffered at once.
+    pub max_buffered_frames: usize,
+    pub pushed_frames: u64,
+    /// Captured frames handed to playback.
+    pub played_frames: u64,
+    /// Frames of silence handed to playback while buffering.
+    pub silence_frames: u64,
+    /// Captured frames thrown away on overruns.
+    pub discarded_frames: u64,
+    /// Times playback ran out of audio.
+    pub underruns: u64,
+    /// Times the buffer grew past the maximum latency.
+    pub overruns: u64,
+}
+
+#[derive(Debug)]
+struct JitterState {
+    /// Interleaved samples.
+    samples: VecDeque<f32>,
+    /// Whether playback waits for the target latency to be reached.
+    buffering: bool,
+    stats: JitterStats,
+}
+
+/// Smooths bursty audio delivery for live playback. Captured buffers are
+/// pushed as they arrive and a playback callback pulls interleaved `f32`
+/// samples at its own pace, getting silence until the target latency is
+/// buffered and after running dry. Clones share the same buffer, so one can
+/// be moved to the playback thread.
+#[derive(Clone, Debug)]
+pub struct JitterBuffer {
+    sample_rate: f64,
+    channels: usize,
+    options: JitterOptions,
+    state: Arc<Mutex<JitterState>>,
+}
+
+impl JitterBuffer {
+    pub fn new(sample_rate: f64, channels: usize, options: JitterOptions) -> Result<Self, AudioError> {
+        AudioFormat::new(sample_rate, channels, SampleFormat::F32, true).validate()?;
+        Ok(Self {
+            sample_rate,
+            channels,
+            options,
+            state: Arc::new(Mutex::new(JitterState {
+                samples: VecDeque::new(),
+                buffering: true,
+                stats: JitterStats::default(),
+            })),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, JitterState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn options(&self) -> &JitterOptions {
+        &self.options
+    }
+
+    fn duration_frames(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.sample_rate).round() as usize
+    }
+
+    fn frames_duration(&self, frames: usize) -> Duration {
+        Duration::from_secs_f64(frames as f64 / self.sample_rate)
+    }
+
+    /// Queues captured audio, which must have the buffer's sample rate and
+    /// channel count.
+    pub fn push(&self, audio: &AudioBuffer) -> Result<(), AudioError> {
+        if audio.sample_rate() != self.sample_rate {
+            return Err(AudioError::UnsupportedFormat);
+        }
+        if audio.channels() != self.channels {
+            return Err(AudioError::ChannelCount {
+                expected: self.channels,
+                actual: audio.channels(),
+            });
+        }
+        let samples = audio.to_interleaved_f32();
+        let target = self.duration_frames(self.options.target_latency);
+        let max = self.duration_frames(self.options.max_latency).max(target);
+        let channels = self.channels;
+
+        let mut state = self.state();
+        state.samples.extend(samples);
+        state.stats.pushed_frames += audio.frames() as u64;
+        let mut buffered = state.samples.len() / channels;
+        if buffered > max {
+            let discard = buffered - target;
+            state.samples.drain(..discard * channels);
+            state.stats.discarded_frames += discard as u64;
+            state.stats.overruns += 1;
+            buffered = target;
+        }
+        if state.buffering && buffered >= target {
+            state.buffering = false;
+        }
+        state.stats.max_buffered_frames = state.stats.max_buffered_frames.max(buffered);
+        Ok(())
+    }
+
+    /// Fills `output` with interleaved samples for playback, padding with
+    /// silence while buffering, and returns the number of captured frames
+    /// written.
+    ///
+    /// # Panics
+    ///
+    /// If the length of `output` isn't a multiple of the channel count.
+    pub fn pull(&self, output: &mut [f32]) -> usize {
+        assert_eq!(output.len() % self.channels, 0, "output must hold whole frames");
+        let frames = output.len() / self.channels;
+        let mut state = self.state();
+        let available = if state.buffering {
+            0
+        } else {
+            state.samples.len() / self.channels
+        };
+        let played = frames.min(available);
+        let copied = played * self.channels;
+        for (out, sample) in output[..copied].iter_mut().zip(state.samples.drain(..copied)) {
+            *out = sample;
+        }
+        output[copied..].iter_mut().for_each(|out| *out = 0.0);
+        if played < frames {
+            if !state.buffering {
+                state.stats.underruns += 1;
+                state.buffering = true;
+            }
+            state.stats.silence_frames += (frames - played) as u64;
+        }
+        state.stats.played_frames += played as u64;
+        played
+    }
+
+    /// Pulls `frames` frames as an interleaved `f32` buffer.
+    pub fn pull_buffer(&self, frames: usize) -> AudioBuffer {
+        let mut samples = vec![0.0; frames * self.channels];
+        self.pull(&mut samples);
+        AudioBuffer::from_interleaved_f32(self.sample_rate, self.channels, &samples).expect("format was validated on creation")
+    }
+
+    pub fn buffered_frames(&self) -> usize {
+        self.state().samples.len() / self.channels
+    }
+
+    /// Duration of the audio waiting to be pulled.
+    pub fn latency(&self) -> Duration {
+        self.frames_duration(self.buffered_frames())
+    }
+
+    /// Whether playback is getting silence until the target latency is
+    /// buffered.
+    pub fn is_buffering(&self) -> bool {
+        self.state().buffering
+    }
+
+    pub fn stats(&self) -> JitterStats {
+        let state = self.state();
+        let (stats, buffered_frames) = (state.stats, state.samples.len() / self.channels);
+        drop(state);
+        JitterStats {
+            buffered_frames,
+            latency: self.frames_duration(buffered_frames),
+            ..stats
+        }
+    }
+
+    /// Zeroes the counters, keeping the buffered audio.
+    pub fn reset_stats(&self) {
+        self.state().stats = JitterStats::default();
+    }
+
+    /// Drops the buffered audio and starts buffering again, e.g. when the
+    /// stream restarts.
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.samples.clear();
+        state.buffering = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer at 1 kHz, so frames are milliseconds, whose frames count up
+    /// from `first`, the same in both channels.
+    fn stereo(first: usize, frames: usize) -> AudioBuffer {
+        let samples: Vec<f32> = (first..first + frames).flat_map(|frame| vec![frame as f32; 2]).collect();
+        AudioBuffer::from_interleaved_f32(1000.0, 2, &samples).unwrap()
+    }
+
+    fn jitter_buffer() -> JitterBuffer {
+        JitterBuffer::new(1000.0, 2, JitterOptions::default()).unwrap()
+    }
+
+    /// Pulls `frames` frames, returning the first channel.
+    fn pull(jitter: &JitterBuffer, frames: usize) -> Vec<f32> {
+        jitter.pull_buffer(frames).channel_f32(0)
+    }
+
+    fn counting(range: std::ops::Range<usize>) -> Vec<f32> {
+        range.map(|frame| frame as f32).collect()
+    }
+
+    #[test]
+    fn silence_until_the_target_latency() {
+        let jitter = jitter_buffer();
+        jitter.push(&stereo(1, 59)).unwrap();
+        assert!(jitter.is_buffering());
+        assert_eq!(pull(&jitter, 10), [0.0; 10]);
+        assert_eq!(jitter.buffered_frames(), 59);
+
+        jitter.push(&stereo(60, 1)).unwrap();
+        assert!(!jitter.is_buffering());
+        assert_eq!(jitter.latency(), Duration::from_millis(60));
+        let mut output = [-1.0; 20];
+        assert_eq!(jitter.pull(&mut output), 10);
+        assert_eq!(output.iter().step_by(2).copied().collect::<Vec<_>>(), counting(1..11));
+
+        let stats = jitter.stats();
+        assert_eq!((stats.pushed_frames, stats.played_frames, stats.silence_frames), (60, 10, 10));
+        assert_eq!((stats.buffered_frames, stats.underruns), (50, 0));
+    }
+
+    #[test]
+    fn underruns_rebuffer() {
+        let jitter = jitter_buffer();
+        jitter.push(&stereo(0, 60)).unwrap();
+        // The rest of the audio, then silence for the rest of the pull.
+        let mut expected = counting(0..60);
+        expected.resize(80, 0.0);
+        assert_eq!(pull(&jitter, 80), expected);
+        assert!(jitter.is_buffering());
+        let stats = jitter.stats();
+        assert_eq!((stats.underruns, stats.played_frames, stats.silence_frames), (1, 60, 20));
+
+        // Audio arriving again isn't played until the target is reached.
+        jitter.push(&stereo(60, 30)).unwrap();
+        assert_eq!(pull(&jitter, 10), [0.0; 10]);
+        jitter.push(&stereo(90, 30)).unwrap();
+        assert_eq!(pull(&jitter, 10), counting(60..70));
+        // Still silent while buffering, which isn't another underrun.
+        let stats = jitter.stats();
+        assert_eq!((stats.underruns, stats.silence_frames), (1, 30));
+
+        jitter.clear();
+        assert!(jitter.is_buffering());
+        assert_eq!(jitter.buffered_frames(), 0);
+        jitter.reset_stats();
+        assert_eq!(jitter.stats(), JitterStats::default());
+    }
+
+    #[test]
+    fn overruns_discard_down_to_the_target() {
+        let jitter = jitter_buffer();
+        jitter.push(&stereo(0, 250)).unwrap();
+        assert_eq!(jitter.stats().overruns, 0);
+        jitter.push(&stereo(250, 1)).unwrap();
+        // The oldest audio goes, leaving the newest 60 ms.
+        let stats = jitter.stats();
+        assert_eq!((stats.overruns, stats.discarded_frames, stats.buffered_frames), (1, 191, 60));
+        assert_eq!(stats.max_buffered_frames, 250);
+        assert_eq!(pull(&jitter, 60), counting(191..251));
+
+        // A single burst larger than the maximum too.
+        jitter.push(&stereo(0, 1000)).unwrap();
+        assert_eq!(pull(&jitter, 60), counting(940..1000));
+        assert_eq!(jitter.stats().discarded_frames, 191 + 940);
+    }
+
+    #[test]
+    fn formats_are_checked() {
+        assert_eq!(
+            JitterBuffer::new(0.0, 2, JitterOptions::default()).unwrap_err(),
+            AudioError::InvalidFormat
+        );
+        let jitter = jitter_buffer();
+        let mono = AudioBuffer::from_interleaved_f32(1000.0, 1, &[0.0; 4]).unwrap();
+        assert_eq!(jitter.push(&mono), Err(AudioError::ChannelCount { expected: 2, actual: 1 }));
+        let other_rate = AudioBuffer::from_interleaved_f32(2000.0, 2, &[0.0; 4]).unwrap();
+        assert_eq!(jitter.push(&other_rate), Err(AudioError::UnsupportedFormat));
+        // Clones share the buffer.
+        jitter.clone().push(&stereo(0, 5)).unwrap();
+        assert_eq!(jitter.buffered_frames(), 5);
+    }
+}
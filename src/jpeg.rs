@@ -0,0 +1,471 @@
+use std::{f32::consts::PI, io::Write};
+
+use crate::{
+    convert::ColorMatrix,
+    frame::VideoFrame,
+    image::{rgb_pixels, ImageError},
+};
+
+/// Natural order index of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43,
+    36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// The example tables from Annex K of the JPEG specification.
+const LUMA_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56,
+    68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMA_QUANTIZATION: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+const LUMA_DC_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const CHROMA_DC_COUNTS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const LUMA_AC_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const LUMA_AC_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08,
+    0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
+    0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+    0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+];
+const CHROMA_AC_COUNTS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const CHROMA_AC_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91,
+    0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58,
+    0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+    0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+];
+
+/// Qualities from here on keep full resolution chroma, which keeps colored
+/// text in screenshots sharp.
+const FULL_CHROMA_QUALITY: u8 = 90;
+
+/// Scales a base quantization table the way libjpeg does, so qualities are
+/// comparable with other encoders.
+fn quantization_table(base: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    let mut table = [0; 64];
+    for (entry, base) in table.iter_mut().zip(base) {
+        *entry = ((*base as u32 * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    table
+}
+
+struct HuffmanTable {
+    codes: [u16; 256],
+    lengths: [u8; 256],
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], symbols: &[u8]) -> Self {
+        let mut table = Self {
+            codes: [0; 256],
+            lengths: [0; 256],
+        };
+        let mut code = 0u16;
+        let mut symbols = symbols.iter();
+        for (index, count) in counts.iter().enumerate() {
+            for symbol in symbols.by_ref().take(*count as usize) {
+                table.codes[*symbol as usize] = code;
+                table.lengths[*symbol as usize] = index as u8 + 1;
+                code += 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+struct BitWriter<W> {
+    writer: W,
+    bits: u32,
+    count: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn write(&mut self, value: u16, count: u8) -> Result<(), ImageError> {
+        self.bits = (self.bits << count) | (value as u32 & ((1 << count) - 1));
+        self.count += count as u32;
+        while self.count >= 8 {
+            let byte = (self.bits >> (self.count - 8)) as u8;
+            // 0xff in entropy coded data must be followed by a stuffed zero.
+            if byte == 0xff {
+                self.writer.write_all(&[0xff, 0])?;
+            } else {
+                self.writer.write_all(&[byte])?;
+            }
+            self.count -= 8;
+        }
+        self.bits &= (1 << self.count) - 1;
+        Ok(())
+    }
+
+    fn write_symbol(&mut self, table: &HuffmanTable, symbol: u8) -> Result<(), ImageError> {
+        self.write(table.codes[symbol as usize], table.lengths[symbol as usize])
+    }
+
+    /// Pads the last byte with one bits.
+    fn flush(&mut self) -> Result<(), ImageError> {
+        if self.count > 0 {
+            let padding = 8 - self.count as u8;
+            self.write((1 << padding) - 1, padding)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bit length of a coefficient, and the bits encoding it.
+fn magnitude(value: i32) -> (u8, u16) {
+    let size = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 {
+        value - 1
+    } else {
+        value
+    };
+    (size, bits as u16 & ((1u32 << size) - 1) as u16)
+}
+
+struct Component {
+    quantization: [f32; 64],
+    dc: HuffmanTable,
+    ac: HuffmanTable,
+    predictor: i32,
+}
+
+impl Component {
+    fn encode_block<W: Write>(&mut self, writer: &mut BitWriter<W>, block: &[f32; 64], cosines: &[[f32; 8]; 8]) -> Result<(), ImageError> {
+        let coefficients = forward_dct(block, cosines);
+        let mut quantized = [0i32; 64];
+        for (index, natural) in ZIGZAG.iter().enumerate() {
+            quantized[index] = (coefficients[*natural] / self.quantization[*natural]).round() as i32;
+        }
+
+        let difference = quantized[0] - self.predictor;
+        self.predictor = quantized[0];
+        let (size, bits) = magnitude(difference);
+        writer.write_symbol(&self.dc, size)?;
+        writer.write(bits, size)?;
+
+        let mut zeros = 0;
+        for coefficient in &quantized[1..] {
+            if *coefficient == 0 {
+                zeros += 1;
+                continue;
+            }
+            while zeros >= 16 {
+                writer.write_symbol(&self.ac, 0xf0)?;
+                zeros -= 16;
+            }
+            let (size, bits) = magnitude(*coefficient);
+            writer.write_symbol(&self.ac, (zeros << 4) | size)?;
+            writer.write(bits, size)?;
+            zeros = 0;
+        }
+        if zeros > 0 {
+            writer.write_symbol(&self.ac, 0x00)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cosine basis scaled so the transform is orthonormal, indexed by
+/// frequency then sample.
+fn dct_cosines() -> [[f32; 8]; 8] {
+    let mut cosines = [[0.0; 8]; 8];
+    for (frequency, row) in cosines.iter_mut().enumerate() {
+        let scale = if frequency == 0 {
+            (1.0f32 / 8.0).sqrt()
+        } else {
+            (2.0f32 / 8.0).sqrt()
+        };
+        for (sample, value) in row.iter_mut().enumerate() {
+            *value = scale * ((2 * sample + 1) as f32 * frequency as f32 * PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+fn forward_dct(block: &[f32; 64], cosines: &[[f32; 8]; 8]) -> [f32; 64] {
+    let mut rows = [0.0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| cosines[u][x] * block[y * 8 + x]).sum();
+        }
+    }
+    let mut output = [0.0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            output[v * 8 + u] = (0..8).map(|y| cosines[v][y] * rows[y * 8 + u]).sum();
+        }
+    }
+    output
+}
+
+fn write_marker<W: Write>(writer: &mut W, marker: u8, data: &[u8]) -> Result<(), ImageError> {
+    writer.write_all(&[0xff, marker])?;
+    writer.write_all(&(data.len() as u16 + 2).to_be_bytes())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// Writes a frame as a baseline JFIF JPEG. `quality` ranges from 1 to 100
+/// like libjpeg's; below 90 chroma is subsampled 2x2.
+pub fn write_jpeg<W: Write>(mut writer: W, frame: &VideoFrame, matrix: ColorMatrix, quality: u8) -> Result<(), ImageError> {
+    let (pixels, _) = rgb_pixels(frame, matrix, false)?;
+    let width = frame.width();
+    let height = frame.height();
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(ImageError::TooLarge);
+    }
+    let subsampled = quality < FULL_CHROMA_QUALITY;
+    let luma_quantization = quantization_table(&LUMA_QUANTIZATION, quality);
+    let chroma_quantization = quantization_table(&CHROMA_QUANTIZATION, quality);
+
+    writer.write_all(&[0xff, 0xd8])?;
+    write_marker(&mut writer, 0xe0, &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0])?;
+    for (id, table) in [&luma_quantization, &chroma_quantization].iter().enumerate() {
+        let mut data = vec![id as u8];
+        data.extend(ZIGZAG.iter().map(|natural| table[*natural] as u8));
+        write_marker(&mut writer, 0xdb, &data)?;
+    }
+    let luma_sampling = if subsampled {
+        0x22
+    } else {
+        0x11
+    };
+    let mut frame_header = vec![8];
+    frame_header.extend_from_slice(&(height as u16).to_be_bytes());
+    frame_header.extend_from_slice(&(width as u16).to_be_bytes());
+    frame_header.extend_from_slice(&[3, 1, luma_sampling, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    write_marker(&mut writer, 0xc0, &frame_header)?;
+    let tables: [(u8, &[u8; 16], &[u8]); 4] = [
+        (0x00, &LUMA_DC_COUNTS, &DC_SYMBOLS),
+        (0x10, &LUMA_AC_COUNTS, &LUMA_AC_SYMBOLS),
+        (0x01, &CHROMA_DC_COUNTS, &DC_SYMBOLS),
+        (0x11, &CHROMA_AC_COUNTS, &CHROMA_AC_SYMBOLS),
+    ];
+    for (class, counts, symbols) in tables.iter() {
+        let mut data = vec![*class];
+        data.extend_from_slice(*counts);
+        data.extend_from_slice(symbols);
+        write_marker(&mut writer, 0xc4, &data)?;
+    }
+    write_marker(&mut writer, 0xda, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0])?;
+
+    let new_component = |quantization: &[u16; 64], chroma: bool| {
+        let (dc, ac) = if chroma {
+            (
+                HuffmanTable::new(&CHROMA_DC_COUNTS, &DC_SYMBOLS),
+                HuffmanTable::new(&CHROMA_AC_COUNTS, &CHROMA_AC_SYMBOLS),
+            )
+        } else {
+            (
+                HuffmanTable::new(&LUMA_DC_COUNTS, &DC_SYMBOLS),
+                HuffmanTable::new(&LUMA_AC_COUNTS, &LUMA_AC_SYMBOLS),
+            )
+        };
+        let mut table = [0.0; 64];
+        for (entry, value) in table.iter_mut().zip(quantization) {
+            *entry = *value as f32;
+        }
+        Component {
+            quantization: table,
+            dc,
+            ac,
+            predictor: 0,
+        }
+    };
+    let mut components = [
+        new_component(&luma_quantization, false),
+        new_component(&chroma_quantization, true),
+        new_component(&chroma_quantization, true),
+    ];
+
+    // JFIF always uses full range BT.601 Y'CbCr, centered on zero for the DCT.
+    let mut planes = vec![vec![0.0f32; width * height]; 3];
+    for (index, pixel) in pixels.chunks_exact(3).enumerate() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        planes[0][index] = 0.299 * r + 0.587 * g + 0.114 * b - 128.0;
+        planes[1][index] = -0.168736 * r - 0.331264 * g + 0.5 * b;
+        planes[2][index] = 0.5 * r - 0.418688 * g - 0.081312 * b;
+    }
+    // Samples past the edges repeat the last row and column.
+    let sample = |plane: &[f32], x: usize, y: usize| plane[y.min(height - 1) * width + x.min(width - 1)];
+
+    let cosines = dct_cosines();
+    let mut bits = BitWriter {
+        writer: &mut writer,
+        bits: 0,
+        count: 0,
+    };
+    let mcu_size = if subsampled {
+        16
+    } else {
+        8
+    };
+    let mut block = [0.0f32; 64];
+    for mcu_y in (0..height).step_by(mcu_size) {
+        for mcu_x in (0..width).step_by(mcu_size) {
+            for block_y in (0..mcu_size).step_by(8) {
+                for block_x in (0..mcu_size).step_by(8) {
+                    for (index, value) in block.iter_mut().enumerate() {
+                        *value = sample(&planes[0], mcu_x + block_x + index % 8, mcu_y + block_y + index / 8);
+                    }
+                    components[0].encode_block(&mut bits, &block, &cosines)?;
+                }
+            }
+            for component in 1..3 {
+                for (index, value) in block.iter_mut().enumerate() {
+                    let (x, y) = (index % 8, index / 8);
+                    *value = if subsampled {
+                        let (x, y) = (mcu_x + x * 2, mcu_y + y * 2);
+                        let plane = &planes[component];
+                        (sample(plane, x, y) + sample(plane, x + 1, y) + sample(plane, x, y + 1) + sample(plane, x + 1, y + 1)) / 4.0
+                    } else {
+                        sample(&planes[component], mcu_x + x, mcu_y + y)
+                    };
+                }
+                components[component].encode_block(&mut bits, &block, &cosines)?;
+            }
+        }
+    }
+    bits.flush()?;
+    writer.write_all(&[0xff, 0xd9])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+
+    /// Splits a JPEG into its marker segments up to the start of scan,
+    /// returning them with the entropy coded data.
+    fn segments(jpeg: &[u8]) -> (Vec<(u8, Vec<u8>)>, Vec<u8>) {
+        assert_eq!(jpeg[..2], [0xff, 0xd8]);
+        assert_eq!(jpeg[jpeg.len() - 2..], [0xff, 0xd9]);
+        let mut segments = Vec::new();
+        let mut rest = &jpeg[2..];
+        loop {
+            assert_eq!(rest[0], 0xff);
+            let marker = rest[1];
+            let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            segments.push((marker, rest[4..2 + length].to_vec()));
+            rest = &rest[2 + length..];
+            if marker == 0xda {
+                return (segments, rest[..rest.len() - 2].to_vec());
+            }
+        }
+    }
+
+    fn frame(width: usize, height: usize) -> VideoFrame {
+        let data: Vec<u8> = (0..width * height).flat_map(|i| [(i * 3) as u8, (i * 5) as u8, 0xff, 255]).collect();
+        VideoFrame::from_bytes(width, height, PixelFormat::BGRA, &data).unwrap()
+    }
+
+    #[test]
+    fn headers() {
+        for &(quality, sampling) in &[(75, 0x22), (95, 0x11)] {
+            let mut jpeg = Vec::new();
+            write_jpeg(&mut jpeg, &frame(21, 13), ColorMatrix::default(), quality).unwrap();
+            let (segments, scan) = segments(&jpeg);
+            let markers: Vec<u8> = segments.iter().map(|(marker, _)| *marker).collect();
+            assert_eq!(markers, [0xe0, 0xdb, 0xdb, 0xc0, 0xc4, 0xc4, 0xc4, 0xc4, 0xda]);
+            assert_eq!(segments[0].1[..5], *b"JFIF\0");
+            assert_eq!(segments[1].1.len(), 65);
+            assert_eq!(segments[3].1, [8, 0, 13, 0, 21, 3, 1, sampling, 0, 2, 0x11, 1, 3, 0x11, 1]);
+            // 0xff bytes in entropy coded data are always stuffed.
+            assert!(!scan.is_empty());
+            for pair in scan.windows(2) {
+                assert!(pair[0] != 0xff || pair[1] == 0, "unstuffed marker in scan");
+            }
+            assert_ne!(scan.last(), Some(&0xff));
+        }
+    }
+
+    #[test]
+    fn quality_scales_quantization() {
+        let tables = |quality| {
+            let mut jpeg = Vec::new();
+            write_jpeg(&mut jpeg, &frame(8, 8), ColorMatrix::default(), quality).unwrap();
+            segments(&jpeg).0[1].1[1..].to_vec()
+        };
+        assert!(tables(100).iter().all(|value| *value == 1));
+        assert!(tables(10).iter().zip(tables(50)).all(|(low, high)| *low >= high));
+        assert!(tables(1).iter().all(|value| *value >= 1));
+    }
+
+    /// Decodes with an independent decoder, returning RGB pixels and the
+    /// largest and mean absolute difference from the frame's.
+    fn decode_error(frame: &VideoFrame, quality: u8) -> (u8, f64) {
+        let mut jpeg = Vec::new();
+        write_jpeg(&mut jpeg, frame, ColorMatrix::default(), quality).unwrap();
+        let mut decoder = jpeg_decoder::Decoder::new(&jpeg[..]);
+        let decoded = decoder.decode().unwrap();
+        let info = decoder.info().unwrap();
+        assert_eq!((info.width as usize, info.height as usize), (frame.width(), frame.height()));
+        assert_eq!(info.pixel_format, jpeg_decoder::PixelFormat::RGB24);
+        let (expected, _) = rgb_pixels(frame, ColorMatrix::default(), false).unwrap();
+        assert_eq!(decoded.len(), expected.len());
+        let differences: Vec<u8> = decoded
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| (*a as i16 - *b as i16).unsigned_abs() as u8)
+            .collect();
+        let mean = differences.iter().map(|difference| *difference as f64).sum::<f64>() / differences.len() as f64;
+        (differences.into_iter().max().unwrap(), mean)
+    }
+
+    #[test]
+    fn flat_colors_decode_to_their_dc_values() {
+        for &(b, g, r) in &[(0, 0, 0), (255, 255, 255), (40, 120, 200), (255, 0, 128)] {
+            let data: Vec<u8> = (0..19 * 11).flat_map(|_| [b, g, r, 255]).collect();
+            let frame = VideoFrame::from_bytes(19, 11, PixelFormat::BGRA, &data).unwrap();
+            for &quality in &[50, 75, 95] {
+                let (max, _) = decode_error(&frame, quality);
+                assert!(max <= 3, "{:?} at {}: off by {}", (b, g, r), quality, max);
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_round_trip() {
+        // Odd sizes leave partial blocks and macroblocks at the edges.
+        let (width, height) = (37, 21);
+        let data: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 6) as u8, (y * 10) as u8, (x * 3 + y * 5) as u8, 255]
+            })
+            .collect();
+        let frame = VideoFrame::from_bytes(width, height, PixelFormat::BGRA, &data).unwrap();
+        for &(quality, max_error, mean_error) in &[(95, 8, 1.5), (75, 16, 2.5)] {
+            let (max, mean) = decode_error(&frame, quality);
+            assert!(max <= max_error && mean <= mean_error, "quality {}: max {} mean {}", quality, max, mean);
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        let frame = VideoFrame::zeroed(u16::MAX as usize + 1, 1, PixelFormat::BGRA).unwrap();
+        assert!(matches!(
+            write_jpeg(Vec::new(), &frame, ColorMatrix::default(), 80),
+            Err(ImageError::TooLarge)
+        ));
+    }
+}
@@ -1,24 +1,69 @@
 #![allow(non_snake_case, non_camel_case_types, non_upper_case_globals, improper_ctypes)]
 
+#[cfg(target_os = "macos")]
 extern crate block2;
-#[cfg(feature = "audio")]
+#[cfg(all(target_os = "macos", feature = "audio"))]
 extern crate core_audio_types;
+#[cfg(target_os = "macos")]
 extern crate core_foundation;
+#[cfg(target_os = "macos")]
+extern crate core_foundation_0_10;
+#[cfg(target_os = "macos")]
 extern crate core_graphics2 as core_graphics;
+#[cfg(target_os = "macos")]
 extern crate core_media;
-#[cfg(feature = "video")]
+#[cfg(all(target_os = "macos", feature = "video"))]
 extern crate core_video;
+#[cfg(target_os = "macos")]
 extern crate dispatch2;
 extern crate libc;
+#[cfg(target_os = "macos")]
 #[macro_use]
 extern crate objc2;
+#[cfg(target_os = "macos")]
 extern crate objc2_foundation;
 
 #[cfg(target_os = "macos")]
 #[link(name = "ScreenCaptureKit", kind = "framework")]
 extern "C" {}
 
+pub mod animation;
+pub mod audio;
+pub mod avsync;
+pub mod change;
+pub mod clock;
+pub mod convert;
+mod deflate;
+pub mod delta;
+#[cfg(target_os = "macos")]
 pub mod encode;
 pub mod error;
+pub mod frame;
+pub mod frame_info;
+pub mod gif;
+pub mod image;
+pub mod jitter;
+pub mod jpeg;
+pub mod meter;
+#[cfg(target_os = "macos")]
+pub mod output;
+pub mod overlay;
+pub mod platform;
+pub mod png;
+pub mod pnm;
+pub mod pool;
+pub mod rawaudio;
+pub mod rawvideo;
+pub mod redact;
+pub mod region;
+pub mod replay;
+pub mod resample;
+pub mod scale;
+#[cfg(all(target_os = "macos", feature = "video"))]
+pub mod screenshot;
+pub mod segment;
+#[cfg(target_os = "macos")]
 pub mod shareable_content;
+pub mod sink;
 pub mod stream;
+pub mod timelapse;
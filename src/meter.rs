@@ -0,0 +1,598 @@
+use std::{collections::VecDeque, f64::consts::PI, time::Duration};
+
+use crate::{
+    audio::AudioBuffer,
+    clock::{cmtime_to_nanos, nanos_to_cmtime},
+    platform::CMTime,
+};
+
+/// Levels are measured in blocks of this length.
+const BLOCK: Duration = Duration::from_millis(10);
+
+/// Level reported for digital silence.
+pub const SILENCE_DB: f32 = -144.0;
+
+/// Decibels relative to full scale of an amplitude.
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+/// Number of whole blocks covering `duration`, at least one.
+fn blocks(duration: Duration) -> usize {
+    (((duration.as_nanos() + BLOCK.as_nanos() - 1) / BLOCK.as_nanos()) as usize).max(1)
+}
+
+/// A biquad filter in direct form II transposed.
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770: a high shelf modelling the head
+/// followed by a high pass.
+#[derive(Clone, Copy, Debug, Default)]
+struct KWeighting([Biquad; 2]);
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let k = (PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        };
+        let k = (PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        };
+        Self([shelf, high_pass])
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let shelved = self.0[0].process(x);
+        self.0[1].process(shelved)
+    }
+}
+
+/// BS.1770 weight of a channel in the loudness sum: surround channels of
+/// 5.1 count more and its LFE channel not at all.
+fn loudness_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Sums of one block of one channel.
+#[derive(Clone, Copy, Debug, Default)]
+struct BlockSums {
+    square: f64,
+    weighted_square: f64,
+    peak: f32,
+    samples: usize,
+}
+
+/// Splits a stream into blocks of [`BLOCK`], tracking presentation times.
+#[derive(Clone, Debug, Default)]
+struct Blocks {
+    sample_rate: f64,
+    channels: usize,
+    block_samples: usize,
+    filters: Vec<KWeighting>,
+    current: Vec<BlockSums>,
+    /// Time of the first sample of the current block, in nanoseconds.
+    block_start: u64,
+    /// Time of the next sample if the stream has no presentation times.
+    next_nanos: u64,
+}
+
+impl Blocks {
+    fn configure(&mut self, audio: &AudioBuffer) -> bool {
+        if self.sample_rate == audio.sample_rate() && self.channels == audio.channels() {
+            return false;
+        }
+        *self = Self {
+            sample_rate: audio.sample_rate(),
+            channels: audio.channels(),
+            block_samples: ((audio.sample_rate() * BLOCK.as_secs_f64()).round() as usize).max(1),
+            filters: vec![KWeighting::new(audio.sample_rate()); audio.channels()],
+            current: vec![BlockSums::default(); audio.channels()],
+            block_start: 0,
+            next_nanos: 0,
+        };
+        true
+    }
+
+    /// Feeds `audio`, calling `complete` with the sums and start time of
+    /// every block it completes.
+    fn push(&mut self, audio: &AudioBuffer, mut complete: impl FnMut(&[BlockSums], u64)) {
+        let start = cmtime_to_nanos(audio.presentation_time).unwrap_or(self.next_nanos);
+        let nanos_per_sample = 1e9 / self.sample_rate;
+        let samples = audio.to_interleaved_f32();
+        for (index, frame) in samples.chunks_exact(self.channels).enumerate() {
+            if self.current[0].samples == 0 {
+                self.block_start = start + (index as f64 * nanos_per_sample) as u64;
+            }
+            for ((sums, filter), &sample) in self.current.iter_mut().zip(&mut self.filters).zip(frame) {
+                let weighted = filter.process(sample as f64);
+                sums.square += sample as f64 * sample as f64;
+                sums.weighted_square += weighted * weighted;
+                sums.peak = sums.peak.max(sample.abs());
+                sums.samples += 1;
+            }
+            if self.current[0].samples == self.block_samples {
+                complete(&self.current, self.block_start);
+                self.current.iter_mut().for_each(|sums| *sums = BlockSums::default());
+            }
+        }
+        self.next_nanos = start + (audio.frames() as f64 * nanos_per_sample) as u64;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeterOptions {
+    /// Span of the RMS and peak levels.
+    pub window: Duration,
+    /// Span of the loudness, 400 ms for momentary and 3 s for short term
+    /// loudness.
+    pub loudness_window: Duration,
+    /// How long the held peak stays before following the current peak.
+    pub peak_hold: Duration,
+}
+
+impl Default for MeterOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(300),
+            loudness_window: Duration::from_millis(400),
+            peak_hold: Duration::from_millis(1500),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelLevel {
+    /// Root mean square amplitude over the window.
+    pub rms: f32,
+    /// Largest absolute sample over the window.
+    pub peak: f32,
+    /// Largest peak over the hold time.
+    pub peak_hold: f32,
+}
+
+impl ChannelLevel {
+    pub fn rms_db(&self) -> f32 {
+        amplitude_to_db(self.rms)
+    }
+
+    pub fn peak_db(&self) -> f32 {
+        amplitude_to_db(self.peak)
+    }
+
+    pub fn peak_hold_db(&self) -> f32 {
+        amplitude_to_db(self.peak_hold)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Levels {
+    pub channels: Vec<ChannelLevel>,
+    /// K-weighted loudness over the loudness window in LUFS, following ITU-R
+    /// BS.1770 without gating.
+    pub loudness: f32,
+    /// Start of the last measured block.
+    pub time: CMTime,
+}
+
+/// Measures per channel RMS and peak levels and the loudness of an audio
+/// stream, updated every 10 ms of audio.
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    options: MeterOptions,
+    blocks: Blocks,
+    /// Completed blocks by age, newest last, enough for the longest window.
+    history: VecDeque<Vec<BlockSums>>,
+    /// Held peak by channel, with the blocks left to hold it.
+    held: Vec<(f32, usize)>,
+    levels: Levels,
+}
+
+impl LevelMeter {
+    pub fn new(options: MeterOptions) -> Self {
+        Self {
+            options,
+            blocks: Blocks::default(),
+            history: VecDeque::new(),
+            held: Vec::new(),
+            levels: Levels {
+                loudness: SILENCE_DB,
+                ..Levels::default()
+            },
+        }
+    }
+
+    pub fn options(&self) -> &MeterOptions {
+        &self.options
+    }
+
+    /// The levels as of the last completed block.
+    pub fn levels(&self) -> &Levels {
+        &self.levels
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.options);
+    }
+
+    /// Measures the next piece of the stream. A change of sample rate or
+    /// channel count starts over.
+    pub fn process(&mut self, audio: &AudioBuffer) -> &Levels {
+        if self.blocks.configure(audio) {
+            self.history.clear();
+            self.held = vec![(0.0, 0); audio.channels()];
+        }
+        let capacity = blocks(self.options.window).max(blocks(self.options.loudness_window));
+        let hold = blocks(self.options.peak_hold);
+        let history = &mut self.history;
+        let held = &mut self.held;
+        let mut completed = false;
+        self.blocks.push(audio, |sums, _| {
+            if history.len() == capacity {
+                history.pop_front();
+            }
+            history.push_back(sums.to_vec());
+            for ((peak, left), sums) in held.iter_mut().zip(sums) {
+                *left = left.saturating_sub(1);
+                if sums.peak >= *peak || *left == 0 {
+                    *peak = sums.peak;
+                    *left = hold;
+                }
+            }
+            completed = true;
+        });
+        if completed {
+            self.update(audio);
+        }
+        &self.levels
+    }
+
+    fn update(&mut self, audio: &AudioBuffer) {
+        let channels = self.blocks.channels;
+        let window = blocks(self.options.window).min(self.history.len());
+        let loudness_window = blocks(self.options.loudness_window).min(self.history.len());
+        let mut levels = Vec::with_capacity(channels);
+        let mut loudness_sum = 0.0;
+        for channel in 0..channels {
+            let (mut square, mut samples, mut peak) = (0.0, 0, 0f32);
+            for sums in self.history.iter().rev().take(window) {
+                square += sums[channel].square;
+                samples += sums[channel].samples;
+                peak = peak.max(sums[channel].peak);
+            }
+            let (mut weighted, mut weighted_samples) = (0.0, 0);
+            for sums in self.history.iter().rev().take(loudness_window) {
+                weighted += sums[channel].weighted_square;
+                weighted_samples += sums[channel].samples;
+            }
+            loudness_sum += loudness_weight(channel, channels) * weighted / weighted_samples.max(1) as f64;
+            levels.push(ChannelLevel {
+                rms: (square / samples.max(1) as f64).sqrt() as f32,
+                peak,
+                peak_hold: self.held[channel].0,
+            });
+        }
+        self.levels = Levels {
+            channels: levels,
+            loudness: if loudness_sum > 0.0 {
+                ((-0.691 + 10.0 * loudness_sum.log10()) as f32).max(SILENCE_DB)
+            } else {
+                SILENCE_DB
+            },
+            time: nanos_to_cmtime(self.blocks.block_start, audio.sample_rate().round() as i32),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SilenceOptions {
+    /// Level below which audio counts as silent, in dBFS RMS of the loudest
+    /// channel.
+    pub threshold_db: f32,
+    /// How far above the threshold audio must rise to end a silence, so
+    /// noise hovering around the threshold doesn't toggle it.
+    pub hysteresis_db: f32,
+    /// How long audio must stay silent before a silence starts.
+    pub min_silence: Duration,
+    /// How long audio must stay loud before a silence ends.
+    pub min_sound: Duration,
+}
+
+impl Default for SilenceOptions {
+    fn default() -> Self {
+        Self {
+            threshold_db: -50.0,
+            hysteresis_db: 6.0,
+            min_silence: Duration::from_millis(500),
+            min_sound: Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SilenceEvent {
+    /// Silence began at `time`, reported once it lasted
+    /// [`SilenceOptions::min_silence`].
+    Started { time: CMTime },
+    /// Sound resumed at `time` after `duration` of silence.
+    Ended { time: CMTime, duration: Duration },
+}
+
+/// Finds silent stretches of an audio stream, in 10 ms steps, e.g. to trim
+/// them from a recording.
+#[derive(Clone, Debug)]
+pub struct SilenceDetector {
+    options: SilenceOptions,
+    blocks: Blocks,
+    silent: bool,
+    /// Start of the run of blocks on the other side of the threshold, and
+    /// how many there are.
+    candidate: Option<(u64, usize)>,
+    /// Start of the current silence.
+    silence_start: u64,
+}
+
+impl SilenceDetector {
+    pub fn new(options: SilenceOptions) -> Self {
+        Self {
+            options,
+            blocks: Blocks::default(),
+            silent: false,
+            candidate: None,
+            silence_start: 0,
+        }
+    }
+
+    pub fn options(&self) -> &SilenceOptions {
+        &self.options
+    }
+
+    /// Whether the stream is in a silence that has been reported as
+    /// started.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.options);
+    }
+
+    /// Examines the next piece of the stream, returning the silences that
+    /// started or ended in it. A change of sample rate or channel count
+    /// starts over, ending a current silence.
+    pub fn process(&mut self, audio: &AudioBuffer) -> Vec<SilenceEvent> {
+        let mut events = Vec::new();
+        let timescale = audio.sample_rate().round() as i32;
+        let time = cmtime_to_nanos(audio.presentation_time).unwrap_or(self.blocks.next_nanos);
+        if self.blocks.configure(audio) {
+            if self.silent {
+                events.push(self.end(time, timescale));
+            }
+            self.candidate = None;
+        }
+        let options = self.options;
+        let (min_silence, min_sound) = (blocks(options.min_silence), blocks(options.min_sound));
+        let mut transitions = Vec::new();
+        let (mut silent, mut candidate) = (self.silent, self.candidate);
+        self.blocks.push(audio, |sums, start| {
+            let rms = sums
+                .iter()
+                .map(|sums| (sums.square / sums.samples.max(1) as f64).sqrt() as f32)
+                .fold(0.0, f32::max);
+            let level = amplitude_to_db(rms);
+            let crossing = if silent {
+                level > options.threshold_db + options.hysteresis_db
+            } else {
+                level < options.threshold_db
+            };
+            if !crossing {
+                candidate = None;
+                return;
+            }
+            let (since, count) = candidate.unwrap_or((start, 0));
+            candidate = Some((since, count + 1));
+            if count + 1
+                >= if silent {
+                    min_sound
+                } else {
+                    min_silence
+                }
+            {
+                silent = !silent;
+                candidate = None;
+                transitions.push((silent, since));
+            }
+        });
+        self.candidate = candidate;
+        for (silent, since) in transitions {
+            if silent {
+                self.silent = true;
+                self.silence_start = since;
+                events.push(SilenceEvent::Started {
+                    time: nanos_to_cmtime(since, timescale),
+                });
+            } else {
+                events.push(self.end(since, timescale));
+            }
+        }
+        events
+    }
+
+    fn end(&mut self, time: u64, timescale: i32) -> SilenceEvent {
+        self.silent = false;
+        SilenceEvent::Ended {
+            time: nanos_to_cmtime(time, timescale),
+            duration: Duration::from_nanos(time.saturating_sub(self.silence_start)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48000.0;
+
+    /// `seconds` of a sine of `amplitude` at 997 Hz, the BS.1770 reference
+    /// frequency, in every channel whose gain is non zero.
+    fn sine(amplitude: f64, gains: &[f64], seconds: f64) -> AudioBuffer {
+        let frames = (RATE * seconds) as usize;
+        let samples: Vec<f32> = (0..frames)
+            .flat_map(|frame| {
+                let sample = amplitude * (2.0 * PI * 997.0 * frame as f64 / RATE).sin();
+                gains.iter().map(move |gain| (gain * sample) as f32)
+            })
+            .collect();
+        AudioBuffer::from_interleaved_f32(RATE, gains.len(), &samples).unwrap()
+    }
+
+    /// Mono audio at a constant level in dBFS.
+    fn constant(level_db: f32, millis: usize) -> AudioBuffer {
+        let amplitude = 10f32.powf(level_db / 20.0);
+        AudioBuffer::from_interleaved_f32(RATE, 1, &vec![amplitude; millis * 48]).unwrap()
+    }
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn decibels() {
+        assert_eq!(amplitude_to_db(1.0), 0.0);
+        assert_near(amplitude_to_db(0.5), -6.0206, 1e-4);
+        assert_near(amplitude_to_db(0.001), -60.0, 1e-4);
+        assert_eq!(amplitude_to_db(1e-9), SILENCE_DB);
+        assert_eq!(amplitude_to_db(0.0), SILENCE_DB);
+        assert_eq!(amplitude_to_db(-0.5), SILENCE_DB);
+    }
+
+    #[test]
+    fn sine_levels() {
+        // (amplitude, channel gains, RMS dB of the first channel, LUFS)
+        let cases: &[(f64, &[f64], f32, f32)] = &[
+            (1.0, &[1.0], -3.01, -3.01),
+            (0.1, &[1.0], -23.01, -23.01),
+            // Equal channels add up to 3 dB more loudness.
+            (0.1, &[1.0, 1.0], -23.01, -20.0),
+            (0.1, &[1.0, 0.0], -23.01, -23.01),
+            // 5.1 surrounds count 1.5 dB more, and the LFE not at all.
+            (0.1, &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0], SILENCE_DB, -21.51),
+            (0.1, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], SILENCE_DB, SILENCE_DB),
+        ];
+        for &(amplitude, gains, rms_db, lufs) in cases {
+            let mut meter = LevelMeter::new(MeterOptions::default());
+            let levels = meter.process(&sine(amplitude, gains, 1.0));
+            let name = format!("{} {:?}", amplitude, gains);
+            assert_eq!(levels.channels.len(), gains.len(), "{}", name);
+            assert_near(levels.channels[0].rms_db(), rms_db, 0.01);
+            assert_near(levels.channels[0].peak, (amplitude * gains[0]) as f32, 1e-3);
+            assert_near(levels.loudness, lufs, 0.05);
+            // The start of the last 10 ms block.
+            assert_eq!(levels.time, nanos_to_cmtime(990_000_000, 48000), "{}", name);
+        }
+    }
+
+    #[test]
+    fn peaks_are_held() {
+        let options = MeterOptions {
+            window: Duration::from_millis(100),
+            peak_hold: Duration::from_millis(500),
+            ..MeterOptions::default()
+        };
+        let mut meter = LevelMeter::new(options);
+        meter.process(&constant(-6.0, 100));
+        let levels = meter.process(&constant(-20.0, 200)).channels[0];
+        // The window has moved on, the hold hasn't.
+        assert_near(levels.peak_db(), -20.0, 1e-3);
+        assert_near(levels.rms_db(), -20.0, 1e-3);
+        assert_near(levels.peak_hold_db(), -6.0, 1e-3);
+        let levels = meter.process(&constant(-20.0, 290)).channels[0];
+        assert_near(levels.peak_hold_db(), -6.0, 1e-3);
+        let levels = meter.process(&constant(-20.0, 10)).channels[0];
+        assert_near(levels.peak_hold_db(), -20.0, 1e-3);
+
+        // A louder peak replaces the held one at once.
+        let levels = meter.process(&constant(-3.0, 10)).channels[0];
+        assert_near(levels.peak_hold_db(), -3.0, 1e-3);
+
+        meter.reset();
+        assert_eq!(meter.levels().loudness, SILENCE_DB);
+        assert!(meter.levels().channels.is_empty());
+    }
+
+    #[test]
+    fn silence_hysteresis() {
+        let millis = |millis: u64| nanos_to_cmtime(millis * 1_000_000, 48000);
+        let mut detector = SilenceDetector::new(SilenceOptions::default());
+        let steps: &[(f32, u64, Vec<SilenceEvent>)] = &[
+            (-20.0, 200, vec![]),
+            // Quiet for long enough.
+            (-53.0, 1000, vec![SilenceEvent::Started { time: millis(200) }]),
+            // Above the threshold but within the hysteresis.
+            (-47.0, 500, vec![]),
+            // Loud, but not for long enough.
+            (-30.0, 40, vec![]),
+            (-53.0, 300, vec![]),
+            (
+                -30.0,
+                200,
+                vec![SilenceEvent::Ended {
+                    time: millis(2040),
+                    duration: Duration::from_millis(1840),
+                }],
+            ),
+            // Not quiet for long enough.
+            (-60.0, 490, vec![]),
+            (-47.0, 100, vec![]),
+            (-60.0, 600, vec![SilenceEvent::Started { time: millis(2830) }]),
+        ];
+        for (level_db, length, events) in steps {
+            assert_eq!(&detector.process(&constant(*level_db, *length as usize)), events, "{} dB", level_db);
+        }
+        assert!(detector.is_silent());
+
+        // Starting over ends the silence where the stream got to.
+        let stereo = AudioBuffer::from_interleaved_f32(RATE, 2, &[0.5; 960]).unwrap();
+        assert_eq!(
+            detector.process(&stereo),
+            [SilenceEvent::Ended {
+                time: millis(3430),
+                duration: Duration::from_millis(600),
+            }]
+        );
+        assert!(!detector.is_silent());
+    }
+}
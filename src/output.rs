@@ -0,0 +1,208 @@
+use std::{error::Error, fmt};
+#[cfg(all(target_os = "macos", feature = "video"))]
+use std::{
+    path::Path,
+    sync::mpsc::{sync_channel, RecvTimeoutError},
+    time::Duration,
+};
+
+use core_foundation_0_10::base::TCFType;
+use core_media::sample_buffer::{CMSampleBuffer, CMSampleBufferRef};
+#[cfg(all(target_os = "macos", feature = "video"))]
+use dispatch2::{Queue, QueueAttribute};
+#[cfg(all(target_os = "macos", feature = "video"))]
+use objc2::runtime::ProtocolObject;
+use objc2::{
+    declare_class, msg_send_id, mutability,
+    rc::{Allocated, Id},
+    ClassType, DeclaredClass,
+};
+use objc2_foundation::{NSError, NSObject, NSObjectProtocol};
+
+use crate::{
+    frame::FrameError,
+    image::ImageError,
+    stream::{SCStream, SCStreamDelegate, SCStreamOutput, SCStreamOutputType},
+};
+#[cfg(all(target_os = "macos", feature = "video"))]
+use crate::{
+    frame::VideoFrame,
+    frame_info::FrameInfo,
+    image::{save, ImageOptions},
+    stream::SCFrameStatus,
+};
+
+type SampleHandler = Box<dyn Fn(&SCStream, &CMSampleBuffer, SCStreamOutputType) + Send + Sync>;
+type StopHandler = Box<dyn Fn(&SCStream, &NSError) + Send + Sync>;
+
+pub struct StreamOutputIvars {
+    handler: SampleHandler,
+}
+
+declare_class!(
+    /// An `SCStreamOutput` forwarding every sample buffer to a closure.
+    pub struct StreamOutput;
+
+    unsafe impl ClassType for StreamOutput {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "ScreenCaptureKitRsStreamOutput";
+    }
+
+    impl DeclaredClass for StreamOutput {
+        type Ivars = StreamOutputIvars;
+    }
+
+    unsafe impl NSObjectProtocol for StreamOutput {}
+
+    unsafe impl SCStreamOutput for StreamOutput {
+        #[method(stream:didOutputSampleBuffer:ofType:)]
+        unsafe fn stream_did_output_sample_buffer(&self, stream: &SCStream, sample_buffer: CMSampleBufferRef, of_type: SCStreamOutputType) {
+            let sample_buffer = CMSampleBuffer::wrap_under_get_rule(sample_buffer);
+            (self.ivars().handler)(stream, &sample_buffer, of_type);
+        }
+    }
+);
+
+impl StreamOutput {
+    /// Creates an output calling `handler` on the sample handler queue it is
+    /// added with.
+    pub fn new<F>(handler: F) -> Id<Self>
+    where
+        F: Fn(&SCStream, &CMSampleBuffer, SCStreamOutputType) + Send + Sync + 'static,
+    {
+        let this: Allocated<Self> = Self::alloc();
+        let this = this.set_ivars(StreamOutputIvars { handler: Box::new(handler) });
+        unsafe { msg_send_id![super(this), init] }
+    }
+}
+
+pub struct StreamDelegateIvars {
+    handler: StopHandler,
+}
+
+declare_class!(
+    /// An `SCStreamDelegate` forwarding stream errors to a closure.
+    pub struct StreamDelegate;
+
+    unsafe impl ClassType for StreamDelegate {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "ScreenCaptureKitRsStreamDelegate";
+    }
+
+    impl DeclaredClass for StreamDelegate {
+        type Ivars = StreamDelegateIvars;
+    }
+
+    unsafe impl NSObjectProtocol for StreamDelegate {}
+
+    unsafe impl SCStreamDelegate for StreamDelegate {
+        #[method(stream:didStopWithError:)]
+        unsafe fn stream_did_stop_with_error(&self, stream: &SCStream, error: &NSError) {
+            (self.ivars().handler)(stream, error);
+        }
+    }
+);
+
+impl StreamDelegate {
+    /// Creates a delegate calling `handler` when the stream stops because of
+    /// an error.
+    pub fn new<F>(handler: F) -> Id<Self>
+    where
+        F: Fn(&SCStream, &NSError) + Send + Sync + 'static,
+    {
+        let this: Allocated<Self> = Self::alloc();
+        let this = this.set_ivars(StreamDelegateIvars { handler: Box::new(handler) });
+        unsafe { msg_send_id![super(this), init] }
+    }
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Stream(Id<NSError>),
+    Frame(FrameError),
+    Image(ImageError),
+    /// No complete frame arrived in time.
+    Timeout,
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stream(error) => write!(f, "{}", error.localizedDescription()),
+            Self::Frame(error) => write!(f, "{}", error),
+            Self::Image(error) => write!(f, "{}", error),
+            Self::Timeout => write!(f, "timed out waiting for a frame"),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Frame(error) => Some(error),
+            Self::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for CaptureError {
+    fn from(error: FrameError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+impl From<ImageError> for CaptureError {
+    fn from(error: ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+/// Waits for the next `Complete` screen frame from a running stream and
+/// copies it. Samples with any other status, or without an image, are
+/// skipped. A temporary output is
+/// added to the stream and removed again before returning.
+#[cfg(all(target_os = "macos", feature = "video"))]
+pub fn next_frame(stream: &SCStream, timeout: Duration) -> Result<VideoFrame, CaptureError> {
+    let (sender, receiver) = sync_channel(1);
+    let output = StreamOutput::new(move |_, sample_buffer, of_type| {
+        if of_type != SCStreamOutputType::Screen {
+            return;
+        }
+        let status = FrameInfo::from_sample_buffer(sample_buffer).and_then(|info| info.status);
+        if status != Some(SCFrameStatus::Complete) {
+            return;
+        }
+        match VideoFrame::from_sample_buffer(sample_buffer) {
+            Err(FrameError::NoImageBuffer) => {}
+            // Only the first frame matters; later ones find the channel full.
+            frame => {
+                let _ = sender.try_send(frame);
+            }
+        }
+    });
+    let queue = Queue::new("com.screen_capture_kit.next_frame", QueueAttribute::Serial);
+    let output = ProtocolObject::from_ref(&*output);
+    stream
+        .add_stream_output(output, SCStreamOutputType::Screen, &queue)
+        .map_err(CaptureError::Stream)?;
+    let result = receiver.recv_timeout(timeout);
+    let removed = stream.remove_stream_output(output, SCStreamOutputType::Screen);
+    let frame = match result {
+        Ok(frame) => frame?,
+        Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return Err(CaptureError::Timeout),
+    };
+    removed.map_err(CaptureError::Stream)?;
+    Ok(frame)
+}
+
+/// Grabs the next complete frame from a running stream and saves it to
+/// `path`, returning the saved frame.
+#[cfg(all(target_os = "macos", feature = "video"))]
+pub fn save_next_frame<P: AsRef<Path>>(stream: &SCStream, path: P, options: &ImageOptions, timeout: Duration) -> Result<VideoFrame, CaptureError> {
+    let frame = next_frame(stream, timeout)?;
+    save(&frame, path, options)?;
+    Ok(frame)
+}
@@ -0,0 +1,928 @@
+use std::{
+    convert::TryFrom,
+    ffi::CStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    clock::{cmtime_to_nanos, ClockMapper},
+    convert::ColorMatrix,
+    frame::{FrameError, PixelFormat, VideoFrame},
+    region::{PixelRect, Region},
+    scale::{channels, read_row, write_row},
+};
+
+/// Column major 5x7 glyphs for printable ASCII, least significant bit at the
+/// top.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x14, 0x08, 0x3e, 0x08, 0x14],
+    [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7f, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02],
+    [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x7f, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7f, 0x40, 0x00],
+    [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20],
+    [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+/// Glyph cell size including one pixel of spacing.
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+/// The glyph for a character, `?` for characters outside printable ASCII.
+fn glyph(character: char) -> &'static [u8; 5] {
+    match character {
+        ' '..='~' => &FONT[character as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+/// Where an item is placed relative to the frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// The top left corner of an item of `width` x `height` pixels. The offset
+    /// moves the item inward from the anchored edges, and right and down for
+    /// centered axes.
+    pub fn position(&self, frame_width: usize, frame_height: usize, width: usize, height: usize, offset: (i32, i32)) -> (i64, i64) {
+        let place = |frame: usize, size: usize, offset: i32, alignment: u8| {
+            let (frame, size, offset) = (frame as i64, size as i64, offset as i64);
+            match alignment {
+                0 => offset,
+                1 => (frame - size) / 2 + offset,
+                _ => frame - size - offset,
+            }
+        };
+        let (horizontal, vertical) = match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        };
+        (
+            place(frame_width, width, offset.0, horizontal),
+            place(frame_height, height, offset.1, vertical),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextStyle {
+    /// RGBA text color.
+    pub color: [u8; 4],
+    pub background: Option<[u8; 4]>,
+    /// Integer magnification of the 5x7 font.
+    pub scale: u32,
+    /// Background margin around the text, in pixels.
+    pub padding: u32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            color: [255, 255, 255, 255],
+            background: Some([0, 0, 0, 160]),
+            scale: 2,
+            padding: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoxStyle {
+    /// RGBA outline color.
+    pub color: [u8; 4],
+    /// Outline width in pixels; zero draws no outline.
+    pub thickness: u32,
+    pub fill: Option<[u8; 4]>,
+}
+
+impl Default for BoxStyle {
+    fn default() -> Self {
+        Self {
+            color: [255, 59, 48, 255],
+            thickness: 2,
+            fill: None,
+        }
+    }
+}
+
+/// An RGBA image with straight alpha, e.g. a logo watermark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl OverlayImage {
+    pub fn from_rgba(width: usize, height: usize, pixels: Vec<u8>) -> Result<Self, FrameError> {
+        if width == 0 || height == 0 {
+            return Err(FrameError::InvalidDimensions);
+        }
+        if pixels.len() < width * height * 4 {
+            return Err(FrameError::BufferTooSmall {
+                plane: 0,
+                expected: width * height * 4,
+                actual: pixels.len(),
+            });
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn from_frame(frame: &VideoFrame) -> Result<Self, FrameError> {
+        let rgba = frame.convert(PixelFormat::RGBA, ColorMatrix::default())?;
+        let pixels = rgba.planes()[0].rows().flatten().copied().collect();
+        Self::from_rgba(frame.width(), frame.height(), pixels)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OverlayItem {
+    /// Text expanded per frame by [`Compositor::expand`]. Lines are separated
+    /// by `\n`.
+    Text {
+        template: String,
+        anchor: Anchor,
+        offset: (i32, i32),
+        style: TextStyle,
+    },
+    Image {
+        image: OverlayImage,
+        anchor: Anchor,
+        offset: (i32, i32),
+        /// Multiplies the image alpha, from 0 to 1.
+        opacity: f32,
+    },
+    /// A highlight box in frame pixels.
+    Box { rect: PixelRect, style: BoxStyle },
+    /// Highlights the dirty rects reported in each frame's info.
+    DirtyRects(BoxStyle),
+}
+
+/// Draws overlay items onto frames.
+#[derive(Clone, Debug)]
+pub struct Compositor {
+    items: Vec<OverlayItem>,
+    color_matrix: ColorMatrix,
+    host_name: String,
+    frame_count: u64,
+    clock: ClockMapper,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            color_matrix: ColorMatrix::default(),
+            host_name: host_name().unwrap_or_default(),
+            frame_count: 0,
+            clock: ClockMapper::new(),
+        }
+    }
+
+    pub fn items(&self) -> &[OverlayItem] {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut Vec<OverlayItem> {
+        &mut self.items
+    }
+
+    pub fn add(&mut self, item: OverlayItem) {
+        self.items.push(item);
+    }
+
+    pub fn add_text(&mut self, template: &str, anchor: Anchor) {
+        self.add(OverlayItem::Text {
+            template: template.to_string(),
+            anchor,
+            offset: (8, 8),
+            style: TextStyle::default(),
+        });
+    }
+
+    /// The matrix used to convert overlay colors for YUV frames.
+    pub fn set_color_matrix(&mut self, color_matrix: ColorMatrix) {
+        self.color_matrix = color_matrix;
+    }
+
+    /// Overrides the machine name used for `{host}`.
+    pub fn set_host_name(&mut self, host_name: &str) {
+        self.host_name = host_name.to_string();
+    }
+
+    /// Number of frames composited so far, used for `{frame}`.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Expands the placeholders of a text template for a frame:
+    ///
+    /// - `{date}` and `{time}`: local capture date and time, from the frame's
+    ///   display time or presentation time, or the current time
+    /// - `{utc}`: capture time in UTC, RFC 3339
+    /// - `{pts}`: presentation time in seconds
+    /// - `{frame}`: index of the frame in this compositor
+    /// - `{host}`: machine name
+    /// - `{status}`: frame status
+    /// - `{size}`: frame size in pixels
+    ///
+    /// Unknown placeholders are kept as is; `{{` and `}}` produce braces.
+    pub fn expand(&self, template: &str, frame: &VideoFrame) -> String {
+        let capture_time = frame
+            .info
+            .display_time
+            .and_then(|time| self.clock.host_time_to_system_time(time))
+            .or_else(|| self.clock.cmtime_to_system_time(frame.presentation_time))
+            .unwrap_or_else(SystemTime::now);
+        let since_epoch = capture_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            let end = match rest.find('}') {
+                Some(end) if rest.starts_with('{') => end,
+                _ => {
+                    expanded.push_str(&rest[..1]);
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            let value = match &rest[1..end] {
+                "date" => local_time(since_epoch).map(|time| format!("{:04}-{:02}-{:02}", time[0], time[1], time[2])),
+                "time" => {
+                    local_time(since_epoch).map(|time| format!("{:02}:{:02}:{:02}.{:03}", time[3], time[4], time[5], since_epoch.subsec_millis()))
+                }
+                "utc" => Some(format_utc(since_epoch)),
+                "pts" => cmtime_to_nanos(frame.presentation_time).map(|nanos| format!("{:.3}", nanos as f64 / 1e9)),
+                "frame" => Some(self.frame_count.to_string()),
+                "host" => Some(self.host_name.clone()),
+                "status" => frame.info.status.map(|status| status.to_string()),
+                "size" => Some(format!("{}x{}", frame.width(), frame.height())),
+                _ => None,
+            };
+            match value {
+                Some(value) => expanded.push_str(&value),
+                None => expanded.push_str(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Draws every item onto the frame in order.
+    pub fn apply(&mut self, frame: &mut VideoFrame) -> Result<(), FrameError> {
+        let (width, height) = (frame.width(), frame.height());
+        for item in &self.items {
+            match item {
+                OverlayItem::Text {
+                    template,
+                    anchor,
+                    offset,
+                    style,
+                } => {
+                    let text = self.expand(template, frame);
+                    if let Some(mut patch) = render_text(&text, style) {
+                        (patch.x, patch.y) = anchor.position(width, height, patch.width, patch.height, *offset);
+                        blend(frame, patch, self.color_matrix)?;
+                    }
+                }
+                OverlayItem::Image {
+                    image,
+                    anchor,
+                    offset,
+                    opacity,
+                } => {
+                    let (x, y) = anchor.position(width, height, image.width, image.height, *offset);
+                    let opacity = opacity.clamp(0.0, 1.0);
+                    let mut pixels = image.pixels[..image.width * image.height * 4].to_vec();
+                    for pixel in pixels.chunks_exact_mut(4) {
+                        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+                    }
+                    let patch = Patch {
+                        x,
+                        y,
+                        width: image.width,
+                        height: image.height,
+                        pixels,
+                    };
+                    blend(frame, patch, self.color_matrix)?;
+                }
+                OverlayItem::Box { rect, style } => draw_box(frame, *rect, style, self.color_matrix)?,
+                OverlayItem::DirtyRects(style) => {
+                    if let Some(region) = Region::from_frame_info(&frame.info) {
+                        for rect in region.clip(PixelRect::new(0, 0, width as u32, height as u32)).rects() {
+                            draw_box(frame, *rect, style, self.color_matrix)?;
+                        }
+                    }
+                }
+            }
+        }
+        self.frame_count += 1;
+        Ok(())
+    }
+}
+
+/// An RGBA image with straight alpha placed on a frame.
+struct Patch {
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Patch {
+    fn solid(x: i64, y: i64, width: usize, height: usize, color: [u8; 4]) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            pixels: color.repeat(width * height),
+        }
+    }
+
+    /// Composites `color` over the pixel at `x`, `y`.
+    fn draw(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let pixel = &mut self.pixels[(y * self.width + x) * 4..][..4];
+        let source_alpha = color[3] as f32 / 255.0;
+        let destination_alpha = pixel[3] as f32 / 255.0 * (1.0 - source_alpha);
+        let alpha = source_alpha + destination_alpha;
+        if alpha > 0.0 {
+            for channel in 0..3 {
+                pixel[channel] = ((color[channel] as f32 * source_alpha + pixel[channel] as f32 * destination_alpha) / alpha).round() as u8;
+            }
+        }
+        pixel[3] = (alpha * 255.0).round() as u8;
+    }
+
+    /// Pads the patch with transparent pixels to even coordinates and size,
+    /// so its chroma samples line up with those of a 4:2:0 frame.
+    fn align_even(self) -> Self {
+        let (left, top) = ((self.x & 1) as usize, (self.y & 1) as usize);
+        let width = (self.width + left + 1) & !1;
+        let height = (self.height + top + 1) & !1;
+        if left == 0 && top == 0 && width == self.width && height == self.height {
+            return self;
+        }
+        let mut pixels = vec![0; width * height * 4];
+        for (y, row) in self.pixels.chunks_exact(self.width * 4).enumerate() {
+            let start = ((y + top) * width + left) * 4;
+            pixels[start..start + row.len()].copy_from_slice(row);
+        }
+        Self {
+            x: self.x - left as i64,
+            y: self.y - top as i64,
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn render_text(text: &str, style: &TextStyle) -> Option<Patch> {
+    let lines: Vec<&str> = text.lines().collect();
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    if columns == 0 {
+        return None;
+    }
+    let scale = style.scale.max(1) as usize;
+    let padding = style.padding as usize;
+    let width = (columns * ADVANCE - 1) * scale + 2 * padding;
+    let height = (lines.len() * LINE_HEIGHT - 1) * scale + 2 * padding;
+    let mut patch = Patch::solid(0, 0, width, height, style.background.unwrap_or([0; 4]));
+    for (line_index, line) in lines.iter().enumerate() {
+        for (column, character) in line.chars().enumerate() {
+            for (glyph_x, bits) in glyph(character).iter().enumerate() {
+                for glyph_y in (0..GLYPH_HEIGHT).filter(|row| bits >> row & 1 != 0) {
+                    let left = padding + (column * ADVANCE + glyph_x) * scale;
+                    let top = padding + (line_index * LINE_HEIGHT + glyph_y) * scale;
+                    for y in top..top + scale {
+                        for x in left..left + scale {
+                            patch.draw(x, y, style.color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Some(patch)
+}
+
+fn draw_box(frame: &mut VideoFrame, rect: PixelRect, style: &BoxStyle, matrix: ColorMatrix) -> Result<(), FrameError> {
+    if rect.is_empty() {
+        return Ok(());
+    }
+    let (x, y) = (rect.x as i64, rect.y as i64);
+    let (width, height) = (rect.width as usize, rect.height as usize);
+    if let Some(fill) = style.fill {
+        blend(frame, Patch::solid(x, y, width, height, fill), matrix)?;
+    }
+    let thickness = (style.thickness as usize).min(width / 2 + width % 2).min(height / 2 + height % 2);
+    if thickness == 0 {
+        return Ok(());
+    }
+    let inner_height = height.saturating_sub(2 * thickness);
+    let edges = [
+        Patch::solid(x, y, width, thickness, style.color),
+        Patch::solid(x, y + (height - thickness) as i64, width, thickness, style.color),
+        Patch::solid(x, y + thickness as i64, thickness, inner_height, style.color),
+        Patch::solid(x + (width - thickness) as i64, y + thickness as i64, thickness, inner_height, style.color),
+    ];
+    for edge in edges {
+        if edge.width > 0 && edge.height > 0 {
+            blend(frame, edge, matrix)?;
+        }
+    }
+    Ok(())
+}
+
+/// Alpha blends a patch onto a frame of any supported format. The patch is
+/// converted to the frame's format, then every plane is blended with the
+/// patch alpha, averaged over the pixels a chroma sample covers.
+fn blend(frame: &mut VideoFrame, patch: Patch, matrix: ColorMatrix) -> Result<(), FrameError> {
+    let pixel_format = frame.pixel_format();
+    if !pixel_format.is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(pixel_format));
+    }
+    let patch = if pixel_format.is_yuv() {
+        patch.align_even()
+    } else {
+        patch
+    };
+    // Colors are converted opaque so alpha channels blend to the union of
+    // both alphas.
+    let mut opaque = patch.pixels.clone();
+    for pixel in opaque.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let convert = |pixels: &[u8]| -> Result<VideoFrame, FrameError> {
+        let source = VideoFrame::from_bytes(patch.width, patch.height, PixelFormat::RGBA, pixels)?;
+        if pixel_format == PixelFormat::RGBA {
+            Ok(source)
+        } else {
+            source.convert(pixel_format, matrix)
+        }
+    };
+    let source = convert(&opaque)?;
+    // Chroma comes from the alpha weighted color of each 2x2 block, so
+    // transparent pixels don't tint the pixels they share a sample with.
+    let chroma_source = if pixel_format.is_yuv() {
+        Some(convert(&block_colors(&patch))?)
+    } else {
+        None
+    };
+    for (index, (mut plane, source_plane)) in frame.planes_mut().zip(source.planes()).enumerate() {
+        let source_plane = match &chroma_source {
+            Some(chroma_source) if index > 0 => &chroma_source.planes()[index],
+            _ => source_plane,
+        };
+        let subsampling = if index > 0 && pixel_format.is_yuv() {
+            2
+        } else {
+            1
+        };
+        let (channels, packed) = channels(pixel_format, &plane);
+        let bytes_per_pixel = plane.bytes_per_pixel();
+        let (origin_x, origin_y) = (patch.x.div_euclid(subsampling), patch.y.div_euclid(subsampling));
+        let first_x = (-origin_x).max(0) as usize;
+        let last_x = (plane.width() as i64 - origin_x).clamp(0, source_plane.width() as i64) as usize;
+        if first_x >= last_x {
+            continue;
+        }
+        let count = last_x - first_x;
+        let mut source_samples = vec![0; count * channels];
+        let mut samples = vec![0; count * channels];
+        let subsampling = subsampling as usize;
+        for source_y in 0..source_plane.height() {
+            let y = origin_y + source_y as i64;
+            if y < 0 || y >= plane.height() as i64 {
+                continue;
+            }
+            let destination_x = (origin_x + first_x as i64) as usize;
+            let destination = &mut plane.row_mut(y as usize)[destination_x * bytes_per_pixel..(destination_x + count) * bytes_per_pixel];
+            read_row(destination, packed, &mut samples);
+            read_row(
+                &source_plane.row(source_y)[first_x * bytes_per_pixel..last_x * bytes_per_pixel],
+                packed,
+                &mut source_samples,
+            );
+            for (offset, (samples, source_samples)) in samples.chunks_exact_mut(channels).zip(source_samples.chunks_exact(channels)).enumerate() {
+                let x = (first_x + offset) * subsampling;
+                let top = source_y * subsampling;
+                let alpha: u32 = (top..top + subsampling)
+                    .flat_map(|y| (x..x + subsampling).map(move |x| (x, y)))
+                    .map(|(x, y)| patch.pixels[(y * patch.width + x) * 4 + 3] as u32)
+                    .sum::<u32>()
+                    / (subsampling * subsampling) as u32;
+                for (sample, source) in samples.iter_mut().zip(source_samples) {
+                    *sample = ((*source as u32 * alpha + *sample as u32 * (255 - alpha) + 127) / 255) as u16;
+                }
+            }
+            write_row(&samples, packed, destination);
+        }
+    }
+    Ok(())
+}
+
+/// The pixels of an even sized patch with every 2x2 block set to its alpha
+/// weighted average color, opaque.
+fn block_colors(patch: &Patch) -> Vec<u8> {
+    let mut pixels = vec![255; patch.pixels.len()];
+    for top in (0..patch.height).step_by(2) {
+        for left in (0..patch.width).step_by(2) {
+            let block: Vec<usize> = (top..top + 2)
+                .flat_map(|y| (left..left + 2).map(move |x| (y * patch.width + x) * 4))
+                .collect();
+            let weight: u32 = block.iter().map(|offset| patch.pixels[offset + 3] as u32).sum();
+            for channel in 0..3 {
+                let sum: u32 = block
+                    .iter()
+                    .map(|offset| patch.pixels[offset + channel] as u32 * patch.pixels[offset + 3] as u32)
+                    .sum();
+                let average = (sum + weight / 2).checked_div(weight).unwrap_or(0) as u8;
+                for offset in &block {
+                    pixels[offset + channel] = average;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+fn host_name() -> Option<String> {
+    let mut buffer = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len()) } != 0 {
+        return None;
+    }
+    buffer[buffer.len() - 1] = 0;
+    let name = unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy().into_owned();
+    Some(name.strip_suffix(".local").map(str::to_string).unwrap_or(name))
+}
+
+/// Year, month, day, hour, minute and second in the local time zone.
+fn local_time(since_epoch: Duration) -> Option<[i64; 6]> {
+    let seconds = libc::time_t::try_from(since_epoch.as_secs()).ok()?;
+    let mut time: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut time) }.is_null() {
+        return None;
+    }
+    Some([
+        time.tm_year as i64 + 1900,
+        time.tm_mon as i64 + 1,
+        time.tm_mday as i64,
+        time.tm_hour as i64,
+        time.tm_min as i64,
+        time.tm_sec as i64,
+    ])
+}
+
+fn format_utc(since_epoch: Duration) -> String {
+    let seconds = since_epoch.as_secs() as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Civil date from days since the epoch, after Howard Hinnant.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, stream::SCFrameStatus};
+
+    fn bgra(width: usize, height: usize) -> VideoFrame {
+        VideoFrame::zeroed(width, height, PixelFormat::BGRA).unwrap()
+    }
+
+    /// Positions of the pixels whose bytes aren't all zero.
+    fn painted(frame: &VideoFrame) -> Vec<(usize, usize)> {
+        let plane = &frame.planes()[0];
+        (0..frame.height())
+            .flat_map(|y| (0..frame.width()).map(move |x| (x, y)))
+            .filter(|(x, y)| plane.row(*y)[x * 4..x * 4 + 4] != [0; 4])
+            .collect()
+    }
+
+    fn rect_pixels(left: usize, top: usize, right: usize, bottom: usize) -> Vec<(usize, usize)> {
+        (top..bottom).flat_map(|y| (left..right).map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let mut compositor = Compositor::new();
+        compositor.set_host_name("studio");
+        let mut frame = bgra(64, 48);
+        frame.presentation_time = nanos_to_cmtime(1_500_000_000, 1000);
+        frame.info.status = Some(SCFrameStatus::Complete);
+        let cases = [
+            ("{pts} s", "1.500 s"),
+            ("#{frame} on {host}", "#0 on studio"),
+            ("{status} {size}", "Complete 64x48"),
+            ("{nope} {}", "{nope} {}"),
+            ("{{pts}} {{{size}}}", "{pts} {64x48}"),
+            ("a } b { c", "a } b { c"),
+            ("open {pts", "open {pts"),
+            ("", ""),
+        ];
+        for (template, expected) in &cases {
+            assert_eq!(compositor.expand(template, &frame), *expected, "{:?}", template);
+        }
+
+        compositor.apply(&mut frame).unwrap();
+        assert_eq!(compositor.expand("{frame}", &frame), "1");
+        frame.info.status = None;
+        assert_eq!(compositor.expand("{status}", &frame), "{status}");
+        let utc = compositor.expand("{utc}", &frame);
+        assert_eq!((utc.len(), &utc[10..11], &utc[23..]), (24, "T", "Z"));
+    }
+
+    #[test]
+    fn utc_formatting() {
+        let cases = [
+            (0, 0, "1970-01-01T00:00:00.000Z"),
+            (951_782_400, 0, "2000-02-29T00:00:00.000Z"),
+            (951_868_799, 999, "2000-02-29T23:59:59.999Z"),
+            (1_700_000_000, 123, "2023-11-14T22:13:20.123Z"),
+            (4_107_542_400, 0, "2100-03-01T00:00:00.000Z"),
+            (253_402_300_799, 0, "9999-12-31T23:59:59.000Z"),
+        ];
+        for &(seconds, millis, expected) in &cases {
+            assert_eq!(format_utc(Duration::from_secs(seconds) + Duration::from_millis(millis)), expected);
+        }
+    }
+
+    #[test]
+    fn text_patch_sizes() {
+        let style = TextStyle {
+            color: [255, 255, 255, 255],
+            background: None,
+            scale: 3,
+            padding: 2,
+        };
+        let patch = render_text("AB\nC", &style).unwrap();
+        // Two columns of 5 pixels with a gap, two lines of 7 with a gap.
+        assert_eq!((patch.width, patch.height), (11 * 3 + 4, 15 * 3 + 4));
+        assert_eq!(patch.pixels.len(), patch.width * patch.height * 4);
+        // Padding stays transparent without a background.
+        assert!(patch.pixels[..patch.width * 4 * 2].iter().all(|value| *value == 0));
+
+        let patch = render_text("|", &TextStyle { scale: 0, ..style }).unwrap();
+        assert_eq!((patch.width, patch.height), (5 + 4, 7 + 4));
+        assert!(render_text("", &style).is_none());
+        assert!(render_text("\n", &style).is_none());
+    }
+
+    #[test]
+    fn box_thickness_is_clamped() {
+        let style = BoxStyle {
+            color: [255, 0, 0, 255],
+            thickness: 10,
+            fill: None,
+        };
+        // Thicker than half the box fills it without spilling.
+        let mut frame = bgra(10, 10);
+        draw_box(&mut frame, PixelRect::new(2, 2, 5, 3), &style, ColorMatrix::default()).unwrap();
+        assert_eq!(painted(&frame), rect_pixels(2, 2, 7, 5));
+        assert_eq!(frame.planes()[0].row(2)[8..12], [0, 0, 255, 255]);
+
+        let mut frame = bgra(10, 10);
+        draw_box(
+            &mut frame,
+            PixelRect::new(1, 1, 4, 4),
+            &BoxStyle { thickness: 1, ..style },
+            ColorMatrix::default(),
+        )
+        .unwrap();
+        let ring: Vec<(usize, usize)> = rect_pixels(1, 1, 5, 5)
+            .into_iter()
+            .filter(|(x, y)| !(2..4).contains(x) || !(2..4).contains(y))
+            .collect();
+        assert_eq!(painted(&frame), ring);
+
+        // No outline, only the fill; empty boxes draw nothing.
+        let mut frame = bgra(10, 10);
+        let fill = BoxStyle {
+            thickness: 0,
+            fill: Some([0, 255, 0, 255]),
+            ..style
+        };
+        draw_box(&mut frame, PixelRect::new(8, 8, 5, 5), &fill, ColorMatrix::default()).unwrap();
+        draw_box(&mut frame, PixelRect::new(0, 0, 0, 5), &style, ColorMatrix::default()).unwrap();
+        assert_eq!(painted(&frame), rect_pixels(8, 8, 10, 10));
+    }
+
+    #[test]
+    fn blend_bgra() {
+        let mut frame = bgra(4, 4);
+        // Clipped at the top left, half transparent.
+        blend(&mut frame, Patch::solid(-1, -1, 3, 3, [200, 100, 50, 128]), ColorMatrix::default()).unwrap();
+        assert_eq!(painted(&frame), rect_pixels(0, 0, 2, 2));
+        // (source * alpha + destination * (255 - alpha) + 127) / 255 per
+        // channel, alpha included.
+        assert_eq!(frame.planes()[0].row(1)[4..8], [25, 50, 100, 128]);
+
+        // Opaque pixels replace, transparent ones keep the frame.
+        let mut pixels = [0, 0, 255, 255].repeat(2);
+        pixels[4..].copy_from_slice(&[255, 255, 255, 0]);
+        let patch = Patch {
+            x: 3,
+            y: 3,
+            width: 2,
+            height: 1,
+            pixels,
+        };
+        blend(&mut frame, patch, ColorMatrix::default()).unwrap();
+        assert_eq!(frame.planes()[0].row(3)[12..16], [255, 0, 0, 255]);
+        assert_eq!(painted(&frame), [rect_pixels(0, 0, 2, 2), vec![(3, 3)]].concat());
+    }
+
+    #[test]
+    fn blend_nv12_at_odd_offsets() {
+        let matrix = ColorMatrix::default();
+        let red = VideoFrame::from_bytes(2, 2, PixelFormat::RGBA, &[255, 0, 0, 255].repeat(4))
+            .unwrap()
+            .convert(PixelFormat::NV12FullRange, matrix)
+            .unwrap();
+        let (red_y, red_uv) = (red.planes()[0].row(0)[0], [red.planes()[1].row(0)[0], red.planes()[1].row(0)[1]]);
+        let black = VideoFrame::from_bytes(8, 6, PixelFormat::RGBA, &[0, 0, 0, 255].repeat(48)).unwrap();
+        let mut frame = black.convert(PixelFormat::NV12FullRange, matrix).unwrap();
+        let (black_y, black_uv) = (frame.planes()[0].row(0)[0], frame.planes()[1].row(0)[0]);
+
+        blend(&mut frame, Patch::solid(3, 1, 2, 2, [255, 0, 0, 255]), matrix).unwrap();
+        let luma = &frame.planes()[0];
+        for y in 0..6 {
+            for x in 0..8 {
+                let expected = if (3..5).contains(&x) && (1..3).contains(&y) {
+                    red_y
+                } else {
+                    black_y
+                };
+                assert_eq!(luma.row(y)[x], expected, "luma {},{}", x, y);
+            }
+        }
+        // Each touched chroma sample covers one patch pixel of four.
+        let chroma = &frame.planes()[1];
+        for y in 0..3 {
+            for x in 0..4 {
+                for (sample, red) in chroma.row(y)[x * 2..x * 2 + 2].iter().zip(red_uv) {
+                    let expected = if (1..3).contains(&x) && y < 2 {
+                        ((red as u32 * 63 + black_uv as u32 * 192 + 127) / 255) as u8
+                    } else {
+                        black_uv
+                    };
+                    assert_eq!(*sample, expected, "chroma {},{}", x, y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! The Core Media and Foundation value types used by the platform-neutral
+//! modules. Other targets get stand-ins with the same fields, so frames,
+//! audio and the encoders built on them can be used and tested anywhere.
+
+#[cfg(target_os = "macos")]
+pub use core_media::{
+    time::{
+        kCMTimeFlags_HasBeenRounded, kCMTimeFlags_ImpliedValueFlagsMask, kCMTimeFlags_Indefinite, kCMTimeFlags_NegativeInfinity,
+        kCMTimeFlags_PositiveInfinity, kCMTimeFlags_Valid, CMTime, CMTimeEpoch, CMTimeFlags, CMTimeScale, CMTimeValue,
+    },
+    OSType,
+};
+#[cfg(target_os = "macos")]
+pub use objc2_foundation::{CGFloat, CGPoint, CGRect, CGSize, NSInteger};
+
+#[cfg(not(target_os = "macos"))]
+pub use self::fallback::*;
+
+#[cfg(not(target_os = "macos"))]
+mod fallback {
+    pub type OSType = u32;
+    pub type NSInteger = isize;
+    pub type CGFloat = f64;
+
+    pub type CMTimeValue = i64;
+    pub type CMTimeScale = i32;
+    pub type CMTimeEpoch = i64;
+    pub type CMTimeFlags = u32;
+
+    pub const kCMTimeFlags_Valid: CMTimeFlags = 1 << 0;
+    pub const kCMTimeFlags_HasBeenRounded: CMTimeFlags = 1 << 1;
+    pub const kCMTimeFlags_PositiveInfinity: CMTimeFlags = 1 << 2;
+    pub const kCMTimeFlags_NegativeInfinity: CMTimeFlags = 1 << 3;
+    pub const kCMTimeFlags_Indefinite: CMTimeFlags = 1 << 4;
+    pub const kCMTimeFlags_ImpliedValueFlagsMask: CMTimeFlags =
+        kCMTimeFlags_PositiveInfinity | kCMTimeFlags_NegativeInfinity | kCMTimeFlags_Indefinite;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct CMTime {
+        pub value: CMTimeValue,
+        pub timescale: CMTimeScale,
+        pub flags: CMTimeFlags,
+        pub epoch: CMTimeEpoch,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct CGPoint {
+        pub x: CGFloat,
+        pub y: CGFloat,
+    }
+
+    impl CGPoint {
+        pub const fn new(x: CGFloat, y: CGFloat) -> Self {
+            Self { x, y }
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct CGSize {
+        pub width: CGFloat,
+        pub height: CGFloat,
+    }
+
+    impl CGSize {
+        pub const fn new(width: CGFloat, height: CGFloat) -> Self {
+            Self { width, height }
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct CGRect {
+        pub origin: CGPoint,
+        pub size: CGSize,
+    }
+
+    impl CGRect {
+        pub const fn new(origin: CGPoint, size: CGSize) -> Self {
+            Self { origin, size }
+        }
+
+        /// Returns the rectangle with a non-negative width and height, moving
+        /// the origin like the `objc2_foundation` type used on macOS.
+        pub fn standardize(self) -> Self {
+            let (x, width) = if self.size.width < 0.0 {
+                (self.origin.x + self.size.width, -self.size.width)
+            } else {
+                (self.origin.x, self.size.width)
+            };
+            let (y, height) = if self.size.height < 0.0 {
+                (self.origin.y + self.size.height, -self.size.height)
+            } else {
+                (self.origin.y, self.size.height)
+            };
+            Self::new(CGPoint::new(x, y), CGSize::new(width, height))
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::{io::Write, time::Duration};
+
+use crate::{
+    convert::ColorMatrix,
+    deflate::{crc32_update, zlib_compress},
+    frame::VideoFrame,
+    image::{changed_rect, rgb_pixels, ColorProfile, ImageError},
+    region::PixelRect,
+};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Chunk lengths and image dimensions are limited to 2^31 - 1.
+const MAX_LENGTH: usize = i32::MAX as usize;
+
+/// The PCS illuminant of ICC profiles.
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// Writes a frame as an 8 bit PNG. The alpha channel is only kept when some
+/// pixel isn't opaque. With a color profile, the matching `sRGB` chunk, or
+/// `cICP` and an embedded ICC profile for Display P3, is written along with
+/// `gAMA` and `cHRM` for older decoders.
+pub fn write_png<W: Write>(mut writer: W, frame: &VideoFrame, matrix: ColorMatrix, profile: Option<ColorProfile>) -> Result<(), ImageError> {
+    let (pixels, channels) = rgb_pixels(frame, matrix, true)?;
+    let width = frame.width();
+    let height = frame.height();
+
+    writer.write_all(&SIGNATURE)?;
+    write_header(&mut writer, width, height, channels)?;
+    if let Some(profile) = profile {
+        write_profile_chunks(&mut writer, profile)?;
+    }
+    let filtered = filter_rows(&pixels, width * channels, channels);
+    write_chunk(&mut writer, b"IDAT", &zlib_compress(&filtered))?;
+    write_chunk(&mut writer, b"IEND", &[])?;
+    Ok(())
+}
+
+/// Writes frames as an animated PNG, each shown for the duration paired
+/// with it. `loop_count` is the number of repetitions, zero for forever.
+/// Frames after the first only store the area that changed, and frames
+/// without changes extend the previous one. Every frame must have the size
+/// of the first; alpha is kept when some pixel of some frame isn't opaque.
+pub fn write_apng<W: Write>(mut writer: W, frames: &[(VideoFrame, Duration)], loop_count: u16, matrix: ColorMatrix) -> Result<(), ImageError> {
+    let (first, _) = frames.first().ok_or(ImageError::NoFrames)?;
+    let (width, height) = (first.width(), first.height());
+    let mut images = Vec::with_capacity(frames.len());
+    for (frame, _) in frames {
+        if frame.width() != width || frame.height() != height {
+            return Err(ImageError::SizeChanged);
+        }
+        images.push(rgb_pixels(frame, matrix, true)?);
+    }
+    let channels = images.iter().map(|(_, channels)| *channels).max().unwrap_or(3);
+    let images: Vec<Vec<u8>> = images
+        .into_iter()
+        .map(|(pixels, image_channels)| {
+            if image_channels == channels {
+                pixels
+            } else {
+                pixels.chunks_exact(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect()
+            }
+        })
+        .collect();
+
+    // Find what changed first, so frames without changes can extend the
+    // delay of the previous one.
+    let mut changes: Vec<(usize, PixelRect, Duration)> = Vec::with_capacity(frames.len());
+    for (index, (_, delay)) in frames.iter().enumerate() {
+        let rect = match index {
+            0 => Some(PixelRect::new(0, 0, width as u32, height as u32)),
+            _ => changed_rect(&images[index - 1], &images[index], width, channels),
+        };
+        match (rect, changes.last_mut()) {
+            (Some(rect), _) => changes.push((index, rect, *delay)),
+            (None, Some(last)) => last.2 += *delay,
+            (None, None) => {}
+        }
+    }
+
+    writer.write_all(&SIGNATURE)?;
+    write_header(&mut writer, width, height, channels)?;
+    let mut animation = (changes.len() as u32).to_be_bytes().to_vec();
+    animation.extend_from_slice(&(loop_count as u32).to_be_bytes());
+    write_chunk(&mut writer, b"acTL", &animation)?;
+    let mut sequence = 0u32;
+    for (index, rect, delay) in changes {
+        // Delays are a fraction of seconds; milliseconds unless too long.
+        let millis = delay.as_millis().min(u32::MAX as u128) as u32;
+        let (numerator, denominator) = if millis <= u16::MAX as u32 {
+            (millis as u16, 1000u16)
+        } else {
+            ((millis / 100).min(u16::MAX as u32) as u16, 10)
+        };
+        let mut control = sequence.to_be_bytes().to_vec();
+        for value in [rect.width, rect.height, rect.x, rect.y] {
+            control.extend_from_slice(&value.to_be_bytes());
+        }
+        control.extend_from_slice(&numerator.to_be_bytes());
+        control.extend_from_slice(&denominator.to_be_bytes());
+        // Leave the frame in place and replace the area it covers.
+        control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut writer, b"fcTL", &control)?;
+        sequence += 1;
+
+        let row_bytes = width * channels;
+        let mut pixels = Vec::with_capacity(rect.area() as usize * channels);
+        for y in rect.y as usize..rect.bottom() as usize {
+            pixels.extend_from_slice(&images[index][y * row_bytes + rect.x as usize * channels..y * row_bytes + rect.right() as usize * channels]);
+        }
+        let data = zlib_compress(&filter_rows(&pixels, rect.width as usize * channels, channels));
+        if index == 0 {
+            write_chunk(&mut writer, b"IDAT", &data)?;
+        } else {
+            let mut frame_data = sequence.to_be_bytes().to_vec();
+            frame_data.extend_from_slice(&data);
+            write_chunk(&mut writer, b"fdAT", &frame_data)?;
+            sequence += 1;
+        }
+    }
+    write_chunk(&mut writer, b"IEND", &[])?;
+    Ok(())
+}
+
+fn write_header<W: Write>(writer: &mut W, width: usize, height: usize, channels: usize) -> Result<(), ImageError> {
+    if width > MAX_LENGTH || height > MAX_LENGTH {
+        return Err(ImageError::TooLarge);
+    }
+    let color_type = if channels == 4 {
+        6
+    } else {
+        2
+    };
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<(), ImageError> {
+    if data.len() > MAX_LENGTH {
+        return Err(ImageError::TooLarge);
+    }
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32_update(crc32_update(!0, kind), data);
+    writer.write_all(&(!crc).to_be_bytes())?;
+    Ok(())
+}
+
+fn write_profile_chunks<W: Write>(writer: &mut W, profile: ColorProfile) -> Result<(), ImageError> {
+    // Chromaticities are stored as multiples of 1 / 100000, white point first.
+    let chromaticities: [u32; 8] = match profile {
+        ColorProfile::Srgb => [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000],
+        ColorProfile::DisplayP3 => [31270, 32900, 68000, 32000, 26500, 69000, 15000, 6000],
+    };
+    match profile {
+        // Perceptual rendering intent.
+        ColorProfile::Srgb => write_chunk(writer, b"sRGB", &[0])?,
+        ColorProfile::DisplayP3 => {
+            // Decoders that know `cICP` prefer it over the ICC profile.
+            let mut icc = b"Display P3\0\0".to_vec();
+            icc.extend_from_slice(&zlib_compress(&display_p3_icc()));
+            write_chunk(writer, b"iCCP", &icc)?;
+            // Display P3 primaries with the sRGB transfer function, RGB, full range.
+            write_chunk(writer, b"cICP", &[12, 13, 0, 1])?;
+        }
+    }
+    write_chunk(writer, b"gAMA", &45455u32.to_be_bytes())?;
+    let chromaticities: Vec<u8> = chromaticities.iter().flat_map(|value| value.to_be_bytes()).collect();
+    write_chunk(writer, b"cHRM", &chromaticities)
+}
+
+/// A minimal ICC v4 display profile for Display P3: the primaries adapted
+/// to D50 with the Bradford transform and the sRGB transfer curve.
+fn display_p3_icc() -> Vec<u8> {
+    let fixed = |values: &[f64]| -> Vec<u8> { values.iter().flat_map(|value| ((value * 65536.0).round() as i32).to_be_bytes()).collect() };
+    let tag = |kind: &[u8; 4], body: &[u8]| [&kind[..], &[0; 4], body].concat();
+    let text = |value: &str| {
+        let utf16: Vec<u8> = value.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        // One English record, starting right after the 28 byte record table.
+        let records = [
+            &1u32.to_be_bytes()[..],
+            &12u32.to_be_bytes(),
+            b"enUS",
+            &(utf16.len() as u32).to_be_bytes(),
+            &28u32.to_be_bytes(),
+        ]
+        .concat();
+        tag(b"mluc", &[records, utf16].concat())
+    };
+    let xyz = |values: &[f64; 3]| tag(b"XYZ ", &fixed(values));
+    // IEC 61966-2-1: Y = ((a * X + b) ^ g) for X >= d, otherwise c * X.
+    let curve = tag(
+        b"para",
+        &[&[0, 3, 0, 0][..], &fixed(&[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])].concat(),
+    );
+    let tags = [
+        (b"desc", text("Display P3")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(&D50)),
+        (b"rXYZ", xyz(&[0.515119, 0.241189, -0.00105])),
+        (b"gXYZ", xyz(&[0.291978, 0.692244, 0.041879])),
+        (b"bXYZ", xyz(&[0.157103, 0.066567, 0.784071])),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+        // Bradford adaptation from D65 to D50.
+        (
+            b"chad",
+            tag(
+                b"sf32",
+                &fixed(&[
+                    1.047886, 0.022919, -0.050216, 0.029582, 0.990484, -0.017079, -0.009252, 0.015073, 0.751678,
+                ]),
+            ),
+        ),
+    ];
+
+    let data_start = 128 + 4 + 12 * tags.len();
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    for (signature, body) in &tags {
+        table.extend_from_slice(&signature[..]);
+        table.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(body);
+        // Tag data starts on 4 byte boundaries.
+        data.resize((data.len() + 3) / 4 * 4, 0);
+    }
+
+    let size = data_start + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    // No preferred CMM, version 4.3, a display profile mapping RGB to XYZ.
+    profile.extend_from_slice(&[0; 4]);
+    profile.extend_from_slice(&[4, 0x30, 0, 0]);
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    for value in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&value.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    // Platform, flags, device and attributes are unset; perceptual intent.
+    profile.resize(68, 0);
+    profile.extend_from_slice(&fixed(&D50));
+    // No creator, and an all zero ID means it wasn't computed.
+    profile.resize(128, 0);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+/// Prefixes every row with the filter type that minimizes the sum of
+/// absolute differences, the heuristic recommended by the PNG specification.
+fn filter_rows(pixels: &[u8], row_bytes: usize, bpp: usize) -> Vec<u8> {
+    let rows = pixels.len().checked_div(row_bytes).unwrap_or(0);
+    let mut output = Vec::with_capacity(rows * (row_bytes + 1));
+    let zero = vec![0; row_bytes];
+    let mut candidates = vec![vec![0u8; row_bytes]; 5];
+    for y in 0..rows {
+        let row = &pixels[y * row_bytes..(y + 1) * row_bytes];
+        let previous = if y == 0 {
+            &zero[..]
+        } else {
+            &pixels[(y - 1) * row_bytes..y * row_bytes]
+        };
+        for (filter, candidate) in candidates.iter_mut().enumerate() {
+            for x in 0..row_bytes {
+                let left = if x >= bpp {
+                    row[x - bpp]
+                } else {
+                    0
+                };
+                let up = previous[x];
+                let up_left = if x >= bpp {
+                    previous[x - bpp]
+                } else {
+                    0
+                };
+                let predictor = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                candidate[x] = row[x].wrapping_sub(predictor);
+            }
+        }
+        let cost = |candidate: &Vec<u8>| candidate.iter().map(|value| (*value as i8).unsigned_abs() as u64).sum::<u64>();
+        let (filter, best) = candidates.iter().enumerate().min_by_key(|(_, candidate)| cost(candidate)).unwrap();
+        output.push(filter as u8);
+        output.extend_from_slice(best);
+    }
+    output
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deflate::zlib_decompress, frame::PixelFormat};
+
+    /// Splits a PNG into its chunks, checking the signature and every CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let kind = [rest[4], rest[5], rest[6], rest[7]];
+            let data = &rest[8..8 + length];
+            let crc = u32::from_be_bytes([rest[8 + length], rest[9 + length], rest[10 + length], rest[11 + length]]);
+            assert_eq!(crc, !crc32_update(crc32_update(!0, &kind), data), "{:?}", kind);
+            chunks.push((kind, data.to_vec()));
+            rest = &rest[12 + length..];
+        }
+        assert_eq!(chunks.last().map(|(kind, data)| (kind, data.len())), Some((b"IEND", 0)));
+        chunks
+    }
+
+    fn unfilter(data: &[u8], row_bytes: usize, bpp: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(data.len());
+        for (y, row) in data.chunks_exact(row_bytes + 1).enumerate() {
+            for x in 0..row_bytes {
+                let left = if x >= bpp {
+                    pixels[y * row_bytes + x - bpp]
+                } else {
+                    0
+                };
+                let up = if y > 0 {
+                    pixels[(y - 1) * row_bytes + x]
+                } else {
+                    0
+                };
+                let up_left = if x >= bpp && y > 0 {
+                    pixels[(y - 1) * row_bytes + x - bpp]
+                } else {
+                    0
+                };
+                let predictor = match row[0] {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    4 => paeth(left, up, up_left),
+                    filter => panic!("invalid filter {}", filter),
+                };
+                pixels.push(row[1 + x].wrapping_add(predictor));
+            }
+        }
+        pixels
+    }
+
+    fn gradient(width: usize, height: usize, alpha: u8) -> VideoFrame {
+        let data: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % width * 7) as u8, (i / width * 11) as u8, (i * 13) as u8, alpha])
+            .collect();
+        VideoFrame::from_bytes(width, height, PixelFormat::RGBA, &data).unwrap()
+    }
+
+    #[test]
+    fn png_round_trip() {
+        for &(alpha, channels, color_type) in &[(255, 3, 2), (128, 4, 6)] {
+            let frame = gradient(19, 7, alpha);
+            let mut png = Vec::new();
+            write_png(&mut png, &frame, ColorMatrix::default(), None).unwrap();
+            let chunks = chunks(&png);
+            let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+            assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+            assert_eq!(
+                chunks[0].1,
+                [&19u32.to_be_bytes()[..], &7u32.to_be_bytes(), &[8, color_type, 0, 0, 0]].concat()
+            );
+
+            let filtered = zlib_decompress(&chunks[1].1, 7 * (19 * channels + 1)).unwrap();
+            let pixels = unfilter(&filtered, 19 * channels, channels);
+            let expected: Vec<u8> = frame.planes()[0]
+                .data()
+                .chunks_exact(4)
+                .flat_map(|pixel| pixel[..channels].to_vec())
+                .collect();
+            assert_eq!(pixels, expected);
+        }
+    }
+
+    #[test]
+    fn png_profile_chunks() {
+        let frame = gradient(2, 2, 255);
+        let srgb: &[&[u8; 4]] = &[b"sRGB"];
+        let display_p3: &[&[u8; 4]] = &[b"iCCP", b"cICP"];
+        for &(profile, profile_kinds) in &[(ColorProfile::Srgb, srgb), (ColorProfile::DisplayP3, display_p3)] {
+            let mut png = Vec::new();
+            write_png(&mut png, &frame, ColorMatrix::default(), Some(profile)).unwrap();
+            let kinds: Vec<[u8; 4]> = chunks(&png).into_iter().map(|(kind, _)| kind).collect();
+            let expected: Vec<[u8; 4]> = [&[b"IHDR"][..], profile_kinds, &[b"gAMA", b"cHRM", b"IDAT", b"IEND"]]
+                .concat()
+                .into_iter()
+                .copied()
+                .collect();
+            assert_eq!(kinds, expected);
+        }
+    }
+
+    #[test]
+    fn display_p3_icc_profile() {
+        let mut png = Vec::new();
+        write_png(&mut png, &gradient(2, 2, 255), ColorMatrix::default(), Some(ColorProfile::DisplayP3)).unwrap();
+        let (_, icc) = chunks(&png).into_iter().find(|(kind, _)| kind == b"iCCP").unwrap();
+        assert_eq!(icc[..12], *b"Display P3\0\0");
+        let profile = zlib_decompress(&icc[12..], 1 << 16).unwrap();
+
+        let read_u32 = |offset: usize| u32::from_be_bytes([profile[offset], profile[offset + 1], profile[offset + 2], profile[offset + 3]]);
+        let read_fixed = |offset: usize| read_u32(offset) as i32 as f64 / 65536.0;
+        assert_eq!(read_u32(0) as usize, profile.len());
+        assert_eq!(profile[8..24], *b"\x04\x30\0\0mntrRGB XYZ ");
+        assert_eq!(profile[36..40], *b"acsp");
+
+        // Every tag lies within the profile, aligned to 4 bytes.
+        let mut tags = Vec::new();
+        for index in 0..read_u32(128) as usize {
+            let entry = 132 + 12 * index;
+            let (offset, size) = (read_u32(entry + 4) as usize, read_u32(entry + 8) as usize);
+            assert_eq!(offset % 4, 0);
+            assert!(offset + size <= profile.len());
+            tags.push((profile[entry..entry + 4].to_vec(), offset));
+        }
+        let tag = |signature: &[u8]| tags.iter().find(|(tag, _)| tag == signature).unwrap().1;
+        assert_eq!(tags.len(), 10);
+        assert_eq!(profile[tag(b"rTRC")..tag(b"rTRC") + 4], *b"para");
+
+        // The primaries at full intensity add up to the D50 white point.
+        for (channel, white) in D50.iter().enumerate() {
+            let sum: f64 = [b"rXYZ", b"gXYZ", b"bXYZ"]
+                .iter()
+                .map(|signature| read_fixed(tag(&signature[..]) + 8 + 4 * channel))
+                .sum();
+            assert!((sum - white).abs() < 1e-3, "{} {}", sum, white);
+            assert!((read_fixed(tag(b"wtpt") + 8 + 4 * channel) - white).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn oversized_chunks_are_rejected() {
+        // Zeroed allocations are lazy, so this doesn't touch 2 GiB of memory.
+        let data = vec![0; MAX_LENGTH + 1];
+        let mut png = Vec::new();
+        assert!(matches!(write_chunk(&mut png, b"IDAT", &data), Err(ImageError::TooLarge)));
+        assert!(matches!(write_header(&mut png, MAX_LENGTH + 1, 1, 3), Err(ImageError::TooLarge)));
+        assert!(png.is_empty());
+    }
+
+    #[test]
+    fn apng_chunks() {
+        let still = gradient(8, 8, 255);
+        let mut changed = still.clone();
+        changed.plane_mut(0).unwrap().row_mut(3)[8..12].copy_from_slice(&[1, 2, 3, 255]);
+        let frames = [
+            (still.clone(), Duration::from_millis(100)),
+            (still, Duration::from_millis(50)),
+            (changed, Duration::from_millis(100)),
+        ];
+        let mut apng = Vec::new();
+        write_apng(&mut apng, &frames, 0, ColorMatrix::default()).unwrap();
+        let chunks = chunks(&apng);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"IEND"]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 2, 0, 0, 0, 0]);
+        // The unchanged frame extends the first one's delay to 150 ms.
+        assert_eq!(chunks[2].1[20..24], [0, 150, 3, 232]);
+        // Sequence numbers count fcTL and fdAT chunks; the second frame only
+        // covers the changed pixel.
+        let control = &chunks[4].1;
+        assert_eq!(control[..4], 1u32.to_be_bytes());
+        assert_eq!(control[4..20], [[0, 0, 0, 1], [0, 0, 0, 1], [0, 0, 0, 2], [0, 0, 0, 3]].concat());
+        assert_eq!(chunks[5].1[..4], 2u32.to_be_bytes());
+        assert!(matches!(
+            write_apng(Vec::new(), &[], 0, ColorMatrix::default()),
+            Err(ImageError::NoFrames)
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::Write;
+
+use crate::{
+    convert::ColorMatrix,
+    frame::VideoFrame,
+    image::{rgb_pixels, ImageError},
+};
+
+/// Writes a frame as a binary PPM (`P6`). Alpha is dropped.
+pub fn write_ppm<W: Write>(mut writer: W, frame: &VideoFrame, matrix: ColorMatrix) -> Result<(), ImageError> {
+    let (pixels, _) = rgb_pixels(frame, matrix, false)?;
+    write!(writer, "P6\n{} {}\n255\n", frame.width(), frame.height())?;
+    writer.write_all(&pixels)?;
+    Ok(())
+}
+
+/// Writes a frame as a PAM (`P7`), with an alpha channel when some pixel
+/// isn't opaque.
+pub fn write_pam<W: Write>(mut writer: W, frame: &VideoFrame, matrix: ColorMatrix) -> Result<(), ImageError> {
+    let (pixels, channels) = rgb_pixels(frame, matrix, true)?;
+    let tuple_type = if channels == 4 {
+        "RGB_ALPHA"
+    } else {
+        "RGB"
+    };
+    write!(
+        writer,
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL 255\nTUPLTYPE {}\nENDHDR\n",
+        frame.width(),
+        frame.height(),
+        channels,
+        tuple_type
+    )?;
+    writer.write_all(&pixels)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+
+    fn frame(alpha: u8) -> VideoFrame {
+        VideoFrame::from_bytes(2, 1, PixelFormat::BGRA, &[1, 2, 3, alpha, 4, 5, 6, 255]).unwrap()
+    }
+
+    #[test]
+    fn ppm() {
+        let mut ppm = Vec::new();
+        write_ppm(&mut ppm, &frame(0), ColorMatrix::default()).unwrap();
+        assert_eq!(ppm, b"P6\n2 1\n255\n\x03\x02\x01\x06\x05\x04");
+    }
+
+    #[test]
+    fn pam() {
+        let mut pam = Vec::new();
+        write_pam(&mut pam, &frame(255), ColorMatrix::default()).unwrap();
+        assert_eq!(
+            pam,
+            &b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\nTUPLTYPE RGB\nENDHDR\n\x03\x02\x01\x06\x05\x04"[..]
+        );
+
+        let mut pam = Vec::new();
+        write_pam(&mut pam, &frame(9), ColorMatrix::default()).unwrap();
+        let header = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        assert_eq!(pam[..header.len()], header[..]);
+        assert_eq!(pam[header.len()..], [3, 2, 1, 9, 6, 5, 4, 255]);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+#[cfg(all(target_os = "macos", feature = "video"))]
+use core_media::sample_buffer::CMSampleBuffer;
+#[cfg(all(target_os = "macos", feature = "video"))]
+use core_video::pixel_buffer::CVPixelBuffer;
+
+use crate::frame::{FrameError, PixelFormat, Plane, PlaneLayout, VideoFrame};
+#[cfg(target_os = "macos")]
+use crate::stream::SCStreamConfiguration;
+
+/// Idle memory a pool keeps by default, enough for a few 4K BGRA frames.
+pub const DEFAULT_MAX_IDLE_BYTES: usize = 128 << 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PoolStats {
+    /// Buffers served from the pool.
+    pub hits: u64,
+    /// Buffers that had to be allocated.
+    pub misses: u64,
+    /// Buffers returned to the pool by dropped planes.
+    pub recycled: u64,
+    /// Buffers freed instead of kept, because the pool was full or their
+    /// size no longer matches the stream.
+    pub discarded: u64,
+    pub idle_buffers: usize,
+    pub idle_bytes: usize,
+}
+
+impl PoolStats {
+    /// Fraction of buffers served without allocating, or zero before the
+    /// first request.
+    pub fn hit_rate(&self) -> f64 {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            0.0
+        } else {
+            self.hits as f64 / requests as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolState {
+    /// Idle buffers by length.
+    idle: HashMap<usize, Vec<Vec<u8>>>,
+    max_idle_bytes: usize,
+    /// Buffer lengths of the current frame format; buffers of other lengths
+    /// aren't kept.
+    expected: Vec<usize>,
+    stats: PoolStats,
+}
+
+impl PoolState {
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        match self.idle.get_mut(&len).and_then(Vec::pop) {
+            Some(buffer) => {
+                self.stats.hits += 1;
+                self.stats.idle_buffers -= 1;
+                self.stats.idle_bytes -= buffer.capacity();
+                buffer
+            }
+            None => {
+                self.stats.misses += 1;
+                vec![0; len]
+            }
+        }
+    }
+
+    fn put(&mut self, buffer: Vec<u8>) {
+        let len = buffer.len();
+        let stale = !self.expected.is_empty() && !self.expected.contains(&len);
+        if len == 0 || stale || self.stats.idle_bytes + buffer.capacity() > self.max_idle_bytes {
+            self.stats.discarded += 1;
+            return;
+        }
+        self.stats.recycled += 1;
+        self.stats.idle_buffers += 1;
+        self.stats.idle_bytes += buffer.capacity();
+        self.idle.entry(len).or_default().push(buffer);
+    }
+
+    fn expect(&mut self, mut lengths: Vec<usize>) {
+        lengths.sort_unstable();
+        lengths.dedup();
+        if lengths == self.expected {
+            return;
+        }
+        self.expected = lengths;
+        let expected = &self.expected;
+        let stats = &mut self.stats;
+        self.idle.retain(|len, buffers| {
+            if expected.contains(len) {
+                return true;
+            }
+            stats.discarded += buffers.len() as u64;
+            stats.idle_buffers -= buffers.len();
+            stats.idle_bytes -= buffers.iter().map(Vec::capacity).sum::<usize>();
+            false
+        });
+    }
+
+    fn trim(&mut self) {
+        while self.stats.idle_bytes > self.max_idle_bytes {
+            let buffer = match self.idle.values_mut().find_map(Vec::pop) {
+                Some(buffer) => buffer,
+                None => break,
+            };
+            self.stats.discarded += 1;
+            self.stats.idle_buffers -= 1;
+            self.stats.idle_bytes -= buffer.capacity();
+        }
+        self.idle.retain(|_, buffers| !buffers.is_empty());
+    }
+}
+
+/// Hands the buffer of a dropped plane back to its pool, if the pool still
+/// exists.
+#[derive(Debug)]
+pub(crate) struct Recycler(Weak<Mutex<PoolState>>);
+
+impl Recycler {
+    pub(crate) fn recycle(&self, buffer: Vec<u8>) {
+        if let Some(state) = self.0.upgrade() {
+            state.lock().unwrap_or_else(PoisonError::into_inner).put(buffer);
+        }
+    }
+}
+
+/// Reuses plane buffers between owned frames, so copying frames out of
+/// sample buffers doesn't allocate once the pool is warm. Frames taken from
+/// the pool return their buffers when dropped, from any thread; clones of a
+/// pool share the same buffers.
+///
+/// Buffers are kept by size for the current frame format only. When frames
+/// of another size or pixel format are requested, or [`update_configuration`]
+/// announces one, idle buffers of the old format are freed.
+///
+/// [`update_configuration`]: FramePool::update_configuration
+#[derive(Clone, Debug)]
+pub struct FramePool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl Default for FramePool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IDLE_BYTES)
+    }
+}
+
+impl FramePool {
+    /// A pool keeping at most `max_idle_bytes` of buffers that aren't in use.
+    /// Buffers held by live frames don't count towards the limit.
+    pub fn new(max_idle_bytes: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PoolState {
+                idle: HashMap::new(),
+                max_idle_bytes,
+                expected: Vec::new(),
+                stats: PoolStats::default(),
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn max_idle_bytes(&self) -> usize {
+        self.state().max_idle_bytes
+    }
+
+    /// Changes the limit, freeing idle buffers beyond it.
+    pub fn set_max_idle_bytes(&self, max_idle_bytes: usize) {
+        let mut state = self.state();
+        state.max_idle_bytes = max_idle_bytes;
+        state.trim();
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.state().stats
+    }
+
+    /// Zeroes the counters, keeping the idle buffer totals.
+    pub fn reset_stats(&self) {
+        let mut state = self.state();
+        state.stats = PoolStats {
+            idle_buffers: state.stats.idle_buffers,
+            idle_bytes: state.stats.idle_bytes,
+            ..PoolStats::default()
+        };
+    }
+
+    /// Frees every idle buffer.
+    pub fn clear(&self) {
+        let mut state = self.state();
+        let max_idle_bytes = state.max_idle_bytes;
+        state.max_idle_bytes = 0;
+        state.trim();
+        state.max_idle_bytes = max_idle_bytes;
+    }
+
+    /// Prepares for frames of the given size and format, freeing idle buffers
+    /// that won't fit them.
+    pub fn set_format(&self, width: usize, height: usize, pixel_format: PixelFormat) -> Result<(), FrameError> {
+        self.expect_layouts(&VideoFrame::layouts(width, height, pixel_format)?);
+        Ok(())
+    }
+
+    /// Prepares for the size and pixel format of a configuration, typically
+    /// the one passed to [`SCStream::update_configuration`].
+    ///
+    /// [`SCStream::update_configuration`]: crate::stream::SCStream::update_configuration
+    #[cfg(target_os = "macos")]
+    pub fn update_configuration(&self, configuration: &SCStreamConfiguration) -> Result<(), FrameError> {
+        self.set_format(
+            configuration.get_width(),
+            configuration.get_height(),
+            PixelFormat(configuration.get_pixel_format()),
+        )
+    }
+
+    pub(crate) fn expect_layouts(&self, layouts: &[PlaneLayout]) {
+        self.state()
+            .expect(layouts.iter().map(|layout| layout.row_bytes() * layout.height).collect());
+    }
+
+    /// A plane over a recycled buffer with unspecified contents.
+    pub(crate) fn take_plane(&self, layout: PlaneLayout) -> Plane {
+        let buffer = self.state().take(layout.row_bytes() * layout.height);
+        Plane::pooled(buffer, layout, Some(Recycler(Arc::downgrade(&self.state))))
+    }
+
+    /// A zeroed plane whose buffer returns to the pool when dropped.
+    pub fn plane(&self, layout: PlaneLayout) -> Plane {
+        let mut plane = self.take_plane(layout);
+        plane.data_mut().fill(0);
+        plane
+    }
+
+    /// A zeroed frame whose buffers return to the pool when dropped.
+    pub fn frame(&self, width: usize, height: usize, pixel_format: PixelFormat) -> Result<VideoFrame, FrameError> {
+        let layouts = VideoFrame::layouts(width, height, pixel_format)?;
+        self.expect_layouts(&layouts);
+        let planes = layouts.into_iter().map(|layout| self.plane(layout)).collect();
+        Ok(VideoFrame::from_parts(width, height, pixel_format, planes))
+    }
+
+    /// Like [`VideoFrame::from_pixel_buffer`], copying into pooled buffers.
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub fn copy_pixel_buffer(&self, pixel_buffer: &CVPixelBuffer) -> Result<VideoFrame, FrameError> {
+        VideoFrame::copy_pixel_buffer(pixel_buffer, Some(self))
+    }
+
+    /// Like [`VideoFrame::from_sample_buffer`], copying into pooled buffers.
+    #[cfg(all(target_os = "macos", feature = "video"))]
+    pub fn copy_sample_buffer(&self, sample_buffer: &CMSampleBuffer) -> Result<VideoFrame, FrameError> {
+        VideoFrame::copy_sample_buffer(sample_buffer, Some(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_frames_return_their_planes() {
+        let pool = FramePool::default();
+        let mut frame = pool.frame(4, 2, PixelFormat::I420VideoRange).unwrap();
+        frame.plane_mut(0).unwrap().row_mut(0).fill(9);
+        drop(frame);
+        let stats = pool.stats();
+        assert_eq!((stats.misses, stats.recycled, stats.idle_buffers, stats.idle_bytes), (3, 3, 3, 12));
+
+        // The buffers come back, zeroed again; dropping on another thread
+        // returns them too.
+        let frame = pool.frame(4, 2, PixelFormat::I420VideoRange).unwrap();
+        assert!(frame.planes().iter().all(|plane| plane.data().iter().all(|value| *value == 0)));
+        std::thread::spawn(move || drop(frame)).join().unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.recycled, stats.idle_buffers), (3, 3, 6, 3));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn other_sizes_miss_and_free_stale_buffers() {
+        let pool = FramePool::default();
+        drop(pool.frame(4, 2, PixelFormat::BGRA).unwrap());
+        assert_eq!(pool.stats().idle_bytes, 32);
+
+        drop(pool.frame(6, 2, PixelFormat::BGRA).unwrap());
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.discarded), (0, 2, 1));
+        assert_eq!((stats.idle_buffers, stats.idle_bytes), (1, 48));
+
+        // A frame of the old size still alive isn't kept when it returns.
+        let old = Plane::pooled(vec![0; 32], PlaneLayout::new(4, 2, 4), Some(Recycler(Arc::downgrade(&pool.state))));
+        drop(old);
+        assert_eq!((pool.stats().discarded, pool.stats().idle_bytes), (2, 48));
+
+        pool.set_format(2, 2, PixelFormat::BGRA).unwrap();
+        assert_eq!((pool.stats().discarded, pool.stats().idle_buffers), (3, 0));
+    }
+
+    #[test]
+    fn idle_bytes_are_capped() {
+        // The chroma planes don't fit beside the 8 byte luma plane.
+        let pool = FramePool::new(10);
+        drop(pool.frame(4, 2, PixelFormat::I420FullRange).unwrap());
+        let stats = pool.stats();
+        assert_eq!((stats.recycled, stats.discarded, stats.idle_bytes), (2, 1, 10));
+
+        pool.set_max_idle_bytes(4);
+        assert!(pool.stats().idle_bytes <= 4);
+        assert_eq!(pool.max_idle_bytes(), 4);
+        pool.clear();
+        assert_eq!((pool.stats().idle_buffers, pool.stats().idle_bytes), (0, 0));
+        assert_eq!(pool.max_idle_bytes(), 4);
+
+        pool.reset_stats();
+        assert_eq!(pool.stats(), PoolStats::default());
+        assert_eq!(pool.stats().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn clones_and_orphans_are_not_pooled() {
+        let pool = FramePool::default();
+        let frame = pool.frame(4, 4, PixelFormat::BGRA).unwrap();
+        drop(frame.clone());
+        assert_eq!(pool.stats().recycled, 0);
+        drop(frame);
+        assert_eq!(pool.stats().recycled, 1);
+
+        // Frames outliving their pool just free their buffers.
+        let frame = pool.frame(4, 4, PixelFormat::BGRA).unwrap();
+        drop(pool);
+        drop(frame);
+    }
+}
@@ -0,0 +1,688 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+#[cfg(target_os = "macos")]
+use crate::stream::SCStreamConfiguration;
+use crate::{
+    audio::{AudioBuffer, SampleFormat},
+    sink::{AudioSink, SinkError},
+};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+/// Size fields are written as this until known, which streaming readers
+/// take as "read to the end".
+const UNKNOWN_SIZE: u32 = u32::MAX;
+
+/// Samples of `audio` interleaved in `sample_format`, little endian.
+fn interleaved_bytes(audio: &AudioBuffer, sample_format: SampleFormat) -> Vec<u8> {
+    let mut bytes = audio.convert(sample_format, true).into_buffers().swap_remove(0);
+    if cfg!(target_endian = "big") {
+        for sample in bytes.chunks_exact_mut(sample_format.bytes_per_sample()) {
+            sample.reverse();
+        }
+    }
+    bytes
+}
+
+/// Output rate and channel count: the ones asked for, or those of the first
+/// buffer.
+fn output_layout(sample_rate: Option<f64>, channels: Option<usize>, audio: &AudioBuffer) -> (f64, usize) {
+    (
+        sample_rate.unwrap_or_else(|| audio.sample_rate()),
+        channels.unwrap_or_else(|| audio.channels()),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn configured_layout(configuration: &SCStreamConfiguration) -> (Option<f64>, Option<usize>) {
+    let sample_rate = configuration.get_sample_rate();
+    let channels = configuration.get_channel_count();
+    (
+        Some(sample_rate).filter(|rate| *rate > 0.0),
+        Some(channels).filter(|channels| *channels > 0),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WavOptions {
+    pub sample_format: SampleFormat,
+    /// Rate every buffer must have; `None` takes it from the first buffer.
+    pub sample_rate: Option<f64>,
+    /// Channel count every buffer must have; `None` takes it from the first
+    /// buffer.
+    pub channels: Option<usize>,
+    /// Seconds of audio between header updates on seekable outputs, so a
+    /// file cut short by a crash is readable up to the last update. Zero
+    /// only writes the sizes when finishing.
+    pub header_interval: f64,
+}
+
+impl Default for WavOptions {
+    fn default() -> Self {
+        Self {
+            sample_format: SampleFormat::F32,
+            sample_rate: None,
+            channels: None,
+            header_interval: 1.0,
+        }
+    }
+}
+
+impl WavOptions {
+    /// Options matching the sample rate and channel count a stream was
+    /// configured with.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Self {
+        let (sample_rate, channels) = configured_layout(configuration);
+        Self {
+            sample_rate,
+            channels,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct WavHeader {
+    sample_rate: f64,
+    channels: usize,
+    /// Offset of the data, from the start of the header.
+    len: u64,
+    /// Offset of the frame count in the `fact` chunk, if there is one.
+    fact: Option<u64>,
+}
+
+impl WavHeader {
+    fn new(sample_rate: f64, channels: usize, sample_format: SampleFormat) -> (Self, Vec<u8>) {
+        let bits = sample_format.bytes_per_sample() as u16 * 8;
+        let tag = if sample_format.is_float() {
+            WAVE_FORMAT_IEEE_FLOAT
+        } else {
+            WAVE_FORMAT_PCM
+        };
+        // Like ffmpeg, only use the extensible format where plain readers
+        // would guess the layout wrong.
+        let extensible = channels > 2 || (!sample_format.is_float() && bits > 16);
+        let block_align = (channels * sample_format.bytes_per_sample()) as u16;
+        let rate = sample_rate.round() as u32;
+        let format_tag = if extensible {
+            WAVE_FORMAT_EXTENSIBLE
+        } else {
+            tag
+        };
+
+        let mut format = Vec::with_capacity(40);
+        format.extend_from_slice(&format_tag.to_le_bytes());
+        format.extend_from_slice(&(channels as u16).to_le_bytes());
+        format.extend_from_slice(&rate.to_le_bytes());
+        format.extend_from_slice(&(rate * block_align as u32).to_le_bytes());
+        format.extend_from_slice(&block_align.to_le_bytes());
+        format.extend_from_slice(&bits.to_le_bytes());
+        if extensible {
+            format.extend_from_slice(&22u16.to_le_bytes());
+            format.extend_from_slice(&bits.to_le_bytes());
+            let mask = if channels >= 32 {
+                u32::MAX
+            } else {
+                (1u32 << channels) - 1
+            };
+            format.extend_from_slice(&mask.to_le_bytes());
+            // KSDATAFORMAT_SUBTYPE_PCM or _IEEE_FLOAT.
+            format.extend_from_slice(&tag.to_le_bytes());
+            format.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71]);
+        } else if tag != WAVE_FORMAT_PCM {
+            format.extend_from_slice(&0u16.to_le_bytes());
+        }
+
+        let mut bytes = Vec::with_capacity(80);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&(format.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&format);
+        let mut fact = None;
+        if tag != WAVE_FORMAT_PCM {
+            bytes.extend_from_slice(b"fact");
+            bytes.extend_from_slice(&4u32.to_le_bytes());
+            fact = Some(bytes.len() as u64);
+            bytes.extend_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        let header = Self {
+            sample_rate,
+            channels,
+            len: bytes.len() as u64,
+            fact,
+        };
+        (header, bytes)
+    }
+
+    /// Size fields as `(offset from the start of the header, value)`.
+    fn sizes(&self, data_bytes: u64, frames: u64) -> Vec<(u64, u32)> {
+        let mut sizes = vec![(4, (self.len - 8 + data_bytes) as u32), (self.len - 4, data_bytes as u32)];
+        if let Some(fact) = self.fact {
+            sizes.push((fact, frames.min(u32::MAX as u64) as u32));
+        }
+        sizes
+    }
+}
+
+type PatchHeader<W> = fn(&mut W, u64, &[(u64, u32)]) -> io::Result<()>;
+
+/// Rewrites the size fields of a header `written` bytes before the current
+/// position, then returns to it.
+fn patch_header<W: Write + Seek>(writer: &mut W, written: u64, sizes: &[(u64, u32)]) -> io::Result<()> {
+    let end = writer.stream_position()?;
+    let start = end - written;
+    for &(offset, value) in sizes {
+        writer.seek(SeekFrom::Start(start + offset))?;
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Writes audio as a WAV file of interleaved little endian samples.
+///
+/// The header is written with the first buffer, with its sizes marked
+/// unknown so readers take everything up to the end of the file. Seekable
+/// outputs created with [`new`] get the real sizes every
+/// [`WavOptions::header_interval`] and when finishing; outputs created with
+/// [`streaming`], such as pipes, keep the unknown sizes.
+///
+/// [`new`]: WavWriter::new
+/// [`streaming`]: WavWriter::streaming
+#[derive(Debug)]
+pub struct WavWriter<W: Write> {
+    writer: W,
+    options: WavOptions,
+    patch: Option<PatchHeader<W>>,
+    header: Option<WavHeader>,
+    data_bytes: u64,
+    frames_written: u64,
+    frames_since_update: u64,
+    finished: bool,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W, options: WavOptions) -> Self {
+        Self::with_patch(writer, options, Some(patch_header::<W>))
+    }
+}
+
+impl<W: Write> WavWriter<W> {
+    /// A writer for outputs that can't seek back to update the header.
+    pub fn streaming(writer: W, options: WavOptions) -> Self {
+        Self::with_patch(writer, options, None)
+    }
+
+    fn with_patch(writer: W, options: WavOptions, patch: Option<PatchHeader<W>>) -> Self {
+        Self {
+            writer,
+            options,
+            patch,
+            header: None,
+            data_bytes: 0,
+            frames_written: 0,
+            frames_since_update: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &WavOptions {
+        &self.options
+    }
+
+    /// Number of sample frames written, one sample per channel each.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self, sample_rate: f64, channels: usize) -> Result<WavHeader, SinkError> {
+        let (header, bytes) = WavHeader::new(sample_rate, channels, self.options.sample_format);
+        self.writer.write_all(&bytes)?;
+        self.header = Some(header);
+        Ok(header)
+    }
+
+    fn update_header(&mut self, header: &WavHeader) -> Result<(), SinkError> {
+        if let Some(patch) = self.patch {
+            patch(
+                &mut self.writer,
+                header.len + self.data_bytes,
+                &header.sizes(self.data_bytes, self.frames_written),
+            )?;
+            self.writer.flush()?;
+        }
+        self.frames_since_update = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> AudioSink for WavWriter<W> {
+    fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let header = match self.header {
+            Some(header) => header,
+            None => {
+                let (sample_rate, channels) = output_layout(self.options.sample_rate, self.options.channels, audio);
+                self.write_header(sample_rate, channels)?
+            }
+        };
+        if audio.sample_rate() != header.sample_rate || audio.channels() != header.channels {
+            return Err(SinkError::FormatChanged);
+        }
+        let bytes = interleaved_bytes(audio, self.options.sample_format);
+        if header.len + self.data_bytes + bytes.len() as u64 > u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::Other, "WAV data exceeds 4 GiB").into());
+        }
+        self.writer.write_all(&bytes)?;
+        self.data_bytes += bytes.len() as u64;
+        self.frames_written += audio.frames() as u64;
+        self.frames_since_update += audio.frames() as u64;
+        let interval = self.options.header_interval * header.sample_rate;
+        if interval > 0.0 && self.frames_since_update as f64 >= interval {
+            self.update_header(&header)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        let header = match (self.header, self.options.sample_rate, self.options.channels) {
+            (Some(header), ..) => Some(header),
+            // Still write an empty file when the format is known.
+            (None, Some(sample_rate), Some(channels)) => Some(self.write_header(sample_rate, channels)?),
+            (None, ..) => None,
+        };
+        if let Some(header) = header {
+            self.update_header(&header)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawAudioOptions {
+    pub sample_format: SampleFormat,
+    /// Rate every buffer must have; `None` takes it from the first buffer.
+    pub sample_rate: Option<f64>,
+    /// Channel count every buffer must have; `None` takes it from the first
+    /// buffer.
+    pub channels: Option<usize>,
+}
+
+impl Default for RawAudioOptions {
+    fn default() -> Self {
+        Self {
+            sample_format: SampleFormat::F32,
+            sample_rate: None,
+            channels: None,
+        }
+    }
+}
+
+impl RawAudioOptions {
+    /// Options matching the sample rate and channel count a stream was
+    /// configured with.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Self {
+        let (sample_rate, channels) = configured_layout(configuration);
+        Self {
+            sample_rate,
+            channels,
+            ..Self::default()
+        }
+    }
+}
+
+/// The ffmpeg `-f` name of the format [`RawAudioWriter`] writes samples in.
+pub fn ffmpeg_sample_format(sample_format: SampleFormat) -> &'static str {
+    match sample_format {
+        SampleFormat::I16 => "s16le",
+        SampleFormat::I32 => "s32le",
+        SampleFormat::F32 => "f32le",
+        SampleFormat::F64 => "f64le",
+    }
+}
+
+/// Writes audio as headerless interleaved little endian samples. The reader
+/// must be told the format, rate and channel count, e.g. `ffmpeg -f f32le
+/// -ar 48000 -ac 2 -i -`.
+#[derive(Debug)]
+pub struct RawAudioWriter<W: Write> {
+    writer: W,
+    options: RawAudioOptions,
+    layout: Option<(f64, usize)>,
+    frames_written: u64,
+    finished: bool,
+}
+
+impl<W: Write> RawAudioWriter<W> {
+    pub fn new(writer: W, options: RawAudioOptions) -> Self {
+        Self {
+            writer,
+            options,
+            layout: None,
+            frames_written: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &RawAudioOptions {
+        &self.options
+    }
+
+    /// Sample rate and channel count of the output, known after the first
+    /// buffer.
+    pub fn layout(&self) -> Option<(f64, usize)> {
+        self.layout
+    }
+
+    /// Number of sample frames written, one sample per channel each.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> AudioSink for RawAudioWriter<W> {
+    fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let options = self.options;
+        let layout = *self
+            .layout
+            .get_or_insert_with(|| output_layout(options.sample_rate, options.channels, audio));
+        if (audio.sample_rate(), audio.channels()) != layout {
+            return Err(SinkError::FormatChanged);
+        }
+        self.writer.write_all(&interleaved_bytes(audio, self.options.sample_format))?;
+        self.frames_written += audio.frames() as u64;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if !self.finished {
+            self.finished = true;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    /// Chunk ids, sizes and data offsets after the `RIFF....WAVE` preamble.
+    fn chunks(bytes: &[u8]) -> Vec<(&[u8], u32, usize)> {
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while offset + 8 <= bytes.len() {
+            let size = u32_at(bytes, offset + 4);
+            chunks.push((&bytes[offset..offset + 4], size, offset + 8));
+            if size == UNKNOWN_SIZE {
+                break;
+            }
+            offset += 8 + size as usize;
+        }
+        chunks
+    }
+
+    fn write_wav(options: WavOptions, buffers: &[AudioBuffer]) -> Vec<u8> {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), options);
+        for audio in buffers {
+            writer.write_audio(audio).unwrap();
+        }
+        writer.finish().unwrap();
+        writer.into_inner().into_inner()
+    }
+
+    #[test]
+    fn pcm16_header_bytes() {
+        let options = WavOptions {
+            sample_format: SampleFormat::I16,
+            ..WavOptions::default()
+        };
+        let audio = AudioBuffer::from_interleaved_f32(44100.0, 1, &[0.5, -0.5, 0.25]).unwrap();
+        let bytes = write_wav(options, &[audio]);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"RIFF");
+        expected.extend_from_slice(&42u32.to_le_bytes());
+        expected.extend_from_slice(b"WAVEfmt ");
+        expected.extend_from_slice(&16u32.to_le_bytes());
+        expected.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        expected.extend_from_slice(&1u16.to_le_bytes());
+        expected.extend_from_slice(&44100u32.to_le_bytes());
+        expected.extend_from_slice(&88200u32.to_le_bytes());
+        expected.extend_from_slice(&2u16.to_le_bytes());
+        expected.extend_from_slice(&16u16.to_le_bytes());
+        expected.extend_from_slice(b"data");
+        expected.extend_from_slice(&6u32.to_le_bytes());
+        for sample in &[16384i16, -16384, 8192] {
+            expected.extend_from_slice(&sample.to_le_bytes());
+        }
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn format_chunks() {
+        // (format, channels, format tag, fmt size, has fact)
+        let cases = [
+            (SampleFormat::I16, 2, WAVE_FORMAT_PCM, 16, false),
+            (SampleFormat::F32, 2, WAVE_FORMAT_IEEE_FLOAT, 18, true),
+            (SampleFormat::F64, 1, WAVE_FORMAT_IEEE_FLOAT, 18, true),
+            (SampleFormat::I32, 2, WAVE_FORMAT_EXTENSIBLE, 40, false),
+            (SampleFormat::I16, 6, WAVE_FORMAT_EXTENSIBLE, 40, false),
+            (SampleFormat::F32, 6, WAVE_FORMAT_EXTENSIBLE, 40, true),
+        ];
+        for &(sample_format, channels, tag, fmt_size, has_fact) in &cases {
+            let options = WavOptions {
+                sample_format,
+                ..WavOptions::default()
+            };
+            let audio = AudioBuffer::from_interleaved_f32(48000.0, channels, &vec![0.0; 5 * channels]).unwrap();
+            let bytes = write_wav(options, &[audio]);
+            let name = format!("{} x{}", sample_format, channels);
+            let block_align = (channels * sample_format.bytes_per_sample()) as u32;
+
+            assert_eq!(&bytes[..4], b"RIFF", "{}", name);
+            assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8, "{}", name);
+            assert_eq!(&bytes[8..12], b"WAVE", "{}", name);
+            let chunks = chunks(&bytes);
+            let ids: Vec<_> = chunks.iter().map(|chunk| chunk.0).collect();
+            let expected_ids: &[&[u8]] = if has_fact {
+                &[b"fmt ", b"fact", b"data"]
+            } else {
+                &[b"fmt ", b"data"]
+            };
+            assert_eq!(ids, expected_ids, "{}", name);
+
+            let (_, size, fmt) = chunks[0];
+            assert_eq!(size, fmt_size, "{}", name);
+            assert_eq!(u16_at(&bytes, fmt), tag, "{}", name);
+            assert_eq!(u16_at(&bytes, fmt + 2), channels as u16, "{}", name);
+            assert_eq!(u32_at(&bytes, fmt + 4), 48000, "{}", name);
+            assert_eq!(u32_at(&bytes, fmt + 8), 48000 * block_align, "{}", name);
+            assert_eq!(u16_at(&bytes, fmt + 12) as u32, block_align, "{}", name);
+            let bits = sample_format.bytes_per_sample() as u16 * 8;
+            assert_eq!(u16_at(&bytes, fmt + 14), bits, "{}", name);
+            if tag == WAVE_FORMAT_EXTENSIBLE {
+                assert_eq!(u16_at(&bytes, fmt + 16), 22, "{}", name);
+                assert_eq!(u16_at(&bytes, fmt + 18), bits, "{}", name);
+                assert_eq!(u32_at(&bytes, fmt + 20), (1 << channels) - 1, "{}", name);
+                let subtype = if sample_format.is_float() {
+                    WAVE_FORMAT_IEEE_FLOAT
+                } else {
+                    WAVE_FORMAT_PCM
+                };
+                assert_eq!(u16_at(&bytes, fmt + 24), subtype, "{}", name);
+            }
+            if has_fact {
+                let (_, size, fact) = chunks[1];
+                assert_eq!((size, u32_at(&bytes, fact)), (4, 5), "{}", name);
+            }
+            let (_, size, data) = *chunks.last().unwrap();
+            assert_eq!(size, 5 * block_align, "{}", name);
+            assert_eq!(data + size as usize, bytes.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn sizes_are_patched_while_writing() {
+        let options = WavOptions {
+            header_interval: 0.5,
+            ..WavOptions::default()
+        };
+        let audio = AudioBuffer::from_interleaved_f32(8.0, 1, &[0.25; 3]).unwrap();
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), options);
+
+        // Sizes stay unknown until four frames, half a second, are written.
+        writer.write_audio(&audio).unwrap();
+        let bytes = writer.get_ref().get_ref().clone();
+        assert_eq!(u32_at(&bytes, 4), UNKNOWN_SIZE);
+        assert_eq!(chunks(&bytes).last().unwrap().1, UNKNOWN_SIZE);
+
+        writer.write_audio(&audio).unwrap();
+        let bytes = writer.get_ref().get_ref().clone();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        let chunks = chunks(&bytes);
+        assert_eq!(chunks[1].0, b"fact");
+        assert_eq!(u32_at(&bytes, chunks[1].2), 6);
+        assert_eq!(chunks[2].1, 24);
+        // Patching returns to the end, so later samples are appended.
+        assert_eq!(writer.get_ref().position() as usize, bytes.len());
+
+        writer.write_audio(&audio).unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().into_inner();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        let chunks = self::chunks(&bytes);
+        assert_eq!(u32_at(&bytes, chunks[1].2), 9);
+        assert_eq!(chunks[2].1, 36);
+    }
+
+    #[test]
+    fn streaming_keeps_unknown_sizes() {
+        let audio = AudioBuffer::from_interleaved_f32(48000.0, 2, &[0.5; 8]).unwrap();
+        let mut writer = WavWriter::streaming(Vec::new(), WavOptions::default());
+        writer.write_audio(&audio).unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(u32_at(&bytes, 4), UNKNOWN_SIZE);
+        let chunks = chunks(&bytes);
+        assert_eq!(u32_at(&bytes, chunks[1].2), UNKNOWN_SIZE);
+        assert_eq!((chunks[2].0, chunks[2].1), (&b"data"[..], UNKNOWN_SIZE));
+        assert_eq!(bytes.len() - chunks[2].2, 32);
+    }
+
+    #[test]
+    fn finishing_without_audio() {
+        // Only written when the layout is known up front.
+        assert!(write_wav(WavOptions::default(), &[]).is_empty());
+        let options = WavOptions {
+            sample_format: SampleFormat::I16,
+            sample_rate: Some(48000.0),
+            channels: Some(2),
+            ..WavOptions::default()
+        };
+        let bytes = write_wav(options, &[]);
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(u32_at(&bytes, 4), 36);
+        assert_eq!(u32_at(&bytes, 40), 0);
+    }
+
+    #[test]
+    fn format_changes_are_rejected() {
+        let options = WavOptions {
+            sample_rate: Some(48000.0),
+            ..WavOptions::default()
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), options);
+        let audio = AudioBuffer::from_interleaved_f32(44100.0, 2, &[0.0; 4]).unwrap();
+        assert!(matches!(writer.write_audio(&audio), Err(SinkError::FormatChanged)));
+
+        let mut writer = RawAudioWriter::new(Vec::new(), RawAudioOptions::default());
+        writer.write_audio(&audio).unwrap();
+        let mono = AudioBuffer::from_interleaved_f32(44100.0, 1, &[0.0; 2]).unwrap();
+        assert!(matches!(writer.write_audio(&mono), Err(SinkError::FormatChanged)));
+        assert_eq!(writer.layout(), Some((44100.0, 2)));
+        writer.finish().unwrap();
+        assert!(matches!(writer.write_audio(&audio), Err(SinkError::Finished)));
+        assert_eq!(writer.frames_written(), 2);
+    }
+
+    #[test]
+    fn raw_sample_formats() {
+        let samples = [0.5, -0.25, 1.0, -1.0];
+        let audio = AudioBuffer::from_planar_f32(48000.0, &[&samples[..2], &samples[2..]]).unwrap();
+        let le_bytes = |samples: &[f64], sample_format| {
+            let mut bytes = Vec::new();
+            for &sample in samples {
+                match sample_format {
+                    SampleFormat::I16 => bytes.extend_from_slice(&(sample as i16).to_le_bytes()),
+                    SampleFormat::I32 => bytes.extend_from_slice(&(sample as i32).to_le_bytes()),
+                    SampleFormat::F32 => bytes.extend_from_slice(&(sample as f32).to_le_bytes()),
+                    SampleFormat::F64 => bytes.extend_from_slice(&sample.to_le_bytes()),
+                }
+            }
+            bytes
+        };
+        // Interleaved, with integers clamped at full scale.
+        let expected = [
+            (SampleFormat::I16, le_bytes(&[16384.0, 32767.0, -8192.0, -32768.0], SampleFormat::I16)),
+            (
+                SampleFormat::I32,
+                le_bytes(&[1073741824.0, 2147483647.0, -536870912.0, -2147483648.0], SampleFormat::I32),
+            ),
+            (SampleFormat::F32, le_bytes(&[0.5, 1.0, -0.25, -1.0], SampleFormat::F32)),
+            (SampleFormat::F64, le_bytes(&[0.5, 1.0, -0.25, -1.0], SampleFormat::F64)),
+        ];
+        for &(sample_format, ref expected) in &expected {
+            let options = RawAudioOptions {
+                sample_format,
+                ..RawAudioOptions::default()
+            };
+            let mut writer = RawAudioWriter::new(Vec::new(), options);
+            writer.write_audio(&audio).unwrap();
+            writer.finish().unwrap();
+            assert_eq!(&writer.into_inner(), expected, "{}", ffmpeg_sample_format(sample_format));
+        }
+    }
+}
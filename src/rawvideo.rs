@@ -0,0 +1,428 @@
+use std::{io::Write, time::Duration};
+
+use crate::{
+    convert::{ColorMatrix, ColorRange},
+    frame::{PixelFormat, VideoFrame},
+    sink::{FrameRateConverter, RateControl, SinkError, VideoSink, DEFAULT_MAX_GAP},
+};
+#[cfg(target_os = "macos")]
+use crate::{
+    platform::{kCMTimeFlags_Valid, CMTime},
+    stream::SCStreamConfiguration,
+};
+
+/// Frame rate used when the configuration doesn't limit it.
+pub const DEFAULT_FRAME_RATE: (u32, u32) = (60, 1);
+
+/// The frame rate implied by a minimum frame interval, or `None` when the
+/// interval is unset or zero.
+#[cfg(target_os = "macos")]
+fn frame_rate_from_interval(interval: CMTime) -> Option<(u32, u32)> {
+    if interval.flags & kCMTimeFlags_Valid == 0 || interval.value <= 0 || interval.timescale <= 0 {
+        return None;
+    }
+    let (mut numerator, mut denominator) = (interval.timescale as u64, interval.value as u64);
+    let divisor = gcd(numerator, denominator);
+    numerator /= divisor;
+    denominator /= divisor;
+    while numerator > u32::MAX as u64 || denominator > u32::MAX as u64 {
+        numerator >>= 1;
+        denominator >>= 1;
+    }
+    Some((numerator.max(1) as u32, denominator.max(1) as u32))
+}
+
+#[cfg(target_os = "macos")]
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Y4mOptions {
+    /// Frames per second as numerator and denominator.
+    pub frame_rate: (u32, u32),
+    pub rate_control: RateControl,
+    /// Longest gap filled with duplicates, see
+    /// [`FrameRateConverter::set_max_gap`].
+    pub max_gap: Option<Duration>,
+    /// Matrix for converting RGB frames; Y4M can't signal it, so readers
+    /// should be told separately, e.g. with ffmpeg's `-colorspace`.
+    pub color_matrix: ColorMatrix,
+    pub color_range: ColorRange,
+}
+
+impl Default for Y4mOptions {
+    fn default() -> Self {
+        Self {
+            frame_rate: DEFAULT_FRAME_RATE,
+            rate_control: RateControl::default(),
+            max_gap: Some(DEFAULT_MAX_GAP),
+            color_matrix: ColorMatrix::default(),
+            color_range: ColorRange::Video,
+        }
+    }
+}
+
+impl Y4mOptions {
+    /// Options matching the frame interval, color matrix and pixel format a
+    /// stream was configured with.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Self {
+        Self {
+            frame_rate: frame_rate_from_interval(configuration.get_minimum_frame_interval()).unwrap_or(DEFAULT_FRAME_RATE),
+            color_matrix: ColorMatrix::from_configuration(configuration).unwrap_or_default(),
+            color_range: ColorRange::of(PixelFormat(configuration.get_pixel_format())),
+            ..Self::default()
+        }
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        match self.color_range {
+            ColorRange::Video => PixelFormat::I420VideoRange,
+            ColorRange::Full => PixelFormat::I420FullRange,
+        }
+    }
+}
+
+/// Writes frames as a YUV4MPEG2 stream of 8 bit 4:2:0 frames with left
+/// sited chroma, as read by `ffmpeg -f yuv4mpegpipe`. Frames are converted
+/// as needed; the header is written with the first frame and the size can't
+/// change afterwards. Pass `std::io::stdout().lock()` to pipe into another
+/// process.
+#[derive(Debug)]
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    options: Y4mOptions,
+    rate: FrameRateConverter,
+    size: Option<(usize, usize)>,
+    frames_written: u64,
+    finished: bool,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(writer: W, options: Y4mOptions) -> Self {
+        Self {
+            writer,
+            rate: rate_converter(options.frame_rate, options.rate_control, options.max_gap),
+            options,
+            size: None,
+            frames_written: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &Y4mOptions {
+        &self.options
+    }
+
+    /// Number of frames in the output, counting duplicates.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self, width: usize, height: usize) -> Result<(), SinkError> {
+        let (numerator, denominator) = self.rate.frame_rate();
+        let range = match self.options.color_range {
+            ColorRange::Video => "LIMITED",
+            ColorRange::Full => "FULL",
+        };
+        writeln!(
+            self.writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420mpeg2 XYSCSS=420MPEG2 XCOLORRANGE={}",
+            width, height, numerator, denominator, range
+        )?;
+        Ok(())
+    }
+}
+
+impl<W: Write> VideoSink for Y4mWriter<W> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let size = (frame.width(), frame.height());
+        match self.size {
+            Some(expected) if expected != size => return Err(SinkError::FormatChanged),
+            Some(_) => {}
+            None => {
+                self.write_header(size.0, size.1)?;
+                self.size = Some(size);
+            }
+        }
+        let count = self.rate.push(frame.presentation_time);
+        if count == 0 {
+            return Ok(());
+        }
+        let frame = frame.convert(self.options.pixel_format(), self.options.color_matrix)?;
+        for _ in 0..count {
+            self.writer.write_all(b"FRAME\n")?;
+            write_planes(&mut self.writer, &frame)?;
+        }
+        self.frames_written += count;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if !self.finished {
+            self.finished = true;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RawOptions {
+    /// Output pixel format; `None` keeps the format of each frame.
+    pub pixel_format: Option<PixelFormat>,
+    pub frame_rate: (u32, u32),
+    pub rate_control: RateControl,
+    /// Longest gap filled with duplicates, see
+    /// [`FrameRateConverter::set_max_gap`].
+    pub max_gap: Option<Duration>,
+    pub color_matrix: ColorMatrix,
+}
+
+impl Default for RawOptions {
+    fn default() -> Self {
+        Self {
+            pixel_format: None,
+            frame_rate: DEFAULT_FRAME_RATE,
+            rate_control: RateControl::default(),
+            max_gap: Some(DEFAULT_MAX_GAP),
+            color_matrix: ColorMatrix::default(),
+        }
+    }
+}
+
+impl RawOptions {
+    /// Options matching the frame interval and color matrix a stream was
+    /// configured with, keeping the captured pixel format.
+    #[cfg(target_os = "macos")]
+    pub fn from_configuration(configuration: &SCStreamConfiguration) -> Self {
+        Self {
+            frame_rate: frame_rate_from_interval(configuration.get_minimum_frame_interval()).unwrap_or(DEFAULT_FRAME_RATE),
+            color_matrix: ColorMatrix::from_configuration(configuration).unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The ffmpeg `-pix_fmt` name of the layout [`RawWriter`] writes a pixel
+/// format in.
+pub fn ffmpeg_pixel_format(pixel_format: PixelFormat) -> Option<&'static str> {
+    match pixel_format {
+        PixelFormat::BGRA => Some("bgra"),
+        PixelFormat::RGBA => Some("rgba"),
+        PixelFormat::RGB24 => Some("rgb24"),
+        PixelFormat::NV12VideoRange | PixelFormat::NV12FullRange => Some("nv12"),
+        PixelFormat::I420VideoRange | PixelFormat::I420FullRange => Some("yuv420p"),
+        PixelFormat::ARGB2101010 => Some("x2rgb10le"),
+        _ => None,
+    }
+}
+
+/// Writes frames as headerless raw video: the planes of every frame one
+/// after the other, rows without padding. The reader must be told the size,
+/// pixel format and rate, e.g. `ffmpeg -f rawvideo -pix_fmt bgra -s 1920x1080
+/// -r 60 -i -`.
+#[derive(Debug)]
+pub struct RawWriter<W: Write> {
+    writer: W,
+    options: RawOptions,
+    rate: FrameRateConverter,
+    format: Option<(usize, usize, PixelFormat)>,
+    frames_written: u64,
+    finished: bool,
+}
+
+impl<W: Write> RawWriter<W> {
+    pub fn new(writer: W, options: RawOptions) -> Self {
+        Self {
+            writer,
+            rate: rate_converter(options.frame_rate, options.rate_control, options.max_gap),
+            options,
+            format: None,
+            frames_written: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &RawOptions {
+        &self.options
+    }
+
+    /// Size and pixel format of the output, known after the first frame.
+    pub fn format(&self) -> Option<(usize, usize, PixelFormat)> {
+        self.format
+    }
+
+    /// Number of frames in the output, counting duplicates.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> VideoSink for RawWriter<W> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let pixel_format = self.options.pixel_format.unwrap_or_else(|| frame.pixel_format());
+        let format = (frame.width(), frame.height(), pixel_format);
+        if *self.format.get_or_insert(format) != format {
+            return Err(SinkError::FormatChanged);
+        }
+        let count = self.rate.push(frame.presentation_time);
+        if count == 0 {
+            return Ok(());
+        }
+        let frame = frame.convert(pixel_format, self.options.color_matrix)?;
+        for _ in 0..count {
+            write_planes(&mut self.writer, &frame)?;
+        }
+        self.frames_written += count;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if !self.finished {
+            self.finished = true;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn rate_converter(frame_rate: (u32, u32), control: RateControl, max_gap: Option<Duration>) -> FrameRateConverter {
+    let mut converter = FrameRateConverter::new(frame_rate, control);
+    converter.set_max_gap(max_gap);
+    converter
+}
+
+fn write_planes<W: Write>(writer: &mut W, frame: &VideoFrame) -> Result<(), SinkError> {
+    for plane in frame.planes() {
+        if plane.stride() == plane.layout().row_bytes() {
+            writer.write_all(&plane.data()[..plane.stride() * plane.height()])?;
+        } else {
+            for row in plane.rows() {
+                writer.write_all(row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, frame::Plane};
+
+    /// A 4x2 I420 frame at `millis`, with distinct samples and padded rows.
+    fn frame(millis: u64, pixel_format: PixelFormat) -> VideoFrame {
+        let samples: [&[u8]; 3] = [&[1, 2, 3, 4, 5, 6, 7, 8], &[20, 21], &[30, 31]];
+        let planes = pixel_format
+            .plane_layouts(4, 2)
+            .unwrap()
+            .into_iter()
+            .zip(samples.iter())
+            .map(|(layout, samples)| {
+                let stride = layout.row_bytes() + 2;
+                let mut data = vec![0xee; stride * layout.height];
+                for (row, samples) in samples.chunks_exact(layout.row_bytes()).enumerate() {
+                    data[row * stride..row * stride + samples.len()].copy_from_slice(samples);
+                }
+                Plane::new(data, layout, stride).unwrap()
+            })
+            .collect();
+        let mut frame = VideoFrame::new(4, 2, pixel_format, planes).unwrap();
+        frame.presentation_time = nanos_to_cmtime(millis * 1_000_000, 1000);
+        frame
+    }
+
+    const SAMPLES: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 20, 21, 30, 31];
+
+    #[test]
+    fn y4m_bytes() {
+        let options = Y4mOptions {
+            frame_rate: (30, 1),
+            ..Y4mOptions::default()
+        };
+        let mut writer = Y4mWriter::new(Vec::new(), options);
+        writer.write_frame(&frame(0, PixelFormat::I420VideoRange)).unwrap();
+        // Two slots later, written for both.
+        writer.write_frame(&frame(67, PixelFormat::I420VideoRange)).unwrap();
+        // Same slot, dropped.
+        writer.write_frame(&frame(70, PixelFormat::I420VideoRange)).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(writer.frames_written(), 3);
+        assert!(matches!(
+            writer.write_frame(&frame(100, PixelFormat::I420VideoRange)),
+            Err(SinkError::Finished)
+        ));
+
+        let header = b"YUV4MPEG2 W4 H2 F30:1 Ip A1:1 C420mpeg2 XYSCSS=420MPEG2 XCOLORRANGE=LIMITED\n";
+        let frame_bytes = [&b"FRAME\n"[..], &SAMPLES].concat();
+        assert_eq!(writer.into_inner(), [&header[..], &frame_bytes.repeat(3)].concat());
+
+        let mut writer = Y4mWriter::new(
+            Vec::new(),
+            Y4mOptions {
+                color_range: ColorRange::Full,
+                ..options
+            },
+        );
+        writer.write_frame(&frame(0, PixelFormat::I420FullRange)).unwrap();
+        assert!(matches!(
+            writer.write_frame(&VideoFrame::zeroed(2, 2, PixelFormat::I420FullRange).unwrap()),
+            Err(SinkError::FormatChanged)
+        ));
+        let output = writer.into_inner();
+        assert!(output.starts_with(b"YUV4MPEG2 W4 H2 F30:1 Ip A1:1 C420mpeg2 XYSCSS=420MPEG2 XCOLORRANGE=FULL\nFRAME\n"));
+        assert!(output.ends_with(&SAMPLES));
+    }
+
+    #[test]
+    fn raw_bytes() {
+        let options = RawOptions {
+            rate_control: RateControl::Passthrough,
+            ..RawOptions::default()
+        };
+        let mut writer = RawWriter::new(Vec::new(), options);
+        writer.write_frame(&frame(0, PixelFormat::I420VideoRange)).unwrap();
+        writer.write_frame(&frame(0, PixelFormat::I420VideoRange)).unwrap();
+        assert_eq!(writer.format(), Some((4, 2, PixelFormat::I420VideoRange)));
+        assert_eq!(ffmpeg_pixel_format(PixelFormat::I420VideoRange), Some("yuv420p"));
+        assert!(matches!(
+            writer.write_frame(&frame(0, PixelFormat::NV12VideoRange)),
+            Err(SinkError::FormatChanged)
+        ));
+        assert_eq!(writer.into_inner(), SAMPLES.repeat(2));
+
+        // NV12 planes are written as they are, chroma interleaved.
+        let mut writer = RawWriter::new(Vec::new(), options);
+        let mut nv12 = VideoFrame::zeroed(4, 2, PixelFormat::NV12VideoRange).unwrap();
+        nv12.plane_mut(1).unwrap().row_mut(0).copy_from_slice(&[20, 30, 21, 31]);
+        writer.write_frame(&nv12).unwrap();
+        assert_eq!(writer.into_inner(), [&[0; 8][..], &[20, 30, 21, 31]].concat());
+    }
+}
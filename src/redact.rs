@@ -0,0 +1,611 @@
+#[cfg(target_os = "macos")]
+use crate::shareable_content::SCWindow;
+use crate::{
+    convert::ColorMatrix,
+    frame::{FrameError, PixelFormat, Plane, PlaneMut, VideoFrame},
+    frame_info::FrameInfo,
+    platform::{CGPoint, CGRect, CGSize},
+    region::{PixelRect, Region},
+    scale::{channels, read_row, write_row},
+    sink::{SinkError, VideoSink},
+};
+
+/// How redacted areas are masked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RedactionStyle {
+    /// Fills with an opaque RGB color.
+    Fill([u8; 3]),
+    /// Replaces blocks of the given size in pixels by their average.
+    Pixelate(u32),
+    /// Box blurs with the given radius in pixels. Small radii can leave text
+    /// legible; prefer `Fill` or `Pixelate` for sensitive content.
+    Blur(u32),
+}
+
+impl Default for RedactionStyle {
+    fn default() -> Self {
+        Self::Fill([0, 0, 0])
+    }
+}
+
+/// Selects windows to redact. Every criterion that is set must match.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WindowRule {
+    pub bundle_identifier: Option<String>,
+    pub application_name: Option<String>,
+    /// Matches when the window title contains this string.
+    pub title_contains: Option<String>,
+}
+
+impl WindowRule {
+    pub fn bundle_identifier(bundle_identifier: &str) -> Self {
+        Self {
+            bundle_identifier: Some(bundle_identifier.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn matches(&self, window: &SCWindow) -> bool {
+        let application = window.owning_application();
+        let bundle_identifier = application.as_ref().map(|application| application.bundle_identifier().to_string());
+        let application_name = application.as_ref().map(|application| application.application_name().to_string());
+        let title = window.title().map(|title| title.to_string());
+        let matches =
+            |expected: &Option<String>, actual: &Option<String>| expected.as_ref().map_or(true, |expected| actual.as_ref() == Some(expected));
+        matches(&self.bundle_identifier, &bundle_identifier)
+            && matches(&self.application_name, &application_name)
+            && self
+                .title_contains
+                .as_ref()
+                .map_or(true, |expected| title.as_ref().is_some_and(|title| title.contains(expected.as_str())))
+    }
+}
+
+/// Maps a rectangle in global screen points, such as `SCWindow::frame`, to
+/// pixels of a frame using its content rect, content scale and scale factor.
+/// Returns `None` when the frame info lacks the screen rect or the rectangle
+/// lies outside the captured content.
+pub fn screen_rect_to_pixels(rect: CGRect, info: &FrameInfo) -> Option<PixelRect> {
+    let screen_rect = info.screen_rect?;
+    let content_scale = info.content_scale.unwrap_or(1.0);
+    let content_origin = info.content_rect.map_or(CGPoint::new(0.0, 0.0), |content_rect| content_rect.origin);
+    let rect = rect.standardize();
+    let in_frame = CGRect::new(
+        CGPoint::new(
+            content_origin.x + (rect.origin.x - screen_rect.origin.x) * content_scale,
+            content_origin.y + (rect.origin.y - screen_rect.origin.y) * content_scale,
+        ),
+        CGSize::new(rect.size.width * content_scale, rect.size.height * content_scale),
+    );
+    PixelRect::from_points(in_frame, info.scale_factor.unwrap_or(1.0))
+}
+
+/// Masks areas of frames before they are saved or sent anywhere. Areas are
+/// static pixel rectangles plus the frames of windows matching the rules,
+/// refreshed with [`Redactor::update_windows`]. Until the windows are
+/// updated after adding a rule, whole frames are masked.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    style: RedactionStyle,
+    color_matrix: ColorMatrix,
+    rects: Vec<PixelRect>,
+    rules: Vec<WindowRule>,
+    window_frames: Vec<CGRect>,
+    windows_updated: bool,
+}
+
+impl Redactor {
+    pub fn new(style: RedactionStyle) -> Self {
+        Self { style, ..Default::default() }
+    }
+
+    pub fn style(&self) -> RedactionStyle {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: RedactionStyle) {
+        self.style = style;
+    }
+
+    /// The matrix used to convert the fill color of YUV frames.
+    pub fn set_color_matrix(&mut self, color_matrix: ColorMatrix) {
+        self.color_matrix = color_matrix;
+    }
+
+    pub fn add_rect(&mut self, rect: PixelRect) {
+        self.rects.push(rect);
+    }
+
+    pub fn add_window_rule(&mut self, rule: WindowRule) {
+        self.rules.push(rule);
+        self.windows_updated = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.rules.clear();
+        self.window_frames.clear();
+        self.windows_updated = false;
+    }
+
+    /// Records the frames of the windows matching a rule, e.g. from
+    /// `SCShareableContent::windows`. Call again when windows move.
+    #[cfg(target_os = "macos")]
+    pub fn update_windows<'a, I>(&mut self, windows: I)
+    where
+        I: IntoIterator<Item = &'a SCWindow>,
+    {
+        let rules = &self.rules;
+        self.window_frames = windows
+            .into_iter()
+            .filter(|window| window.on_screen() && rules.iter().any(|rule| rule.matches(window)))
+            .map(SCWindow::frame)
+            .collect();
+        self.windows_updated = true;
+    }
+
+    /// The area to mask in a frame of `width` x `height` pixels. When the
+    /// windows matching the rules aren't known yet, or can't be mapped to the
+    /// frame because its info lacks the screen rect, the whole frame is
+    /// masked rather than risk a leak.
+    pub fn region(&self, info: &FrameInfo, width: usize, height: usize) -> Region {
+        let bounds = PixelRect::new(0, 0, width as u32, height as u32);
+        let windows_unknown = !self.rules.is_empty() && !self.windows_updated;
+        if windows_unknown || (!self.window_frames.is_empty() && info.screen_rect.is_none()) {
+            return Region::from_rect(bounds);
+        }
+        let windows = self.window_frames.iter().filter_map(|frame| screen_rect_to_pixels(*frame, info));
+        Region::from_rects(self.rects.iter().copied().chain(windows)).clip(bounds)
+    }
+
+    pub fn apply(&self, frame: &mut VideoFrame) -> Result<(), FrameError> {
+        let region = self.region(&frame.info, frame.width(), frame.height());
+        redact(frame, &region, self.style, self.color_matrix)
+    }
+}
+
+/// Redacts frames before passing them to another sink.
+#[derive(Debug)]
+pub struct RedactingSink<S> {
+    sink: S,
+    redactor: Redactor,
+}
+
+impl<S: VideoSink> RedactingSink<S> {
+    pub fn new(sink: S, redactor: Redactor) -> Self {
+        Self { sink, redactor }
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// The redactor, e.g. to update the windows when they move.
+    pub fn redactor_mut(&mut self) -> &mut Redactor {
+        &mut self.redactor
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: VideoSink> VideoSink for RedactingSink<S> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        let mut frame = frame.clone();
+        self.redactor.apply(&mut frame)?;
+        self.sink.write_frame(&frame)
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.sink.finish()
+    }
+}
+
+/// Masks `region` of a frame in place.
+pub fn redact(frame: &mut VideoFrame, region: &Region, style: RedactionStyle, matrix: ColorMatrix) -> Result<(), FrameError> {
+    let pixel_format = frame.pixel_format();
+    if !pixel_format.is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(pixel_format));
+    }
+    let (width, height) = (frame.width(), frame.height());
+    let region = region.clip(PixelRect::new(0, 0, width as u32, height as u32));
+    if region.is_empty() {
+        return Ok(());
+    }
+    let fill = match style {
+        RedactionStyle::Fill([red, green, blue]) => Some(fill_samples(pixel_format, [red, green, blue, 255], matrix)?),
+        _ => None,
+    };
+    for (index, mut plane) in frame.planes_mut().enumerate() {
+        let (channels, packed) = channels(pixel_format, &plane);
+        // Pixels per sample, 2 for subsampled chroma.
+        let (step_x, step_y) = (
+            (width + plane.width() - 1) / plane.width(),
+            (height + plane.height() - 1) / plane.height(),
+        );
+        let scaled = |length: u32, step: usize| ((length as usize + step / 2) / step).max(1);
+        for rect in region.rects() {
+            // Grow chroma rects outward so no sample of the area survives.
+            let left = rect.x as usize / step_x;
+            let top = rect.y as usize / step_y;
+            let right = ((rect.right() as usize + step_x - 1) / step_x).min(plane.width());
+            let bottom = ((rect.bottom() as usize + step_y - 1) / step_y).min(plane.height());
+            let area = SampleArea {
+                left,
+                top,
+                width: right - left,
+                height: bottom - top,
+                channels,
+                packed,
+            };
+            let mut samples = area.read(&plane);
+            match style {
+                RedactionStyle::Fill(_) => {
+                    let fill = &fill.as_ref().unwrap()[index];
+                    for pixel in samples.chunks_exact_mut(channels) {
+                        pixel.copy_from_slice(fill);
+                    }
+                }
+                RedactionStyle::Pixelate(block) => pixelate(&mut samples, &area, scaled(block, step_x), scaled(block, step_y)),
+                RedactionStyle::Blur(radius) => {
+                    for _ in 0..3 {
+                        box_blur(&mut samples, &area, scaled(radius, step_x), scaled(radius, step_y));
+                    }
+                }
+            }
+            area.write(&samples, &mut plane);
+        }
+    }
+    Ok(())
+}
+
+impl VideoFrame {
+    pub fn redact(&mut self, region: &Region, style: RedactionStyle, matrix: ColorMatrix) -> Result<(), FrameError> {
+        redact(self, region, style, matrix)
+    }
+}
+
+/// The samples of one pixel of every plane for a color, found by converting
+/// a 2x2 RGBA frame so subsampled chroma is covered.
+fn fill_samples(pixel_format: PixelFormat, color: [u8; 4], matrix: ColorMatrix) -> Result<Vec<Vec<u16>>, FrameError> {
+    let source = VideoFrame::from_bytes(2, 2, PixelFormat::RGBA, &color.repeat(4))?;
+    let converted = source.convert(pixel_format, matrix)?;
+    Ok(converted
+        .planes()
+        .iter()
+        .map(|plane| {
+            let (channels, packed) = channels(pixel_format, plane);
+            let mut samples = vec![0; channels];
+            read_row(&plane.row(0)[..plane.bytes_per_pixel()], packed, &mut samples);
+            samples
+        })
+        .collect())
+}
+
+/// A rectangle of a plane, unpacked to one `u16` per channel.
+struct SampleArea {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+    channels: usize,
+    packed: bool,
+}
+
+impl SampleArea {
+    fn bytes(&self, plane: &Plane) -> (usize, usize) {
+        (self.left * plane.bytes_per_pixel(), (self.left + self.width) * plane.bytes_per_pixel())
+    }
+
+    fn read(&self, plane: &Plane) -> Vec<u16> {
+        let (start, end) = self.bytes(plane);
+        let row_samples = self.width * self.channels;
+        let mut samples = vec![0; row_samples * self.height];
+        for (y, samples) in samples.chunks_exact_mut(row_samples.max(1)).enumerate() {
+            read_row(&plane.row(self.top + y)[start..end], self.packed, samples);
+        }
+        samples
+    }
+
+    fn write(&self, samples: &[u16], plane: &mut PlaneMut) {
+        let (start, end) = self.bytes(plane);
+        let row_samples = self.width * self.channels;
+        for (y, samples) in samples.chunks_exact(row_samples.max(1)).enumerate() {
+            write_row(samples, self.packed, &mut plane.row_mut(self.top + y)[start..end]);
+        }
+    }
+}
+
+fn pixelate(samples: &mut [u16], area: &SampleArea, block_width: usize, block_height: usize) {
+    let channels = area.channels;
+    let stride = area.width * channels;
+    for block_top in (0..area.height).step_by(block_height) {
+        let block_bottom = (block_top + block_height).min(area.height);
+        for block_left in (0..area.width).step_by(block_width) {
+            let block_right = (block_left + block_width).min(area.width);
+            let count = ((block_bottom - block_top) * (block_right - block_left)) as u32;
+            for channel in 0..channels {
+                let index = |x: usize, y: usize| y * stride + x * channels + channel;
+                let sum: u32 = (block_top..block_bottom)
+                    .flat_map(|y| (block_left..block_right).map(move |x| (x, y)))
+                    .map(|(x, y)| samples[index(x, y)] as u32)
+                    .sum();
+                let average = ((sum + count / 2) / count) as u16;
+                for y in block_top..block_bottom {
+                    for x in block_left..block_right {
+                        samples[index(x, y)] = average;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One horizontal and one vertical box blur pass. Samples outside the area
+/// are never read, so nothing from outside bleeds in and the edges repeat.
+fn box_blur(samples: &mut [u16], area: &SampleArea, radius_x: usize, radius_y: usize) {
+    let channels = area.channels;
+    let stride = area.width * channels;
+    let mut line = Vec::new();
+    for y in 0..area.height {
+        for channel in 0..channels {
+            line.clear();
+            line.extend((0..area.width).map(|x| samples[y * stride + x * channels + channel]));
+            for (x, value) in blur_line(&line, radius_x).into_iter().enumerate() {
+                samples[y * stride + x * channels + channel] = value;
+            }
+        }
+    }
+    for x in 0..area.width {
+        for channel in 0..channels {
+            line.clear();
+            line.extend((0..area.height).map(|y| samples[y * stride + x * channels + channel]));
+            for (y, value) in blur_line(&line, radius_y).into_iter().enumerate() {
+                samples[y * stride + x * channels + channel] = value;
+            }
+        }
+    }
+}
+
+/// Running sum box filter with clamped edges.
+fn blur_line(line: &[u16], radius: usize) -> Vec<u16> {
+    let length = line.len() as isize;
+    let radius = radius as isize;
+    let at = |index: isize| line[index.clamp(0, length - 1) as usize] as u32;
+    let window = (2 * radius + 1) as u32;
+    let mut sum: u32 = (-radius..=radius).map(at).sum();
+    let mut blurred = Vec::with_capacity(line.len());
+    for index in 0..length {
+        blurred.push(((sum + window / 2) / window) as u16);
+        sum = sum + at(index + radius + 1) - at(index - radius);
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [PixelFormat; 3] = [PixelFormat::BGRA, PixelFormat::NV12VideoRange, PixelFormat::I420FullRange];
+    const STYLES: [RedactionStyle; 3] = [RedactionStyle::Fill([200, 30, 90]), RedactionStyle::Pixelate(4), RedactionStyle::Blur(2)];
+
+    /// A textured frame with every row padded by a few bytes of 0xff.
+    fn textured(pixel_format: PixelFormat, width: usize, height: usize) -> VideoFrame {
+        let planes = pixel_format
+            .plane_layouts(width, height)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(index, layout)| {
+                let stride = layout.row_bytes() + 3;
+                let mut data = vec![0xff; stride * layout.height];
+                for y in 0..layout.height {
+                    for x in 0..layout.row_bytes() {
+                        data[y * stride + x] = ((x * 37 + y * 91 + index * 53) % 251) as u8;
+                    }
+                }
+                Plane::new(data, layout, stride).unwrap()
+            })
+            .collect();
+        VideoFrame::new(width, height, pixel_format, planes).unwrap()
+    }
+
+    /// Checks that exactly the samples of `area` changed in every plane,
+    /// `area` being given per plane as left, top, right and bottom.
+    fn assert_touches(before: &VideoFrame, after: &VideoFrame, areas: &[(usize, usize, usize, usize)], what: &str) {
+        for (index, (before, after)) in before.planes().iter().zip(after.planes()).enumerate() {
+            assert_eq!(before.stride(), after.stride());
+            let (left, top, right, bottom) = areas[index];
+            let bytes_per_pixel = before.bytes_per_pixel();
+            for y in 0..before.height() {
+                for x in 0..before.width() {
+                    let range = x * bytes_per_pixel..(x + 1) * bytes_per_pixel;
+                    let inside = (left..right).contains(&x) && (top..bottom).contains(&y);
+                    let changed = before.row(y)[range.clone()] != after.row(y)[range];
+                    if !inside {
+                        assert!(!changed, "{}: plane {} changed at {},{}", what, index, x, y);
+                    }
+                }
+            }
+            // Row padding is left alone.
+            assert_eq!(before.data().len(), after.data().len());
+            for y in 0..before.height() {
+                let padding = y * before.stride() + before.layout().row_bytes()..(y + 1) * before.stride();
+                assert_eq!(before.data()[padding.clone()], after.data()[padding]);
+            }
+        }
+    }
+
+    #[test]
+    fn styles_touch_exactly_the_region() {
+        for &pixel_format in &FORMATS {
+            let source = textured(pixel_format, 23, 17);
+            for &style in &STYLES {
+                // Odd coordinates grow chroma areas outward.
+                let rect = PixelRect::new(5, 3, 10, 9);
+                let mut frame = source.clone();
+                redact(&mut frame, &Region::from_rect(rect), style, ColorMatrix::default()).unwrap();
+                let chroma = (2, 1, 8, 6);
+                let areas = [(5, 3, 15, 12), chroma, chroma];
+                let what = format!("{} {:?}", pixel_format, style);
+                assert_touches(&source, &frame, &areas, &what);
+
+                // Every sample of the area changes with the texture beneath.
+                for (index, (before, after)) in source.planes().iter().zip(frame.planes()).enumerate() {
+                    let (left, top, right, bottom) = areas[index];
+                    let bytes_per_pixel = before.bytes_per_pixel();
+                    let range = left * bytes_per_pixel..right * bytes_per_pixel;
+                    let changed = (top..bottom)
+                        .filter(|y| before.row(*y)[range.clone()] != after.row(*y)[range.clone()])
+                        .count();
+                    assert_eq!(changed, bottom - top, "{}: plane {}", what, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fill_uses_the_color_in_every_plane() {
+        let rect = PixelRect::new(1, 1, 2, 2);
+        let mut bgra = textured(PixelFormat::BGRA, 4, 4);
+        redact(
+            &mut bgra,
+            &Region::from_rect(rect),
+            RedactionStyle::Fill([200, 30, 90]),
+            ColorMatrix::default(),
+        )
+        .unwrap();
+        assert_eq!(bgra.planes()[0].row(1)[4..12], [90, 30, 200, 255, 90, 30, 200, 255]);
+
+        // Video range black.
+        let mut nv12 = textured(PixelFormat::NV12VideoRange, 4, 4);
+        redact(
+            &mut nv12,
+            &Region::from_rect(rect),
+            RedactionStyle::Fill([0, 0, 0]),
+            ColorMatrix::default(),
+        )
+        .unwrap();
+        assert_eq!(nv12.planes()[0].row(1)[1..3], [16, 16]);
+        assert_eq!(nv12.planes()[1].row(0)[..4], [128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn pixelate_averages_blocks() {
+        let mut frame = textured(PixelFormat::BGRA, 8, 4);
+        redact(
+            &mut frame,
+            &Region::from_rect(PixelRect::new(0, 0, 8, 4)),
+            RedactionStyle::Pixelate(4),
+            ColorMatrix::default(),
+        )
+        .unwrap();
+        let plane = &frame.planes()[0];
+        for block in 0..2 {
+            let pixel = &plane.row(0)[block * 16..block * 16 + 4];
+            for y in 0..4 {
+                for x in 0..4 {
+                    let offset = (block * 4 + x) * 4;
+                    assert_eq!(&plane.row(y)[offset..offset + 4], pixel);
+                }
+            }
+        }
+        assert_ne!(plane.row(0)[..4], plane.row(0)[16..20]);
+    }
+
+    #[test]
+    fn screen_rects_map_through_content_rect_and_scale() {
+        let mut info = FrameInfo {
+            screen_rect: Some(CGRect::new(CGPoint::new(100.0, 50.0), CGSize::new(800.0, 600.0))),
+            content_rect: Some(CGRect::new(CGPoint::new(10.0, 20.0), CGSize::new(400.0, 300.0))),
+            content_scale: Some(0.5),
+            scale_factor: Some(2.0),
+            ..Default::default()
+        };
+        let window = CGRect::new(CGPoint::new(200.0, 150.0), CGSize::new(100.0, 40.0));
+        // 100 points in, halved, offset by the content rect, then doubled.
+        assert_eq!(screen_rect_to_pixels(window, &info), Some(PixelRect::new(120, 140, 100, 40)));
+
+        // A window partly left of the content is clipped to it.
+        let left = CGRect::new(CGPoint::new(60.0, 50.0), CGSize::new(100.0, 10.0));
+        assert_eq!(screen_rect_to_pixels(left, &info), Some(PixelRect::new(0, 40, 80, 10)));
+
+        info.content_rect = None;
+        info.content_scale = None;
+        info.scale_factor = None;
+        assert_eq!(screen_rect_to_pixels(window, &info), Some(PixelRect::new(100, 100, 100, 40)));
+        info.screen_rect = None;
+        assert_eq!(screen_rect_to_pixels(window, &info), None);
+    }
+
+    #[test]
+    fn unknown_windows_mask_the_whole_frame() {
+        let info = FrameInfo {
+            screen_rect: Some(CGRect::new(CGPoint::new(0.0, 0.0), CGSize::new(64.0, 48.0))),
+            ..Default::default()
+        };
+        let full = Region::from_rect(PixelRect::new(0, 0, 64, 48));
+        let mut redactor = Redactor::default();
+        redactor.add_rect(PixelRect::new(60, 40, 10, 10));
+        assert_eq!(redactor.region(&info, 64, 48), Region::from_rect(PixelRect::new(60, 40, 4, 8)));
+
+        // Rules without known windows fail closed.
+        redactor.add_window_rule(WindowRule::bundle_identifier("com.example.secrets"));
+        assert_eq!(redactor.region(&info, 64, 48), full);
+
+        // As `update_windows` does once they are known.
+        redactor.window_frames = vec![CGRect::new(CGPoint::new(8.0, 4.0), CGSize::new(16.0, 8.0))];
+        redactor.windows_updated = true;
+        assert_eq!(
+            redactor.region(&info, 64, 48),
+            Region::from_rects([PixelRect::new(60, 40, 4, 8), PixelRect::new(8, 4, 16, 8)])
+        );
+        // Without a screen rect the windows can't be placed.
+        assert_eq!(redactor.region(&FrameInfo::default(), 64, 48), full);
+
+        // A new rule needs the windows updated again.
+        redactor.add_window_rule(WindowRule::bundle_identifier("com.example.more"));
+        assert_eq!(redactor.region(&info, 64, 48), full);
+        redactor.clear();
+        assert!(redactor.region(&info, 64, 48).is_empty());
+    }
+
+    #[derive(Default)]
+    struct Frames(Vec<VideoFrame>, bool);
+
+    impl VideoSink for Frames {
+        fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+            self.0.push(frame.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), SinkError> {
+            self.1 = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_redacts_copies() {
+        let mut redactor = Redactor::new(RedactionStyle::Fill([0, 0, 0]));
+        redactor.add_rect(PixelRect::new(0, 0, 1, 1));
+        let mut sink = RedactingSink::new(Frames::default(), redactor);
+        let frame = VideoFrame::from_bytes(2, 1, PixelFormat::BGRA, &[255; 8]).unwrap();
+        sink.write_frame(&frame).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(frame.planes()[0].data(), [255; 8]);
+        let Frames(frames, finished) = sink.into_inner();
+        assert!(finished);
+        assert_eq!(frames[0].planes()[0].data(), [0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+}
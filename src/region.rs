@@ -0,0 +1,534 @@
+use std::collections::BTreeSet;
+
+use crate::{frame_info::FrameInfo, platform::CGRect};
+
+/// An axis aligned rectangle in pixel coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    fn from_edges(left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        Self::new(left, top, right.saturating_sub(left), bottom.saturating_sub(top))
+    }
+
+    /// Converts a rectangle in points to the smallest pixel rectangle covering
+    /// it. Parts lying at negative coordinates are dropped.
+    pub fn from_points(rect: CGRect, scale_factor: f64) -> Option<Self> {
+        let rect = rect.standardize();
+        let edge = |value: f64| (value * scale_factor).clamp(0.0, u32::MAX as f64);
+        let left = edge(rect.origin.x).floor();
+        let top = edge(rect.origin.y).floor();
+        let right = edge(rect.origin.x + rect.size.width).ceil();
+        let bottom = edge(rect.origin.y + rect.size.height).ceil();
+        if !(left.is_finite() && top.is_finite() && right.is_finite() && bottom.is_finite()) {
+            return None;
+        }
+        let rect = Self::from_edges(left as u32, top as u32, right as u32, bottom as u32);
+        if rect.is_empty() {
+            None
+        } else {
+            Some(rect)
+        }
+    }
+
+    pub fn right(&self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    pub fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn contains(&self, other: &PixelRect) -> bool {
+        other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    pub fn intersects(&self, other: &PixelRect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn intersection(&self, other: &PixelRect) -> Option<PixelRect> {
+        let rect = Self::from_edges(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+        if rect.is_empty() {
+            None
+        } else {
+            Some(rect)
+        }
+    }
+
+    /// The smallest rectangle containing both rectangles.
+    pub fn bounding_union(&self, other: &PixelRect) -> PixelRect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Self::from_edges(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        )
+    }
+
+    /// The parts of `self` not covered by `other`, as at most four disjoint
+    /// rectangles.
+    pub fn subtract(&self, other: &PixelRect) -> Vec<PixelRect> {
+        let overlap = match self.intersection(other) {
+            Some(overlap) => overlap,
+            None => {
+                return if self.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![*self]
+                }
+            }
+        };
+        let pieces = [
+            Self::from_edges(self.x, self.y, self.right(), overlap.y),
+            Self::from_edges(self.x, overlap.bottom(), self.right(), self.bottom()),
+            Self::from_edges(self.x, overlap.y, overlap.x, overlap.bottom()),
+            Self::from_edges(overlap.right(), overlap.y, self.right(), overlap.bottom()),
+        ];
+        pieces.iter().filter(|piece| !piece.is_empty()).copied().collect()
+    }
+
+    /// Grows the rectangle outwards to the nearest tile boundaries.
+    pub fn snap_to_grid(&self, tile_width: u32, tile_height: u32) -> PixelRect {
+        let tile_width = tile_width.max(1);
+        let tile_height = tile_height.max(1);
+        let round_up = |value: u32, step: u32| ((value as u64 + step as u64 - 1) / step as u64 * step as u64).min(u32::MAX as u64) as u32;
+        Self::from_edges(
+            self.x - self.x % tile_width,
+            self.y - self.y % tile_height,
+            round_up(self.right(), tile_width),
+            round_up(self.bottom(), tile_height),
+        )
+    }
+
+    pub fn scale(&self, scale_x: f64, scale_y: f64) -> PixelRect {
+        let edge = |value: u32, scale: f64| (value as f64 * scale).clamp(0.0, u32::MAX as f64);
+        Self::from_edges(
+            edge(self.x, scale_x).floor() as u32,
+            edge(self.y, scale_y).floor() as u32,
+            edge(self.right(), scale_x).ceil() as u32,
+            edge(self.bottom(), scale_y).ceil() as u32,
+        )
+    }
+}
+
+/// A set of pixels stored as disjoint, non-empty rectangles.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    rects: Vec<PixelRect>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_rect(rect: PixelRect) -> Self {
+        let mut region = Self::new();
+        region.add_rect(rect);
+        region
+    }
+
+    pub fn from_rects<I>(rects: I) -> Self
+    where
+        I: IntoIterator<Item = PixelRect>,
+    {
+        let mut region = Self::new();
+        for rect in rects {
+            region.add_rect(rect);
+        }
+        region
+    }
+
+    /// Builds a region from rectangles in points, such as
+    /// `SCStreamFrameInfoDirtyRects`, scaled to pixels.
+    pub fn from_points(rects: &[CGRect], scale_factor: f64) -> Self {
+        Self::from_rects(rects.iter().filter_map(|rect| PixelRect::from_points(*rect, scale_factor)))
+    }
+
+    /// The dirty region of a frame in pixels, or `None` when the frame info
+    /// doesn't report dirty rectangles.
+    pub fn from_frame_info(info: &FrameInfo) -> Option<Self> {
+        let scale_factor = info.scale_factor.unwrap_or(1.0);
+        info.dirty_rects.as_ref().map(|rects| Self::from_points(rects, scale_factor))
+    }
+
+    pub fn rects(&self) -> &[PixelRect] {
+        &self.rects
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn area(&self) -> u64 {
+        self.rects.iter().map(PixelRect::area).sum()
+    }
+
+    pub fn bounds(&self) -> Option<PixelRect> {
+        self.rects.iter().copied().reduce(|bounds, rect| bounds.bounding_union(&rect))
+    }
+
+    pub fn add_rect(&mut self, rect: PixelRect) {
+        if rect.is_empty() {
+            return;
+        }
+        let mut pieces = vec![rect];
+        for existing in &self.rects {
+            pieces = pieces.iter().flat_map(|piece| piece.subtract(existing)).collect();
+            if pieces.is_empty() {
+                return;
+            }
+        }
+        self.rects.extend(pieces);
+    }
+
+    pub fn union(&self, other: &Region) -> Region {
+        let mut region = self.clone();
+        for rect in &other.rects {
+            region.add_rect(*rect);
+        }
+        region
+    }
+
+    pub fn intersection(&self, other: &Region) -> Region {
+        Region {
+            rects: self
+                .rects
+                .iter()
+                .flat_map(|a| other.rects.iter().filter_map(move |b| a.intersection(b)))
+                .collect(),
+        }
+    }
+
+    pub fn subtract(&self, other: &Region) -> Region {
+        let mut rects = self.rects.clone();
+        for hole in &other.rects {
+            rects = rects.iter().flat_map(|rect| rect.subtract(hole)).collect();
+        }
+        Region { rects }
+    }
+
+    pub fn clip(&self, bounds: PixelRect) -> Region {
+        Region {
+            rects: self.rects.iter().filter_map(|rect| rect.intersection(&bounds)).collect(),
+        }
+    }
+
+    /// Merges rectangles until at most `max_rects` remain. Rectangles are
+    /// only merged with their neighbours in row and column order, cheapest
+    /// first by the extra pixels their bounding box covers, so adjacent
+    /// rectangles forming a larger rectangle merge losslessly. The result
+    /// always covers the original region.
+    pub fn coalesce(&self, max_rects: usize) -> Region {
+        let max_rects = max_rects.max(1);
+        let mut rects = self.rects.clone();
+        merge_overlapping(&mut rects);
+        while rects.len() > max_rects {
+            let mut by_row: Vec<usize> = (0..rects.len()).collect();
+            by_row.sort_unstable_by_key(|&k| (rects[k].y, rects[k].x));
+            let mut by_column = by_row.clone();
+            by_column.sort_unstable_by_key(|&k| (rects[k].x, rects[k].y));
+            let mut pairs: Vec<(u64, usize, usize)> = by_row
+                .windows(2)
+                .chain(by_column.windows(2))
+                .map(|pair| {
+                    let (a, b) = (rects[pair[0]], rects[pair[1]]);
+                    (a.bounding_union(&b).area().saturating_sub(a.area() + b.area()), pair[0], pair[1])
+                })
+                .collect();
+            pairs.sort_unstable();
+            let mut excess = rects.len() - max_rects;
+            let mut taken = vec![false; rects.len()];
+            for (_, i, j) in pairs {
+                if excess == 0 {
+                    break;
+                }
+                if taken[i] || taken[j] {
+                    continue;
+                }
+                taken[i] = true;
+                taken[j] = true;
+                rects[i] = rects[i].bounding_union(&rects[j]);
+                rects[j] = PixelRect::default();
+                excess -= 1;
+            }
+            rects.retain(|rect| !rect.is_empty());
+            merge_overlapping(&mut rects);
+        }
+        Region { rects }
+    }
+
+    /// Expands every rectangle to tile boundaries.
+    pub fn snap_to_grid(&self, tile_width: u32, tile_height: u32) -> Region {
+        Self::from_rects(self.rects.iter().map(|rect| rect.snap_to_grid(tile_width, tile_height)))
+    }
+
+    /// The `(column, row)` indices of every tile touched by the region, in
+    /// row-major order.
+    pub fn tiles(&self, tile_width: u32, tile_height: u32) -> Vec<(u32, u32)> {
+        let tile_width = tile_width.max(1);
+        let tile_height = tile_height.max(1);
+        let mut tiles = BTreeSet::new();
+        for rect in &self.rects {
+            for row in rect.y / tile_height..=(rect.bottom() - 1) / tile_height {
+                for column in rect.x / tile_width..=(rect.right() - 1) / tile_width {
+                    tiles.insert((row, column));
+                }
+            }
+        }
+        tiles.into_iter().map(|(row, column)| (column, row)).collect()
+    }
+
+    /// The share of a `width` x `height` frame covered by the region.
+    pub fn area_fraction(&self, width: u32, height: u32) -> f64 {
+        let frame = PixelRect::new(0, 0, width, height);
+        if frame.is_empty() {
+            return 0.0;
+        }
+        self.clip(frame).area() as f64 / frame.area() as f64
+    }
+
+    /// Scales the region, e.g. from points to pixels. Rectangles are rounded
+    /// outwards so the result still covers every scaled pixel.
+    pub fn scale(&self, scale_x: f64, scale_y: f64) -> Region {
+        Self::from_rects(self.rects.iter().map(|rect| rect.scale(scale_x, scale_y)))
+    }
+}
+
+/// Replaces overlapping rectangles by their bounding box until none overlap,
+/// sweeping over the rectangles from left to right.
+fn merge_overlapping(rects: &mut Vec<PixelRect>) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        rects.sort_unstable_by_key(|rect| rect.x);
+        let mut done = Vec::with_capacity(rects.len());
+        let mut active: Vec<PixelRect> = Vec::new();
+        for mut rect in rects.drain(..) {
+            // Later rectangles start at or right of the sweep line, so ones
+            // ending before it can't overlap them.
+            let sweep = rect.x;
+            let mut k = 0;
+            while k < active.len() {
+                if active[k].right() <= sweep {
+                    done.push(active.swap_remove(k));
+                } else if active[k].intersects(&rect) {
+                    rect = rect.bounding_union(&active.swap_remove(k));
+                    changed = true;
+                    k = 0;
+                } else {
+                    k += 1;
+                }
+            }
+            active.push(rect);
+        }
+        done.append(&mut active);
+        *rects = done;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{CGPoint, CGSize};
+
+    fn points(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(CGPoint::new(x, y), CGSize::new(width, height))
+    }
+
+    /// Marks the pixels covered by the region, checking its rectangles are
+    /// disjoint.
+    fn covered(region: &Region, width: u32, height: u32) -> Vec<bool> {
+        let mut pixels = vec![false; (width * height) as usize];
+        for rect in region.rects() {
+            assert!(!rect.is_empty());
+            for y in rect.y..rect.bottom() {
+                for x in rect.x..rect.right() {
+                    let pixel = &mut pixels[(y * width + x) as usize];
+                    assert!(!*pixel, "rectangles overlap at {}, {}", x, y);
+                    *pixel = true;
+                }
+            }
+        }
+        pixels
+    }
+
+    fn sample() -> (Region, Region) {
+        let a = Region::from_rects(vec![PixelRect::new(0, 0, 20, 10), PixelRect::new(30, 20, 10, 10)]);
+        let b = Region::from_rects(vec![PixelRect::new(10, 5, 25, 20), PixelRect::new(0, 30, 5, 5)]);
+        (a, b)
+    }
+
+    #[test]
+    fn union() {
+        let (a, b) = sample();
+        let (pa, pb) = (covered(&a, 40, 40), covered(&b, 40, 40));
+        let union = a.union(&b);
+        let expected: Vec<bool> = pa.iter().zip(&pb).map(|(a, b)| *a || *b).collect();
+        assert_eq!(covered(&union, 40, 40), expected);
+        assert_eq!(union.area(), expected.iter().filter(|pixel| **pixel).count() as u64);
+        assert_eq!(a.union(&Region::new()), a);
+    }
+
+    #[test]
+    fn intersection() {
+        let (a, b) = sample();
+        let (pa, pb) = (covered(&a, 40, 40), covered(&b, 40, 40));
+        let expected: Vec<bool> = pa.iter().zip(&pb).map(|(a, b)| *a && *b).collect();
+        assert_eq!(covered(&a.intersection(&b), 40, 40), expected);
+        assert_eq!(a.intersection(&b).area(), 10 * 5 + 5 * 5);
+        assert!(a.intersection(&Region::new()).is_empty());
+    }
+
+    #[test]
+    fn subtract() {
+        let (a, b) = sample();
+        let (pa, pb) = (covered(&a, 40, 40), covered(&b, 40, 40));
+        let expected: Vec<bool> = pa.iter().zip(&pb).map(|(a, b)| *a && !*b).collect();
+        assert_eq!(covered(&a.subtract(&b), 40, 40), expected);
+        assert!(a.subtract(&a).is_empty());
+        assert_eq!(a.subtract(&Region::new()), a);
+
+        let rect = PixelRect::new(0, 0, 10, 10);
+        assert_eq!(rect.subtract(&PixelRect::new(3, 3, 4, 4)).len(), 4);
+        assert_eq!(rect.subtract(&PixelRect::new(20, 20, 4, 4)), vec![rect]);
+        assert!(rect.subtract(&PixelRect::new(0, 0, 20, 20)).is_empty());
+    }
+
+    #[test]
+    fn coalesce_merges_adjacent_tiles_losslessly() {
+        let tiles = Region::from_rects((0..8).flat_map(|row| (0..8).map(move |column| PixelRect::new(column * 16, row * 16, 16, 16))));
+        assert_eq!(tiles.len(), 64);
+        let merged = tiles.coalesce(1);
+        assert_eq!(merged.rects(), &[PixelRect::new(0, 0, 128, 128)]);
+    }
+
+    #[test]
+    fn coalesce_covers_region() {
+        let region = Region::from_rects((0..50).map(|k| PixelRect::new(k * 37 % 200, k * 53 % 150, 5 + k % 7, 3 + k % 5)));
+        let original = covered(&region, 220, 160);
+        for &max_rects in &[1, 3, 10, 1000] {
+            let merged = region.coalesce(max_rects);
+            assert!(merged.len() <= max_rects.max(1));
+            let pixels = covered(&merged, 220, 160);
+            assert!(original.iter().zip(&pixels).all(|(original, merged)| !*original || *merged));
+        }
+        assert_eq!(region.coalesce(1000).area(), region.area());
+    }
+
+    #[test]
+    fn snap_to_grid() {
+        let rect = PixelRect::new(5, 17, 10, 1).snap_to_grid(16, 16);
+        assert_eq!(rect, PixelRect::new(0, 16, 16, 16));
+        assert_eq!(PixelRect::new(u32::MAX - 3, 0, 3, 1).snap_to_grid(16, 16).right(), u32::MAX);
+    }
+
+    #[test]
+    fn from_points() {
+        // Fractional edges round outwards.
+        assert_eq!(
+            PixelRect::from_points(points(10.25, 5.5, 20.0, 10.0), 2.0),
+            Some(PixelRect::new(20, 11, 41, 20))
+        );
+        assert_eq!(PixelRect::from_points(points(1.2, 1.2, 0.1, 0.1), 1.0), Some(PixelRect::new(1, 1, 1, 1)));
+        // Negative sizes are standardized and negative coordinates dropped.
+        assert_eq!(
+            PixelRect::from_points(points(30.0, 20.0, -10.0, -5.0), 1.0),
+            Some(PixelRect::new(20, 15, 10, 5))
+        );
+        assert_eq!(
+            PixelRect::from_points(points(-5.0, -5.0, 10.0, 8.0), 1.0),
+            Some(PixelRect::new(0, 0, 5, 3))
+        );
+        assert_eq!(PixelRect::from_points(points(-5.0, 0.0, 4.0, 8.0), 1.0), None);
+        assert_eq!(PixelRect::from_points(points(0.0, 0.0, 0.0, 8.0), 2.0), None);
+        assert_eq!(PixelRect::from_points(points(f64::NAN, 0.0, 4.0, 8.0), 1.0), None);
+
+        let rects = [points(0.0, 0.0, 10.0, 10.0), points(5.0, 5.0, 10.0, 10.0), points(0.0, 0.0, 0.0, 0.0)];
+        assert_eq!(Region::from_points(&rects, 2.0).area(), 20 * 20 * 2 - 10 * 10);
+    }
+
+    #[test]
+    fn from_frame_info() {
+        assert_eq!(Region::from_frame_info(&FrameInfo::default()), None);
+        let info = FrameInfo {
+            scale_factor: Some(2.0),
+            dirty_rects: Some(vec![points(1.0, 2.0, 3.0, 4.0)]),
+            ..Default::default()
+        };
+        assert_eq!(Region::from_frame_info(&info).unwrap().rects(), &[PixelRect::new(2, 4, 6, 8)]);
+        let info = FrameInfo {
+            dirty_rects: Some(vec![]),
+            ..Default::default()
+        };
+        assert_eq!(Region::from_frame_info(&info), Some(Region::new()));
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(PixelRect::new(2, 4, 6, 8).scale(2.0, 0.5), PixelRect::new(4, 2, 12, 4));
+        // Fractional results round outwards so every scaled pixel is covered.
+        assert_eq!(PixelRect::new(1, 1, 1, 1).scale(0.5, 0.5), PixelRect::new(0, 0, 1, 1));
+        assert_eq!(PixelRect::new(3, 3, 3, 3).scale(1.5, 1.5), PixelRect::new(4, 4, 5, 5));
+        assert_eq!(PixelRect::new(3, 3, 3, 3).scale(0.0, 1.0), PixelRect::new(0, 3, 0, 3));
+
+        let region = Region::from_rects(vec![PixelRect::new(0, 0, 3, 3), PixelRect::new(3, 0, 3, 3)]);
+        let scaled = region.scale(1.0 / 3.0, 1.0 / 3.0);
+        assert_eq!(scaled.bounds(), Some(PixelRect::new(0, 0, 2, 1)));
+        assert_eq!(scaled.area(), 2);
+    }
+
+    #[test]
+    fn tiles() {
+        let region = Region::from_rects(vec![PixelRect::new(15, 0, 2, 1), PixelRect::new(40, 40, 8, 8)]);
+        assert_eq!(region.tiles(16, 16), [(0, 0), (1, 0), (2, 2)]);
+        // Rectangles ending on a tile edge don't touch the next tile.
+        assert_eq!(Region::from_rect(PixelRect::new(0, 0, 16, 32)).tiles(16, 16), [(0, 0), (0, 1)]);
+        // Tiles at the frame edge are partial; a zero tile size counts as one.
+        assert_eq!(Region::from_rect(PixelRect::new(30, 10, 3, 1)).tiles(16, 16), [(1, 0), (2, 0)]);
+        assert_eq!(Region::from_rect(PixelRect::new(1, 1, 2, 1)).tiles(0, 0), [(1, 1), (2, 1)]);
+        assert!(Region::new().tiles(16, 16).is_empty());
+    }
+
+    #[test]
+    fn area_fraction() {
+        let region = Region::from_rects(vec![PixelRect::new(0, 0, 10, 10), PixelRect::new(90, 90, 20, 20)]);
+        assert_eq!(region.area_fraction(100, 100), (100.0 + 100.0) / 10000.0);
+        assert_eq!(region.area_fraction(10, 10), 1.0);
+        assert_eq!(region.area_fraction(0, 100), 0.0);
+        assert_eq!(Region::new().area_fraction(100, 100), 0.0);
+    }
+}
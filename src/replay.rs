@@ -0,0 +1,577 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    audio::AudioBuffer,
+    clock::cmtime_to_nanos,
+    delta::{is_key_frame, DeltaDecoder, DeltaEncoder, DeltaError},
+    frame::{FrameError, VideoFrame},
+    frame_info::FrameInfo,
+    platform::CMTime,
+    sink::{AudioSink, SinkError, VideoSink},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReplayOptions {
+    /// Captured time kept.
+    pub duration: Duration,
+    /// Memory the samples may take; the oldest are dropped beyond it.
+    pub max_bytes: Option<usize>,
+    /// Whether frames are kept as [`DeltaEncoder`] messages holding only
+    /// the tiles that changed, which costs time on every frame but keeps
+    /// mostly static screens small. Frames are converted to BGRA.
+    pub compress: bool,
+    /// Interval between compressed frames kept in full. The window can
+    /// reach back this much further than [`duration`](Self::duration),
+    /// since it must start with a full frame.
+    pub key_frame_interval: Duration,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(60),
+            max_bytes: None,
+            compress: false,
+            key_frame_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ReplayStats {
+    pub video_frames: usize,
+    pub audio_buffers: usize,
+    pub bytes: usize,
+    /// Time from the oldest kept sample to the end of the newest.
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+enum FrameData {
+    Raw(VideoFrame),
+    Delta {
+        messages: Vec<u8>,
+        presentation_time: CMTime,
+        duration: CMTime,
+        info: FrameInfo,
+    },
+}
+
+#[derive(Debug)]
+struct StoredFrame {
+    time: u64,
+    /// Whether decoding can start at this frame.
+    key: bool,
+    bytes: usize,
+    data: FrameData,
+}
+
+#[derive(Debug)]
+struct StoredAudio {
+    time: u64,
+    end: u64,
+    bytes: usize,
+    audio: AudioBuffer,
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    video: VecDeque<Arc<StoredFrame>>,
+    audio: VecDeque<Arc<StoredAudio>>,
+    encoder: Option<DeltaEncoder>,
+    last_key: Option<u64>,
+    /// Latest time reached by either stream.
+    latest: u64,
+    bytes: usize,
+}
+
+impl ReplayState {
+    /// Index of the second key frame, up to which the oldest frames can be
+    /// dropped.
+    fn next_key(&self) -> Option<usize> {
+        self.video.iter().skip(1).position(|frame| frame.key).map(|index| index + 1)
+    }
+
+    fn drop_video(&mut self, end: usize) {
+        for frame in self.video.drain(..end) {
+            self.bytes -= frame.bytes;
+        }
+    }
+
+    fn drop_audio(&mut self) {
+        if let Some(audio) = self.audio.pop_front() {
+            self.bytes -= audio.bytes;
+        }
+    }
+
+    fn evict(&mut self, options: &ReplayOptions) {
+        let cutoff = self.latest.saturating_sub(options.duration.as_nanos() as u64);
+        while self.audio.front().is_some_and(|audio| audio.end <= cutoff) {
+            self.drop_audio();
+        }
+        // The frame on screen at the cutoff stays.
+        while let Some(next_key) = self.next_key().filter(|next_key| self.video[*next_key].time <= cutoff) {
+            self.drop_video(next_key);
+        }
+        let max_bytes = match options.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return,
+        };
+        while self.bytes > max_bytes {
+            let video = self.next_key().map(|next_key| (self.video[0].time, next_key));
+            let audio = self.audio.front().map(|audio| audio.time);
+            match (video, audio) {
+                (Some((video, next_key)), Some(audio)) if video <= audio => self.drop_video(next_key),
+                (_, Some(_)) => self.drop_audio(),
+                (Some((_, next_key)), None) => self.drop_video(next_key),
+                (None, None) => break,
+            }
+        }
+    }
+}
+
+/// Keeps the last stretch of captured video and audio in memory, so it can
+/// be saved after something interesting happened without recording
+/// everything. Clones share the same buffer: capture can keep pushing while
+/// another thread dumps a [`ReplayClip`].
+#[derive(Clone, Debug)]
+pub struct ReplayBuffer {
+    options: ReplayOptions,
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl Default for ReplayBuffer {
+    fn default() -> Self {
+        Self::new(ReplayOptions::default())
+    }
+}
+
+impl ReplayBuffer {
+    pub fn new(options: ReplayOptions) -> Self {
+        Self {
+            options,
+            state: Arc::new(Mutex::new(ReplayState::default())),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn options(&self) -> &ReplayOptions {
+        &self.options
+    }
+
+    /// Keeps a frame, dropping the ones that fell out of the window.
+    pub fn push_video(&self, frame: &VideoFrame) -> Result<(), FrameError> {
+        let mut state = self.state();
+        let state = &mut *state;
+        let time = cmtime_to_nanos(frame.presentation_time).unwrap_or(state.latest);
+        let stored = if self.options.compress {
+            let encoder = state.encoder.get_or_insert_with(DeltaEncoder::default);
+            let interval = self.options.key_frame_interval.as_nanos() as u64;
+            if state.last_key.map_or(true, |last_key| time.saturating_sub(last_key) >= interval) {
+                encoder.request_key_frame();
+            }
+            let messages = encoder.encode(frame)?;
+            let key = is_key_frame(&messages);
+            if key {
+                state.last_key = Some(time);
+            }
+            StoredFrame {
+                time,
+                key,
+                bytes: messages.len(),
+                data: FrameData::Delta {
+                    messages,
+                    presentation_time: frame.presentation_time,
+                    duration: frame.duration,
+                    info: frame.info.clone(),
+                },
+            }
+        } else {
+            StoredFrame {
+                time,
+                key: true,
+                bytes: frame.byte_size(),
+                data: FrameData::Raw(frame.clone()),
+            }
+        };
+        state.latest = state.latest.max(time);
+        state.bytes += stored.bytes;
+        state.video.push_back(Arc::new(stored));
+        state.evict(&self.options);
+        Ok(())
+    }
+
+    /// Keeps an audio buffer, dropping the ones that fell out of the window.
+    pub fn push_audio(&self, audio: &AudioBuffer) {
+        let mut state = self.state();
+        let time = cmtime_to_nanos(audio.presentation_time).unwrap_or(state.latest);
+        let end = time + (audio.seconds() * 1e9) as u64;
+        let bytes = audio.buffers().iter().map(Vec::len).sum();
+        state.latest = state.latest.max(end);
+        state.bytes += bytes;
+        state.audio.push_back(Arc::new(StoredAudio {
+            time,
+            end,
+            bytes,
+            audio: audio.clone(),
+        }));
+        state.evict(&self.options);
+    }
+
+    pub fn stats(&self) -> ReplayStats {
+        let state = self.state();
+        let start = state
+            .video
+            .front()
+            .map(|frame| frame.time)
+            .into_iter()
+            .chain(state.audio.front().map(|audio| audio.time))
+            .min();
+        ReplayStats {
+            video_frames: state.video.len(),
+            audio_buffers: state.audio.len(),
+            bytes: state.bytes,
+            duration: Duration::from_nanos(start.map_or(0, |start| state.latest.saturating_sub(start))),
+        }
+    }
+
+    /// The samples in the window right now. Taking a clip is cheap and
+    /// doesn't hold up capture while it's written out.
+    pub fn clip(&self) -> ReplayClip {
+        let state = self.state();
+        ReplayClip {
+            video: state.video.iter().cloned().collect(),
+            audio: state.audio.iter().cloned().collect(),
+        }
+    }
+
+    /// Drops everything kept so far.
+    pub fn clear(&self) {
+        let mut state = self.state();
+        *state = ReplayState::default();
+    }
+}
+
+/// A snapshot of a [`ReplayBuffer`], sharing its samples.
+#[derive(Clone, Debug)]
+pub struct ReplayClip {
+    video: Vec<Arc<StoredFrame>>,
+    audio: Vec<Arc<StoredAudio>>,
+}
+
+fn decode_error(error: DeltaError) -> SinkError {
+    match error {
+        DeltaError::Frame(error) => SinkError::Frame(error),
+        error => SinkError::Io(io::Error::new(io::ErrorKind::InvalidData, error.to_string())),
+    }
+}
+
+impl ReplayClip {
+    pub fn video_frames(&self) -> usize {
+        self.video.len()
+    }
+
+    pub fn audio_buffers(&self) -> usize {
+        self.audio.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.video.is_empty() && self.audio.is_empty()
+    }
+
+    /// Presentation time the clip starts at, in nanoseconds: that of the
+    /// first frame, or of the first audio without video.
+    pub fn start_nanos(&self) -> Option<u64> {
+        self.video
+            .first()
+            .map(|frame| frame.time)
+            .or_else(|| self.audio.first().map(|audio| audio.time))
+    }
+
+    /// Presentation time the clip ends at, in nanoseconds.
+    pub fn end_nanos(&self) -> Option<u64> {
+        let video = self.video.last().map(|frame| frame.time);
+        let audio = self.audio.last().map(|audio| audio.end);
+        video.max(audio)
+    }
+
+    pub fn duration(&self) -> Duration {
+        match (self.start_nanos(), self.end_nanos()) {
+            (Some(start), Some(end)) => Duration::from_nanos(end.saturating_sub(start)),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Writes every frame of the clip to `sink` and finishes it, returning
+    /// the number of frames written.
+    pub fn dump_video<S: VideoSink + ?Sized>(&self, sink: &mut S) -> Result<u64, SinkError> {
+        let mut decoder = DeltaDecoder::new();
+        for stored in &self.video {
+            match &stored.data {
+                FrameData::Raw(frame) => sink.write_frame(frame)?,
+                FrameData::Delta {
+                    messages,
+                    presentation_time,
+                    duration,
+                    info,
+                } => {
+                    decoder.push(messages).map_err(decode_error)?;
+                    let mut frame = decoder
+                        .framebuffer()
+                        .cloned()
+                        .ok_or_else(|| decode_error(DeltaError::Corrupt("frame before init")))?;
+                    frame.presentation_time = *presentation_time;
+                    frame.duration = *duration;
+                    frame.info = info.clone();
+                    sink.write_frame(&frame)?;
+                }
+            }
+        }
+        sink.finish()?;
+        Ok(self.video.len() as u64)
+    }
+
+    /// Writes the audio of the clip to `sink` and finishes it, returning
+    /// the number of audio frames written. Audio from before the first
+    /// video frame is left out, so both dumps start together.
+    pub fn dump_audio<S: AudioSink + ?Sized>(&self, sink: &mut S) -> Result<u64, SinkError> {
+        let start = self.video.first().map(|frame| frame.time);
+        let mut frames = 0;
+        for stored in &self.audio {
+            let audio = &stored.audio;
+            let skip = match start {
+                Some(start) if stored.end <= start => continue,
+                Some(start) if stored.time < start => ((start - stored.time) as f64 * audio.sample_rate() / 1e9).round() as usize,
+                _ => 0,
+            };
+            if skip == 0 {
+                sink.write_audio(audio)?;
+                frames += audio.frames() as u64;
+            } else if skip < audio.frames() {
+                let trimmed = audio.slice(skip..audio.frames());
+                sink.write_audio(&trimmed)?;
+                frames += trimmed.frames() as u64;
+            }
+        }
+        sink.finish()?;
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, frame::PixelFormat};
+
+    const MILLIS: u64 = 1_000_000;
+
+    /// An 8x8 BGRA frame at `millis`, with a gradient shifted by `seed` and
+    /// a square that moves with it.
+    fn frame(millis: u64, seed: u8) -> VideoFrame {
+        let mut data = Vec::with_capacity(8 * 8 * 4);
+        for y in 0..8u8 {
+            for x in 0..8u8 {
+                let square = (x / 2 == seed % 4 && y / 2 == seed / 4 % 4) as u8 * 200;
+                data.extend_from_slice(&[(x * 16).wrapping_add(seed), y * 16, square, 255]);
+            }
+        }
+        let mut frame = VideoFrame::from_bytes(8, 8, PixelFormat::BGRA, &data).unwrap();
+        frame.presentation_time = nanos_to_cmtime(millis * MILLIS, 1000);
+        frame.duration = nanos_to_cmtime(100 * MILLIS, 1000);
+        frame.info.display_time = Some(millis);
+        frame
+    }
+
+    /// `millis` of mono audio starting at `start`, at 1 kHz so frames are
+    /// milliseconds.
+    fn audio(start: u64, millis: usize) -> AudioBuffer {
+        let mut audio = AudioBuffer::from_interleaved_f32(1000.0, 1, &vec![0.5; millis]).unwrap();
+        audio.presentation_time = nanos_to_cmtime(start * MILLIS, 1000);
+        audio
+    }
+
+    fn frame_times(clip: &ReplayClip) -> Vec<u64> {
+        clip.video.iter().map(|frame| frame.time / MILLIS).collect()
+    }
+
+    #[derive(Default)]
+    struct Frames(Vec<VideoFrame>, bool);
+
+    impl VideoSink for Frames {
+        fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+            self.0.push(frame.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), SinkError> {
+            self.1 = true;
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Audio(Vec<AudioBuffer>);
+
+    impl AudioSink for Audio {
+        fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError> {
+            self.0.push(audio.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), SinkError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn window_keeps_the_frame_at_the_cutoff() {
+        let replay = ReplayBuffer::new(ReplayOptions {
+            duration: Duration::from_millis(1000),
+            ..ReplayOptions::default()
+        });
+        for millis in (0..=2000).step_by(300) {
+            replay.push_video(&frame(millis, 0)).unwrap();
+        }
+        // The window reaches back to 800 ms, when the frame from 600 ms was
+        // on screen.
+        let clip = replay.clip();
+        assert_eq!(frame_times(&clip), [600, 900, 1200, 1500, 1800]);
+
+        // Audio ending before the cutoff goes.
+        for start in (1000..2000).step_by(100) {
+            replay.push_audio(&audio(start, 100));
+        }
+        replay.push_audio(&audio(2000, 300));
+        let clip = replay.clip();
+        assert_eq!(frame_times(&clip), [1200, 1500, 1800]);
+        assert_eq!(
+            clip.audio.iter().map(|audio| audio.time / MILLIS).collect::<Vec<_>>(),
+            (1300..=2000).step_by(100).collect::<Vec<_>>()
+        );
+        assert_eq!(clip.start_nanos(), Some(1200 * MILLIS));
+        assert_eq!(clip.end_nanos(), Some(2300 * MILLIS));
+        assert_eq!(clip.duration(), Duration::from_millis(1100));
+
+        let stats = replay.stats();
+        assert_eq!((stats.video_frames, stats.audio_buffers), (3, 8));
+        assert_eq!(stats.bytes, 3 * 256 + 7 * 400 + 1200);
+        assert_eq!(stats.duration, Duration::from_millis(1100));
+
+        replay.clear();
+        assert!(replay.clip().is_empty());
+        assert_eq!(replay.stats(), ReplayStats::default());
+    }
+
+    #[test]
+    fn compressed_windows_start_at_a_key_frame() {
+        let replay = ReplayBuffer::new(ReplayOptions {
+            duration: Duration::from_millis(1500),
+            compress: true,
+            key_frame_interval: Duration::from_millis(1000),
+            ..ReplayOptions::default()
+        });
+        for millis in (0..=2900).step_by(100) {
+            replay.push_video(&frame(millis, (millis / 100) as u8)).unwrap();
+        }
+        let keys: Vec<u64> = replay
+            .clip()
+            .video
+            .iter()
+            .filter(|frame| frame.key)
+            .map(|frame| frame.time / MILLIS)
+            .collect();
+        assert_eq!(keys, [1000, 2000]);
+        // The cutoff at 1400 ms needs the key frame from 1000 ms.
+        assert_eq!(replay.clip().start_nanos(), Some(1000 * MILLIS));
+        replay.push_video(&frame(3500, 35)).unwrap();
+        assert_eq!(replay.clip().start_nanos(), Some(2000 * MILLIS));
+    }
+
+    #[test]
+    fn max_bytes_drops_the_oldest_samples() {
+        let replay = ReplayBuffer::new(ReplayOptions {
+            max_bytes: Some(1000),
+            ..ReplayOptions::default()
+        });
+        // 256 byte frames and 400 byte buffers, interleaved.
+        replay.push_video(&frame(0, 0)).unwrap();
+        replay.push_audio(&audio(0, 100));
+        replay.push_video(&frame(100, 0)).unwrap();
+        assert_eq!(replay.stats().bytes, 912);
+        replay.push_audio(&audio(100, 100));
+        // Oldest first across both streams, until under the limit.
+        let clip = replay.clip();
+        assert_eq!((frame_times(&clip), clip.audio_buffers()), (vec![100], 1));
+        assert_eq!(replay.stats().bytes, 656);
+        replay.push_video(&frame(200, 0)).unwrap();
+        let clip = replay.clip();
+        assert_eq!((frame_times(&clip), clip.audio_buffers()), (vec![100, 200], 1));
+        assert_eq!(replay.stats().bytes, 912);
+
+        // Compressed frames go a key frame interval at a time, and the
+        // last one stays even when over the limit.
+        let replay = ReplayBuffer::new(ReplayOptions {
+            max_bytes: Some(1),
+            compress: true,
+            key_frame_interval: Duration::from_millis(200),
+            ..ReplayOptions::default()
+        });
+        for millis in (0..=300).step_by(100) {
+            replay.push_video(&frame(millis, millis as u8)).unwrap();
+        }
+        assert_eq!(frame_times(&replay.clip()), [200, 300]);
+    }
+
+    #[test]
+    fn compressed_clips_round_trip() {
+        let replay = ReplayBuffer::new(ReplayOptions {
+            duration: Duration::from_millis(1000),
+            compress: true,
+            key_frame_interval: Duration::from_millis(500),
+            ..ReplayOptions::default()
+        });
+        let frames: Vec<VideoFrame> = (0..20).map(|index| frame(index * 100, (index % 7) as u8)).collect();
+        replay.push_audio(&audio(0, 2000));
+        for frame in &frames {
+            replay.push_video(frame).unwrap();
+        }
+
+        let clip = replay.clip();
+        let mut sink = Frames::default();
+        // The audio already reaches 2 s, so the window starts at the key frame
+        // at 1 s.
+        assert_eq!(clip.dump_video(&mut sink).unwrap(), 10);
+        assert!(sink.1);
+        for (decoded, original) in sink.0.iter().zip(&frames[10..]) {
+            assert_eq!(decoded.planes()[0].data(), original.planes()[0].data());
+            assert_eq!(decoded.presentation_time, original.presentation_time);
+            assert_eq!(decoded.duration, original.duration);
+            assert_eq!(decoded.info, original.info);
+        }
+
+        // Audio is trimmed to start with the video.
+        let mut sink = Audio::default();
+        assert_eq!(clip.dump_audio(&mut sink).unwrap(), 1000);
+        assert_eq!(sink.0[0].presentation_time, frames[10].presentation_time);
+
+        // A still screen takes next to nothing.
+        let replay = ReplayBuffer::new(ReplayOptions {
+            compress: true,
+            ..ReplayOptions::default()
+        });
+        let mut still = VideoFrame::zeroed(256, 256, PixelFormat::BGRA).unwrap();
+        for index in 0..10 {
+            still.presentation_time = nanos_to_cmtime(index * 100 * MILLIS, 1000);
+            replay.push_video(&still).unwrap();
+        }
+        assert!(replay.stats().bytes < still.byte_size() / 100, "{}", replay.stats().bytes);
+    }
+}
@@ -0,0 +1,761 @@
+use std::f64::consts::PI;
+
+use crate::{
+    audio::{AudioBuffer, AudioError, AudioFormat, SampleFormat},
+    clock::{cmtime_to_nanos, nanos_to_cmtime},
+    platform::{kCMTimeFlags_Valid, CMTime},
+};
+
+/// Phases computed directly; ratios needing more interpolate between them.
+const MAX_PHASES: usize = 1024;
+/// Fewest phases kept, for interpolating positions off the rational grid.
+const MIN_PHASES: usize = 256;
+
+/// Maps input channels to output channels with a gain matrix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMixer {
+    inputs: usize,
+    outputs: usize,
+    /// Gains by output, then input.
+    matrix: Vec<f32>,
+}
+
+impl ChannelMixer {
+    /// A mixer with `matrix` holding `outputs` rows of `inputs` gains.
+    pub fn new(inputs: usize, outputs: usize, matrix: Vec<f32>) -> Result<Self, AudioError> {
+        if inputs == 0 || outputs == 0 || matrix.len() != inputs * outputs {
+            return Err(AudioError::InvalidFormat);
+        }
+        Ok(Self { inputs, outputs, matrix })
+    }
+
+    /// The usual conversion between channel counts: downmixing to mono
+    /// averages every channel, mono is copied to every output, other
+    /// downmixes fold input `i` into output `i % outputs`, and upmixes keep
+    /// the inputs and leave the extra outputs silent.
+    pub fn remix(inputs: usize, outputs: usize) -> Result<Self, AudioError> {
+        let mut mixer = Self::new(inputs, outputs, vec![0.0; inputs * outputs])?;
+        if inputs == 1 {
+            mixer.matrix.fill(1.0);
+        } else if inputs <= outputs {
+            for channel in 0..inputs {
+                mixer.set_gain(channel, channel, 1.0);
+            }
+        } else {
+            for output in 0..outputs {
+                let sources = (output..inputs).step_by(outputs);
+                let gain = 1.0 / sources.len() as f32;
+                for input in sources {
+                    mixer.set_gain(output, input, gain);
+                }
+            }
+        }
+        Ok(mixer)
+    }
+
+    /// Picks input channels by index, e.g. `&[1]` for the right channel of a
+    /// stereo input alone.
+    pub fn select(inputs: usize, channels: &[usize]) -> Result<Self, AudioError> {
+        let mut mixer = Self::new(inputs, channels.len(), vec![0.0; inputs * channels.len()])?;
+        for (output, &input) in channels.iter().enumerate() {
+            if input >= inputs {
+                return Err(AudioError::ChannelCount {
+                    expected: inputs,
+                    actual: input + 1,
+                });
+            }
+            mixer.set_gain(output, input, 1.0);
+        }
+        Ok(mixer)
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        self.matrix[output * self.inputs + input]
+    }
+
+    pub fn set_gain(&mut self, output: usize, input: usize, gain: f32) {
+        self.matrix[output * self.inputs + input] = gain;
+    }
+
+    /// Mixes `audio` into interleaved 32 bit float samples, keeping its
+    /// timing.
+    pub fn apply(&self, audio: &AudioBuffer) -> Result<AudioBuffer, AudioError> {
+        if audio.channels() != self.inputs {
+            return Err(AudioError::ChannelCount {
+                expected: self.inputs,
+                actual: audio.channels(),
+            });
+        }
+        let input = audio.to_interleaved_f32();
+        let mut samples = Vec::with_capacity(audio.frames() * self.outputs);
+        for frame in input.chunks_exact(self.inputs) {
+            for gains in self.matrix.chunks_exact(self.inputs) {
+                samples.push(gains.iter().zip(frame).map(|(gain, sample)| gain * sample).sum());
+            }
+        }
+        let mut mixed = AudioBuffer::from_interleaved_f32(audio.sample_rate(), self.outputs, &samples)?;
+        mixed.presentation_time = audio.presentation_time;
+        mixed.duration = audio.duration;
+        Ok(mixed)
+    }
+}
+
+/// Trades resampling cost for stopband attenuation and passband width.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResamplerQuality {
+    Fast,
+    Balanced,
+    #[default]
+    High,
+}
+
+impl ResamplerQuality {
+    /// Filter taps at unity ratio, the fraction of the output Nyquist
+    /// frequency kept and the Kaiser window beta.
+    fn parameters(self) -> (usize, f64, f64) {
+        match self {
+            Self::Fast => (16, 0.90, 6.0),
+            Self::Balanced => (32, 0.94, 8.0),
+            Self::High => (64, 0.97, 10.0),
+        }
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+/// Modified Bessel function of the first kind, order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..64 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Converts audio between sample rates with a windowed sinc filter,
+/// keeping state between buffers so a stream can be fed in pieces of any
+/// size. Output is interleaved 32 bit float; call [`flush`] at the end of a
+/// stream for the samples still held back by the filter.
+///
+/// [`flush`]: Resampler::flush
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    input_rate: f64,
+    output_rate: f64,
+    channels: usize,
+    /// Output samples per `step` input samples.
+    phases_per_input: u64,
+    step: u64,
+    taps: usize,
+    phases: usize,
+    /// `phases + 1` rows of `taps` coefficients.
+    filter: Vec<f32>,
+    /// Pending input by channel.
+    history: Vec<Vec<f32>>,
+    /// The next output sample is at `history[..][start + taps / 2 - 1]`
+    /// plus `fraction / phases_per_input + skew` input samples.
+    start: usize,
+    fraction: u64,
+    /// Offset from the rational grid built up by `adjustment`, in
+    /// `0.0..1.0`.
+    skew: f64,
+    /// Extra input samples consumed per output sample.
+    adjustment: f64,
+    /// Input samples dropped from the front of `history` so far.
+    drained: u64,
+    /// Input samples received so far.
+    received: u64,
+    /// Presentation time of some input sample, in nanoseconds, and its
+    /// index.
+    anchor: Option<(u64, u64)>,
+}
+
+impl Resampler {
+    /// A resampler for `channels` channels. Rates are rounded to whole
+    /// Hertz.
+    pub fn new(input_rate: f64, output_rate: f64, channels: usize, quality: ResamplerQuality) -> Result<Self, AudioError> {
+        let (input, output) = (input_rate.round(), output_rate.round());
+        if channels == 0 || !(input >= 1.0 && output >= 1.0 && input <= u32::MAX as f64 && output <= u32::MAX as f64) {
+            return Err(AudioError::InvalidFormat);
+        }
+        let (input, output) = (input as u64, output as u64);
+        let divisor = gcd(input, output);
+        let (phases_per_input, step) = (output / divisor, input / divisor);
+
+        let (base_taps, passband, beta) = quality.parameters();
+        let ratio = output as f64 / input as f64;
+        // Wider filters when decimating keep the attenuation at the lower
+        // cutoff.
+        let taps = ((base_taps as f64 / ratio.min(1.0)).ceil() as usize + 1) & !1;
+        let cutoff = 0.5 * ratio.min(1.0) * passband;
+        // A multiple of the rational phases, so positions on the grid need
+        // no interpolation.
+        let phases = match phases_per_input as usize {
+            phases if phases > MAX_PHASES => MAX_PHASES,
+            phases => phases * ((MIN_PHASES + phases - 1) / phases),
+        };
+        let half = taps / 2;
+        let window_scale = bessel_i0(beta);
+        let mut filter = Vec::with_capacity((phases + 1) * taps);
+        for phase in 0..=phases {
+            let offset = phase as f64 / phases as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    let x = tap as f64 - (half - 1) as f64 - offset;
+                    let r = x / half as f64;
+                    let window = if r.abs() >= 1.0 {
+                        0.0
+                    } else {
+                        bessel_i0(beta * (1.0 - r * r).sqrt()) / window_scale
+                    };
+                    let argument = 2.0 * cutoff * x;
+                    let sinc = if argument == 0.0 {
+                        1.0
+                    } else {
+                        (PI * argument).sin() / (PI * argument)
+                    };
+                    2.0 * cutoff * sinc * window
+                })
+                .collect();
+            let sum: f64 = row.iter().sum();
+            filter.extend(row.iter().map(|coefficient| (coefficient / sum) as f32));
+        }
+
+        let mut resampler = Self {
+            input_rate: input as f64,
+            output_rate: output as f64,
+            channels,
+            phases_per_input,
+            step,
+            taps,
+            phases,
+            filter,
+            history: vec![Vec::new(); channels],
+            start: 0,
+            fraction: 0,
+            skew: 0.0,
+            adjustment: 0.0,
+            drained: 0,
+            received: 0,
+            anchor: None,
+        };
+        resampler.reset();
+        Ok(resampler)
+    }
+
+    pub fn input_rate(&self) -> f64 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> f64 {
+        self.output_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Input samples the filter looks ahead, which are held back until more
+    /// input or [`flush`](Resampler::flush) arrives.
+    pub fn latency(&self) -> usize {
+        self.taps / 2
+    }
+
+    /// Consumes input `ppm` parts per million faster than the nominal ratio,
+    /// or slower if negative, to follow an input clock that drifts from its
+    /// nominal rate. Changes take effect smoothly from the next output
+    /// sample.
+    pub fn set_adjustment_ppm(&mut self, ppm: f64) {
+        self.adjustment = self.step as f64 / self.phases_per_input as f64 * ppm * 1e-6;
+    }
+
+    pub fn adjustment_ppm(&self) -> f64 {
+        self.adjustment * self.phases_per_input as f64 / self.step as f64 * 1e6
+    }
+
+    /// Forgets all buffered input, as if newly created, keeping the
+    /// adjustment.
+    pub fn reset(&mut self) {
+        for history in &mut self.history {
+            history.clear();
+            history.resize(self.taps / 2 - 1, 0.0);
+        }
+        self.start = 0;
+        self.fraction = 0;
+        self.skew = 0.0;
+        self.drained = 0;
+        self.received = 0;
+        self.anchor = None;
+    }
+
+    /// Resamples the next piece of the stream, which must have the input rate
+    /// and channel count. The output may be empty while the filter fills, and
+    /// is timed from the input presentation times.
+    pub fn process(&mut self, audio: &AudioBuffer) -> Result<AudioBuffer, AudioError> {
+        if audio.sample_rate().round() != self.input_rate {
+            return Err(AudioError::UnsupportedFormat);
+        }
+        if audio.channels() != self.channels {
+            return Err(AudioError::ChannelCount {
+                expected: self.channels,
+                actual: audio.channels(),
+            });
+        }
+        if let Some(nanos) = cmtime_to_nanos(audio.presentation_time) {
+            self.anchor = Some((nanos, self.received));
+        }
+        for (channel, history) in self.history.iter_mut().enumerate() {
+            history.extend((0..audio.frames()).map(|frame| audio.sample(frame, channel)));
+        }
+        self.received += audio.frames() as u64;
+        self.drain(None)
+    }
+
+    /// Returns the output still held back by the filter and resets for a new
+    /// stream.
+    pub fn flush(&mut self) -> Result<AudioBuffer, AudioError> {
+        let end = self.received;
+        for history in &mut self.history {
+            history.resize(history.len() + self.taps, 0.0);
+        }
+        let output = self.drain(Some(end));
+        self.reset();
+        output
+    }
+
+    /// Computes every output sample the buffered input allows, stopping
+    /// before input index `end` if given.
+    fn drain(&mut self, end: Option<u64>) -> Result<AudioBuffer, AudioError> {
+        let first_position = self.position();
+        let available = self.history[0].len();
+        let mut samples = Vec::new();
+        loop {
+            let offset = self.fraction as f64 / self.phases_per_input as f64 + self.skew;
+            let start = self.start + (offset >= 1.0) as usize;
+            if start + self.taps > available || end.is_some_and(|end| self.position() >= end as f64) {
+                break;
+            }
+            let phase = offset.fract() * self.phases as f64;
+            let row = phase as usize;
+            let weight = (phase - row as f64) as f32;
+            let current = &self.filter[row * self.taps..(row + 1) * self.taps];
+            for history in &self.history {
+                let window = &history[start..start + self.taps];
+                let mut sample: f32 = current.iter().zip(window).map(|(coefficient, sample)| coefficient * sample).sum();
+                if weight > 0.0 {
+                    let next = &self.filter[(row + 1) * self.taps..(row + 2) * self.taps];
+                    let other: f32 = next.iter().zip(window).map(|(coefficient, sample)| coefficient * sample).sum();
+                    sample += weight * (other - sample);
+                }
+                samples.push(sample);
+            }
+            self.fraction += self.step;
+            self.start += (self.fraction / self.phases_per_input) as usize;
+            self.fraction %= self.phases_per_input;
+            self.skew += self.adjustment;
+            if self.skew >= 1.0 {
+                self.start += 1;
+                self.skew -= 1.0;
+            } else if self.skew < 0.0 {
+                if self.start > 0 {
+                    self.start -= 1;
+                    self.skew += 1.0;
+                } else {
+                    self.skew = 0.0;
+                }
+            }
+        }
+        // One sample is kept so a negative adjustment can step back.
+        let consumed = self.start.saturating_sub(1).min(available);
+        for history in &mut self.history {
+            history.drain(..consumed);
+        }
+        self.start -= consumed;
+        self.drained += consumed as u64;
+
+        let frames = samples.len() / self.channels;
+        let mut output = AudioBuffer::from_interleaved_f32(self.output_rate, self.channels, &samples)?;
+        let timescale = self.output_rate as i32;
+        if let Some((nanos, index)) = self.anchor {
+            let offset = (first_position - index as f64) / self.input_rate * 1e9;
+            output.presentation_time = nanos_to_cmtime((nanos as f64 + offset).max(0.0) as u64, timescale);
+        }
+        output.duration = CMTime {
+            value: frames as i64,
+            timescale,
+            flags: kCMTimeFlags_Valid,
+            epoch: 0,
+        };
+        Ok(output)
+    }
+
+    /// Input index of the next output sample.
+    fn position(&self) -> f64 {
+        (self.drained + self.start as u64) as f64 + self.fraction as f64 / self.phases_per_input as f64 + self.skew
+    }
+}
+
+/// Converts captured audio to a fixed sample rate and channel count, e.g.
+/// 16 kHz mono for speech recognition, as interleaved 32 bit float. The
+/// input format is taken from each buffer; when it changes, the converter
+/// starts over.
+#[derive(Clone, Debug)]
+pub struct AudioConverter {
+    sample_rate: f64,
+    channels: usize,
+    quality: ResamplerQuality,
+    custom_mixer: Option<ChannelMixer>,
+    input: Option<(f64, usize)>,
+    mixer: Option<ChannelMixer>,
+    resampler: Option<Resampler>,
+}
+
+impl AudioConverter {
+    pub fn new(sample_rate: f64, channels: usize, quality: ResamplerQuality) -> Result<Self, AudioError> {
+        AudioFormat::new(sample_rate, channels, SampleFormat::F32, true).validate()?;
+        Ok(Self {
+            sample_rate,
+            channels,
+            quality,
+            custom_mixer: None,
+            input: None,
+            mixer: None,
+            resampler: None,
+        })
+    }
+
+    /// Mixes with `mixer` instead of [`ChannelMixer::remix`] for inputs with
+    /// its channel count.
+    pub fn set_mixer(&mut self, mixer: ChannelMixer) -> Result<(), AudioError> {
+        if mixer.outputs() != self.channels {
+            return Err(AudioError::ChannelCount {
+                expected: self.channels,
+                actual: mixer.outputs(),
+            });
+        }
+        self.custom_mixer = Some(mixer);
+        self.input = None;
+        Ok(())
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        AudioFormat::new(self.sample_rate, self.channels, SampleFormat::F32, true)
+    }
+
+    fn configure(&mut self, sample_rate: f64, channels: usize) -> Result<(), AudioError> {
+        self.mixer = match &self.custom_mixer {
+            Some(mixer) if mixer.inputs() == channels => Some(mixer.clone()),
+            _ if channels == self.channels => None,
+            _ => Some(ChannelMixer::remix(channels, self.channels)?),
+        };
+        // Mix first when that leaves fewer channels to resample.
+        let resampled_channels = if self.channels < channels {
+            self.channels
+        } else {
+            channels
+        };
+        self.resampler = if sample_rate.round() == self.sample_rate.round() {
+            None
+        } else {
+            Some(Resampler::new(sample_rate, self.sample_rate, resampled_channels, self.quality)?)
+        };
+        self.input = Some((sample_rate, channels));
+        Ok(())
+    }
+
+    pub fn process(&mut self, audio: &AudioBuffer) -> Result<AudioBuffer, AudioError> {
+        let input = (audio.sample_rate(), audio.channels());
+        if self.input != Some(input) {
+            self.configure(input.0, input.1)?;
+        }
+        let mix_first = self.channels < input.1;
+        let mut audio = match &self.mixer {
+            Some(mixer) if mix_first => mixer.apply(audio)?,
+            _ => audio.convert(SampleFormat::F32, true),
+        };
+        if let Some(resampler) = &mut self.resampler {
+            audio = resampler.process(&audio)?;
+        }
+        match &self.mixer {
+            Some(mixer) if !mix_first => mixer.apply(&audio),
+            _ => Ok(audio),
+        }
+    }
+
+    /// Returns the output still held back by the resampler and starts over.
+    pub fn flush(&mut self) -> Result<AudioBuffer, AudioError> {
+        let audio = match &mut self.resampler {
+            Some(resampler) => resampler.flush()?,
+            None => return AudioBuffer::silence(self.format(), 0),
+        };
+        let output = match &self.mixer {
+            Some(mixer) if audio.channels() != self.channels => mixer.apply(&audio),
+            _ => Ok(audio),
+        };
+        self.input = None;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_rejects_other_formats() {
+        let mut resampler = Resampler::new(44100.0, 48000.0, 2, ResamplerQuality::default()).unwrap();
+        let silence = |sample_rate, channels| AudioBuffer::silence(AudioFormat::new(sample_rate, channels, SampleFormat::F32, true), 441).unwrap();
+        assert_eq!(resampler.process(&silence(48000.0, 2)), Err(AudioError::UnsupportedFormat));
+        assert_eq!(
+            resampler.process(&silence(44100.0, 1)),
+            Err(AudioError::ChannelCount { expected: 2, actual: 1 })
+        );
+        assert!(resampler.process(&silence(44100.0, 2)).is_ok());
+    }
+
+    fn sine(sample_rate: f64, frequency: f64, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|frame| (0.5 * (2.0 * PI * frequency * frame as f64 / sample_rate).sin()) as f32)
+            .collect()
+    }
+
+    fn gains(mixer: &ChannelMixer) -> Vec<Vec<f32>> {
+        (0..mixer.outputs())
+            .map(|output| (0..mixer.inputs()).map(|input| mixer.gain(output, input)).collect())
+            .collect()
+    }
+
+    /// Resamples `samples` in pieces of the given sizes, cycling through
+    /// them, and returns the output of every call, the flush last.
+    fn resample_in_pieces(resampler: &mut Resampler, samples: &[f32], pieces: &[usize]) -> Vec<AudioBuffer> {
+        let channels = resampler.channels();
+        let mut outputs = Vec::new();
+        let mut offset = 0;
+        for &piece in pieces.iter().cycle() {
+            if offset == samples.len() {
+                break;
+            }
+            let end = (offset + piece * channels).min(samples.len());
+            let mut audio = AudioBuffer::from_interleaved_f32(resampler.input_rate(), channels, &samples[offset..end]).unwrap();
+            audio.presentation_time = nanos_to_cmtime(0, 1000);
+            if offset > 0 {
+                // Only the first piece is timed; later ones follow on.
+                audio.presentation_time.flags = 0;
+            }
+            outputs.push(resampler.process(&audio).unwrap());
+            offset = end;
+        }
+        outputs.push(resampler.flush().unwrap());
+        outputs
+    }
+
+    /// Output samples for `frames` input samples once flushed, rounded up.
+    fn output_frames(frames: usize, input_rate: f64, output_rate: f64) -> usize {
+        let (input_rate, output_rate) = (input_rate as usize, output_rate as usize);
+        (frames * output_rate + input_rate - 1) / input_rate
+    }
+
+    fn joined(outputs: &[AudioBuffer]) -> Vec<f32> {
+        outputs.iter().flat_map(|output| output.to_interleaved_f32()).collect()
+    }
+
+    #[test]
+    fn mixer_matrices() {
+        assert_eq!(gains(&ChannelMixer::remix(2, 1).unwrap()), [[0.5, 0.5]]);
+        assert_eq!(gains(&ChannelMixer::remix(1, 3).unwrap()), [[1.0], [1.0], [1.0]]);
+        assert_eq!(
+            gains(&ChannelMixer::remix(2, 4).unwrap()),
+            [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0], [0.0, 0.0]]
+        );
+        let third = 1.0 / 3.0;
+        assert_eq!(
+            gains(&ChannelMixer::remix(6, 2).unwrap()),
+            [[third, 0.0, third, 0.0, third, 0.0], [0.0, third, 0.0, third, 0.0, third]]
+        );
+        // Uneven folds average each output's own sources.
+        assert_eq!(gains(&ChannelMixer::remix(3, 2).unwrap()), [[0.5, 0.0, 0.5], [0.0, 1.0, 0.0]]);
+        assert_eq!(gains(&ChannelMixer::select(2, &[1, 1, 0]).unwrap()), [[0.0, 1.0], [0.0, 1.0], [1.0, 0.0]]);
+
+        assert_eq!(ChannelMixer::new(2, 2, vec![1.0; 3]), Err(AudioError::InvalidFormat));
+        assert_eq!(ChannelMixer::remix(0, 2), Err(AudioError::InvalidFormat));
+        assert_eq!(ChannelMixer::select(2, &[]), Err(AudioError::InvalidFormat));
+        assert_eq!(ChannelMixer::select(2, &[2]), Err(AudioError::ChannelCount { expected: 2, actual: 3 }));
+    }
+
+    #[test]
+    fn mixer_applies_gains() {
+        let mut mixer = ChannelMixer::new(2, 2, vec![0.5, 0.5, 1.0, -1.0]).unwrap();
+        let mut audio = AudioBuffer::from_interleaved_f32(48000.0, 2, &[0.5, 0.25, -1.0, 0.5]).unwrap();
+        audio.presentation_time = nanos_to_cmtime(1_000_000_000, 48000);
+        let mixed = mixer.apply(&audio.convert(SampleFormat::I16, false)).unwrap();
+        assert_eq!(mixed.to_interleaved_f32(), [0.375, 0.25, -0.25, -1.5]);
+        assert_eq!(mixed.presentation_time, audio.presentation_time);
+        assert_eq!(mixed.duration, audio.duration);
+
+        mixer.set_gain(1, 1, 0.0);
+        assert_eq!(mixer.apply(&audio).unwrap().channel_f32(1), [0.5, -1.0]);
+        assert_eq!(
+            ChannelMixer::remix(1, 2).unwrap().apply(&audio),
+            Err(AudioError::ChannelCount { expected: 1, actual: 2 })
+        );
+    }
+
+    #[test]
+    fn streams_match_whole_buffers() {
+        let samples: Vec<f32> = sine(44100.0, 1000.0, 4410).iter().flat_map(|&sample| vec![sample, -sample]).collect();
+        let mut resampler = Resampler::new(44100.0, 48000.0, 2, ResamplerQuality::default()).unwrap();
+        let whole = joined(&resample_in_pieces(&mut resampler, &samples, &[4410]));
+        // Pieces smaller than the filter, and single samples.
+        for pieces in [&[1, 7, 480][..], &[1], &[31, 1024]] {
+            let outputs = resample_in_pieces(&mut resampler, &samples, pieces);
+            assert_eq!(joined(&outputs), whole, "{:?}", pieces);
+        }
+        assert_eq!(whole.len(), 4800 * 2);
+
+        // Every piece starts where the previous one left off.
+        let outputs = resample_in_pieces(&mut resampler, &samples, &[100, 333]);
+        let mut frames = 0;
+        for output in &outputs {
+            if output.frames() > 0 {
+                assert_eq!(output.presentation_time.timescale, 48000);
+                assert!(
+                    (output.presentation_time.value - frames).abs() <= 1,
+                    "{} {}",
+                    output.presentation_time.value,
+                    frames
+                );
+            }
+            assert_eq!(output.duration.value, output.frames() as i64);
+            frames += output.frames() as i64;
+        }
+
+        // And the output is the same sine, away from the edges.
+        let expected = sine(48000.0, 1000.0, 4800);
+        let latency = resampler.latency() * 2;
+        for (frame, (sample, expected)) in whole.chunks_exact(2).zip(&expected).enumerate().take(4800 - latency).skip(latency) {
+            assert!((sample[0] - expected).abs() < 1e-3, "{}: {} {}", frame, sample[0], expected);
+            assert_eq!(sample[1], -sample[0]);
+        }
+    }
+
+    #[test]
+    fn output_length_does_not_drift() {
+        for &(input_rate, output_rate) in &[(44100.0, 48000.0), (48000.0, 16000.0), (48000.0, 44100.0), (44101.0, 48000.0)] {
+            let mut resampler = Resampler::new(input_rate, output_rate, 1, ResamplerQuality::Fast).unwrap();
+            let ratio = output_rate / input_rate;
+            let slack = (resampler.latency() as f64 * ratio).ceil() + 1.0;
+            let audio = AudioBuffer::from_interleaved_f32(input_rate, 1, &[0.25; 441]).unwrap();
+            let (mut received, mut produced) = (0, 0);
+            for _ in 0..1000 {
+                produced += resampler.process(&audio).unwrap().frames();
+                received += 441;
+                // Behind by no more than the filter latency.
+                let expected = received as f64 * ratio;
+                assert!(
+                    produced as f64 <= expected + 1.0 && produced as f64 >= expected - slack,
+                    "{} {} {}",
+                    input_rate,
+                    produced,
+                    expected
+                );
+            }
+            produced += resampler.flush().unwrap().frames();
+            assert_eq!(
+                produced,
+                output_frames(received, input_rate, output_rate),
+                "{} to {}",
+                input_rate,
+                output_rate
+            );
+        }
+    }
+
+    #[test]
+    fn ratio_edge_cases() {
+        for &(input_rate, output_rate) in &[
+            (0.0, 48000.0),
+            (48000.0, 0.4),
+            (-1.0, 48000.0),
+            (f64::NAN, 48000.0),
+            (48000.0, f64::INFINITY),
+            (1e10, 48000.0),
+        ] {
+            assert!(
+                Resampler::new(input_rate, output_rate, 1, ResamplerQuality::default()).is_err(),
+                "{} {}",
+                input_rate,
+                output_rate
+            );
+        }
+        assert!(Resampler::new(48000.0, 44100.0, 0, ResamplerQuality::default()).is_err());
+
+        // Rates are rounded to whole Hertz.
+        let mut resampler = Resampler::new(44099.6, 48000.2, 1, ResamplerQuality::default()).unwrap();
+        assert_eq!((resampler.input_rate(), resampler.output_rate()), (44100.0, 48000.0));
+        let audio = AudioBuffer::from_interleaved_f32(44100.4, 1, &[0.0; 10]).unwrap();
+        assert!(resampler.process(&audio).is_ok());
+
+        // Unity, extreme and coprime ratios keep the length and a constant
+        // level.
+        for &(input_rate, output_rate) in &[(48000.0, 48000.0), (8000.0, 192000.0), (192000.0, 8000.0), (1.0, 7.0), (44101.0, 48000.0)] {
+            let mut resampler = Resampler::new(input_rate, output_rate, 1, ResamplerQuality::Balanced).unwrap();
+            let frames = (input_rate as usize).max(1000);
+            let samples = vec![0.5; frames];
+            let output = joined(&resample_in_pieces(&mut resampler, &samples, &[frames / 3 + 1]));
+            let ratio = output_rate / input_rate;
+            assert_eq!(
+                output.len(),
+                output_frames(frames, input_rate, output_rate),
+                "{} to {}",
+                input_rate,
+                output_rate
+            );
+            let margin = (resampler.latency() as f64 * ratio.max(1.0)) as usize * 2;
+            for sample in &output[margin..output.len() - margin] {
+                assert!((sample - 0.5).abs() < 1e-3, "{} to {}: {}", input_rate, output_rate, sample);
+            }
+        }
+    }
+
+    #[test]
+    fn adjustment_changes_consumption() {
+        let mut resampler = Resampler::new(48000.0, 48000.0, 1, ResamplerQuality::Fast).unwrap();
+        resampler.set_adjustment_ppm(1000.0);
+        assert!((resampler.adjustment_ppm() - 1000.0).abs() < 1e-9);
+        let audio = AudioBuffer::from_interleaved_f32(48000.0, 1, &[0.0; 4800]).unwrap();
+        let mut produced = 0;
+        for _ in 0..100 {
+            produced += resampler.process(&audio).unwrap().frames();
+        }
+        produced += resampler.flush().unwrap().frames();
+        // A thousandth faster consumption leaves a thousandth fewer outputs.
+        assert!((produced as i64 - 479520).abs() <= 1, "{}", produced);
+
+        resampler.set_adjustment_ppm(-1000.0);
+        let mut produced = 0;
+        for _ in 0..100 {
+            produced += resampler.process(&audio).unwrap().frames();
+        }
+        produced += resampler.flush().unwrap().frames();
+        assert!((produced as i64 - 480481).abs() <= 1, "{}", produced);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{
+    convert::unpack_l10r,
+    frame::{FrameError, PixelFormat, Plane, PlaneMut, VideoFrame},
+    region::PixelRect,
+};
+
+const SHIFT: u32 = 14;
+const ONE: i32 = 1 << SHIFT;
+
+/// How samples are interpolated when resizing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ScaleFilter {
+    /// Picks the closest source sample. Fastest, but blocky and aliased.
+    Nearest,
+    /// Interpolates between the four closest source samples.
+    #[default]
+    Bilinear,
+    /// Averages every source sample covered by the output sample, weighted
+    /// by coverage. Best suited to downscaling, e.g. for thumbnails.
+    Area,
+}
+
+/// The source samples contributing to one output sample, with weights
+/// summing to `ONE`.
+struct Contribution {
+    start: usize,
+    weights: Vec<i32>,
+}
+
+/// Computes the contributions for resizing an axis of `src_len` samples to
+/// `dst_len` samples, where one output sample spans `scale` source samples.
+/// `scale` is passed separately so subsampled chroma planes use the same
+/// mapping as their luma plane.
+fn contributions(src_len: usize, dst_len: usize, scale: f64, filter: ScaleFilter) -> Vec<Contribution> {
+    let last = src_len as f64 - 1.0;
+    (0..dst_len)
+        .map(|dst| {
+            let weights: Vec<(usize, f64)> = match filter {
+                ScaleFilter::Nearest => {
+                    let src = ((dst as f64 + 0.5) * scale).floor().clamp(0.0, last);
+                    vec![(src as usize, 1.0)]
+                }
+                ScaleFilter::Bilinear => {
+                    let src = ((dst as f64 + 0.5) * scale - 0.5).clamp(0.0, last);
+                    let left = src.floor();
+                    let fraction = src - left;
+                    let right = (left + 1.0).min(last);
+                    vec![(left as usize, 1.0 - fraction), (right as usize, fraction)]
+                }
+                ScaleFilter::Area => {
+                    let begin = (dst as f64 * scale).min(last);
+                    let end = ((dst as f64 + 1.0) * scale).clamp(begin + f64::EPSILON, src_len as f64);
+                    let first = begin.floor() as usize;
+                    let past = (end.ceil() as usize).clamp(first + 1, src_len);
+                    (first..past)
+                        .map(|src| {
+                            let covered = (end.min(src as f64 + 1.0) - begin.max(src as f64)).max(0.0);
+                            (src, covered)
+                        })
+                        .collect()
+                }
+            };
+            quantize(weights)
+        })
+        .collect()
+}
+
+/// Converts floating point weights to fixed point, assigning the rounding
+/// error to the largest weight so every output sample keeps unit gain.
+fn quantize(weights: Vec<(usize, f64)>) -> Contribution {
+    let start = weights.iter().map(|(index, _)| *index).min().unwrap_or(0);
+    let end = weights.iter().map(|(index, _)| *index + 1).max().unwrap_or(start + 1);
+    let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut fixed = vec![0; end - start];
+    for (index, weight) in weights {
+        let weight = if total > 0.0 {
+            weight / total
+        } else {
+            1.0
+        };
+        fixed[index - start] += (weight * ONE as f64).round() as i32;
+    }
+    let error = ONE - fixed.iter().sum::<i32>();
+    if let Some(largest) = fixed.iter_mut().max_by_key(|weight| **weight) {
+        *largest += error;
+    }
+    Contribution { start, weights: fixed }
+}
+
+/// Number of channels a plane is resampled in, and whether its samples are
+/// packed `l10r` words rather than bytes.
+pub(crate) fn channels(pixel_format: PixelFormat, plane: &Plane) -> (usize, bool) {
+    if pixel_format == PixelFormat::ARGB2101010 {
+        (4, true)
+    } else {
+        (plane.bytes_per_pixel(), false)
+    }
+}
+
+pub(crate) fn read_row(src: &[u8], packed: bool, dst: &mut [u16]) {
+    if packed {
+        unpack_l10r(src, dst);
+    } else {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = *src as u16;
+        }
+    }
+}
+
+pub(crate) fn write_row(src: &[u16], packed: bool, dst: &mut [u8]) {
+    if packed {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            let channel = |value: u16| (value as u32).min(0x3ff);
+            let alpha = (channel(src[3]) + 170) / 341;
+            let value = (alpha << 30) | (channel(src[0]) << 20) | (channel(src[1]) << 10) | channel(src[2]);
+            dst.copy_from_slice(&value.to_le_bytes());
+        }
+    } else {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = (*src).min(255) as u8;
+        }
+    }
+}
+
+fn filter_row(src: &[u16], channels: usize, horizontal: &[Contribution], dst: &mut [u16]) {
+    for (contribution, dst) in horizontal.iter().zip(dst.chunks_exact_mut(channels)) {
+        for (channel, dst) in dst.iter_mut().enumerate() {
+            let sum: i32 = contribution
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(tap, weight)| src[(contribution.start + tap) * channels + channel] as i32 * weight)
+                .sum();
+            *dst = ((sum + ONE / 2) >> SHIFT).max(0) as u16;
+        }
+    }
+}
+
+/// Resizes one plane in two separable passes: source rows are filtered
+/// horizontally as output rows need them, then blended vertically. Output
+/// rows only move down the source, so the filtered rows are kept in a ring
+/// holding as many rows as one output row blends.
+fn resize_plane(src: &Plane, dst: &mut PlaneMut, pixel_format: PixelFormat, scale_x: f64, scale_y: f64, filter: ScaleFilter) {
+    let (channels, packed) = channels(pixel_format, src);
+    let horizontal = contributions(src.width(), dst.width(), scale_x, filter);
+    let vertical = contributions(src.height(), dst.height(), scale_y, filter);
+    let dst_samples = dst.width() * channels;
+    let window = vertical.iter().map(|contribution| contribution.weights.len()).max().unwrap_or(1);
+
+    let mut source_row = vec![0; src.width() * channels];
+    let mut filtered = vec![0u16; window * dst_samples];
+    let mut cached = vec![None; window];
+    let mut output_row = vec![0; dst_samples];
+    for (y, contribution) in vertical.iter().enumerate() {
+        let rows = contribution.start..contribution.start + contribution.weights.len();
+        for row in rows.clone() {
+            let slot = row % window;
+            if cached[slot] != Some(row) {
+                read_row(src.row(row), packed, &mut source_row);
+                filter_row(
+                    &source_row,
+                    channels,
+                    &horizontal,
+                    &mut filtered[slot * dst_samples..(slot + 1) * dst_samples],
+                );
+                cached[slot] = Some(row);
+            }
+        }
+        for (x, output) in output_row.iter_mut().enumerate() {
+            let sum: i32 = rows
+                .clone()
+                .zip(&contribution.weights)
+                .map(|(row, weight)| filtered[row % window * dst_samples + x] as i32 * weight)
+                .sum();
+            *output = ((sum + ONE / 2) >> SHIFT).max(0) as u16;
+        }
+        write_row(&output_row, packed, dst.row_mut(y));
+    }
+}
+
+/// Resizes a frame to `width` x `height`. Chroma planes of YUV frames are
+/// resampled with the luma scale so both stay aligned.
+pub fn resize(src: &VideoFrame, width: usize, height: usize, filter: ScaleFilter) -> Result<VideoFrame, FrameError> {
+    if !src.pixel_format().is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(src.pixel_format()));
+    }
+    if width == src.width() && height == src.height() {
+        return Ok(src.clone());
+    }
+    let mut dst = VideoFrame::zeroed(width, height, src.pixel_format())?;
+    let scale_x = src.width() as f64 / width as f64;
+    let scale_y = src.height() as f64 / height as f64;
+    let pixel_format = src.pixel_format();
+    for (src, mut dst) in src.planes().iter().zip(dst.planes_mut()) {
+        resize_plane(src, &mut dst, pixel_format, scale_x, scale_y, filter);
+    }
+    dst.copy_metadata_from(src);
+    Ok(dst)
+}
+
+/// Copies the part of a frame inside `rect`, clipped to the frame. YUV 4:2:0
+/// frames are cropped from even coordinates so chroma samples stay paired
+/// with their luma, growing the crop by at most one pixel up and left.
+pub fn crop(src: &VideoFrame, rect: PixelRect) -> Result<VideoFrame, FrameError> {
+    let pixel_format = src.pixel_format();
+    if !pixel_format.is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(pixel_format));
+    }
+    let bounds = PixelRect::new(0, 0, src.width() as u32, src.height() as u32);
+    let mut rect = rect.intersection(&bounds).ok_or(FrameError::InvalidDimensions)?;
+    if pixel_format.is_yuv() {
+        rect = PixelRect::new(rect.x & !1, rect.y & !1, rect.width + (rect.x & 1), rect.height + (rect.y & 1));
+    }
+    let (x, y) = (rect.x as usize, rect.y as usize);
+    let (width, height) = (rect.width as usize, rect.height as usize);
+    let layouts = pixel_format
+        .plane_layouts(width, height)
+        .ok_or(FrameError::UnsupportedPixelFormat(pixel_format))?;
+    let planes = src
+        .planes()
+        .iter()
+        .zip(layouts)
+        .enumerate()
+        .map(|(index, (plane, layout))| {
+            let (plane_x, plane_y) = if index == 0 {
+                (x, y)
+            } else {
+                (x / 2, y / 2)
+            };
+            let start = plane_y * plane.stride() + plane_x * plane.bytes_per_pixel();
+            Plane::copy_from_slice(&plane.data()[start..], layout, plane.stride())
+        })
+        .collect::<Result<_, _>>()?;
+    let mut dst = VideoFrame::new(width, height, pixel_format, planes)?;
+    dst.copy_metadata_from(src);
+    Ok(dst)
+}
+
+/// The largest size fitting inside `max_width` x `max_height` that keeps the
+/// aspect ratio of `width` x `height`. Never returns a zero dimension.
+pub fn fit_size(width: usize, height: usize, max_width: usize, max_height: usize) -> (usize, usize) {
+    if width == 0 || height == 0 {
+        return (max_width.max(1), max_height.max(1));
+    }
+    let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    let fit = |length: usize| ((length as f64 * scale).round() as usize).max(1);
+    (fit(width), fit(height))
+}
+
+impl VideoFrame {
+    pub fn resize(&self, width: usize, height: usize, filter: ScaleFilter) -> Result<VideoFrame, FrameError> {
+        resize(self, width, height, filter)
+    }
+
+    pub fn crop(&self, rect: PixelRect) -> Result<VideoFrame, FrameError> {
+        crop(self, rect)
+    }
+
+    /// Crops to `rect` and resizes the result to `width` x `height`.
+    pub fn crop_and_resize(&self, rect: PixelRect, width: usize, height: usize, filter: ScaleFilter) -> Result<VideoFrame, FrameError> {
+        crop(self, rect)?.resize(width, height, filter)
+    }
+
+    /// Downscales the frame to fit inside `max_width` x `max_height` while
+    /// keeping its aspect ratio. Frames already small enough are copied
+    /// unchanged.
+    pub fn thumbnail(&self, max_width: usize, max_height: usize) -> Result<VideoFrame, FrameError> {
+        if self.width() <= max_width && self.height() <= max_height {
+            return Ok(self.clone());
+        }
+        let (width, height) = fit_size(self.width(), self.height(), max_width, max_height);
+        self.resize(width, height, ScaleFilter::Area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [ScaleFilter; 3] = [ScaleFilter::Nearest, ScaleFilter::Bilinear, ScaleFilter::Area];
+    const YUV_FORMATS: [PixelFormat; 2] = [PixelFormat::NV12FullRange, PixelFormat::I420VideoRange];
+
+    /// A frame with `sample(plane, x, y, channel)` samples, each row padded
+    /// with `padding` bytes of 0xff.
+    fn frame(
+        pixel_format: PixelFormat,
+        width: usize,
+        height: usize,
+        padding: usize,
+        sample: impl Fn(usize, usize, usize, usize) -> u8,
+    ) -> VideoFrame {
+        let planes = pixel_format
+            .plane_layouts(width, height)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(index, layout)| {
+                let stride = layout.row_bytes() + padding;
+                let mut data = vec![0xff; stride * layout.height];
+                for y in 0..layout.height {
+                    for x in 0..layout.width {
+                        for channel in 0..layout.bytes_per_pixel {
+                            data[y * stride + x * layout.bytes_per_pixel + channel] = sample(index, x, y, channel);
+                        }
+                    }
+                }
+                Plane::new(data, layout, stride).unwrap()
+            })
+            .collect();
+        VideoFrame::new(width, height, pixel_format, planes).unwrap()
+    }
+
+    fn sample(frame: &VideoFrame, plane: usize, x: usize, y: usize, channel: usize) -> u8 {
+        let plane = &frame.planes()[plane];
+        plane.row(y)[x * plane.bytes_per_pixel() + channel]
+    }
+
+    fn rows(frame: &VideoFrame) -> Vec<Vec<u8>> {
+        frame.planes().iter().flat_map(|plane| plane.rows().map(<[u8]>::to_vec)).collect()
+    }
+
+    /// Even samples stepping by 2 across and 20 down, offset by 60 per plane.
+    fn gradient(plane: usize, x: usize, y: usize, channel: usize) -> u8 {
+        (2 * x + 20 * y + 60 * (plane + channel)) as u8
+    }
+
+    #[test]
+    fn uniform_planes_stay_uniform() {
+        let levels = [100, 50, 200];
+        for &pixel_format in &YUV_FORMATS {
+            let src = frame(pixel_format, 7, 5, 3, |plane, _, _, channel| levels[plane + channel]);
+            for &filter in &FILTERS {
+                for &(width, height) in &[(3, 2), (11, 9), (4, 4), (1, 1)] {
+                    let dst = src.resize(width, height, filter).unwrap();
+                    assert_eq!((dst.width(), dst.height()), (width, height));
+                    let layouts: Vec<_> = dst.planes().iter().map(Plane::layout).collect();
+                    assert_eq!(Some(layouts), pixel_format.plane_layouts(width, height));
+                    for (index, plane) in dst.planes().iter().enumerate() {
+                        for (position, value) in plane.rows().flatten().enumerate() {
+                            let channel = position % plane.bytes_per_pixel();
+                            assert_eq!(*value, levels[index + channel], "{} {:?} to {}x{}", pixel_format, filter, width, height);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn row_padding_is_ignored() {
+        for &pixel_format in &[PixelFormat::NV12VideoRange, PixelFormat::I420FullRange, PixelFormat::BGRA] {
+            let padded = frame(pixel_format, 9, 7, 5, gradient);
+            let packed = frame(pixel_format, 9, 7, 0, gradient);
+            for &filter in &FILTERS {
+                for &(width, height) in &[(4, 3), (13, 10)] {
+                    assert_eq!(
+                        rows(&padded.resize(width, height, filter).unwrap()),
+                        rows(&packed.resize(width, height, filter).unwrap())
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn downscaling_picks_and_averages_the_right_samples() {
+        for &pixel_format in &YUV_FORMATS {
+            let src = frame(pixel_format, 8, 6, 2, gradient);
+            let nearest = src.resize(4, 3, ScaleFilter::Nearest).unwrap();
+            for plane in 0..src.planes().len() {
+                let layout = nearest.planes()[plane].layout();
+                let last_row = src.planes()[plane].height() - 1;
+                for y in 0..layout.height {
+                    for x in 0..layout.width {
+                        for channel in 0..layout.bytes_per_pixel {
+                            let expected = gradient(plane, 2 * x + 1, (2 * y + 1).min(last_row), channel);
+                            assert_eq!(sample(&nearest, plane, x, y, channel), expected);
+                        }
+                    }
+                }
+            }
+
+            // Halving averages 2x2 blocks; the last chroma row of the 3 row
+            // source chroma plane only covers one row.
+            for &filter in &[ScaleFilter::Bilinear, ScaleFilter::Area] {
+                let dst = src.resize(4, 3, filter).unwrap();
+                for y in 0..3 {
+                    for x in 0..4 {
+                        assert_eq!(sample(&dst, 0, x, y, 0), (4 * x + 40 * y + 11) as u8, "{:?}", filter);
+                    }
+                }
+                for plane in 1..dst.planes().len() {
+                    let layout = dst.planes()[plane].layout();
+                    for channel in 0..layout.bytes_per_pixel {
+                        for x in 0..2 {
+                            let offset = 60 * (plane + channel);
+                            assert_eq!(sample(&dst, plane, x, 0, channel) as usize, 4 * x + 11 + offset);
+                            assert_eq!(sample(&dst, plane, x, 1, channel) as usize, 4 * x + 41 + offset);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crop_aligns_yuv_to_even_coordinates() {
+        for &pixel_format in &YUV_FORMATS {
+            let src = frame(pixel_format, 7, 5, 3, gradient);
+            let dst = src.crop(PixelRect::new(3, 1, 3, 3)).unwrap();
+            assert_eq!((dst.width(), dst.height()), (4, 4));
+            for plane in 0..dst.planes().len() {
+                let layout = dst.planes()[plane].layout();
+                let (offset_x, offset_y) = if plane == 0 {
+                    (2, 0)
+                } else {
+                    (1, 0)
+                };
+                assert_eq!(dst.planes()[plane].stride(), layout.row_bytes());
+                for y in 0..layout.height {
+                    for x in 0..layout.width {
+                        for channel in 0..layout.bytes_per_pixel {
+                            assert_eq!(sample(&dst, plane, x, y, channel), gradient(plane, x + offset_x, y + offset_y, channel));
+                        }
+                    }
+                }
+            }
+
+            // Clipped to the frame, then aligned, leaving odd sizes.
+            let clipped = src.crop(PixelRect::new(5, 3, 10, 10)).unwrap();
+            assert_eq!((clipped.width(), clipped.height()), (3, 3));
+            assert_eq!(clipped.planes()[1].width(), 2);
+            assert_eq!(sample(&clipped, 0, 0, 0, 0), gradient(0, 4, 2, 0));
+            assert_eq!(sample(&clipped, 1, 0, 0, 0), gradient(1, 2, 1, 0));
+        }
+
+        let bgra = frame(PixelFormat::BGRA, 7, 5, 4, gradient);
+        let dst = bgra.crop(PixelRect::new(3, 1, 3, 3)).unwrap();
+        assert_eq!((dst.width(), dst.height()), (3, 3));
+        assert_eq!(sample(&dst, 0, 0, 0, 2), gradient(0, 3, 1, 2));
+        assert_eq!(bgra.crop(PixelRect::new(7, 0, 2, 2)), Err(FrameError::InvalidDimensions));
+    }
+}
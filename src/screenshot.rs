@@ -0,0 +1,206 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, sync_channel, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use core_graphics::{
+    display::{get_displays_with_point, CGDisplay},
+    geometry::CGPoint,
+};
+use dispatch2::{Queue, QueueAttribute};
+use libc::size_t;
+use objc2::{rc::Id, runtime::ProtocolObject, ClassType};
+use objc2_foundation::{NSArray, NSError};
+
+use crate::{
+    frame::{FrameError, PixelFormat, VideoFrame},
+    frame_info::FrameInfo,
+    output::{CaptureError, StreamDelegate, StreamOutput},
+    shareable_content::{SCDisplay, SCWindow},
+    stream::{SCContentFilter, SCFrameStatus, SCStream, SCStreamConfiguration, SCStreamOutputType},
+};
+
+/// What a screenshot captures.
+#[derive(Clone, Debug)]
+pub enum ScreenshotSource {
+    /// A whole display, without excluding any window.
+    Display(Id<SCDisplay>),
+    /// A single window, independent of the display it is on.
+    Window(Id<SCWindow>),
+    /// A prepared content filter. Its size can't be queried, so
+    /// [`ScreenshotOptions::size`] must be set.
+    Filter(Id<SCContentFilter>),
+}
+
+impl ScreenshotSource {
+    fn content_filter(&self) -> Id<SCContentFilter> {
+        match self {
+            Self::Display(display) => SCContentFilter::init_with_display_exclude_windows(SCContentFilter::alloc(), display, &NSArray::new()),
+            Self::Window(window) => SCContentFilter::init_with_desktop_independent_window(SCContentFilter::alloc(), window),
+            Self::Filter(filter) => filter.clone(),
+        }
+    }
+
+    /// The size of the source in pixels, taking the backing scale factor of
+    /// its display into account.
+    fn native_size(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Display(display) => {
+                let mode = CGDisplay::new(display.display_id()).copy_display_mode();
+                match mode {
+                    Some(mode) if mode.pixel_width() > 0 && mode.pixel_height() > 0 => Some((mode.pixel_width(), mode.pixel_height())),
+                    _ => Some((display.width().max(0) as usize, display.height().max(0) as usize)),
+                }
+            }
+            Self::Window(window) => {
+                let frame = window.frame();
+                let center = CGPoint {
+                    x: frame.origin.x + frame.size.width / 2.0,
+                    y: frame.origin.y + frame.size.height / 2.0,
+                };
+                let scale = get_displays_with_point(center, 1)
+                    .and_then(|displays| displays.first().and_then(CGDisplay::copy_display_mode))
+                    .filter(|mode| mode.width() > 0)
+                    .map_or(1.0, |mode| mode.pixel_width() as f64 / mode.width() as f64);
+                let size = |points: f64| (points * scale).round().max(0.0) as usize;
+                Some((size(frame.size.width), size(frame.size.height)))
+            }
+            Self::Filter(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenshotOptions {
+    /// Output size in pixels. Defaults to the native pixel size of the
+    /// display or window.
+    pub size: Option<(usize, usize)>,
+    pub pixel_format: PixelFormat,
+    pub shows_cursor: bool,
+    /// How long to wait for the stream to start and for each frame.
+    pub timeout: Duration,
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        Self {
+            size: None,
+            pixel_format: PixelFormat::BGRA,
+            shows_cursor: false,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+enum Event {
+    Started(Option<Id<NSError>>),
+    Frame(Box<Result<VideoFrame, FrameError>>),
+    Stopped(Id<NSError>),
+}
+
+/// Captures a single frame: starts a stream on `source`, waits for the
+/// first `Complete` frame and stops the stream again.
+pub fn screenshot(source: &ScreenshotSource, options: &ScreenshotOptions) -> Result<VideoFrame, CaptureError> {
+    let mut frames = screenshot_burst(source, options, 1)?;
+    frames.pop().ok_or(CaptureError::Timeout)
+}
+
+/// Captures `count` consecutive `Complete` frames from one stream. Only
+/// frames with new content count, so a static screen may time out waiting
+/// for later frames.
+pub fn screenshot_burst(source: &ScreenshotSource, options: &ScreenshotOptions, count: usize) -> Result<Vec<VideoFrame>, CaptureError> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let (width, height) = options
+        .size
+        .or_else(|| source.native_size())
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or(FrameError::InvalidDimensions)?;
+    if !options.pixel_format.is_supported() {
+        return Err(FrameError::UnsupportedPixelFormat(options.pixel_format).into());
+    }
+
+    let configuration = SCStreamConfiguration::new();
+    configuration.set_width(width as size_t);
+    configuration.set_height(height as size_t);
+    configuration.set_pixel_format(options.pixel_format.0);
+    configuration.set_show_cursor(options.shows_cursor);
+
+    // The channel is unbounded so start and stop errors are never dropped;
+    // frames past the requested count are never sent instead. Senders
+    // aren't `Sync` before Rust 1.72, hence the mutexes.
+    let (sender, receiver) = channel();
+    let stop_sender = Mutex::new(sender.clone());
+    let delegate = StreamDelegate::new(move |_, error| {
+        if let Ok(sender) = stop_sender.lock() {
+            let _ = sender.send(Event::Stopped(error.retain()));
+        }
+    });
+    let frame_sender = Mutex::new(sender.clone());
+    let frames_sent = AtomicUsize::new(0);
+    let output = StreamOutput::new(move |_, sample_buffer, of_type| {
+        if of_type != SCStreamOutputType::Screen {
+            return;
+        }
+        let status = FrameInfo::from_sample_buffer(sample_buffer).and_then(|info| info.status);
+        if status != Some(SCFrameStatus::Complete) || frames_sent.load(Ordering::Relaxed) >= count {
+            return;
+        }
+        let frame = match VideoFrame::from_sample_buffer(sample_buffer) {
+            Err(FrameError::NoImageBuffer) => return,
+            frame => frame,
+        };
+        frames_sent.fetch_add(1, Ordering::Relaxed);
+        if let Ok(sender) = frame_sender.lock() {
+            let _ = sender.send(Event::Frame(Box::new(frame)));
+        }
+    });
+
+    let filter = source.content_filter();
+    let stream = SCStream::init_with_filter(SCStream::alloc(), &filter, &configuration, ProtocolObject::from_ref(&*delegate));
+    let queue = Queue::new("com.screen_capture_kit.screenshot", QueueAttribute::Serial);
+    let output = ProtocolObject::from_ref(&*output);
+    stream
+        .add_stream_output(output, SCStreamOutputType::Screen, &queue)
+        .map_err(CaptureError::Stream)?;
+    stream.start_capture(move |error| {
+        let _ = sender.send(Event::Started(error));
+    });
+
+    let result = receive_frames(&receiver, count, options.timeout);
+    let (stop_sender, stop_receiver) = sync_channel(1);
+    stream.stop_capture(move |error| {
+        let _ = stop_sender.try_send(error);
+    });
+    // The stream must be fully stopped before the output goes away.
+    let stopped = stop_receiver.recv_timeout(options.timeout);
+    let frames = result?;
+    match stopped {
+        Ok(Some(error)) => Err(CaptureError::Stream(error)),
+        Ok(None) => Ok(frames),
+        Err(_) => Err(CaptureError::Timeout),
+    }
+}
+
+fn receive_frames(receiver: &Receiver<Event>, count: usize, timeout: Duration) -> Result<Vec<VideoFrame>, CaptureError> {
+    let mut frames = Vec::with_capacity(count);
+    let mut deadline = Instant::now() + timeout;
+    while frames.len() < count {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(remaining) {
+            Ok(Event::Started(None)) => {}
+            Ok(Event::Started(Some(error))) | Ok(Event::Stopped(error)) => return Err(CaptureError::Stream(error)),
+            Ok(Event::Frame(frame)) => {
+                frames.push((*frame)?);
+                deadline = Instant::now() + timeout;
+            }
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return Err(CaptureError::Timeout),
+        }
+    }
+    Ok(frames)
+}
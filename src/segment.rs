@@ -0,0 +1,745 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    audio::AudioBuffer,
+    clock::cmtime_to_nanos,
+    frame::VideoFrame,
+    sink::{AudioSink, SinkError, VideoSink},
+};
+
+/// Finishes the sink of a segment, as a video or audio sink.
+type FinishSink<S> = fn(&mut S) -> Result<(), SinkError>;
+
+/// Suffix of segments still being written.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// When old segments are deleted. Limits are checked each time a segment is
+/// finalized, and the newest finalized segment is always kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Retention {
+    pub max_segments: Option<usize>,
+    /// Segments finalized longer ago than this are deleted.
+    pub max_age: Option<Duration>,
+    pub max_total_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SegmentOptions {
+    pub directory: PathBuf,
+    /// Segments are named `<prefix>-<index>.<extension>`, and the manifest
+    /// `<prefix>.json`.
+    pub prefix: String,
+    pub extension: String,
+    /// Starts a new segment once one spans this much presentation time.
+    pub max_duration: Option<Duration>,
+    /// Starts a new segment once one holds this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Starts a new segment at every multiple of this interval of wall clock
+    /// time, e.g. an hour for segments starting on the hour.
+    pub wall_clock_interval: Option<Duration>,
+    pub retention: Retention,
+    /// Whether the manifest indexing the finalized segments is written.
+    pub manifest: bool,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("."),
+            prefix: "segment".to_owned(),
+            extension: "y4m".to_owned(),
+            max_duration: Some(Duration::from_secs(600)),
+            max_bytes: None,
+            wall_clock_interval: None,
+            retention: Retention::default(),
+            manifest: true,
+        }
+    }
+}
+
+/// A finalized segment.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Segment {
+    pub index: u64,
+    pub path: PathBuf,
+    /// Presentation time of the first frame or sample, in nanoseconds.
+    pub start_nanos: Option<u64>,
+    /// Presentation time at which the last frame or sample ends.
+    pub end_nanos: Option<u64>,
+    pub started: SystemTime,
+    pub finished: SystemTime,
+    /// Video frames, or audio frames, written to the segment.
+    pub frames: u64,
+    pub bytes: u64,
+}
+
+impl Segment {
+    pub fn duration(&self) -> Option<Duration> {
+        match (self.start_nanos, self.end_nanos) {
+            (Some(start), Some(end)) => Some(Duration::from_nanos(end.saturating_sub(start))),
+            _ => None,
+        }
+    }
+}
+
+/// The file a segment is written to, handed to the sink of each segment.
+/// Writes are buffered.
+#[derive(Debug)]
+pub struct SegmentFile {
+    file: BufWriter<File>,
+    position: u64,
+    len: Arc<AtomicU64>,
+}
+
+impl SegmentFile {
+    /// Bytes written so far.
+    pub fn len(&self) -> u64 {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Write for SegmentFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.position += written as u64;
+        self.len.fetch_max(self.position, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for SegmentFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.file.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+struct OpenSegment<S> {
+    index: u64,
+    path: PathBuf,
+    partial: PathBuf,
+    sink: S,
+    /// Second handle to the file, to sync it once the sink is done.
+    file: File,
+    len: Arc<AtomicU64>,
+    start_nanos: Option<u64>,
+    end_nanos: Option<u64>,
+    started: SystemTime,
+    /// Wall clock interval the segment started in.
+    interval: Option<u128>,
+    frames: u64,
+    /// Whether the sink finished, so retrying a failed finalize doesn't
+    /// finish it twice.
+    sink_finished: bool,
+}
+
+impl<S> OpenSegment<S> {
+    /// Counts `frames` presented at `time` for `duration`.
+    fn record(&mut self, time: Option<u64>, duration: Option<u64>, frames: u64) {
+        if let Some(time) = time {
+            self.start_nanos.get_or_insert(time);
+            let end = time + duration.unwrap_or(0);
+            self.end_nanos = Some(self.end_nanos.map_or(end, |previous| previous.max(end)));
+        }
+        self.frames += frames;
+    }
+}
+
+/// Splits a long recording into segment files. Each segment gets its own
+/// sink, made by the factory from the segment's file, and is written under
+/// a `.partial` name until it's finished, synced and renamed into place, so
+/// complete files never appear half written.
+///
+/// Segment numbering continues after the segments already in the directory.
+/// Finalized segments from earlier recordings stay in the manifest and count
+/// towards retention, with their details read from the existing manifest, or
+/// from the file when it isn't listed there.
+pub struct SegmentRecorder<S, F> {
+    options: SegmentOptions,
+    factory: F,
+    finish_sink: FinishSink<S>,
+    current: Option<OpenSegment<S>>,
+    segments: Vec<Segment>,
+    next_index: u64,
+    deleted: u64,
+    finished: bool,
+}
+
+impl<S, F> SegmentRecorder<S, F>
+where
+    F: FnMut(SegmentFile) -> Result<S, SinkError>,
+{
+    fn new(options: SegmentOptions, factory: F, finish_sink: FinishSink<S>) -> Result<Self, SinkError> {
+        fs::create_dir_all(&options.directory)?;
+        let mut listed = read_manifest(&manifest_path(&options), &options.directory)?;
+        let mut segments = Vec::new();
+        let mut next_index = 0;
+        for entry in fs::read_dir(&options.directory)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            let index = match segment_index(&options, name) {
+                Some(index) => index,
+                None => continue,
+            };
+            next_index = next_index.max(index + 1);
+            if name.ends_with(PARTIAL_SUFFIX) {
+                continue;
+            }
+            let segment = match listed.remove(&index) {
+                Some(segment) => segment,
+                None => {
+                    let metadata = entry.metadata()?;
+                    let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+                    Segment {
+                        index,
+                        path: entry.path(),
+                        start_nanos: None,
+                        end_nanos: None,
+                        started: modified,
+                        finished: modified,
+                        frames: 0,
+                        bytes: metadata.len(),
+                    }
+                }
+            };
+            segments.push(segment);
+        }
+        segments.sort_by_key(|segment| segment.index);
+        Ok(Self {
+            options,
+            factory,
+            finish_sink,
+            current: None,
+            segments,
+            next_index,
+            deleted: 0,
+            finished: false,
+        })
+    }
+
+    /// Records video, creating the directory if needed. The first segment
+    /// is opened when the first frame is written.
+    pub fn video(options: SegmentOptions, factory: F) -> Result<Self, SinkError>
+    where
+        S: VideoSink,
+    {
+        Self::new(options, factory, <S as VideoSink>::finish)
+    }
+
+    /// Records audio, creating the directory if needed. The first segment
+    /// is opened when the first sample is written.
+    pub fn audio(options: SegmentOptions, factory: F) -> Result<Self, SinkError>
+    where
+        S: AudioSink,
+    {
+        Self::new(options, factory, <S as AudioSink>::finish)
+    }
+
+    pub fn options(&self) -> &SegmentOptions {
+        &self.options
+    }
+
+    /// Finalized segments that haven't been deleted, oldest first, including
+    /// those of earlier recordings in the directory.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Path the open segment will have once finalized.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|current| current.path.as_path())
+    }
+
+    /// Segments deleted by the retention policy.
+    pub fn segments_deleted(&self) -> u64 {
+        self.deleted
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        manifest_path(&self.options)
+    }
+
+    /// Finalizes the open segment, if any, so the next write starts a new
+    /// one. If finalizing fails the segment stays open, so rotating again
+    /// retries it.
+    pub fn rotate(&mut self) -> Result<Option<Segment>, SinkError> {
+        if self.current.is_none() {
+            return Ok(None);
+        }
+        self.finalize().map(Some)
+    }
+
+    /// The sink of the segment to write a frame or sample presented at
+    /// `time` to, rotating first if a limit was reached.
+    fn prepare(&mut self, time: Option<u64>) -> Result<&mut OpenSegment<S>, SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        let interval = self.wall_clock_interval();
+        let rotate = self.current.as_ref().is_some_and(|current| {
+            let options = &self.options;
+            let elapsed = time
+                .zip(current.start_nanos)
+                .map(|(time, start)| Duration::from_nanos(time.saturating_sub(start)));
+            current.frames > 0
+                && (options.max_duration.zip(elapsed).is_some_and(|(max, elapsed)| elapsed >= max)
+                    || options.max_bytes.is_some_and(|max| current.len.load(Ordering::Relaxed) >= max)
+                    || current.interval != interval)
+        });
+        if rotate {
+            self.rotate()?;
+        }
+        if self.current.is_none() {
+            let current = self.open(interval)?;
+            self.current = Some(current);
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+
+    fn wall_clock_interval(&self) -> Option<u128> {
+        let interval = self.options.wall_clock_interval?.as_nanos().max(1);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(now.as_nanos() / interval)
+    }
+
+    fn open(&mut self, interval: Option<u128>) -> Result<OpenSegment<S>, SinkError> {
+        let index = self.next_index;
+        self.next_index += 1;
+        let name = format!("{}-{:05}.{}", self.options.prefix, index, self.options.extension);
+        let path = self.options.directory.join(&name);
+        let partial = self.options.directory.join(name + PARTIAL_SUFFIX);
+        let file = File::create(&partial)?;
+        let len = Arc::new(AtomicU64::new(0));
+        let segment_file = SegmentFile {
+            file: BufWriter::new(file.try_clone()?),
+            position: 0,
+            len: len.clone(),
+        };
+        let sink = (self.factory)(segment_file)?;
+        Ok(OpenSegment {
+            index,
+            path,
+            partial,
+            sink,
+            file,
+            len,
+            start_nanos: None,
+            end_nanos: None,
+            started: SystemTime::now(),
+            interval,
+            frames: 0,
+            sink_finished: false,
+        })
+    }
+
+    /// Finishes, syncs and renames the open segment, which is only taken
+    /// once it's in place.
+    fn finalize(&mut self) -> Result<Segment, SinkError> {
+        let current = self.current.as_mut().expect("no open segment");
+        if !current.sink_finished {
+            (self.finish_sink)(&mut current.sink)?;
+            current.sink_finished = true;
+        }
+        current.file.sync_all()?;
+        fs::rename(&current.partial, &current.path)?;
+        let current = self.current.take().unwrap();
+        let segment = Segment {
+            index: current.index,
+            path: current.path,
+            start_nanos: current.start_nanos,
+            end_nanos: current.end_nanos,
+            started: current.started,
+            finished: SystemTime::now(),
+            frames: current.frames,
+            bytes: current.len.load(Ordering::Relaxed),
+        };
+        self.segments.push(segment.clone());
+        self.apply_retention()?;
+        if self.options.manifest {
+            self.write_manifest()?;
+        }
+        Ok(segment)
+    }
+
+    fn apply_retention(&mut self) -> Result<(), SinkError> {
+        let retention = self.options.retention;
+        let now = SystemTime::now();
+        while self.segments.len() > 1 {
+            let oldest = &self.segments[0];
+            let total: u64 = self.segments.iter().map(|segment| segment.bytes).sum();
+            let expired = retention.max_segments.is_some_and(|max| self.segments.len() > max)
+                || retention.max_total_bytes.is_some_and(|max| total > max)
+                || retention
+                    .max_age
+                    .is_some_and(|max| now.duration_since(oldest.finished).is_ok_and(|age| age > max));
+            if !expired {
+                break;
+            }
+            let segment = self.segments.remove(0);
+            match fs::remove_file(&segment.path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+                _ => self.deleted += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the manifest, replacing the old one atomically.
+    fn write_manifest(&self) -> Result<(), SinkError> {
+        let path = self.manifest_path();
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let mut file = BufWriter::new(File::create(&partial)?);
+        file.write_all(manifest(&self.segments).as_bytes())?;
+        file.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn finish_all(&mut self) -> Result<(), SinkError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.rotate()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+fn manifest_path(options: &SegmentOptions) -> PathBuf {
+    options.directory.join(format!("{}.json", options.prefix))
+}
+
+/// Index of a segment file name of this recorder, finalized or not.
+fn segment_index(options: &SegmentOptions, name: &str) -> Option<u64> {
+    let name = name.strip_suffix(PARTIAL_SUFFIX).unwrap_or(name);
+    let index = name
+        .strip_prefix(options.prefix.as_str())?
+        .strip_prefix('-')?
+        .strip_suffix(options.extension.as_str())?
+        .strip_suffix('.')?;
+    if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok()
+}
+
+/// Segments listed in the manifest of an earlier recording, by index.
+fn read_manifest(path: &Path, directory: &Path) -> io::Result<HashMap<u64, Segment>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(error) => return Err(error),
+    };
+    Ok(json
+        .lines()
+        .filter_map(|line| parse_manifest_entry(line, directory))
+        .map(|segment| (segment.index, segment))
+        .collect())
+}
+
+/// Parses one line of the manifest written by [`manifest`], which puts each
+/// segment on a line of its own.
+fn parse_manifest_entry(line: &str, directory: &Path) -> Option<Segment> {
+    let number = |key| json_field(line, key)?.parse::<u64>().ok();
+    let nanos = |key| match json_field(line, key)? {
+        "null" => Some(None),
+        value => value.parse().ok().map(Some),
+    };
+    let time = |key| UNIX_EPOCH.checked_add(Duration::from_millis(number(key)?));
+    Some(Segment {
+        index: number("index")?,
+        path: directory.join(parse_json_string(json_field(line, "file")?)?),
+        start_nanos: nanos("start")?,
+        end_nanos: nanos("end")?,
+        started: time("started")?,
+        finished: time("finished")?,
+        frames: number("frames")?,
+        bytes: number("bytes")?,
+    })
+}
+
+/// The raw value of `key` in a flat JSON object: a string with its quotes,
+/// or the text up to the next `,` or `}`.
+fn json_field<'a>(object: &'a str, key: &str) -> Option<&'a str> {
+    let start = object.find(&format!("\"{}\": ", key))? + key.len() + 4;
+    let value = &object[start..];
+    if !value.starts_with('"') {
+        return value.find([',', '}']).map(|end| &value[..end]);
+    }
+    let mut escaped = false;
+    for (i, c) in value.char_indices().skip(1) {
+        match c {
+            '"' if !escaped => return Some(&value[..=i]),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Undoes [`json_string`].
+fn parse_json_string(json: &str) -> Option<String> {
+    let mut chars = json.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut value = String::with_capacity(json.len());
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'u' => {
+                let code: String = chars.by_ref().take(4).collect();
+                value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            c => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_nanos(nanos: Option<u64>) -> String {
+    nanos.map_or_else(|| "null".to_owned(), |nanos| nanos.to_string())
+}
+
+/// JSON index of `segments`, with file names relative to the manifest,
+/// presentation times in nanoseconds and wall clock times in Unix
+/// milliseconds.
+fn manifest(segments: &[Segment]) -> String {
+    let mut json = String::from("{\n  \"segments\": [");
+    for (i, segment) in segments.iter().enumerate() {
+        let file = segment.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        write!(
+            json,
+            "{}\n    {{\"index\": {}, \"file\": {}, \"start\": {}, \"end\": {}, \"started\": {}, \"finished\": {}, \"frames\": {}, \"bytes\": {}}}",
+            if i == 0 {
+                ""
+            } else {
+                ","
+            },
+            segment.index,
+            json_string(&file),
+            json_nanos(segment.start_nanos),
+            json_nanos(segment.end_nanos),
+            unix_millis(segment.started),
+            unix_millis(segment.finished),
+            segment.frames,
+            segment.bytes,
+        )
+        .unwrap();
+    }
+    json.push_str("\n  ]\n}\n");
+    json
+}
+
+impl<S, F> VideoSink for SegmentRecorder<S, F>
+where
+    S: VideoSink,
+    F: FnMut(SegmentFile) -> Result<S, SinkError>,
+{
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        let time = cmtime_to_nanos(frame.presentation_time);
+        let current = self.prepare(time)?;
+        current.sink.write_frame(frame)?;
+        current.record(time, cmtime_to_nanos(frame.duration), 1);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.finish_all()
+    }
+}
+
+impl<S, F> AudioSink for SegmentRecorder<S, F>
+where
+    S: AudioSink,
+    F: FnMut(SegmentFile) -> Result<S, SinkError>,
+{
+    fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError> {
+        let time = cmtime_to_nanos(audio.presentation_time);
+        let current = self.prepare(time)?;
+        current.sink.write_audio(audio)?;
+        let duration = (audio.frames() as f64 * 1e9 / audio.sample_rate()) as u64;
+        current.record(time, Some(duration), audio.frames() as u64);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.finish_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, frame::PixelFormat};
+
+    struct TestSink {
+        file: SegmentFile,
+        failures: Rc<Cell<u32>>,
+    }
+
+    impl VideoSink for TestSink {
+        fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+            self.file.write_all(&frame.planes()[0].data()[..4])?;
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), SinkError> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(SinkError::Finished);
+            }
+            self.file.flush()?;
+            Ok(())
+        }
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("segment-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn recorder(
+        options: SegmentOptions,
+        failures: &Rc<Cell<u32>>,
+    ) -> SegmentRecorder<TestSink, impl FnMut(SegmentFile) -> Result<TestSink, SinkError>> {
+        let failures = failures.clone();
+        SegmentRecorder::video(options, move |file| {
+            Ok(TestSink {
+                file,
+                failures: failures.clone(),
+            })
+        })
+        .unwrap()
+    }
+
+    fn frame(seconds: u64) -> VideoFrame {
+        let mut frame = VideoFrame::zeroed(2, 2, PixelFormat::BGRA).unwrap();
+        frame.presentation_time = nanos_to_cmtime(seconds * 1_000_000_000, 1000);
+        frame
+    }
+
+    fn indices(segments: &[Segment]) -> Vec<u64> {
+        segments.iter().map(|segment| segment.index).collect()
+    }
+
+    #[test]
+    fn manifest_entries_round_trip() {
+        let directory = Path::new("/recordings");
+        let segment = Segment {
+            index: 12,
+            path: directory.join("odd \"name\\\u{1}.y4m"),
+            start_nanos: Some(5),
+            end_nanos: None,
+            started: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            finished: UNIX_EPOCH + Duration::from_millis(1_700_000_060_456),
+            frames: 3,
+            bytes: 4096,
+        };
+        let json = manifest(&[segment.clone(), segment.clone()]);
+        let parsed: Vec<_> = json.lines().filter_map(|line| parse_manifest_entry(line, directory)).collect();
+        assert_eq!(parsed, [segment.clone(), segment]);
+        assert_eq!(parse_manifest_entry("{\"index\": 1}", directory), None);
+    }
+
+    #[test]
+    fn failed_finalize_keeps_the_segment() {
+        let options = SegmentOptions {
+            directory: directory("finalize"),
+            ..Default::default()
+        };
+        let failures = Rc::new(Cell::new(1));
+        let mut recorder = recorder(options, &failures);
+        recorder.write_frame(&frame(0)).unwrap();
+        let path = recorder.current_path().unwrap().to_owned();
+
+        assert!(recorder.finish().is_err());
+        assert_eq!(recorder.current_path(), Some(path.as_path()));
+        assert!(!path.exists());
+
+        recorder.finish().unwrap();
+        assert!(recorder.current_path().is_none());
+        assert_eq!(indices(recorder.segments()), [0]);
+        assert_eq!(fs::read(&path).unwrap(), [0; 4]);
+        fs::remove_dir_all(&recorder.options().directory).unwrap();
+    }
+
+    #[test]
+    fn earlier_recordings_count_towards_retention() {
+        let mut options = SegmentOptions {
+            directory: directory("retention"),
+            ..Default::default()
+        };
+        let failures = Rc::new(Cell::new(0));
+        let mut first = recorder(options.clone(), &failures);
+        for seconds in 0..3 {
+            first.write_frame(&frame(seconds)).unwrap();
+            first.rotate().unwrap();
+        }
+        first.finish().unwrap();
+        let earlier = first.segments().to_vec();
+        // A segment the manifest doesn't know about.
+        fs::write(options.directory.join("segment-00007.y4m"), [0; 10]).unwrap();
+
+        options.retention.max_segments = Some(3);
+        let mut second = recorder(options.clone(), &failures);
+        assert_eq!(indices(second.segments()), [0, 1, 2, 7]);
+        // The manifest keeps wall clock times to the millisecond.
+        assert_eq!(manifest(&second.segments()[..3]), manifest(&earlier));
+        assert_eq!((second.segments()[3].bytes, second.segments()[3].frames), (10, 0));
+
+        second.write_frame(&frame(10)).unwrap();
+        second.finish().unwrap();
+        assert_eq!(indices(second.segments()), [2, 7, 8]);
+        assert_eq!(second.segments_deleted(), 2);
+        assert!(!earlier[0].path.exists() && !earlier[1].path.exists());
+        assert_eq!(fs::read_to_string(second.manifest_path()).unwrap(), manifest(second.segments()));
+        fs::remove_dir_all(&options.directory).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+use std::{error::Error, fmt, io, time::Duration};
+
+use crate::{
+    audio::{AudioBuffer, AudioError},
+    clock::cmtime_to_nanos,
+    frame::{FrameError, VideoFrame},
+    image::ImageError,
+    platform::CMTime,
+};
+
+#[derive(Debug)]
+pub enum SinkError {
+    Frame(FrameError),
+    Audio(AudioError),
+    Image(ImageError),
+    Io(io::Error),
+    /// The frame or audio doesn't match the format the sink was started
+    /// with, e.g. the frame size or sample rate changed mid stream.
+    FormatChanged,
+    /// The sink was already finished.
+    Finished,
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frame(error) => write!(f, "{}", error),
+            Self::Audio(error) => write!(f, "{}", error),
+            Self::Image(error) => write!(f, "{}", error),
+            Self::Io(error) => write!(f, "{}", error),
+            Self::FormatChanged => write!(f, "format changed mid stream"),
+            Self::Finished => write!(f, "sink already finished"),
+        }
+    }
+}
+
+impl Error for SinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Frame(error) => Some(error),
+            Self::Audio(error) => Some(error),
+            Self::Image(error) => Some(error),
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FrameError> for SinkError {
+    fn from(error: FrameError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+impl From<AudioError> for SinkError {
+    fn from(error: AudioError) -> Self {
+        Self::Audio(error)
+    }
+}
+
+impl From<ImageError> for SinkError {
+    fn from(error: ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl From<io::Error> for SinkError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// A destination for captured video frames.
+pub trait VideoSink {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError>;
+
+    /// Flushes buffered output and finalizes the stream. Writing afterwards
+    /// fails with [`SinkError::Finished`].
+    fn finish(&mut self) -> Result<(), SinkError>;
+}
+
+impl<S: VideoSink + ?Sized> VideoSink for Box<S> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        (**self).write_frame(frame)
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        (**self).finish()
+    }
+}
+
+/// A destination for captured audio.
+pub trait AudioSink {
+    fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError>;
+
+    /// Flushes buffered output and finalizes the stream. Writing afterwards
+    /// fails with [`SinkError::Finished`].
+    fn finish(&mut self) -> Result<(), SinkError>;
+}
+
+impl<S: AudioSink + ?Sized> AudioSink for Box<S> {
+    fn write_audio(&mut self, audio: &AudioBuffer) -> Result<(), SinkError> {
+        (**self).write_audio(audio)
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        (**self).finish()
+    }
+}
+
+/// How frames are timed in the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateControl {
+    /// Every frame is written once, whatever its timestamp.
+    Passthrough,
+    /// Frames are placed on a constant rate grid by presentation time.
+    Constant {
+        /// Repeats a frame to fill the slots skipped before the next one,
+        /// e.g. while the screen is idle.
+        duplicate: bool,
+        /// Drops frames arriving for a slot already filled.
+        drop: bool,
+    },
+}
+
+impl Default for RateControl {
+    fn default() -> Self {
+        Self::Constant { duplicate: true, drop: true }
+    }
+}
+
+/// Longest gap [`FrameRateConverter`] fills with duplicates by default.
+pub const DEFAULT_MAX_GAP: Duration = Duration::from_secs(5);
+
+/// Maps variable rate frames onto a constant frame rate.
+#[derive(Clone, Debug)]
+pub struct FrameRateConverter {
+    frame_rate: (u32, u32),
+    control: RateControl,
+    max_gap: Option<Duration>,
+    start: Option<u64>,
+    next_slot: u64,
+    /// Slots left out of the grid by gaps longer than `max_gap`.
+    skipped_slots: u64,
+}
+
+impl FrameRateConverter {
+    /// `frame_rate` is a fraction of frames per second, as numerator and
+    /// denominator.
+    pub fn new(frame_rate: (u32, u32), control: RateControl) -> Self {
+        Self {
+            frame_rate: (frame_rate.0.max(1), frame_rate.1.max(1)),
+            control,
+            max_gap: Some(DEFAULT_MAX_GAP),
+            start: None,
+            next_slot: 0,
+            skipped_slots: 0,
+        }
+    }
+
+    pub fn max_gap(&self) -> Option<Duration> {
+        self.max_gap
+    }
+
+    /// Limits how long a gap between frames is filled with duplicates. The
+    /// rest of a longer gap is left out of the output, so an idle screen
+    /// doesn't produce an unbounded number of frames. `None` fills every
+    /// gap, keeping the output as long as the capture.
+    pub fn set_max_gap(&mut self, max_gap: Option<Duration>) {
+        self.max_gap = max_gap;
+    }
+
+    pub fn frame_rate(&self) -> (u32, u32) {
+        self.frame_rate
+    }
+
+    /// Index of the next slot on the output grid.
+    pub fn next_slot(&self) -> u64 {
+        self.next_slot
+    }
+
+    /// How many times a frame presented at `time` must be written: zero to
+    /// drop it, more than one to fill skipped slots. Frames without a valid
+    /// time are written once.
+    pub fn push(&mut self, time: CMTime) -> u64 {
+        let (duplicate, drop) = match self.control {
+            RateControl::Passthrough => {
+                self.next_slot += 1;
+                return 1;
+            }
+            RateControl::Constant { duplicate, drop } => (duplicate, drop),
+        };
+        let nanos = match cmtime_to_nanos(time) {
+            Some(nanos) => nanos,
+            None => {
+                self.next_slot += 1;
+                return 1;
+            }
+        };
+        let start = *self.start.get_or_insert(nanos);
+        let (numerator, denominator) = (self.frame_rate.0 as u128, self.frame_rate.1 as u128);
+        let elapsed = nanos.saturating_sub(start) as u128;
+        let slot = ((elapsed * numerator + denominator * 500_000_000) / (denominator * 1_000_000_000)) as u64;
+        let slot = slot.saturating_sub(self.skipped_slots);
+        let count = if slot < self.next_slot {
+            !drop as u64
+        } else if duplicate {
+            let count = slot - self.next_slot + 1;
+            let max_count = self
+                .max_gap
+                .map(|gap| ((gap.as_nanos() * numerator / (denominator * 1_000_000_000)) as u64).max(1));
+            match max_count {
+                Some(max_count) if count > max_count => {
+                    self.skipped_slots += count - max_count;
+                    max_count
+                }
+                _ => count,
+            }
+        } else {
+            1
+        };
+        // Without duplication the grid follows the frames, so a late frame
+        // isn't dropped because of earlier gaps.
+        self.next_slot = if duplicate || count == 0 {
+            self.next_slot + count
+        } else {
+            slot.max(self.next_slot) + 1
+        };
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::nanos_to_cmtime, platform::CMTime};
+
+    fn counts(converter: &mut FrameRateConverter, millis: &[u64]) -> Vec<u64> {
+        millis
+            .iter()
+            .map(|millis| converter.push(nanos_to_cmtime(millis * 1_000_000, 1000)))
+            .collect()
+    }
+
+    const DUPLICATE_AND_DROP: RateControl = RateControl::Constant { duplicate: true, drop: true };
+
+    #[test]
+    fn jittery_frames_round_to_the_nearest_slot() {
+        // 30 fps slots are 33.3 ms apart; frames within half a slot keep
+        // one slot each.
+        let mut converter = FrameRateConverter::new((30, 1), DUPLICATE_AND_DROP);
+        assert_eq!(counts(&mut converter, &[1000, 1020, 1070, 1095, 1140, 1160]), [1, 1, 1, 1, 1, 1]);
+        assert_eq!(converter.next_slot(), 6);
+
+        // NTSC rates keep their fraction: slot 1000 starts at 33.35 s.
+        let mut converter = FrameRateConverter::new((30000, 1001), DUPLICATE_AND_DROP);
+        converter.set_max_gap(None);
+        assert_eq!(counts(&mut converter, &[0, 33_350, 33_400]), [1, 1000, 1]);
+    }
+
+    #[test]
+    fn gaps_are_filled_up_to_the_limit() {
+        let mut converter = FrameRateConverter::new((10, 1), DUPLICATE_AND_DROP);
+        assert_eq!(counts(&mut converter, &[0, 100, 500, 600]), [1, 1, 4, 1]);
+
+        // An hour of idle screen is cut to the default 5 s.
+        assert_eq!(counts(&mut converter, &[3_600_600, 3_600_700]), [50, 1]);
+        assert_eq!(converter.next_slot(), 58);
+        converter.set_max_gap(None);
+        assert_eq!(counts(&mut converter, &[3_601_700]), [10]);
+
+        // Without duplication every frame is written once.
+        let mut converter = FrameRateConverter::new(
+            (10, 1),
+            RateControl::Constant {
+                duplicate: false,
+                drop: true,
+            },
+        );
+        assert_eq!(counts(&mut converter, &[0, 500, 520, 600]), [1, 1, 0, 1]);
+        assert_eq!(converter.next_slot(), 7);
+    }
+
+    #[test]
+    fn late_frames() {
+        // Frames for a filled slot, or before the first one, are dropped.
+        let mut converter = FrameRateConverter::new((10, 1), DUPLICATE_AND_DROP);
+        assert_eq!(counts(&mut converter, &[1000, 1020, 900, 1100, 1090]), [1, 0, 0, 1, 0]);
+
+        // Or kept, pushing the grid along.
+        let mut converter = FrameRateConverter::new(
+            (10, 1),
+            RateControl::Constant {
+                duplicate: true,
+                drop: false,
+            },
+        );
+        assert_eq!(counts(&mut converter, &[1000, 1020, 1040, 1200]), [1, 1, 1, 1]);
+        assert_eq!(counts(&mut converter, &[1300, 1600]), [1, 2]);
+
+        let mut converter = FrameRateConverter::new((10, 1), RateControl::Passthrough);
+        assert_eq!(counts(&mut converter, &[1000, 1000, 0, 5000]), [1, 1, 1, 1]);
+        assert_eq!(converter.next_slot(), 4);
+
+        // Frames without a valid time are written once, taking a slot.
+        let mut converter = FrameRateConverter::new((10, 1), DUPLICATE_AND_DROP);
+        assert_eq!(converter.push(CMTime::default()), 1);
+        assert_eq!(counts(&mut converter, &[0, 100, 200]), [0, 1, 1]);
+        assert_eq!(converter.next_slot(), 3);
+    }
+}
@@ -1,11 +1,20 @@
+#[cfg(target_os = "macos")]
 use std::ptr::null_mut;
+use std::{convert::TryFrom, fmt};
 
+#[cfg(target_os = "macos")]
 use block2::RcBlock;
+#[cfg(target_os = "macos")]
 use core_foundation::{base::TCFType, string::CFStringRef};
+#[cfg(target_os = "macos")]
 use core_graphics::color::CGColor;
+#[cfg(target_os = "macos")]
 use core_media::{sample_buffer::CMSampleBufferRef, time::CMTime, OSType};
+#[cfg(target_os = "macos")]
 use dispatch2::Queue;
+#[cfg(target_os = "macos")]
 use libc::size_t;
+#[cfg(target_os = "macos")]
 use objc2::{
     encode::{Encode, Encoding},
     extern_class, msg_send, msg_send_id,
@@ -14,12 +23,15 @@ use objc2::{
     runtime::ProtocolObject,
     ClassType, ProtocolType,
 };
-use objc2_foundation::{CGRect, NSArray, NSError, NSInteger, NSObject, NSObjectProtocol, NSString};
+#[cfg(target_os = "macos")]
+use objc2_foundation::{CGRect, NSArray, NSError, NSObject, NSObjectProtocol, NSString};
 
+#[cfg(target_os = "macos")]
 use crate::{
     encode,
     shareable_content::{SCDisplay, SCRunningApplication, SCWindow},
 };
+use crate::{error::UnknownValueError, platform::NSInteger};
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -30,12 +42,46 @@ impl SCStreamOutputType {
     pub const Screen: Self = Self(0);
     #[doc(alias = "SCStreamOutputTypeAudio")]
     pub const Audio: Self = Self(1);
+
+    pub fn is_known(&self) -> bool {
+        matches!(*self, Self::Screen | Self::Audio)
+    }
 }
 
+#[cfg(target_os = "macos")]
 unsafe impl Encode for SCStreamOutputType {
     const ENCODING: Encoding = Encoding::Int;
 }
 
+impl TryFrom<NSInteger> for SCStreamOutputType {
+    type Error = UnknownValueError;
+
+    fn try_from(value: NSInteger) -> Result<Self, Self::Error> {
+        let output_type = Self(value);
+        if output_type.is_known() {
+            Ok(output_type)
+        } else {
+            Err(UnknownValueError(value))
+        }
+    }
+}
+
+impl From<SCStreamOutputType> for NSInteger {
+    fn from(output_type: SCStreamOutputType) -> Self {
+        output_type.0
+    }
+}
+
+impl fmt::Display for SCStreamOutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Screen => f.write_str("Screen"),
+            Self::Audio => f.write_str("Audio"),
+            Self(value) => write!(f, "Unknown({})", value),
+        }
+    }
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SCFrameStatus(pub NSInteger);
@@ -53,8 +99,63 @@ impl SCFrameStatus {
     pub const Started: Self = Self(4);
     #[doc(alias = "SCFrameStatusStopped")]
     pub const Stopped: Self = Self(5);
+
+    pub fn is_known(&self) -> bool {
+        (Self::Complete.0..=Self::Stopped.0).contains(&self.0)
+    }
+
+    /// Whether the sample carries a freshly rendered image.
+    pub fn has_new_content(&self) -> bool {
+        matches!(*self, Self::Complete | Self::Started)
+    }
+
+    /// Whether the most recent image is still valid for display. Idle frames
+    /// carry no image but leave the previous one on screen, while blank,
+    /// suspended and stopped frames mean it should be cleared.
+    pub fn should_render(&self) -> bool {
+        matches!(*self, Self::Complete | Self::Started | Self::Idle)
+    }
+
+    /// Whether no further frames will follow this one.
+    pub fn is_terminal(&self) -> bool {
+        *self == Self::Stopped
+    }
+}
+
+impl TryFrom<NSInteger> for SCFrameStatus {
+    type Error = UnknownValueError;
+
+    fn try_from(value: NSInteger) -> Result<Self, Self::Error> {
+        let status = Self(value);
+        if status.is_known() {
+            Ok(status)
+        } else {
+            Err(UnknownValueError(value))
+        }
+    }
+}
+
+impl From<SCFrameStatus> for NSInteger {
+    fn from(status: SCFrameStatus) -> Self {
+        status.0
+    }
 }
 
+impl fmt::Display for SCFrameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Complete => f.write_str("Complete"),
+            Self::Idle => f.write_str("Idle"),
+            Self::Blank => f.write_str("Blank"),
+            Self::Suspended => f.write_str("Suspended"),
+            Self::Started => f.write_str("Started"),
+            Self::Stopped => f.write_str("Stopped"),
+            Self(value) => write!(f, "Unknown({})", value),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
 extern_class!(
     #[derive(Debug, PartialEq, Eq, Hash)]
     pub struct SCContentFilter;
@@ -65,8 +166,10 @@ extern_class!(
     }
 );
 
+#[cfg(target_os = "macos")]
 unsafe impl NSObjectProtocol for SCContentFilter {}
 
+#[cfg(target_os = "macos")]
 impl SCContentFilter {
     pub fn new() -> Id<Self> {
         unsafe { msg_send_id![SCContentFilter::class(), new] }
@@ -103,6 +206,7 @@ impl SCContentFilter {
     }
 }
 
+#[cfg(target_os = "macos")]
 extern_class!(
     #[derive(Debug, PartialEq, Eq, Hash)]
     pub struct SCStreamConfiguration;
@@ -113,8 +217,10 @@ extern_class!(
     }
 );
 
+#[cfg(target_os = "macos")]
 unsafe impl NSObjectProtocol for SCStreamConfiguration {}
 
+#[cfg(target_os = "macos")]
 impl SCStreamConfiguration {
     pub fn new() -> Id<Self> {
         unsafe { msg_send_id![SCStreamConfiguration::class(), new] }
@@ -255,8 +361,10 @@ impl SCStreamConfiguration {
     }
 }
 
+#[cfg(target_os = "macos")]
 pub type SCStreamFrameInfo = NSString;
 
+#[cfg(target_os = "macos")]
 extern "C" {
     pub static SCStreamFrameInfoStatus: &'static NSString;
     pub static SCStreamFrameInfoDisplayTime: &'static NSString;
@@ -267,6 +375,7 @@ extern "C" {
     pub static SCStreamFrameInfoScreenRect: &'static NSString;
 }
 
+#[cfg(target_os = "macos")]
 extern_class!(
     #[derive(Debug, PartialEq, Eq, Hash)]
     pub struct SCStream;
@@ -277,10 +386,13 @@ extern_class!(
     }
 );
 
+#[cfg(target_os = "macos")]
 unsafe impl NSObjectProtocol for SCStream {}
 
+#[cfg(target_os = "macos")]
 type CompletionHandler = RcBlock<dyn Fn(*mut NSError)>;
 
+#[cfg(target_os = "macos")]
 impl SCStream {
     pub fn new() -> Id<Self> {
         unsafe { msg_send_id![SCStream::class(), new] }
@@ -368,6 +480,7 @@ impl SCStream {
     }
 }
 
+#[cfg(target_os = "macos")]
 extern_protocol!(
     pub unsafe trait SCStreamOutput: NSObjectProtocol {
         #[method(stream:didOutputSampleBuffer:ofType:)]
@@ -378,6 +491,7 @@ extern_protocol!(
     unsafe impl ProtocolType for dyn SCStreamOutput {}
 );
 
+#[cfg(target_os = "macos")]
 extern_protocol!(
     pub unsafe trait SCStreamDelegate: NSObjectProtocol {
         #[method(stream:didStopWithError:)]
@@ -387,3 +501,70 @@ extern_protocol!(
 
     unsafe impl ProtocolType for dyn SCStreamDelegate {}
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_type_conversions() {
+        let cases = [
+            (-1, None),
+            (0, Some(SCStreamOutputType::Screen)),
+            (1, Some(SCStreamOutputType::Audio)),
+            (2, None),
+            (NSInteger::MAX, None),
+        ];
+        for &(value, expected) in &cases {
+            assert_eq!(SCStreamOutputType::try_from(value).ok(), expected, "{}", value);
+            assert_eq!(SCStreamOutputType(value).is_known(), expected.is_some(), "{}", value);
+            assert_eq!(NSInteger::from(SCStreamOutputType(value)), value);
+            if expected.is_none() {
+                assert_eq!(SCStreamOutputType::try_from(value), Err(UnknownValueError(value)));
+            }
+        }
+        assert_eq!(SCStreamOutputType::Audio.to_string(), "Audio");
+        assert_eq!(SCStreamOutputType(7).to_string(), "Unknown(7)");
+    }
+
+    #[test]
+    fn frame_status_conversions() {
+        let cases = [
+            (-1, None),
+            (0, Some(SCFrameStatus::Complete)),
+            (1, Some(SCFrameStatus::Idle)),
+            (2, Some(SCFrameStatus::Blank)),
+            (3, Some(SCFrameStatus::Suspended)),
+            (4, Some(SCFrameStatus::Started)),
+            (5, Some(SCFrameStatus::Stopped)),
+            (6, None),
+            (NSInteger::MIN, None),
+        ];
+        for &(value, expected) in &cases {
+            assert_eq!(SCFrameStatus::try_from(value).ok(), expected, "{}", value);
+            assert_eq!(SCFrameStatus(value).is_known(), expected.is_some(), "{}", value);
+            assert_eq!(NSInteger::from(SCFrameStatus(value)), value);
+        }
+        assert_eq!(SCFrameStatus::try_from(6), Err(UnknownValueError(6)));
+    }
+
+    #[test]
+    fn frame_status_predicates() {
+        // (status, has_new_content, should_render, is_terminal)
+        let cases = [
+            (SCFrameStatus::Complete, true, true, false),
+            (SCFrameStatus::Idle, false, true, false),
+            (SCFrameStatus::Blank, false, false, false),
+            (SCFrameStatus::Suspended, false, false, false),
+            (SCFrameStatus::Started, true, true, false),
+            (SCFrameStatus::Stopped, false, false, true),
+            (SCFrameStatus(6), false, false, false),
+            (SCFrameStatus(-1), false, false, false),
+        ];
+        for &(status, has_new_content, should_render, is_terminal) in &cases {
+            assert_eq!(status.has_new_content(), has_new_content, "{}", status);
+            assert_eq!(status.should_render(), should_render, "{}", status);
+            assert_eq!(status.is_terminal(), is_terminal, "{}", status);
+        }
+    }
+}
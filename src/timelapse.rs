@@ -0,0 +1,404 @@
+use std::time::Duration;
+
+use crate::{
+    change::ChangeDetector,
+    clock::{cmtime_to_nanos, nanos_to_cmtime},
+    frame::VideoFrame,
+    sink::{SinkError, VideoSink},
+};
+#[cfg(target_os = "macos")]
+use crate::{platform::CMTimeScale, stream::SCStreamConfiguration};
+
+/// Timescale of the minimum frame interval set on stream configurations.
+#[cfg(target_os = "macos")]
+const NANOS_TIMESCALE: CMTimeScale = 1_000_000_000;
+
+/// Which of the frames captured within an interval is kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FrameSelection {
+    /// The last frame of the interval. The stream only needs to deliver one
+    /// frame per interval.
+    #[default]
+    Latest,
+    /// The frame differing most from the previously kept one, so short lived
+    /// changes aren't missed. The stream delivers
+    /// [`samples_per_interval`](TimelapseOptions::samples_per_interval)
+    /// frames per interval to pick from.
+    MostChanged,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimelapseOptions {
+    /// Captured time each kept frame stands for.
+    pub interval: Duration,
+    pub selection: FrameSelection,
+    /// Frames delivered per interval with [`FrameSelection::MostChanged`].
+    pub samples_per_interval: u32,
+    /// Frame rate kept frames are retimed to, one after the other, e.g. 30
+    /// for video sinks. `None`, the default, keeps their capture timestamps,
+    /// which an [`ImageSequenceWriter`](crate::image::ImageSequenceWriter)
+    /// names its files after.
+    pub playback_frame_rate: Option<f64>,
+}
+
+impl Default for TimelapseOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            selection: FrameSelection::default(),
+            samples_per_interval: 10,
+            playback_frame_rate: None,
+        }
+    }
+}
+
+impl TimelapseOptions {
+    /// Interval between the frames the stream needs to deliver.
+    pub fn capture_interval(&self) -> Duration {
+        match self.selection {
+            FrameSelection::Latest => self.interval,
+            FrameSelection::MostChanged => self.interval / self.samples_per_interval.max(1),
+        }
+    }
+
+    /// Sets the minimum frame interval of a stream configuration, so frames
+    /// aren't captured more often than selection needs.
+    #[cfg(target_os = "macos")]
+    pub fn apply_to(&self, configuration: &SCStreamConfiguration) {
+        let interval = self.capture_interval().as_nanos().min(u64::MAX as u128) as u64;
+        configuration.set_minimum_frame_interval(nanos_to_cmtime(interval, NANOS_TIMESCALE));
+    }
+}
+
+#[derive(Debug)]
+struct Candidate {
+    frame: VideoFrame,
+    interval: u64,
+    changed_fraction: f64,
+    /// Detector holding the candidate as its previous frame.
+    detector: Option<ChangeDetector>,
+}
+
+/// Reduces a capture to one frame per interval and passes the kept frames to
+/// another sink, e.g. an encoder or an
+/// [`ImageSequenceWriter`](crate::image::ImageSequenceWriter). Frames
+/// without new content, such as idle and blank ones, are skipped. A frame is
+/// only kept once a later interval starts or the writer is finished.
+#[derive(Debug)]
+pub struct TimelapseWriter<S> {
+    sink: S,
+    options: TimelapseOptions,
+    /// Presentation time intervals are counted from.
+    start: Option<u64>,
+    last_time: u64,
+    candidate: Option<Candidate>,
+    /// Detector holding the last kept frame.
+    detector: ChangeDetector,
+    frames_written: u64,
+    frames_skipped: u64,
+    finished: bool,
+}
+
+impl<S: VideoSink> TimelapseWriter<S> {
+    pub fn new(sink: S, options: TimelapseOptions) -> Self {
+        Self {
+            sink,
+            options,
+            start: None,
+            last_time: 0,
+            candidate: None,
+            detector: ChangeDetector::default(),
+            frames_written: 0,
+            frames_skipped: 0,
+            finished: false,
+        }
+    }
+
+    pub fn options(&self) -> &TimelapseOptions {
+        &self.options
+    }
+
+    /// Frames passed to the sink.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    /// Frames skipped for carrying no new content.
+    pub fn frames_skipped(&self) -> u64 {
+        self.frames_skipped
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    fn write_candidate(&mut self) -> Result<(), SinkError> {
+        let candidate = match self.candidate.take() {
+            Some(candidate) => candidate,
+            None => return Ok(()),
+        };
+        let mut frame = candidate.frame;
+        if let Some(frame_rate) = self.options.playback_frame_rate.filter(|frame_rate| *frame_rate > 0.0) {
+            let timescale = frame.presentation_time.timescale.max(1);
+            frame.presentation_time = nanos_to_cmtime((self.frames_written as f64 * 1e9 / frame_rate) as u64, timescale);
+            frame.duration = nanos_to_cmtime((1e9 / frame_rate) as u64, timescale);
+        }
+        if let Some(detector) = candidate.detector {
+            self.detector = detector;
+        }
+        self.sink.write_frame(&frame)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+}
+
+impl<S: VideoSink> VideoSink for TimelapseWriter<S> {
+    fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+        if self.finished {
+            return Err(SinkError::Finished);
+        }
+        if frame.info.status.is_some_and(|status| !status.has_new_content()) {
+            self.frames_skipped += 1;
+            return Ok(());
+        }
+        let time = cmtime_to_nanos(frame.presentation_time).unwrap_or(self.last_time);
+        self.last_time = time;
+        let start = *self.start.get_or_insert(time);
+        let interval = time.saturating_sub(start) / (self.options.interval.as_nanos() as u64).max(1);
+        if self.candidate.as_ref().is_some_and(|candidate| candidate.interval != interval) {
+            self.write_candidate()?;
+        }
+        let (changed_fraction, detector) = match self.options.selection {
+            FrameSelection::Latest => (0.0, None),
+            FrameSelection::MostChanged => {
+                let mut detector = self.detector.clone();
+                let report = detector.process(frame)?;
+                // Both the kept frame and the candidate stay the previous
+                // frame of their detectors past this one.
+                self.detector.skip(frame);
+                (report.changed_fraction, Some(detector))
+            }
+        };
+        // Ties go to the later frame, showing the most recent state.
+        match &mut self.candidate {
+            Some(candidate) if changed_fraction < candidate.changed_fraction => {
+                if let Some(detector) = &mut candidate.detector {
+                    detector.skip(frame);
+                }
+            }
+            _ => {
+                self.candidate = Some(Candidate {
+                    frame: frame.clone(),
+                    interval,
+                    changed_fraction,
+                    detector,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.write_candidate()?;
+        self.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        frame::PixelFormat,
+        image::{encode, ImageFormat, ImageOptions, ImageSequenceWriter},
+        platform::{CGPoint, CGRect, CGSize},
+        stream::SCFrameStatus,
+    };
+
+    type Rect = (usize, usize, usize, usize);
+
+    /// A black 128x96 frame at `millis` with white `blocks`, and dirty
+    /// rects if given.
+    fn screen(millis: u64, blocks: &[Rect], dirty_rects: Option<&[Rect]>) -> VideoFrame {
+        let mut frame = VideoFrame::zeroed(128, 96, PixelFormat::BGRA).unwrap();
+        let mut plane = frame.plane_mut(0).unwrap();
+        for &(x, y, width, height) in blocks {
+            for row in y..y + height {
+                plane.row_mut(row)[x * 4..(x + width) * 4].fill(255);
+            }
+        }
+        frame.presentation_time = nanos_to_cmtime(millis * 1_000_000, 1000);
+        frame.info.dirty_rects = dirty_rects.map(|rects| {
+            rects
+                .iter()
+                .map(|&(x, y, width, height)| CGRect::new(CGPoint::new(x as f64, y as f64), CGSize::new(width as f64, height as f64)))
+                .collect()
+        });
+        frame
+    }
+
+    #[derive(Default)]
+    struct Frames(Vec<VideoFrame>, bool);
+
+    impl VideoSink for Frames {
+        fn write_frame(&mut self, frame: &VideoFrame) -> Result<(), SinkError> {
+            self.0.push(frame.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), SinkError> {
+            self.1 = true;
+            Ok(())
+        }
+    }
+
+    fn millis(frames: &Frames) -> Vec<u64> {
+        frames
+            .0
+            .iter()
+            .map(|frame| cmtime_to_nanos(frame.presentation_time).unwrap() / 1_000_000)
+            .collect()
+    }
+
+    fn most_changed(interval: Duration) -> TimelapseOptions {
+        TimelapseOptions {
+            interval,
+            selection: FrameSelection::MostChanged,
+            ..TimelapseOptions::default()
+        }
+    }
+
+    #[test]
+    fn capture_intervals() {
+        assert_eq!(TimelapseOptions::default().capture_interval(), Duration::from_secs(10));
+        assert_eq!(most_changed(Duration::from_secs(10)).capture_interval(), Duration::from_secs(1));
+        let options = TimelapseOptions {
+            samples_per_interval: 0,
+            ..most_changed(Duration::from_secs(10))
+        };
+        assert_eq!(options.capture_interval(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn latest_frames_are_kept() {
+        let mut writer = TimelapseWriter::new(Frames::default(), TimelapseOptions::default());
+        for second in 0..25 {
+            let mut frame = screen(second * 1000, &[], None);
+            // Frames without new content never stand for an interval.
+            frame.info.status = match second {
+                9 => Some(SCFrameStatus::Idle),
+                24 => Some(SCFrameStatus::Blank),
+                _ => Some(SCFrameStatus::Complete),
+            };
+            writer.write_frame(&frame).unwrap();
+        }
+        // Each interval's frame is written once the next one starts.
+        assert_eq!(millis(writer.get_ref()), [8000, 19000]);
+        writer.finish().unwrap();
+        assert!(matches!(writer.write_frame(&screen(30_000, &[], None)), Err(SinkError::Finished)));
+        assert_eq!((writer.frames_written(), writer.frames_skipped()), (3, 2));
+        let frames = writer.into_inner();
+        assert!(frames.1);
+        assert_eq!(millis(&frames), [8000, 19000, 23000]);
+    }
+
+    #[test]
+    fn playback_rate_retimes_frames() {
+        let options = TimelapseOptions {
+            interval: Duration::from_secs(1),
+            playback_frame_rate: Some(30.0),
+            ..TimelapseOptions::default()
+        };
+        let mut writer = TimelapseWriter::new(Frames::default(), options);
+        for millis in (0..3000).step_by(500) {
+            writer.write_frame(&screen(millis, &[], None)).unwrap();
+        }
+        writer.finish().unwrap();
+        let frames = writer.into_inner();
+        assert_eq!(millis(&frames), [0, 33, 66]);
+        assert!(frames.0.iter().all(|frame| frame.duration == nanos_to_cmtime(33_333_333, 1000)));
+    }
+
+    #[test]
+    fn most_changed_frames_are_kept() {
+        let mut writer = TimelapseWriter::new(Frames::default(), most_changed(Duration::from_secs(1)));
+        let small = (0, 0, 32, 32);
+        let large = (64, 32, 64, 64);
+        let steps: &[(u64, &[Rect])] = &[
+            (0, &[]),
+            // The largest change from the kept blank screen.
+            (1000, &[small]),
+            (1300, &[large]),
+            (1600, &[(96, 0, 32, 32)]),
+            // Ties go to the later frame.
+            (2000, &[large]),
+            (2500, &[large]),
+        ];
+        for &(millis, blocks) in steps {
+            writer.write_frame(&screen(millis, blocks, None)).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(millis(writer.get_ref()), [0, 1300, 2500]);
+    }
+
+    #[test]
+    fn changes_in_passed_over_frames_count() {
+        let mut writer = TimelapseWriter::new(Frames::default(), most_changed(Duration::from_secs(1)));
+        let window = (0, 0, 64, 64);
+        let cursor = (96, 64, 32, 32);
+        writer.write_frame(&screen(0, &[], None)).unwrap();
+        writer.write_frame(&screen(1000, &[window], Some(&[window]))).unwrap();
+        // Only the cursor is dirty, but the window that appeared in the
+        // previous frame is still a change from the kept frame.
+        writer.write_frame(&screen(1500, &[window, cursor], Some(&[cursor]))).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(millis(writer.get_ref()), [0, 1500]);
+    }
+
+    #[test]
+    fn image_sequences_are_named_by_capture_time() {
+        let directory = std::env::temp_dir().join(format!("timelapse-test-{}-sequence", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let sink = ImageSequenceWriter::new(&directory, "lapse", ImageFormat::Ppm, ImageOptions::default());
+        let options = TimelapseOptions {
+            interval: Duration::from_secs(1),
+            ..TimelapseOptions::default()
+        };
+        let mut writer = TimelapseWriter::new(sink, options);
+        let frames: Vec<VideoFrame> = (0..12).map(|index| screen(index * 250, &[(index as usize * 8, 0, 8, 8)], None)).collect();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let sink = writer.into_inner();
+        assert_eq!(
+            sink.paths(),
+            [
+                directory.join("lapse-000000-0000000750.ppm"),
+                directory.join("lapse-000001-0000001750.ppm"),
+                directory.join("lapse-000002-0000002750.ppm"),
+            ]
+        );
+        for (path, frame) in sink.paths().iter().zip(frames.iter().skip(3).step_by(4)) {
+            let mut expected = Vec::new();
+            encode(&mut expected, frame, ImageFormat::Ppm, sink.options()).unwrap();
+            assert_eq!(fs::read(path).unwrap(), expected);
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+}